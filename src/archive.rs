@@ -0,0 +1,314 @@
+// archive 子命令: 探测输入的编码与换行符, 切分、核对后提交, 最后删除输入; 阶段记录在 <prefix>.archive 中
+
+use std::fs::File;
+use std::io::{self, Write, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use encoding_rs::GBK;
+use crate::config::{self, Config};
+use crate::hash::{parse_sha256_hex, sha256, to_hex, Sha256};
+use crate::manifest::chunk_list;
+use crate::merge::{run_verify, MergeOptions};
+use crate::naming::temp_path;
+use crate::source::{Destination, Source, MIN_BUFFER_SIZE};
+use crate::split::{confirm_overwrite, run_split};
+use crate::{Format, LineEndings};
+
+const ARCHIVE_SNIFF_SIZE: usize = 1024 * 1024; // archive 从输入开头取样判断编码与换行符的字节数
+
+const ARCHIVE_DICTIONARY_CHUNK: usize = 4 * 1024 * 1024; // 分块不超过此大小时 archive 训练内嵌字典
+
+// archive 的输入探测: 开头含 NUL 时按二进制处理; 否则取最常见的换行符,
+// 不是有效的 UTF-8 而能按 GBK 解码时为 GBK. 样本可能截断在多字节字符中间
+fn detect_layout(sample: &[u8]) -> Vec<String> {
+    if sample.contains(&0) {
+        return vec!["--binary".to_string()];
+    }
+    let mut endings = LineEndings::default();
+    endings.update(sample);
+    let line_ending = endings.dominant().unwrap_or("LF");
+    let utf8 = std::str::from_utf8(sample).map_or_else(|e| e.error_len().is_none(), |_| true);
+    let gbk = || [sample, &sample[..sample.len().saturating_sub(1)]]
+        .iter()
+        .any(|text| GBK.decode_without_bom_handling_and_without_replacement(text).is_some());
+    let encoding = if !utf8 && gbk() { "GBK" } else { "UTF-8" };
+    ["--line-ending", line_ending, "--encoding", encoding].iter().map(|arg| arg.to_string()).collect()
+}
+
+fn file_sha256(path: &str) -> io::Result<[u8; 32]> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; MIN_BUFFER_SIZE];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(hasher.finish()),
+            n => hasher.update(&buffer[..n]),
+        }
+    }
+}
+
+// archive 的阶段, 进入下一阶段前先把标记落盘: 切分与镜像进行中 -> 分卷与镜像都已落盘并核对 -> manifest 已提交, 可以删除输入
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArchivePhase {
+    Split,
+    Verified,
+    Committed,
+}
+
+impl ArchivePhase {
+    fn name(self) -> &'static str {
+        match self {
+            ArchivePhase::Split => "split",
+            ArchivePhase::Verified => "verified",
+            ArchivePhase::Committed => "committed",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        [ArchivePhase::Split, ArchivePhase::Verified, ArchivePhase::Committed].into_iter().find(|phase| phase.name() == value)
+    }
+}
+
+// archive 的进度标记 <output_prefix>.archive, 中途崩溃后用同样的参数重新运行即从记录的阶段继续;
+// 只有 committed 之后才删除输入, 任何时刻崩溃都至少留有一份完整的数据
+#[derive(Debug, PartialEq)]
+struct ArchiveMarker {
+    path: PathBuf,
+    input: String,
+    // 开始时输入的大小与修改时间(纳秒), 删除前核对, 期间被改动的输入不删除
+    bytes: u64,
+    mtime: u128,
+    phase: ArchivePhase,
+    // 提交时 manifest 的 SHA-256, 删除输入前核对分卷集没有被之后的运行覆盖
+    manifest_sha256: Option<[u8; 32]>,
+}
+
+impl ArchiveMarker {
+    fn path(prefix: &str) -> PathBuf {
+        PathBuf::from(format!("{}.archive", prefix))
+    }
+
+    fn read(prefix: &str) -> io::Result<Option<Self>> {
+        let path = Self::path(prefix);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let field = |name: &str| text.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix('='));
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("无效的归档标记: {}", path.display()));
+        Ok(Some(ArchiveMarker {
+            input: field("input").ok_or_else(invalid)?.to_string(),
+            bytes: field("bytes").and_then(|v| v.parse().ok()).ok_or_else(invalid)?,
+            mtime: field("mtime").and_then(|v| v.parse().ok()).ok_or_else(invalid)?,
+            phase: field("phase").and_then(ArchivePhase::parse).ok_or_else(invalid)?,
+            manifest_sha256: field("manifest_sha256").map(|v| parse_sha256_hex(v).ok_or_else(invalid)).transpose()?,
+            path,
+        }))
+    }
+
+    // 写临时文件并落盘后重命名, 崩溃时标记要么是旧阶段要么是新阶段
+    fn write(&self) -> io::Result<()> {
+        let tmp = temp_path(&self.path);
+        let mut file = File::create(&tmp)?;
+        writeln!(file, "input={}", self.input)?;
+        writeln!(file, "bytes={}", self.bytes)?;
+        writeln!(file, "mtime={}", self.mtime)?;
+        writeln!(file, "phase={}", self.phase.name())?;
+        if let Some(digest) = self.manifest_sha256 {
+            writeln!(file, "manifest_sha256={}", to_hex(&digest))?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        sync_parent(&self.path)
+    }
+}
+
+// 输入的大小与修改时间
+fn file_stamp(path: &str) -> io::Result<(u64, u128)> {
+    let metadata = std::fs::metadata(path)?;
+    let mtime = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    Ok((metadata.len(), mtime))
+}
+
+// 让目录项(新建、重命名与删除的文件)落盘
+pub(crate) fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => File::open(".")?.sync_all(),
+    }
+}
+
+// 分卷与 manifest 落盘; 本地目录镜像逐个与分卷比较后落盘, S3 与 unix: 目标无法读回, 以写入时的返回状态为准
+fn settle_archive(prefix: &str, mirrors: &[Destination]) -> io::Result<()> {
+    let entries = chunk_list(prefix)?.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("找不到 {}.manifest", prefix)))?;
+    for entry in &entries {
+        File::open(&entry.file)?.sync_all()?;
+    }
+    let manifest = PathBuf::from(format!("{}.manifest", prefix));
+    File::open(&manifest)?.sync_all()?;
+    sync_parent(&manifest)?;
+    for dir in mirrors.iter().filter_map(|mirror| match mirror {
+        Destination::Dir(dir) => Some(dir),
+        _ => None,
+    }) {
+        for entry in &entries {
+            let copy = dir.join(Path::new(&entry.file).file_name().unwrap_or_default());
+            if std::fs::read(&copy)? != std::fs::read(&entry.file)? {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("镜像 {} 与分卷 {} 不一致", copy.display(), entry.file)));
+            }
+            File::open(&copy)?.sync_all()?;
+        }
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// 一步归档本地文件: 探测编码与换行符, 计算 SHA-256, 按压缩后大小均衡切分并在 manifest 中记录各分卷的摘要,
+// 分卷小时训练内嵌字典; 切分后解压全部分卷与输入核对, 镜像(--upload)也都写入并核对后才提交并删除输入(--keep 保留).
+// 其余选项原样交给切分, 可以覆盖探测结果. 各阶段记录在 <output_prefix>.archive 中, 中断后重新运行从中断处继续
+pub fn run_archive(input: &str, prefix: &str, args: &[String]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let mut keep = false;
+    let mut uploads = Vec::new();
+    let mut passthrough = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keep" => keep = true,
+            "--upload" => uploads.push(args.next().ok_or_else(|| invalid("选项 --upload 缺少参数".to_string()))?.clone()),
+            _ => passthrough.push(arg.clone()),
+        }
+    }
+
+    let resumed = ArchiveMarker::read(prefix)?;
+    if let Some(marker) = &resumed {
+        if marker.input != input {
+            return Err(invalid(format!("{} 记录的是另一个输入 {} 的归档", marker.path.display(), marker.input)));
+        }
+        println!("从上次中断的阶段 {} 继续归档", marker.phase.name());
+    }
+    let mut marker = match resumed {
+        Some(marker) if marker.phase != ArchivePhase::Split => marker,
+        resumed => {
+            if !matches!(Source::parse(input), Source::Local(_)) || !Path::new(input).is_file() {
+                return Err(invalid(format!("archive 只支持本地普通文件: {}", input)));
+            }
+            let mut sample = Vec::new();
+            File::open(input)?.take(ARCHIVE_SNIFF_SIZE as u64).read_to_end(&mut sample)?;
+            let (bytes, mtime) = file_stamp(input)?;
+            let digest = file_sha256(input)?;
+            let mut split_args = vec!["zstd_compressor".to_string(), input.to_string(), prefix.to_string()];
+            split_args.extend(detect_layout(&sample));
+            split_args.extend(["--balance-compressed", "--content-addressed", "--input-sha256"].map(str::to_string));
+            split_args.push(to_hex(&digest));
+            for upload in uploads {
+                split_args.extend(["--output".to_string(), upload]);
+            }
+            split_args.extend(passthrough);
+
+            let mut config = config::layered_args(split_args).and_then(Config::parse).map_err(invalid)?;
+            // 字典对小分卷的压缩比帮助明显, 大分卷几乎没有收益却要多读一遍输入采样
+            config.inline_dictionary |= cfg!(feature = "dictionary")
+                && config.format == Format::Zstd
+                && config.single_output.is_none()
+                && !config.stream
+                && config.chunk_size <= ARCHIVE_DICTIONARY_CHUNK;
+            // 上次中断时留下的是本归档自己写了一半的分卷, 直接覆盖
+            config.yes |= resumed.is_some();
+            confirm_overwrite(&config)?;
+
+            let marker = ArchiveMarker { path: ArchiveMarker::path(prefix), input: input.to_string(), bytes, mtime, phase: ArchivePhase::Split, manifest_sha256: None };
+            marker.write()?;
+            run_split(&config)?;
+            settle_archive(prefix, &config.mirrors)?;
+            run_verify(prefix, &MergeOptions { check_source: true, ..MergeOptions::default() })?;
+            let marker = ArchiveMarker { phase: ArchivePhase::Verified, ..marker };
+            marker.write()?;
+            marker
+        }
+    };
+    if marker.phase == ArchivePhase::Verified {
+        marker.manifest_sha256 = Some(sha256(&std::fs::read(format!("{}.manifest", prefix))?));
+        marker.phase = ArchivePhase::Committed;
+        marker.write()?;
+    }
+
+    if Some(sha256(&std::fs::read(format!("{}.manifest", prefix))?)) != marker.manifest_sha256 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}.manifest 在提交后被改动, 不删除输入", prefix)));
+    }
+    if !keep && Path::new(input).exists() {
+        if file_stamp(input)? != (marker.bytes, marker.mtime) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} 在归档开始后被改动, 不删除", input)));
+        }
+        std::fs::remove_file(input)?;
+        sync_parent(Path::new(input))?;
+        println!("已删除输入文件 {}", input);
+    }
+    std::fs::remove_file(&marker.path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::read_manifest;
+    use crate::merge::merge_chunks;
+    use crate::testing::Scratch;
+
+    #[test]
+    fn archive_detects_layout_verifies_and_removes_the_input() {
+        let (gbk, _, _) = GBK.encode("第一行\r\n第二行\r\n");
+        assert_eq!(detect_layout(&gbk), ["--line-ending", "CRLF", "--encoding", "GBK"]);
+        assert_eq!(detect_layout("中文\n".as_bytes()[..4].as_ref()), ["--line-ending", "LF", "--encoding", "UTF-8"]);
+        assert_eq!(detect_layout(b"a\rb\r\0"), ["--binary"]);
+
+        let dir = Scratch::new("archive");
+        let input = dir.join("in.log");
+        let text: String = (0..120_000).map(|i| format!("{} 第 {} 行\r\n", i % 7, i)).collect();
+        std::fs::write(&input, &text).unwrap();
+        let prefix = dir.join("part").display().to_string();
+        let mirror = dir.join("mirror").display().to_string();
+        run_archive(&input.display().to_string(), &prefix, &["--upload", &mirror, "--target-size", "1", "--porcelain"].map(String::from)).unwrap();
+        assert!(!input.exists());
+        let table = read_manifest(&prefix).unwrap().unwrap();
+        assert!(table.column("sha256").is_some());
+        assert!(table.lineage.iter().any(|(key, value)| key == "source" && value.contains(&to_hex(&sha256(text.as_bytes())))));
+        let mut merged = Vec::new();
+        merge_chunks(&prefix, &mut merged, &MergeOptions::default()).unwrap();
+        assert_eq!(merged, text.as_bytes());
+        assert!(dir.join("mirror").join("part.001.zst").exists());
+        assert!(run_archive(&input.display().to_string(), &prefix, &[]).is_err());
+    }
+
+    #[test]
+    fn interrupted_archive_resumes_without_losing_the_input() {
+        let dir = Scratch::new("archive_resume");
+        let input = dir.join("in.log");
+        let text: String = (0..150_000).map(|i| format!("{} 第 {} 行\n", i % 7, i)).collect();
+        std::fs::write(&input, &text).unwrap();
+        let (input, prefix) = (input.display().to_string(), dir.join("part").display().to_string());
+        let args = |extra: &[&str]| [&["--target-size", "1", "--porcelain"], extra].concat().iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        assert!(run_archive(&input, &prefix, &args(&["--inject-failure", "write:chunk=2"])).is_err());
+        let marker = ArchiveMarker::read(&prefix).unwrap().unwrap();
+        assert_eq!(marker.phase, ArchivePhase::Split);
+        assert!(Path::new(&input).exists());
+        run_archive(&input, &prefix, &args(&[])).unwrap();
+        assert!(!Path::new(&input).exists() && ArchiveMarker::read(&prefix).unwrap().is_none());
+        let mut merged = Vec::new();
+        merge_chunks(&prefix, &mut merged, &MergeOptions::default()).unwrap();
+        assert_eq!(merged, text.as_bytes());
+
+        // 提交后输入被改动: 不删除, 标记留待处理
+        std::fs::write(&input, "changed\n").unwrap();
+        let manifest_sha256 = Some(sha256(&std::fs::read(format!("{}.manifest", prefix)).unwrap()));
+        let committed = ArchiveMarker { manifest_sha256, phase: ArchivePhase::Committed, ..marker };
+        committed.write().unwrap();
+        assert_eq!(ArchiveMarker::read(&prefix).unwrap().as_ref(), Some(&committed));
+        let error = run_archive(&input, &prefix, &args(&[])).unwrap_err();
+        assert!(error.to_string().contains("被改动"), "{}", error);
+        assert!(Path::new(&input).exists());
+        assert!(run_archive("other.log", &prefix, &[]).is_err());
+    }
+}
@@ -0,0 +1,1073 @@
+// 记录边界的查找策略: 换行符或自定义分隔符, 以匹配正则的行开始的多行记录, CSV, 定长记录, 固定行数的记录,
+// SQL 转储中的语句与表, XML 中重复出现的元素, 以及由内容决定的切分点(--cdc).
+// Chunker 只在这里找到的记录结束处切分
+
+use encoding_rs::{Encoding, UTF_8, GBK};
+use crate::regex::Regex;
+
+// 调用方持有扫描位置 pos, 数据可以分批到达, 每次从 pos 继续向后扫描;
+// 在某个记录结束处切掉之前的数据后, 调用方把 pos 置零并调用 reset, 从新记录的开头重新扫描
+pub trait Boundary: Send {
+    // 返回 data[*pos..] 中下一个记录结束的位置(记录之后的字节位置), 并把 *pos 推进到已扫描处;
+    // more 为 true 时 data 之后还有数据, 末尾不足以判断的部分留到下次
+    fn next_end(&mut self, data: &[u8], pos: &mut usize, more: bool) -> Option<usize>;
+
+    // 扫描到末尾, 返回其中最后一个记录结束的位置
+    fn last_end(&mut self, data: &[u8], pos: &mut usize, more: bool) -> Option<usize> {
+        let mut last = None;
+        while let Some(end) = self.next_end(data, pos, more) {
+            last = Some(end);
+        }
+        last
+    }
+
+    fn reset(&mut self) {}
+}
+
+// 解析 --records 的值: csv, jsonl, fastq, fasta, sql, sql-table, xml:<元素名>, fixed:<字节数> 或 regex:<正则>
+pub fn parse(value: &str, encoding: &'static Encoding) -> Result<Box<dyn Boundary>, String> {
+    if value.eq_ignore_ascii_case("csv") {
+        return Ok(Box::new(Csv::default()));
+    }
+    // JSON 字符串中的换行必须转义, 每个 LF 都结束一条记录, 行尾的 CR 属于 JSON 的空白
+    if value.eq_ignore_ascii_case("jsonl") {
+        return Ok(Box::new(Delimiter::new("\n", encoding)));
+    }
+    // FASTQ 每条读段正好 4 行(标识, 序列, +, 质量); 质量行可能以 @ 开头, 不能按行首判断
+    if value.eq_ignore_ascii_case("fastq") {
+        return Ok(Box::new(LineGroups::new(4)));
+    }
+    // FASTA 的序列可以折成多行, 每条记录从 > 开头的标题行开始
+    if value.eq_ignore_ascii_case("fasta") {
+        return Ok(Box::new(RecordStart::new(Regex::parse("^>").expect("固定的正则"), encoding)));
+    }
+    // mysqldump/pg_dump 的输出: 每个分卷只含完整的语句, 或只在两张表之间切分, 各分卷可以并行导入
+    if value.eq_ignore_ascii_case("sql") || value.eq_ignore_ascii_case("sql-table") {
+        // GBK 字符的后续字节可能是反斜杠或反引号, 按字节扫描会误判引号
+        if encoding != UTF_8 {
+            return Err(format!("--records {} 按字节扫描引号与分号, 只支持 UTF-8 编码", value));
+        }
+        return Ok(Box::new(SqlDump::new(value.eq_ignore_ascii_case("sql-table"))));
+    }
+    if let Some(name) = value.strip_prefix("xml:") {
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || "<>/\"'=".contains(c)) {
+            return Err(format!("无效的 XML 元素名: {}", name));
+        }
+        if encoding != UTF_8 {
+            return Err("--records xml: 按字节扫描标签, 只支持 UTF-8 编码".to_string());
+        }
+        return Ok(Box::new(XmlElement::new(name)));
+    }
+    if let Some(width) = value.strip_prefix("fixed:") {
+        return match width.parse::<usize>() {
+            Ok(width) if width > 0 => Ok(Box::new(FixedWidth::new(width))),
+            _ => Err(format!("无效的定长记录长度: {}", width)),
+        };
+    }
+    if let Some(pattern) = value.strip_prefix("regex:") {
+        let regex = Regex::parse(pattern).map_err(|e| format!("记录首行的正则无效: {}", e))?;
+        return Ok(Box::new(RecordStart::new(regex, encoding)));
+    }
+    Err(format!("无效的记录格式: {}. 请使用 csv, jsonl, fastq, fasta, sql, sql-table, xml:NAME, fixed:N 或 regex:PATTERN", value))
+}
+
+// 换行符的字节不可能出现在多字节字符内部时, 可以不解码直接按字节查找:
+// UTF-8 多字节序列的每个字节都 >= 0x80; GBK/GB18030 的后续字节 >= 0x30
+fn byte_scannable(delimiter: &[u8], encoding: &'static Encoding) -> bool {
+    if encoding == UTF_8 {
+        delimiter.is_ascii()
+    } else if encoding == GBK {
+        delimiter.iter().all(|&b| b < 0x30)
+    } else {
+        false
+    }
+}
+
+// 按 8 字节一组查找单个字节: 与重复的目标字节异或后, 含零字节的一组才逐字节确认
+const LOW_BITS: u64 = 0x0101_0101_0101_0101;
+const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+
+fn has_zero_byte(word: u64) -> bool {
+    word.wrapping_sub(LOW_BITS) & !word & HIGH_BITS != 0
+}
+
+fn memchr(needle: u8, data: &[u8]) -> Option<usize> {
+    let repeated = LOW_BITS * needle as u64;
+    let mut groups = data.chunks_exact(8);
+    for (n, group) in groups.by_ref().enumerate() {
+        if has_zero_byte(u64::from_ne_bytes(group.try_into().unwrap()) ^ repeated) {
+            return group.iter().position(|&b| b == needle).map(|i| n * 8 + i);
+        }
+    }
+    let tail = data.len() - groups.remainder().len();
+    groups.remainder().iter().position(|&b| b == needle).map(|i| tail + i)
+}
+
+fn memrchr(needle: u8, data: &[u8]) -> Option<usize> {
+    let repeated = LOW_BITS * needle as u64;
+    let mut groups = data.rchunks_exact(8);
+    for (n, group) in groups.by_ref().enumerate() {
+        if has_zero_byte(u64::from_ne_bytes(group.try_into().unwrap()) ^ repeated) {
+            let start = data.len() - (n + 1) * 8;
+            return group.iter().rposition(|&b| b == needle).map(|i| start + i);
+        }
+    }
+    groups.remainder().iter().rposition(|&b| b == needle)
+}
+
+// 位置 i 处字符的字节长度, 畸形或被截断的字节按单字节处理, 保证总能前进并重新同步
+pub(crate) fn char_len(data: &[u8], i: usize, encoding: &'static Encoding) -> usize {
+    let continuation = |offset: usize, range: std::ops::RangeInclusive<u8>| {
+        data.get(i + offset).is_some_and(|b| range.contains(b))
+    };
+
+    if encoding == UTF_8 {
+        let len = match data[i] {
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => return 1,
+        };
+        if (1..len).all(|offset| continuation(offset, 0x80..=0xBF)) { len } else { 1 }
+    } else if encoding == GBK {
+        match data[i] {
+            0x81..=0xFE if continuation(1, 0x40..=0x7E) || continuation(1, 0x80..=0xFE) => 2,
+            0x81..=0xFE if continuation(1, 0x30..=0x39) && continuation(2, 0x81..=0xFE) && continuation(3, 0x30..=0x39) => 4,
+            _ => 1,
+        }
+    } else {
+        1
+    }
+}
+
+// 换行符(LF, CRLF, CR)或自定义分隔符, 分隔符属于它之前的记录
+pub struct Delimiter {
+    delimiter: Vec<u8>,
+    encoding: &'static Encoding,
+    scannable: bool,
+}
+
+impl Delimiter {
+    pub fn new(delimiter: &str, encoding: &'static Encoding) -> Self {
+        let delimiter = encoding.encode(delimiter).0.into_owned();
+        let scannable = byte_scannable(&delimiter, encoding);
+        Delimiter { delimiter, encoding, scannable }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.delimiter
+    }
+
+    // 可按字节查找且没有既是前缀又是后缀的真子串时, 分隔符的各次出现互不重叠,
+    // 从任意位置开始向后查找得到的位置与从头扫描时相同, 可以把输入分段各自查找
+    pub fn self_synchronizing(&self) -> bool {
+        let len = self.delimiter.len();
+        self.scannable && (1..len).all(|k| self.delimiter[..k] != self.delimiter[len - k..])
+    }
+}
+
+impl Boundary for Delimiter {
+    // 逐字符前进, 只在字符边界上匹配, 即使输入含畸形字节得到的也是精确的字节位置;
+    // 可按字节查找时直接跳到分隔符首字节出现的位置
+    fn next_end(&mut self, data: &[u8], pos: &mut usize, more: bool) -> Option<usize> {
+        // 末尾可能是被截断的分隔符或字符, 等更多数据到来再判断
+        let lookahead = if self.scannable { self.delimiter.len() } else { self.delimiter.len().max(4) };
+        if self.scannable {
+            let limit = if more { data.len().saturating_sub(lookahead - 1) } else { data.len() };
+            let mut i = *pos;
+            while i < limit {
+                let Some(found) = memchr(self.delimiter[0], &data[i..limit]) else { break };
+                let start = i + found;
+                if data[start..].starts_with(&self.delimiter) {
+                    *pos = start + self.delimiter.len();
+                    return Some(*pos);
+                }
+                i = start + 1;
+            }
+            *pos = (*pos).max(limit);
+            return None;
+        }
+        let mut i = *pos;
+        while i < data.len() && !(more && i + lookahead > data.len()) {
+            if data[i..].starts_with(&self.delimiter) {
+                *pos = i + self.delimiter.len();
+                return Some(*pos);
+            }
+            i += char_len(data, i, self.encoding);
+        }
+        *pos = i;
+        None
+    }
+
+    // 快速路径: 单字节分隔符(如 \n)直接从后向前查找; 多字节分隔符可能与自身重叠(如 \r\n\r\n),
+    // 从后向前找到的位置不一定是从前向后逐个匹配时的位置, 仍逐个向后查找
+    fn last_end(&mut self, data: &[u8], pos: &mut usize, more: bool) -> Option<usize> {
+        let (&[byte], true) = (&self.delimiter[..], self.scannable) else {
+            let mut last = None;
+            while let Some(end) = self.next_end(data, pos, more) {
+                last = Some(end);
+            }
+            return last;
+        };
+        let last = memrchr(byte, &data[*pos..]).map(|i| *pos + i + 1);
+        *pos = data.len().max(*pos);
+        last
+    }
+}
+
+// CSV: 引号外的换行结束一条记录, 带引号的字段中可以有换行; 转义的 "" 连续翻转两次, 不影响状态
+#[derive(Default)]
+pub struct Csv {
+    quoted: bool,
+}
+
+impl Boundary for Csv {
+    fn next_end(&mut self, data: &[u8], pos: &mut usize, _more: bool) -> Option<usize> {
+        for (i, &byte) in data.iter().enumerate().skip(*pos) {
+            match byte {
+                b'"' => self.quoted = !self.quoted,
+                b'\n' if !self.quoted => {
+                    *pos = i + 1;
+                    return Some(i + 1);
+                }
+                _ => {}
+            }
+        }
+        *pos = data.len();
+        None
+    }
+
+    fn reset(&mut self) {
+        self.quoted = false;
+    }
+}
+
+// --quote-char: 引号之间的换行符不结束记录, 带引号的多行字段不会被切开; 成对的转义引号连续翻转两次, 不影响状态.
+// 换行符与引号都按字节查找, 不能出现在多字节字符内部
+pub struct Quoted {
+    delimiter: Vec<u8>,
+    quote: u8,
+    quoted: bool,
+}
+
+impl Quoted {
+    pub fn new(delimiter: &str, quote: char, encoding: &'static Encoding) -> Result<Self, String> {
+        let delimiter = encoding.encode(delimiter).0.into_owned();
+        let quote_bytes = encoding.encode(quote.encode_utf8(&mut [0; 4])).0.into_owned();
+        let &[quote_byte] = &quote_bytes[..] else {
+            return Err(format!("引号字符 {} 在 {} 中不是单个字节", quote, encoding.name()));
+        };
+        if !byte_scannable(&delimiter, encoding) || !byte_scannable(&quote_bytes, encoding) {
+            return Err(format!("--quote-char 按字节查找, {} 中的换行符或引号 {} 可能出现在多字节字符内部", encoding.name(), quote));
+        }
+        if delimiter.contains(&quote_byte) {
+            return Err(format!("引号字符 {} 不能是换行符的一部分", quote.escape_default()));
+        }
+        Ok(Quoted { delimiter, quote: quote_byte, quoted: false })
+    }
+}
+
+impl Boundary for Quoted {
+    fn next_end(&mut self, data: &[u8], pos: &mut usize, more: bool) -> Option<usize> {
+        // 末尾可能是被截断的换行符, 等更多数据到来再判断
+        let limit = if more { data.len().saturating_sub(self.delimiter.len() - 1) } else { data.len() };
+        let mut i = *pos;
+        while i < limit {
+            if data[i] == self.quote {
+                self.quoted = !self.quoted;
+            } else if !self.quoted && data[i..].starts_with(&self.delimiter) {
+                *pos = i + self.delimiter.len();
+                return Some(*pos);
+            }
+            i += 1;
+        }
+        *pos = (*pos).max(limit);
+        None
+    }
+
+    fn reset(&mut self) {
+        self.quoted = false;
+    }
+}
+
+// 定长记录, 从数据开头(总是某条记录的开头)起每 width 字节一条
+pub struct FixedWidth {
+    width: usize,
+}
+
+impl FixedWidth {
+    pub fn new(width: usize) -> Self {
+        assert!(width > 0, "定长记录的长度必须大于 0");
+        FixedWidth { width }
+    }
+}
+
+impl Boundary for FixedWidth {
+    fn next_end(&mut self, data: &[u8], pos: &mut usize, _more: bool) -> Option<usize> {
+        let end = (*pos / self.width + 1) * self.width;
+        if end <= data.len() {
+            *pos = end;
+            Some(end)
+        } else {
+            *pos = data.len();
+            None
+        }
+    }
+
+    fn last_end(&mut self, data: &[u8], pos: &mut usize, _more: bool) -> Option<usize> {
+        let end = data.len() / self.width * self.width;
+        let last = (end > *pos).then_some(end);
+        *pos = data.len();
+        last
+    }
+}
+
+// 每 lines 行一条记录, 从数据开头(总是某条记录的开头)起计数; 行以 LF 结尾, 行尾的 CR 属于这一行
+pub struct LineGroups {
+    lines: usize,
+    // 当前记录已经结束的行数
+    counted: usize,
+}
+
+impl LineGroups {
+    pub fn new(lines: usize) -> Self {
+        assert!(lines > 0, "每条记录的行数必须大于 0");
+        LineGroups { lines, counted: 0 }
+    }
+}
+
+impl Boundary for LineGroups {
+    fn next_end(&mut self, data: &[u8], pos: &mut usize, _more: bool) -> Option<usize> {
+        let mut i = *pos;
+        while let Some(found) = memchr(b'\n', &data[i..]) {
+            i += found + 1;
+            self.counted += 1;
+            if self.counted == self.lines {
+                self.counted = 0;
+                *pos = i;
+                return Some(i);
+            }
+        }
+        *pos = data.len();
+        None
+    }
+
+    fn reset(&mut self) {
+        self.counted = 0;
+    }
+}
+
+// 以匹配正则的行开始一条记录, 之后不匹配的行(如异常堆栈)属于同一条记录; 行以 LF 或 CRLF 结尾
+pub struct RecordStart {
+    regex: Regex,
+    encoding: &'static Encoding,
+}
+
+impl RecordStart {
+    pub fn new(regex: Regex, encoding: &'static Encoding) -> Self {
+        RecordStart { regex, encoding }
+    }
+
+    fn matches(&self, line: &[u8]) -> bool {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        self.regex.is_match(&self.encoding.decode_without_bom_handling(line).0)
+    }
+}
+
+impl Boundary for RecordStart {
+    // 记录在下一条记录的首行之前结束, 数据开头的行不算
+    fn next_end(&mut self, data: &[u8], pos: &mut usize, more: bool) -> Option<usize> {
+        let mut i = *pos;
+        while let Some(newline) = data[i..].iter().position(|&b| b == b'\n') {
+            let start = i + newline + 1;
+            let line_end = match data[start..].iter().position(|&b| b == b'\n') {
+                Some(len) => start + len,
+                // 行还没有读完, 等数据到齐再匹配; 输入结束时按已有部分匹配
+                None if more => {
+                    *pos = i + newline;
+                    return None;
+                }
+                None => data.len(),
+            };
+            if start < data.len() && self.matches(&data[start..line_end]) {
+                *pos = start;
+                return Some(start);
+            }
+            i = start;
+        }
+        *pos = data.len();
+        None
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum SqlState {
+    Code,
+    // 字符串或带引号的标识符; escaped 为上一个字节是转义用的反斜杠
+    Quoted { quote: u8, escaped: bool },
+    // PostgreSQL 的 $tag$ 字符串(函数体), 其中的 ; 不结束语句
+    Dollar(Vec<u8>),
+    LineComment,
+    BlockComment,
+    // COPY ... FROM stdin; 之后的数据行, 直到单独一行 \.
+    CopyData,
+}
+
+// SQL 转储: 引号、注释、$tag$ 字符串与 COPY 数据之外的 ; 结束一条语句, 记录在语句所在行的行尾结束,
+// 语句之间的空行与注释属于下一条语句. tables 为 true 时只在下一行起是表的注释头
+// (mysqldump 的 "-- Table structure for table", pg_dump 的 "-- Name: ...; Type: TABLE" 与 "Type: TABLE DATA")时结束记录,
+// 一张表的建表语句与数据留在同一个分卷. 按字节扫描, 只用于 UTF-8
+pub struct SqlDump {
+    tables: bool,
+    // MySQL 字符串中的反斜杠是转义; pg_dump 设置 standard_conforming_strings = on 后不是
+    backslash_escapes: bool,
+    state: SqlState,
+    // 当前语句第一个字节的位置, 语句之间为 None
+    statement: Option<usize>,
+    // 上一条语句已经结束, 其后到行尾只有空白与注释
+    terminated: bool,
+    // 刚结束的语句是 COPY ... FROM stdin, 下一行起是数据
+    copy: bool,
+}
+
+impl SqlDump {
+    pub fn new(tables: bool) -> Self {
+        SqlDump { tables, backslash_escapes: true, state: SqlState::Code, statement: None, terminated: false, copy: false }
+    }
+
+    fn is_table_header(line: &[u8]) -> bool {
+        let contains = |needle: &[u8]| line.windows(needle.len()).any(|window| window == needle);
+        line.starts_with(b"-- Table structure for table ")
+            || (line.starts_with(b"-- ") && (contains(b"; Type: TABLE;") || contains(b"; Type: TABLE DATA;")))
+    }
+
+    // 语句在 start 之前结束后能否在这里切分; 按表切分时向后看到第一个不是空行或注释的行, 数据不够时为 None
+    fn cut_before(&self, data: &[u8], start: usize, more: bool) -> Option<bool> {
+        if !self.tables {
+            return Some(true);
+        }
+        let mut i = start;
+        loop {
+            let Some(n) = memchr(b'\n', &data[i..]) else { return (!more).then_some(false) };
+            let line = &data[i..i + n];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if Self::is_table_header(line) {
+                return Some(true);
+            }
+            if !(line.is_empty() || line.starts_with(b"--")) {
+                return Some(false);
+            }
+            i += n + 1;
+        }
+    }
+
+    fn end_statement(&mut self, text: &[u8]) {
+        let text = String::from_utf8_lossy(text).to_ascii_lowercase();
+        let text = text.trim();
+        if text.starts_with("copy ") && text.ends_with("from stdin") {
+            self.copy = true;
+        }
+        if text.starts_with("set standard_conforming_strings") {
+            self.backslash_escapes = !text.ends_with("on");
+        }
+        self.statement = None;
+        self.terminated = true;
+    }
+
+    fn open_statement(&mut self, i: usize) {
+        self.statement.get_or_insert(i);
+        self.terminated = false;
+    }
+}
+
+impl Boundary for SqlDump {
+    // 需要后面的字节才能判断时(如 - 之后是否还是 -), 停在该字节处等更多数据到来
+    fn next_end(&mut self, data: &[u8], pos: &mut usize, more: bool) -> Option<usize> {
+        let mut i = *pos;
+        while i < data.len() {
+            let byte = data[i];
+            let next = data.get(i + 1).copied();
+            let undecided = next.is_none() && more;
+            match &self.state {
+                SqlState::LineComment => {
+                    if byte == b'\n' {
+                        // 换行符照常结束语句所在的行
+                        self.state = SqlState::Code;
+                        continue;
+                    }
+                }
+                SqlState::BlockComment => match (byte, next) {
+                    (b'*', Some(b'/')) => {
+                        self.state = SqlState::Code;
+                        i += 1;
+                    }
+                    (b'*', None) if more => break,
+                    _ => {}
+                },
+                &SqlState::Quoted { quote, escaped } => {
+                    // 成对的引号连续关闭又打开, 不影响状态
+                    if escaped {
+                        self.state = SqlState::Quoted { quote, escaped: false };
+                    } else if byte == b'\\' && quote == b'\'' && self.backslash_escapes {
+                        self.state = SqlState::Quoted { quote, escaped: true };
+                    } else if byte == quote {
+                        self.state = SqlState::Code;
+                    }
+                }
+                SqlState::Dollar(tag) => {
+                    if data[i..].starts_with(tag) {
+                        i += tag.len();
+                        self.state = SqlState::Code;
+                        continue;
+                    }
+                    if more && data.len() - i < tag.len() && tag.starts_with(&data[i..]) {
+                        break;
+                    }
+                }
+                SqlState::CopyData => {
+                    let Some(n) = memchr(b'\n', &data[i..]) else {
+                        if !more {
+                            i = data.len();
+                        }
+                        break;
+                    };
+                    let line = &data[i..i + n];
+                    if line.strip_suffix(b"\r").unwrap_or(line) == b"\\." {
+                        // 数据结束, 行尾的换行符像语句结束后一样处理
+                        self.state = SqlState::Code;
+                        self.terminated = true;
+                        i += n;
+                    } else {
+                        i += n + 1;
+                    }
+                    continue;
+                }
+                SqlState::Code => match byte {
+                    b'\n' if self.copy => {
+                        self.copy = false;
+                        self.state = SqlState::CopyData;
+                    }
+                    b'\n' if self.terminated && self.statement.is_none() => match self.cut_before(data, i + 1, more) {
+                        None => {
+                            *pos = i;
+                            return None;
+                        }
+                        Some(cut) => {
+                            self.terminated = false;
+                            if cut {
+                                *pos = i + 1;
+                                return Some(i + 1);
+                            }
+                        }
+                    },
+                    b';' => {
+                        let start = self.statement.unwrap_or(i);
+                        self.end_statement(&data[start..i]);
+                    }
+                    b'-' | b'/' if undecided => break,
+                    b'-' if next == Some(b'-') => self.state = SqlState::LineComment,
+                    b'/' if next == Some(b'*') => {
+                        // mysqldump 的 /*!40101 SET ... */; 是可执行的注释, 按语句的一部分处理
+                        self.open_statement(i);
+                        self.state = SqlState::BlockComment;
+                        i += 1;
+                    }
+                    b'\'' | b'"' | b'`' => {
+                        self.open_statement(i);
+                        self.state = SqlState::Quoted { quote: byte, escaped: false };
+                    }
+                    b'$' => {
+                        self.open_statement(i);
+                        let identifier = |b: &u8| b.is_ascii_alphanumeric() || *b == b'_';
+                        let tag_len = data[i + 1..].iter().take_while(|b| identifier(b)).count();
+                        let close = i + 1 + tag_len;
+                        if close == data.len() && more {
+                            break;
+                        }
+                        // $1 是参数, 标识符中的 $ 也不开始字符串
+                        let in_identifier = i > 0 && (identifier(&data[i - 1]) || data[i - 1] == b'$');
+                        let digit_tag = data.get(i + 1).is_some_and(u8::is_ascii_digit);
+                        if data.get(close) == Some(&b'$') && !in_identifier && !digit_tag {
+                            self.state = SqlState::Dollar(data[i..=close].to_vec());
+                            i = close + 1;
+                            continue;
+                        }
+                    }
+                    b' ' | b'\t' | b'\r' | b'\n' => {}
+                    _ => self.open_statement(i),
+                },
+            }
+            i += 1;
+        }
+        *pos = i;
+        None
+    }
+
+    // 字符串转义方式对整个转储有效, 切分后保留
+    fn reset(&mut self) {
+        *self = SqlDump { backslash_escapes: self.backslash_escapes, ..SqlDump::new(self.tables) };
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TagKind {
+    Other,
+    // 记录元素的开始标签与结束标签
+    Open,
+    Close,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum XmlState {
+    Text,
+    Comment,
+    CData,
+    // 标签内; slash 为上一个非空白字符是 /, 即自闭合标签
+    Tag { kind: TagKind, quote: Option<u8>, slash: bool },
+}
+
+// XML 中重复出现的元素: 最外层的 <name> 在对应的 </name> 处结束(同名元素可以嵌套), <name/> 自成一条记录;
+// 紧随其后的换行符属于这条记录. 注释与 CDATA 中的标签不算, 属性值中的 > 不结束标签
+pub struct XmlElement {
+    open: Vec<u8>,
+    close: Vec<u8>,
+    state: XmlState,
+    depth: usize,
+}
+
+impl XmlElement {
+    pub fn new(name: &str) -> Self {
+        XmlElement { open: format!("<{}", name).into_bytes(), close: format!("</{}", name).into_bytes(), state: XmlState::Text, depth: 0 }
+    }
+
+    // data[i] 为 < 时的标签种类与长度; 数据不够判断时为 None
+    fn tag_at(&self, data: &[u8], i: usize, more: bool) -> Option<(XmlState, usize)> {
+        let rest = &data[i..];
+        let longest = self.close.len().max(b"<![CDATA[".len()) + 1;
+        if more && rest.len() < longest {
+            return None;
+        }
+        let named = |tag: &[u8]| rest.starts_with(tag) && rest.get(tag.len()).is_none_or(|b| b.is_ascii_whitespace() || b"/>".contains(b));
+        let tag = |kind| XmlState::Tag { kind, quote: None, slash: false };
+        Some(if rest.starts_with(b"<!--") {
+            (XmlState::Comment, 4)
+        } else if rest.starts_with(b"<![CDATA[") {
+            (XmlState::CData, 9)
+        } else if named(&self.close) {
+            (tag(TagKind::Close), self.close.len())
+        } else if named(&self.open) {
+            (tag(TagKind::Open), self.open.len())
+        } else {
+            (tag(TagKind::Other), 1)
+        })
+    }
+}
+
+impl Boundary for XmlElement {
+    fn next_end(&mut self, data: &[u8], pos: &mut usize, more: bool) -> Option<usize> {
+        let mut i = *pos;
+        while i < data.len() {
+            match self.state {
+                XmlState::Text => {
+                    let Some(found) = memchr(b'<', &data[i..]) else {
+                        i = data.len();
+                        break;
+                    };
+                    i += found;
+                    let Some((state, len)) = self.tag_at(data, i, more) else { break };
+                    self.state = state;
+                    i += len;
+                }
+                XmlState::Comment | XmlState::CData => {
+                    let terminator: &[u8] = if self.state == XmlState::Comment { b"-->" } else { b"]]>" };
+                    match data[i..].windows(3).position(|window| window == terminator) {
+                        Some(found) => {
+                            self.state = XmlState::Text;
+                            i += found + 3;
+                        }
+                        // 结尾可能是被截断的结束符, 留到下次
+                        None => {
+                            i = data.len().saturating_sub(2).max(i);
+                            break;
+                        }
+                    }
+                }
+                XmlState::Tag { kind, quote, slash } => {
+                    let byte = data[i];
+                    match quote {
+                        Some(q) if byte == q => self.state = XmlState::Tag { kind, quote: None, slash: false },
+                        Some(_) => {}
+                        None if byte == b'>' => {
+                            let closes = match kind {
+                                TagKind::Close => self.depth <= 1,
+                                TagKind::Open => slash && self.depth == 0,
+                                TagKind::Other => false,
+                            };
+                            if closes {
+                                // 记录结束, 带上紧随其后的换行符; 数据不够判断时停在 > 处, 状态不变
+                                let end = match &data[i + 1..] {
+                                    [b'\n', ..] => i + 2,
+                                    [b'\r', b'\n', ..] => i + 3,
+                                    [] | [b'\r'] if more => break,
+                                    _ => i + 1,
+                                };
+                                self.depth = 0;
+                                self.state = XmlState::Text;
+                                *pos = end;
+                                return Some(end);
+                            }
+                            match kind {
+                                TagKind::Open if !slash => self.depth += 1,
+                                TagKind::Close => self.depth -= 1,
+                                _ => {}
+                            }
+                            self.state = XmlState::Text;
+                        }
+                        None if byte == b'"' || byte == b'\'' => self.state = XmlState::Tag { kind, quote: Some(byte), slash: false },
+                        None if byte.is_ascii_whitespace() => {}
+                        None => self.state = XmlState::Tag { kind, quote: None, slash: byte == b'/' },
+                    }
+                    i += 1;
+                }
+            }
+        }
+        *pos = i;
+        None
+    }
+
+    fn reset(&mut self) {
+        self.state = XmlState::Text;
+        self.depth = 0;
+    }
+}
+
+// FastCDC 的 gear 表: 每个字节值对应一个 64 位随机数, 由固定种子的 splitmix64 生成, 各版本间不变
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+// FastCDC 的切分点判断: 跳过开头 min 字节后逐字节更新 gear 哈希, 哈希的高位满足掩码时为切分点.
+// 不到平均大小时用位数更多的掩码, 超过后用位数更少的, 分卷大小集中在平均大小附近; 达到 max 字节时强制切分
+struct GearCut {
+    min: usize,
+    average: usize,
+    max: usize,
+    mask_small: u64,
+    mask_large: u64,
+    hash: u64,
+    // 当前分卷已扫描的字节数
+    len: usize,
+}
+
+impl GearCut {
+    fn new(average: usize) -> Self {
+        let average = average.max(256);
+        let bits = average.ilog2().min(60);
+        let mask = |bits: u32| !(u64::MAX >> bits);
+        GearCut { min: average / 4, average, max: average.saturating_mul(4), mask_small: mask(bits + 2), mask_large: mask(bits - 2), hash: 0, len: 0 }
+    }
+
+    // 扫描 bytes, 返回其中第一个切分点之后的字节数
+    fn scan(&mut self, bytes: &[u8]) -> Option<usize> {
+        let skip = self.min.saturating_sub(self.len).min(bytes.len());
+        self.len += skip;
+        for (i, &byte) in bytes.iter().enumerate().skip(skip) {
+            self.len += 1;
+            self.hash = (self.hash << 1).wrapping_add(GEAR[byte as usize]);
+            let mask = if self.len < self.average { self.mask_small } else { self.mask_large };
+            if self.hash & mask == 0 || self.len >= self.max {
+                return Some(i + 1);
+            }
+        }
+        None
+    }
+
+    fn reset(&mut self) {
+        self.hash = 0;
+        self.len = 0;
+    }
+}
+
+// 内容定义的切分(FastCDC): 切分点只取决于附近的内容, 输入中插入或删除少量数据后, 之后的切分点随内容一起移动,
+// 大部分分卷与上次相同. 给出 records 时在切分点所在记录的结束处结束(超长的记录不会被切开), 否则按字节切分
+pub struct ContentDefined {
+    records: Option<Box<dyn Boundary>>,
+    gear: GearCut,
+    // 当前分卷中已经出现过切分点
+    found: bool,
+}
+
+impl ContentDefined {
+    pub fn new(average: usize, records: Option<Box<dyn Boundary>>) -> Self {
+        ContentDefined { records, gear: GearCut::new(average), found: false }
+    }
+}
+
+impl Boundary for ContentDefined {
+    fn next_end(&mut self, data: &[u8], pos: &mut usize, more: bool) -> Option<usize> {
+        let Some(records) = &mut self.records else {
+            let start = *pos;
+            let cut = self.gear.scan(&data[start..]);
+            *pos = cut.map_or(data.len(), |n| start + n);
+            cut?;
+            self.gear.reset();
+            return Some(*pos);
+        };
+        loop {
+            let start = *pos;
+            let end = records.next_end(data, pos, more);
+            if !self.found {
+                self.found = self.gear.scan(&data[start..*pos]).is_some();
+            }
+            let end = end?;
+            if self.found {
+                self.found = false;
+                self.gear.reset();
+                return Some(end);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        if let Some(records) = &mut self.records {
+            records.reset();
+        }
+        self.gear.reset();
+        self.found = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last(mut boundary: Delimiter, data: &[u8]) -> Option<usize> {
+        boundary.last_end(data, &mut 0, false)
+    }
+
+    // 数据每次多到达 step 字节, 每次都扫描到末尾, 返回找到的全部记录结束位置
+    fn ends_in_batches(boundary: &mut dyn Boundary, data: &[u8], step: usize) -> Vec<usize> {
+        let (mut ends, mut pos, mut len) = (Vec::new(), 0, 0);
+        while len < data.len() {
+            len = (len + step).min(data.len());
+            while let Some(end) = boundary.next_end(&data[..len], &mut pos, len < data.len()) {
+                ends.push(end);
+            }
+        }
+        ends
+    }
+
+    // 无论数据怎样分批到达, 找到的边界都相同, last_end 与 next_end 一致
+    fn check_ends(make: impl Fn() -> Box<dyn Boundary>, data: &[u8], expected: &[usize]) {
+        for step in 1..=data.len().max(1) {
+            assert_eq!(ends_in_batches(make().as_mut(), data, step), expected, "每次 {} 字节", step);
+            let (mut boundary, mut pos, mut last, mut len) = (make(), 0, None, 0);
+            while len < data.len() {
+                len = (len + step).min(data.len());
+                last = boundary.last_end(&data[..len], &mut pos, len < data.len()).or(last);
+            }
+            assert_eq!(last, expected.last().copied(), "每次 {} 字节", step);
+        }
+    }
+
+    #[test]
+    fn byte_search_matches_naive_search() {
+        let data: Vec<u8> = (0..200u32).map(|i| (i * 37 % 11) as u8).collect();
+        for start in 0..20 {
+            for end in (start..data.len()).step_by(7) {
+                let slice = &data[start..end];
+                for needle in 0..12 {
+                    assert_eq!(memchr(needle, slice), slice.iter().position(|&b| b == needle));
+                    assert_eq!(memrchr(needle, slice), slice.iter().rposition(|&b| b == needle));
+                }
+            }
+        }
+        assert_eq!(memrchr(0x80, &[0x80, 0, 0, 0, 0, 0, 0, 0, 0x7F]), Some(0));
+    }
+
+    #[test]
+    fn line_endings_across_batches() {
+        check_ends(|| Box::new(Delimiter::new("\n", UTF_8)), b"a\nbc\n\nd", &[2, 5, 6]);
+        check_ends(|| Box::new(Delimiter::new("\r\n", UTF_8)), b"a\r\nb\rc\n\r\r\n", &[3, 10]);
+        check_ends(|| Box::new(Delimiter::new("\r", UTF_8)), b"\ra\r\nb\r", &[1, 3, 6]);
+        check_ends(|| Box::new(Delimiter::new("\n", UTF_8)), b"", &[]);
+        check_ends(|| Box::new(Delimiter::new("\n", UTF_8)), b"no newline", &[]);
+        // 与自身重叠的分隔符按从前向后逐个匹配的位置
+        check_ends(|| Box::new(Delimiter::new("\r\n\r\n", UTF_8)), b"a\r\n\r\n\r\nb\r\n\r\n", &[5, 12]);
+        // 只有不与自身重叠的分隔符可以从任意位置开始查找
+        assert!(Delimiter::new("\r\n", UTF_8).self_synchronizing());
+        assert!(Delimiter::new("\n", GBK).self_synchronizing());
+        assert!(!Delimiter::new("\r\n\r\n", UTF_8).self_synchronizing());
+        assert!(!Delimiter::new("。", GBK).self_synchronizing());
+    }
+
+    #[test]
+    fn custom_separators_across_batches() {
+        let data = "一分隔二分隔分三分隔".as_bytes();
+        check_ends(|| Box::new(Delimiter::new("分隔", UTF_8)), data, &[9, 18, 30]);
+        let data = GBK.encode("记录一。记录二。尾").0;
+        check_ends(|| Box::new(Delimiter::new("。", GBK)), &data, &[8, 16]);
+        check_ends(|| Box::new(Delimiter::new("@@", GBK)), b"a\x81\x40\x40b@@c", &[7]);
+    }
+
+    #[test]
+    fn utf8_invalid_bytes_keep_exact_offsets() {
+        // 旧实现把每个畸形字节替换为 3 字节的 U+FFFD 再编码, 偏移会偏大
+        let data = b"\xff\xfe\nabc";
+        assert_eq!(last(Delimiter::new("\n", UTF_8), data), Some(3));
+
+        let data = b"ok\n\xe4\xb8bad\r\n\xffx";
+        assert_eq!(last(Delimiter::new("\r\n", UTF_8), data), Some(10));
+    }
+
+    #[test]
+    fn utf8_multibyte_delimiter_with_invalid_bytes() {
+        let mut data = "第一行分隔".as_bytes().to_vec();
+        data.extend_from_slice(b"\xff\xc3");
+        data.extend_from_slice("第二行分隔尾".as_bytes());
+        let expected = data.len() - "尾".len();
+        assert_eq!(last(Delimiter::new("分隔", UTF_8), &data), Some(expected));
+    }
+
+    #[test]
+    fn gbk_delimiter_inside_character_is_not_a_boundary() {
+        // "丂" 在 GBK 中是 0x81 0x40, 其后续字节与 '@' 相同
+        let data = b"a\x81\x40\x40b";
+        assert_eq!(last(Delimiter::new("@@", GBK), data), None);
+
+        let data = b"a@@\x81\x40\x40b";
+        assert_eq!(last(Delimiter::new("@@", GBK), data), Some(3));
+    }
+
+    #[test]
+    fn gbk_malformed_bytes_resynchronize() {
+        // 0xFF 非法, 0x81 后跟非法后续字节时只消耗一个字节
+        let data = b"\xff@@x\x81 @@y\xc4\xe3";
+        assert_eq!(last(Delimiter::new("@@", GBK), data), Some(8));
+    }
+
+    #[test]
+    fn gbk_custom_chinese_delimiter() {
+        let (encoded, _, _) = GBK.encode("记录一。记录二。\u{20ac}尾");
+        let mut data = encoded.to_vec();
+        data.insert(0, 0xFF);
+        let expected = 1 + GBK.encode("记录一。记录二。").0.len();
+        assert_eq!(last(Delimiter::new("。", GBK), &data), Some(expected));
+    }
+
+    #[test]
+    fn csv_newlines_inside_quotes_do_not_end_records() {
+        let data = b"id,text\r\n1,\"a\nb\"\n2,\"say \"\"hi\"\"\n\"\n3,x";
+        check_ends(|| Box::new(Csv::default()), data, &[9, 17, 33]);
+    }
+
+    #[test]
+    fn quoted_line_endings_do_not_end_records() {
+        let data = b"a\r\n'x\r\ny'\r\nb'\r\n'c\r\nd";
+        check_ends(|| Box::new(Quoted::new("\r\n", '\'', UTF_8).unwrap()), data, &[3, 11, 19]);
+        assert!(Quoted::new("\n", '\n', UTF_8).is_err());
+        assert!(Quoted::new("\n", '中', UTF_8).is_err());
+        assert!(Quoted::new("\n", '|', GBK).is_err());
+    }
+
+    #[test]
+    fn fixed_width_records() {
+        check_ends(|| Box::new(FixedWidth::new(3)), b"abcdefghij", &[3, 6, 9]);
+        check_ends(|| Box::new(FixedWidth::new(4)), b"abcd", &[4]);
+        check_ends(|| Box::new(FixedWidth::new(1)), b"", &[]);
+    }
+
+    #[test]
+    fn regex_records_start_at_matching_lines() {
+        let data = b"2024-01 a\n  at x\r\n  at y\n2024-02 b\n2024-03 c\nx";
+        let make = || Box::new(RecordStart::new(Regex::parse(r"^\d{4}-").unwrap(), UTF_8)) as Box<dyn Boundary>;
+        check_ends(make, data, &[25, 35]);
+        let data = GBK.encode("错误 a\n 详情\n错误 b\n").0;
+        check_ends(|| Box::new(RecordStart::new(Regex::parse("^错误").unwrap(), GBK)), &data, &[13]);
+    }
+
+    #[test]
+    fn sequence_records_keep_reads_whole() {
+        let fastq = b"@r1\nACGT\n+\n@@@@\n@r2\r\nGG\r\n+\r\nII\r\n@r3\nA";
+        check_ends(|| parse("fastq", UTF_8).unwrap(), fastq, &[16, 32]);
+        let fasta = b">s1 desc\nACGT\nACGT\n>s2\nGG\n>s3\n";
+        check_ends(|| parse("FASTA", UTF_8).unwrap(), fasta, &[19, 26]);
+    }
+
+    #[test]
+    fn sql_dumps_split_between_statements_or_tables() {
+        let ends = |data: &str, lines: &[&str]| -> Vec<usize> { lines.iter().map(|line| data.find(line).unwrap() + line.len()).collect() };
+        let mysql = "-- MySQL dump 10.13\n/*!40101 SET NAMES utf8mb4 */;\nSET @x = 'a;b\\';c';\n\n\
+            --\n-- Table structure for table `t1`\n--\n\nDROP TABLE IF EXISTS `t1`;\nCREATE TABLE `t1` (\n  `a` int -- a; b\n);\n\n\
+            --\n-- Dumping data for table `t1`\n--\n\nINSERT INTO `t1` VALUES (1,'x\\n;'),(2,'it''s');\n\n\
+            --\n-- Table structure for table `t2`\n--\n\nCREATE TABLE `t2` (`b` text);\nINSERT INTO `t2` VALUES ('/* no; comment');\n";
+        let statements = ends(mysql, &["*/;\n", "c';\n", "`t1`;\n", "\n);\n", "'s');\n", "text);\n", "comment');\n"]);
+        check_ends(|| parse("sql", UTF_8).unwrap(), mysql.as_bytes(), &statements);
+        check_ends(|| parse("sql-table", UTF_8).unwrap(), mysql.as_bytes(), &[statements[1], statements[4]]);
+
+        let postgres = "SET standard_conforming_strings = on;\nSELECT pg_catalog.set_config('search_path', '', false);\n\n\
+            --\n-- Name: f(); Type: FUNCTION; Schema: public; Owner: u\n--\n\nCREATE FUNCTION public.f() RETURNS int AS $_$ SELECT 1; $_$;\n\n\
+            --\n-- Name: t; Type: TABLE; Schema: public; Owner: u\n--\n\nCREATE TABLE public.t (a text);\n\n\
+            --\n-- Data for Name: t; Type: TABLE DATA; Schema: public; Owner: u\n--\n\nCOPY public.t (a) FROM stdin;\nx;y\nC:\\\n\\.\n\n\
+            INSERT INTO public.t VALUES ('C:\\');\n";
+        let statements = ends(postgres, &["on;\n", "false);\n", "$_$;\n", "text);\n", "\\.\n", "');\n"]);
+        check_ends(|| parse("sql", UTF_8).unwrap(), postgres.as_bytes(), &statements);
+        check_ends(|| parse("SQL-TABLE", UTF_8).unwrap(), postgres.as_bytes(), &[statements[2], statements[3]]);
+    }
+
+    #[test]
+    fn xml_elements_end_at_their_closing_tags() {
+        let data = "<?xml version=\"1.0\"?>\n<root>\n  <row id=\"1\"><a>x</a></row>\n  <row a='>'>\n    <row/><!-- </row> --><![CDATA[</row>]]>\n  </row>\r\n  <rows/>\n  <row/>\n</root>\n";
+        let end_of = |tail: &str| data.find(tail).unwrap() + tail.len();
+        let expected = [end_of("</a></row>\n"), end_of("  </row>\r\n"), end_of("  <row/>\n")];
+        check_ends(|| parse("xml:row", UTF_8).unwrap(), data.as_bytes(), &expected);
+        check_ends(|| parse("xml:a", UTF_8).unwrap(), b"<a>1</a><a>2</a>", &[8, 16]);
+        assert!(parse("xml:", UTF_8).is_err());
+        assert!(parse("xml:a b", UTF_8).is_err());
+    }
+
+    #[test]
+    fn content_defined_cuts_move_with_the_content() {
+        let mut state = 1u32;
+        let data: Vec<u8> = (0..3000)
+            .map(|i| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                if i % 37 == 36 { b'\n' } else { b'a' + (state >> 16) as u8 % 26 }
+            })
+            .collect();
+        for lines in [false, true] {
+            let make = || Box::new(ContentDefined::new(256, lines.then(|| Box::new(Delimiter::new("\n", UTF_8)) as Box<dyn Boundary>))) as Box<dyn Boundary>;
+            let expected = ends_in_batches(make().as_mut(), &data, data.len());
+            assert!(expected.len() >= 4 && expected.windows(2).all(|pair| pair[1] - pair[0] >= 64), "{:?}", expected);
+            assert!(!lines || expected.iter().all(|&end| data[end - 1] == b'\n'));
+            check_ends(make, &data, &expected);
+
+            // 开头插入数据后, 之后的切分点随内容平移
+            let edited = [b"inserted\n".as_slice(), &data].concat();
+            let shifted: Vec<usize> = ends_in_batches(make().as_mut(), &edited, edited.len()).iter().map(|end| end - 9).collect();
+            assert!(expected[2..].iter().all(|end| shifted.contains(end)), "{:?} {:?}", expected, shifted);
+        }
+    }
+
+    #[test]
+    fn record_formats_are_parsed() {
+        assert!(parse("csv", UTF_8).is_ok());
+        assert!(parse("fixed:80", UTF_8).is_ok());
+        assert!(parse("regex:^\\d", UTF_8).is_ok());
+        assert!(parse("fixed:0", UTF_8).is_err());
+        assert!(parse("regex:(", UTF_8).is_err());
+        assert!(parse("json", UTF_8).is_err());
+        assert!(parse("sql", GBK).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+// capabilities 子命令: 本构建启用的功能、CPU 特性与可用的外部工具
+
+use std::env;
+use std::io;
+use std::thread;
+use crate::codec::find_tool;
+
+// 运行时检测到的 CPU SIMD 特性
+fn detected_cpu_features() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut features = Vec::new();
+    #[cfg(target_arch = "x86_64")]
+    {
+        macro_rules! detect {
+            ($($feature:tt),*) => { $(if is_x86_feature_detected!($feature) { features.push($feature); })* };
+        }
+        detect!("sse2", "ssse3", "sse4.2", "avx", "avx2", "bmi2", "avx512f", "pclmulqdq", "sha");
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        macro_rules! detect {
+            ($($feature:tt),*) => { $(if std::arch::is_aarch64_feature_detected!($feature) { features.push($feature); })* };
+        }
+        detect!("neon", "crc", "aes", "sha2", "sve", "sve2");
+    }
+    features
+}
+
+// 编译时启用的目标特性, 只有这些能被编译器直接用于生成代码
+fn compiled_target_features() -> Vec<&'static str> {
+    [
+        ("sse2", cfg!(target_feature = "sse2")),
+        ("sse4.2", cfg!(target_feature = "sse4.2")),
+        ("avx2", cfg!(target_feature = "avx2")),
+        ("bmi2", cfg!(target_feature = "bmi2")),
+        ("neon", cfg!(target_feature = "neon")),
+        ("crc", cfg!(target_feature = "crc")),
+        ("sve", cfg!(target_feature = "sve")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+// 诊断当前机器上实际生效的执行路径, 便于在 Graviton / Apple Silicon 等平台上确认
+pub fn run_capabilities() -> io::Result<()> {
+    let list = |items: Vec<&str>| if items.is_empty() { "无".to_string() } else { items.join(" ") };
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    println!("运行环境:");
+    println!("- 架构: {} ({})", env::consts::ARCH, env::consts::OS);
+    println!("- 逻辑 CPU: {}", threads);
+    println!("- CPU 支持的 SIMD 特性: {}", list(detected_cpu_features()));
+    println!("- 编译时启用的目标特性: {}", list(compiled_target_features()));
+
+    let multithread = zstd::zstd_safe::CCtx::create().set_parameter(zstd::zstd_safe::CParameter::NbWorkers(2)).is_ok();
+    println!("zstd:");
+    println!("- 库版本: {}", zstd::zstd_safe::version_string());
+    println!("- 库内多线程压缩: {}", if multithread { "已编入 (--threads 设置工作线程数, 默认单线程)" } else { "未编入" });
+    // libzstd 在 x86_64 上按运行时检测决定是否走 BMI2 解码路径
+    let bmi2 = cfg!(target_arch = "x86_64") && detected_cpu_features().contains(&"bmi2");
+    println!("- BMI2 解码路径: {}", if bmi2 { "启用" } else { "未启用 (只在支持 BMI2 的 x86_64 上可用)" });
+
+    println!("本程序:");
+    println!("- 换行符查找: 不解码, 单字节或 ASCII 分隔符按 8 字节一组查找首字节(SWAR), 未使用 SIMD 指令");
+    println!("- CRC-32 / CRC-32C: 查表实现, 未使用硬件 CRC 指令");
+    println!("- SHA-256: 软件实现");
+    println!("- 线程: 切分与写出各一个线程, 压缩 --jobs 个线程(--pipeline-depth 0 时都在主线程); --readahead 使用独立读取线程; 合并与校验的 --prefetch 每个分卷一个下载线程");
+
+    println!("输出格式与外部工具:");
+    let tool = |name: &str| match find_tool(name) {
+        Some(path) => format!("{} ({})", name, path.display()),
+        None => format!("{} (未找到)", name),
+    };
+    println!("- zstd, snappy: 内置");
+    println!("- 7z: LZMA2 需要 {}; --7z-codec zstd 不需要外部命令", tool("xz"));
+    for name in ["gzip", "xz", "lz4"] {
+        println!("- {}: 需要 {}", name, tool(name));
+    }
+    for (name, enabled) in [("brotli", cfg!(feature = "brotli")), ("bzip2", cfg!(feature = "bzip2"))] {
+        if enabled {
+            println!("- {}: 需要 {}", name, tool(name));
+        } else {
+            println!("- {}: 此构建未启用 (cargo build --features {})", name, name);
+        }
+    }
+    println!("- 远程输入: https/sftp 需要 {}, s3 需要 {}", tool("curl"), tool("aws"));
+    println!("- gRPC 服务模式(serve): {}", if cfg!(feature = "grpc") { "已编入" } else { "此构建未启用 (cargo build --features grpc)" });
+    Ok(())
+}
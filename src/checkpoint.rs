@@ -0,0 +1,235 @@
+// 检查点与续跑: 定期同步分卷与清单并把进度写入 <prefix>.state, --resume 从记录的位置继续
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+use crate::archive::sync_parent;
+use crate::config::{CheckpointInterval, Config};
+use crate::exit::{failure, EXIT_TIMEOUT};
+use crate::writer::{ChunkWriter, Output};
+
+// 记录已完成的进度, 使长时间运行在崩溃后最多只需重做一个检查点间隔的工作;
+// 有 --max-runtime 时到期后在刚写完的分卷处记下进度并停下
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    interval: Option<CheckpointInterval>,
+    deadline: Option<Instant>,
+    // 有检查点间隔、时间上限或是续跑时才写状态文件; 续跑完成后要把上次的状态标记为完成
+    enabled: bool,
+    pending: Vec<PathBuf>,
+    // 上次检查点之后写出的分卷数
+    unflushed_chunks: usize,
+    last_flush: Instant,
+    next_chunk: usize,
+    input_offset: usize,
+}
+
+impl Checkpoint {
+    pub(crate) fn new(config: &Config) -> Self {
+        Checkpoint {
+            path: PathBuf::from(format!("{}.state", config.output_prefix)),
+            interval: config.checkpoint_interval,
+            deadline: config.max_runtime.map(|runtime| Instant::now() + runtime),
+            enabled: config.checkpoint_interval.is_some() || config.max_runtime.is_some() || config.resume.is_some(),
+            pending: Vec::new(),
+            unflushed_chunks: 0,
+            last_flush: Instant::now(),
+            next_chunk: config.resume.map_or(1, |point| point.next_chunk),
+            input_offset: 0,
+        }
+    }
+
+    pub(crate) fn chunk_written(&mut self, written: Vec<PathBuf>, writer: &ChunkWriter, input_offset: usize) -> io::Result<()> {
+        let next_chunk = writer.next_number;
+        if !self.enabled {
+            return Ok(());
+        }
+        let due = match self.interval {
+            None => false,
+            Some(CheckpointInterval::Every(d)) => self.last_flush.elapsed() >= d,
+            Some(CheckpointInterval::Chunks(n)) => self.unflushed_chunks + 1 >= n,
+        };
+        // 单文件输出时每个分卷写的是同一组文件, 只需同步一次
+        for path in written {
+            if !self.pending.contains(&path) {
+                self.pending.push(path);
+            }
+        }
+        self.unflushed_chunks += 1;
+        self.next_chunk = next_chunk;
+        self.input_offset = input_offset;
+        let expired = self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if due || expired {
+            self.flush(writer, false)?;
+        }
+        if expired {
+            let message = format!("运行时间已到 --max-runtime 上限, 在分卷 {} 之后停止; 进度记录在 {}, 用同样的参数加上 --resume 继续", next_chunk - 1, self.path.display());
+            return Err(failure(EXIT_TIMEOUT, message));
+        }
+        Ok(())
+    }
+
+    // 按时间的检查点在分卷写到一半时也会到期(--stream 的长分卷), 由调用方先把编码器中的数据刷出
+    pub(crate) fn due_within_chunk(&self) -> bool {
+        self.enabled && matches!(self.interval, Some(CheckpointInterval::Every(d)) if self.last_flush.elapsed() >= d)
+    }
+
+    pub(crate) fn finish(&mut self, writer: &ChunkWriter) -> io::Result<()> {
+        self.flush(writer, true)
+    }
+
+    pub(crate) fn flush(&mut self, writer: &ChunkWriter, complete: bool) -> io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let config = writer.config;
+
+        // 先确保分卷与清单落盘, 再更新状态文件; 完成时的 JSON 清单由调用方写出
+        for path in self.pending.drain(..) {
+            File::open(&path)?.sync_all()?;
+        }
+        self.unflushed_chunks = 0;
+        if let Output::Files { manifest: Some(manifest), .. } = &writer.output {
+            manifest.manifest.sync_all()?;
+        }
+        if let Some(json) = writer.json.as_ref().filter(|_| !complete) {
+            json.write(config, false)?;
+        }
+
+        // 写临时文件后重命名, 避免崩溃时留下半个状态文件
+        let tmp_path = self.path.with_extension("state.tmp");
+        let mut file = File::create(&tmp_path)?;
+        writeln!(file, "run_id={}", config.run_id)?;
+        writeln!(file, "input={}", config.input_path)?;
+        writeln!(file, "next_chunk={}", self.next_chunk)?;
+        // 分卷的偏移从跳过的部分之后算起, 记录的是在输入中的位置
+        writeln!(file, "input_offset={}", config.skip_bytes + self.input_offset as u64)?;
+        writeln!(file, "complete={}", complete)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        sync_parent(&self.path)?;
+
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+}
+
+// --resume 的续跑位置, 取自上次运行写下的 <output_prefix>.state
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ResumePoint {
+    pub(crate) next_chunk: usize,
+    pub(crate) input_offset: u64,
+}
+
+impl ResumePoint {
+    pub(crate) fn read(output_prefix: &str, input_path: &str) -> Result<Self, String> {
+        let path = format!("{}.state", output_prefix);
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("无法读取进度文件 {}: {}", path, e))?;
+        let value = |key: &str| text.lines().find_map(|line| line.strip_prefix(key)?.strip_prefix('='));
+        if value("input") != Some(input_path) {
+            return Err(format!("进度文件 {} 记录的输入是 {}, 不是 {}", path, value("input").unwrap_or("?"), input_path));
+        }
+        if value("complete") == Some("true") {
+            return Err(format!("进度文件 {} 表明上次运行已经完成, 无需续跑", path));
+        }
+        let number = |key: &str| value(key).and_then(|value| value.parse::<u64>().ok()).ok_or_else(|| format!("进度文件 {} 缺少有效的 {}", path, key));
+        Ok(ResumePoint { next_chunk: number("next_chunk")?.max(1) as usize, input_offset: number("input_offset")? })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+use crate::ChunkSink;
+    use super::*;
+    use crate::chunk_name;
+    use crate::config::{parse_checkpoint_interval, parse_runtime};
+    use crate::exit::exit_code;
+    use crate::manifest::JsonManifest;
+    use crate::merge::{run_join, MergeOptions};
+    use crate::naming::{chunk_path, temp_path};
+    use crate::profiler::Profiler;
+    use crate::split::run_split;
+    use crate::testing::{manifest_chunks, split_config, Scratch};
+    use crate::writer::StreamingWriter;
+
+    #[test]
+    fn runs_stop_at_the_time_limit_and_resume_from_the_state_file() {
+        let dir = Scratch::new("max_runtime");
+        let (input, text) = dir.lines("in.log", 20_000);
+        let prefix = dir.arg("part");
+        let parse = |extra: &str| {
+            let args = ["zstd_compressor", &input, &prefix, "--content-addressed", extra, "--porcelain"];
+            let mut config = Config::parse(args.iter().map(|arg| arg.to_string()))?;
+            config.chunk_size = 32 * 1024;
+            config.buffer_size = Some(4096);
+            Ok::<_, String>(config)
+        };
+
+        // 上限为零时写完第一个分卷就停下
+        let mut config = parse("--yes").unwrap();
+        config.max_runtime = Some(Duration::ZERO);
+        assert_eq!(run_split(&config).err().map(|e| exit_code(&e)), Some(EXIT_TIMEOUT));
+        let state = std::fs::read_to_string(dir.join("part.state")).unwrap();
+        assert!(state.contains("next_chunk=2\n") && state.contains("complete=false\n"), "{}", state);
+        let json = std::fs::read_to_string(dir.join("part.manifest.json")).unwrap();
+        assert!(json.contains("\"complete\": false") && manifest_chunks(&json).len() == 1, "{}", json);
+        // 停下之后才写出的行与分卷在续跑时被替换
+        let mut manifest = std::fs::OpenOptions::new().append(true).open(dir.join("part.manifest")).unwrap();
+        manifest.write_all(b"2\tpart.002.zst\t1").unwrap();
+
+        let stats = run_split(&parse("--resume").unwrap()).unwrap();
+        assert!(stats.chunks > 2 && Path::new(&chunk_name(&prefix, stats.chunks, "zst")).exists());
+        let merged = dir.join("merged");
+        run_join(&prefix, &merged.display().to_string(), &MergeOptions::parse(&[]).unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(&merged).unwrap(), text);
+        assert!(std::fs::read_to_string(dir.join("part.state")).unwrap().contains("complete=true"));
+        let chunks = manifest_chunks(&std::fs::read_to_string(dir.join("part.manifest.json")).unwrap());
+        assert!(chunks.iter().map(|chunk| chunk.chunk).eq(1..=stats.chunks));
+        // 续跑时输入从分卷边界读起, 与读取缓冲区不再对齐, 切分点仍与一次跑完的相同
+        let clean = dir.arg("clean");
+        assert_eq!(run_split(&split_config(&[&input, &clean], 32 * 1024)).unwrap().chunks, stats.chunks);
+        let chunk = |prefix: &str, n| zstd::decode_all(File::open(chunk_name(prefix, n, "zst")).unwrap()).unwrap();
+        assert!((1..=stats.chunks).all(|n| chunk(&prefix, n) == chunk(&clean, n)));
+        assert!(parse("--resume").unwrap_err().contains("已经完成"));
+        assert_eq!(parse_runtime("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_runtime("0m").is_err());
+    }
+
+    #[test]
+    fn checkpoints_flush_state_and_manifests() {
+        let dir = Scratch::new("checkpoint");
+        let (input, text) = dir.lines("in.log", 20_000);
+        let prefix = dir.arg("part");
+        let parse = |extra: &[&str]| split_config(&[&[input.as_str(), &prefix], extra].concat(), 32 * 1024);
+
+        // 每 2 个分卷一个检查点: 第 4 个分卷失败时, 状态与两种清单停在第 2 个分卷之后
+        let config = parse(&["--checkpoint-interval", "2", "--content-addressed", "--pipeline-depth", "0", "--inject-failure", "write:chunk=4"]);
+        assert!(run_split(&config).is_err());
+        let state = std::fs::read_to_string(dir.join("part.state")).unwrap();
+        assert!(state.contains("next_chunk=3\n") && state.contains("complete=false\n"), "{}", state);
+        let json = std::fs::read_to_string(dir.join("part.manifest.json")).unwrap();
+        assert!(json.contains("\"complete\": false") && manifest_chunks(&json).len() == 2, "{}", json);
+        assert!(std::fs::read_to_string(dir.join("part.manifest")).unwrap().lines().any(|line| line.starts_with("2\t")));
+
+        // 按时间的检查点在 --stream 的分卷写到一半时也会到期, 编码器中的数据先刷到临时文件
+        let mut config = parse(&["--stream"]);
+        config.checkpoint_interval = Some(CheckpointInterval::Every(Duration::ZERO));
+        let (mut writer, mut checkpoint) = (ChunkWriter::new(&config, Output::open(&config).unwrap()), Checkpoint::new(&config));
+        writer.json = Some(JsonManifest::create(&config).unwrap());
+        let profiler = Profiler::new(false);
+        let mut sink = StreamingWriter { writer: &mut writer, checkpoint: &mut checkpoint, profiler: &profiler, current: None };
+        sink.data(&text.as_bytes()[..1000], 0).unwrap();
+        sink.data(&text.as_bytes()[1000..2000], 1000).unwrap();
+        let state = std::fs::read_to_string(dir.join("part.state")).unwrap();
+        assert!(state.contains("next_chunk=1\n") && state.contains("complete=false\n"), "{}", state);
+        let partial = std::fs::read(temp_path(&chunk_path(&prefix, 1, "zst"))).unwrap();
+        let mut flushed = Vec::new();
+        let _ = zstd::stream::read::Decoder::new(&partial[..]).unwrap().read_to_end(&mut flushed);
+        assert_eq!(flushed, &text.as_bytes()[..2000]);
+        assert!(parse_checkpoint_interval("0s").is_err());
+    }
+}
@@ -0,0 +1,1165 @@
+//! 各压缩格式的编码与解码. 切分写出分卷、合并与校验、compress/decompress 子命令都经由这里,
+//! 不再各自调用 zstd 库或外部命令行工具.
+
+use std::env;
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread;
+use crate::{Format, MemberEnds, ZstdParams};
+
+// 把一段数据压缩成一个完整的压缩流
+pub trait Compressor {
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+
+    // 把 input 全部压缩写到 output; 默认读入内存后整体压缩, 能流式压缩的格式覆盖此方法
+    fn compress_stream(&self, input: &mut dyn Read, output: &mut dyn Write) -> io::Result<()> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        output.write_all(&self.compress(&data)?)
+    }
+}
+
+// 按文件头识别一种格式并解码
+pub trait Decompressor {
+    fn name(&self) -> &'static str;
+    fn detect(&self, head: &[u8]) -> bool;
+    fn decoder(&self, input: io::BufReader<Box<dyn Read + Send>>) -> io::Result<Box<dyn Read + Send>>;
+}
+
+pub struct Zstd(pub ZstdParams);
+
+impl Compressor for Zstd {
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        if self.0.threads() == 0 {
+            return zstd::encode_all(data, self.0.level());
+        }
+        let mut encoder = self.0.encoder(Vec::new())?;
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+
+    // 流式压缩, 不必把整个输入读进内存
+    fn compress_stream(&self, input: &mut dyn Read, output: &mut dyn Write) -> io::Result<()> {
+        let mut encoder = self.0.encoder(output)?;
+        io::copy(input, &mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    }
+}
+
+impl Decompressor for Zstd {
+    fn name(&self) -> &'static str {
+        "zstd"
+    }
+
+    // 以可跳过帧开头的文件同样是 zstd
+    fn detect(&self, head: &[u8]) -> bool {
+        let magic = head.get(..4).map(|m| u32::from_le_bytes(m.try_into().unwrap()));
+        magic.is_some_and(|m| m == ZSTD_MAGIC || SKIPPABLE_MAGIC.contains(&m))
+    }
+
+    fn decoder(&self, input: io::BufReader<Box<dyn Read + Send>>) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(zstd::stream::read::Decoder::with_buffer(input)?))
+    }
+}
+
+// 用预先训练的字典压缩的 zstd, 解压时需要同一个字典
+pub struct ZstdDictionary<'a> {
+    pub params: ZstdParams,
+    pub dictionary: &'a [u8],
+}
+
+impl Compressor for ZstdDictionary<'_> {
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encoder = self.params.encoder_with_dictionary(Vec::new(), self.dictionary)?;
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+}
+
+// 从样本训练 zstd 字典; samples 为首尾相接的各个样本, sizes 为各样本的长度
+#[cfg(feature = "dictionary")]
+pub fn train_dictionary(samples: &[u8], sizes: &[usize], max_size: usize) -> io::Result<Vec<u8>> {
+    zstd::dict::from_continuous(samples, sizes, max_size)
+}
+
+// 压缩调用系统中的 gzip, 解压用内置的 GzipReader
+pub struct Gzip;
+
+impl Compressor for Gzip {
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        run_tool("gzip", &["-c", "-6"], data)
+    }
+}
+
+impl Decompressor for Gzip {
+    fn name(&self) -> &'static str {
+        "gzip"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        head.starts_with(&[0x1F, 0x8B])
+    }
+
+    fn decoder(&self, input: io::BufReader<Box<dyn Read + Send>>) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(GzipReader::new(input, None)))
+    }
+}
+
+// snappy 分帧格式, 供 Hadoop/Spark 读取; 编解码都是内置的
+pub struct Snappy;
+
+impl Compressor for Snappy {
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(snappy_framed(data))
+    }
+}
+
+impl Decompressor for Snappy {
+    fn name(&self) -> &'static str {
+        "snappy"
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        head.starts_with(SNAPPY_STREAM_ID)
+    }
+
+    fn decoder(&self, input: io::BufReader<Box<dyn Read + Send>>) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(SnappyReader::new(input)))
+    }
+}
+
+// 由系统中的命令行工具编解码的格式, 数据经标准输入输出传递; magic 为空的格式不参与识别
+pub struct Tool {
+    pub name: &'static str,
+    pub program: &'static str,
+    pub compress_args: &'static [&'static str],
+    pub decompress_args: &'static [&'static str],
+    pub magic: &'static [u8],
+}
+
+pub const XZ: Tool = Tool { name: "xz", program: "xz", compress_args: &["-c", "-6"], decompress_args: &["-dc"], magic: &[0xFD, b'7', b'z', b'X', b'Z', 0x00] };
+pub const LZ4: Tool = Tool { name: "lz4", program: "lz4", compress_args: &["-c", "-q"], decompress_args: &["-dc", "-q"], magic: &[0x04, 0x22, 0x4D, 0x18] };
+pub const BZIP2: Tool = Tool { name: "bzip2", program: "bzip2", compress_args: &["-c", "-9"], decompress_args: &["-dc"], magic: b"BZh" };
+pub const BROTLI: Tool = Tool { name: "brotli", program: "brotli", compress_args: &["-c", "-q", "9"], decompress_args: &["-dc"], magic: &[] };
+// 7z 中 LZMA2 编码器的数据就是原始 LZMA2 流
+pub const LZMA2_RAW: Tool = Tool {
+    name: "lzma2",
+    program: "xz",
+    compress_args: &["--format=raw", "--lzma2=preset=6,dict=8MiB", "-c"],
+    decompress_args: &["--format=raw", "--lzma2=dict=8MiB", "-dc"],
+    magic: &[],
+};
+
+impl Compressor for Tool {
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        run_tool(self.program, self.compress_args, data)
+    }
+}
+
+impl Decompressor for Tool {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn detect(&self, head: &[u8]) -> bool {
+        !self.magic.is_empty() && head.starts_with(self.magic)
+    }
+
+    fn decoder(&self, input: io::BufReader<Box<dyn Read + Send>>) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(ToolReader::pipe(self.program, self.decompress_args, input)?))
+    }
+}
+
+pub fn compressor(format: Format, zstd: ZstdParams) -> Box<dyn Compressor> {
+    match format {
+        Format::Zstd => Box::new(Zstd(zstd)),
+        Format::Gzip => Box::new(Gzip),
+        Format::Xz => Box::new(XZ),
+        Format::Lz4 => Box::new(LZ4),
+        Format::Snappy => Box::new(Snappy),
+        Format::SevenZip => Box::new(LZMA2_RAW),
+        #[cfg(feature = "brotli")]
+        Format::Brotli => Box::new(BROTLI),
+        #[cfg(feature = "bzip2")]
+        Format::Bzip2 => Box::new(BZIP2),
+    }
+}
+
+pub fn compress(data: &[u8], format: Format, zstd: ZstdParams) -> io::Result<Vec<u8>> {
+    compressor(format, zstd).compress(data)
+}
+
+// 能按文件头识别的格式; xz, lz4 与 bzip2 需要系统中的同名命令
+pub fn decompressors() -> [&'static dyn Decompressor; 6] {
+    const ZSTD: Zstd = Zstd(ZstdParams { level: None, threads: None });
+    [&ZSTD, &Gzip, &XZ, &LZ4, &BZIP2, &Snappy]
+}
+
+// 按文件头选择解码器, 返回格式名与解压后的数据流; 以内嵌字典开头的分卷用其中的字典解压
+pub fn open_decoder(source: Box<dyn Read + Send>) -> io::Result<(&'static str, Box<dyn Read + Send>)> {
+    open_decoder_with(source, &mut None)
+}
+
+// 同 open_decoder, 依次解压一个分卷集时共用 dictionary: 第一个分卷开头的内嵌字典存入其中,
+// 之后各分卷的 zstd 帧都用它解压
+pub fn open_decoder_with(source: Box<dyn Read + Send>, dictionary: &mut Option<Vec<u8>>) -> io::Result<(&'static str, Box<dyn Read + Send>)> {
+    let (embedded, source) = take_dictionary(source)?;
+    if embedded.is_some() {
+        *dictionary = embedded;
+    }
+    let mut reader = io::BufReader::new(source);
+    let head = reader.fill_buf()?;
+    let Some(codec) = decompressors().into_iter().find(|codec| codec.detect(head)) else {
+        let names: Vec<&str> = decompressors().iter().map(|codec| codec.name()).collect();
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("无法识别的压缩格式, 支持 {}", names.join(", "))));
+    };
+    match dictionary {
+        Some(dictionary) if codec.name() == "zstd" => Ok(("zstd", Box::new(zstd::stream::read::Decoder::with_dictionary(reader, dictionary)?))),
+        _ => Ok((codec.name(), codec.decoder(reader)?)),
+    }
+}
+
+pub type Stream = Box<dyn Read + Send>;
+
+// 内嵌字典所在的可跳过帧, 只出现在分卷集第一个分卷的开头
+pub const DICTIONARY_MAGIC: u32 = 0x184D_2A5D;
+
+pub fn dictionary_frame(dictionary: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(8 + dictionary.len());
+    frame.extend_from_slice(&DICTIONARY_MAGIC.to_le_bytes());
+    frame.extend_from_slice(&(dictionary.len() as u32).to_le_bytes());
+    frame.extend_from_slice(dictionary);
+    frame
+}
+
+// 数据以内嵌字典帧开头时读出字典, 返回的数据流从字典帧之后开始; 否则数据流原样返回.
+// 预读的长度要覆盖各格式的文件头, 之后按文件头识别格式时才能一次看到完整的 magic
+pub fn take_dictionary(mut source: Stream) -> io::Result<(Option<Vec<u8>>, Stream)> {
+    let mut head = Vec::with_capacity(16);
+    source.by_ref().take(16).read_to_end(&mut head)?;
+    if head.len() >= 8 && head[..4] == DICTIONARY_MAGIC.to_le_bytes() {
+        let len = u32::from_le_bytes(head[4..8].try_into().unwrap()) as usize;
+        let mut dictionary = head.split_off(8);
+        if dictionary.len() > len {
+            let rest = dictionary.split_off(len);
+            return Ok((Some(dictionary), Box::new(io::Cursor::new(rest).chain(source))));
+        }
+        let read = dictionary.len();
+        dictionary.resize(len, 0);
+        source.read_exact(&mut dictionary[read..])?;
+        return Ok((Some(dictionary), source));
+    }
+    Ok((None, Box::new(io::Cursor::new(head).chain(source))))
+}
+
+// 解压内存中的一段数据, 格式按文件头识别
+pub fn decode_all(data: &[u8]) -> io::Result<Vec<u8>> {
+    let (_, mut decoder) = open_decoder(Box::new(io::Cursor::new(data.to_vec())))?;
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out)?;
+    Ok(out)
+}
+
+pub const ZSTD_MAGIC: u32 = 0xFD2F_B528;
+pub const SKIPPABLE_MAGIC: std::ops::RangeInclusive<u32> = 0x184D_2A50..=0x184D_2A5F;
+
+const SNAPPY_BLOCK_SIZE: usize = 65536; // 分帧格式中每个数据块的原始大小上限
+const SNAPPY_STREAM_ID: &[u8] = b"\xff\x06\x00\x00sNaPpY";
+
+// 查表计算的反射 CRC-32, poly 为反射后的多项式
+const fn crc_table(poly: u32) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ poly } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 256] = crc_table(0xEDB8_8320);
+static CRC32C_TABLE: [u32; 256] = crc_table(0x82F6_3B78);
+
+// 在前一段数据的结果 crc 之上继续计算, 首段传入 0
+fn crc_update(table: &[u32; 256], crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc = table[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+// CRC-32C (Castagnoli), snappy 分帧格式用它校验每个数据块
+fn crc32c(data: &[u8]) -> u32 {
+    crc_update(&CRC32C_TABLE, 0, data)
+}
+
+// 标准 CRC-32, 7z 与 gzip 用它校验数据
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+// 在前一段数据的 CRC-32 之上继续计算, 用于流式校验
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    crc_update(&CRC32_TABLE, crc, data)
+}
+
+// snappy 分帧格式: 流标识之后是一系列数据块, 每块带掩码后的 CRC-32C,
+// 压缩后没有明显变小的块原样存储
+fn snappy_framed(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / 2 + 16);
+    out.extend_from_slice(SNAPPY_STREAM_ID);
+
+    for block in data.chunks(SNAPPY_BLOCK_SIZE) {
+        let crc = crc32c(block);
+        let masked = crc.rotate_right(15).wrapping_add(0xA282_EAD8);
+        let compressed = snappy_compress_block(block);
+        let (kind, body) = if compressed.len() < block.len() - block.len() / 8 {
+            (0x00, &compressed[..])
+        } else {
+            (0x01, block)
+        };
+        out.push(kind);
+        out.extend_from_slice(&((body.len() + 4) as u32).to_le_bytes()[..3]);
+        out.extend_from_slice(&masked.to_le_bytes());
+        out.extend_from_slice(body);
+    }
+    out
+}
+
+// snappy 原始格式: 变长整数表示的原始长度, 后接字面量与回溯复制; 用 4 字节哈希贪心匹配
+fn snappy_compress_block(block: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(block.len() + block.len() / 6 + 8);
+    let mut len = block.len();
+    while len >= 0x80 {
+        out.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    out.push(len as u8);
+
+    // 表中保存位置 + 1, 0 表示空
+    let mut table = vec![0u32; 1 << 14];
+    let load = |i: usize| u32::from_le_bytes(block[i..i + 4].try_into().unwrap());
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i + 4 <= block.len() {
+        let hash = (load(i).wrapping_mul(0x1E35_A7BD) >> 18) as usize;
+        let candidate = table[hash] as usize;
+        table[hash] = i as u32 + 1;
+        if candidate == 0 || load(candidate - 1) != load(i) {
+            i += 1;
+            continue;
+        }
+
+        let candidate = candidate - 1;
+        let mut matched = 4;
+        while i + matched < block.len() && block[candidate + matched] == block[i + matched] {
+            matched += 1;
+        }
+        snappy_literal(&mut out, &block[literal_start..i]);
+        snappy_copy(&mut out, i - candidate, matched);
+        i += matched;
+        literal_start = i;
+    }
+    snappy_literal(&mut out, &block[literal_start..]);
+    out
+}
+
+fn snappy_literal(out: &mut Vec<u8>, literal: &[u8]) {
+    if literal.is_empty() {
+        return;
+    }
+    let n = literal.len() - 1;
+    if n < 60 {
+        out.push((n as u8) << 2);
+    } else {
+        // 长度减一存放在标记之后的 1-4 个字节中
+        let bytes = (n.ilog2() / 8 + 1) as usize;
+        out.push(((59 + bytes) as u8) << 2);
+        out.extend_from_slice(&(n as u32).to_le_bytes()[..bytes]);
+    }
+    out.extend_from_slice(literal);
+}
+
+fn snappy_copy(out: &mut Vec<u8>, offset: usize, mut len: usize) {
+    // 单个复制最长 64 字节, 拆分时保证剩余部分不少于 4 字节
+    while len >= 68 {
+        snappy_copy(out, offset, 64);
+        len -= 64;
+    }
+    if len > 64 {
+        snappy_copy(out, offset, 60);
+        len -= 60;
+    }
+    if (4..12).contains(&len) && offset < 2048 {
+        out.push((((offset >> 8) as u8) << 5) | (((len - 4) as u8) << 2) | 0x01);
+        out.push(offset as u8);
+    } else {
+        out.push((((len - 1) as u8) << 2) | 0x02);
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+    }
+}
+
+fn snappy_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("snappy 数据损坏: {}", message))
+}
+
+// 解压 snappy 原始格式的一个数据块
+fn snappy_decompress_block(data: &[u8]) -> io::Result<Vec<u8>> {
+    let (mut expected, mut shift, mut i) = (0usize, 0, 0);
+    loop {
+        let byte = *data.get(i).ok_or_else(|| snappy_error("长度不完整"))?;
+        if shift > 28 {
+            return Err(snappy_error("长度过大"));
+        }
+        expected |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        i += 1;
+        if byte < 0x80 {
+            break;
+        }
+    }
+    if expected > SNAPPY_BLOCK_SIZE {
+        return Err(snappy_error("数据块过大"));
+    }
+
+    let truncated = || snappy_error("数据块被截断");
+    let mut out: Vec<u8> = Vec::with_capacity(expected);
+    while i < data.len() {
+        let tag = data[i];
+        i += 1;
+        // 字面量之外的三种复制分别带 1, 2, 4 字节偏移
+        let (offset_bytes, len) = match tag & 0x03 {
+            0x00 => {
+                let mut n = (tag >> 2) as usize;
+                if n >= 60 {
+                    let bytes = n - 59;
+                    let mut le = [0u8; 4];
+                    le[..bytes].copy_from_slice(data.get(i..i + bytes).ok_or_else(truncated)?);
+                    n = u32::from_le_bytes(le) as usize;
+                    i += bytes;
+                }
+                let literal = data.get(i..i.saturating_add(n).saturating_add(1)).ok_or_else(truncated)?;
+                out.extend_from_slice(literal);
+                i += literal.len();
+                continue;
+            }
+            0x01 => (1, ((tag >> 2) & 0x07) as usize + 4),
+            0x02 => (2, (tag >> 2) as usize + 1),
+            _ => (4, (tag >> 2) as usize + 1),
+        };
+        let bytes = data.get(i..i + offset_bytes).ok_or_else(truncated)?;
+        let mut le = [0u8; 4];
+        le[..offset_bytes].copy_from_slice(bytes);
+        let mut offset = u32::from_le_bytes(le) as usize;
+        if offset_bytes == 1 {
+            offset |= (tag as usize >> 5) << 8;
+        }
+        i += offset_bytes;
+        if offset == 0 || offset > out.len() || out.len() + len > expected {
+            return Err(snappy_error("复制超出范围"));
+        }
+        for _ in 0..len {
+            out.push(out[out.len() - offset]);
+        }
+    }
+    if out.len() != expected {
+        return Err(snappy_error("长度与头部不符"));
+    }
+    Ok(out)
+}
+
+// 流式解压 snappy 分帧格式, 逐块校验 CRC-32C; 流标识可以重复出现(多个流首尾相接)
+pub struct SnappyReader<R: Read> {
+    input: R,
+    block: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> SnappyReader<R> {
+    pub fn new(input: R) -> SnappyReader<R> {
+        SnappyReader { input, block: Vec::new(), pos: 0 }
+    }
+
+    // 读入下一个数据块; 流结束时返回 false
+    fn next_block(&mut self) -> io::Result<bool> {
+        loop {
+            let mut header = [0u8; 4];
+            let mut filled = 0;
+            while filled < 4 {
+                match self.input.read(&mut header[filled..])? {
+                    0 if filled == 0 => return Ok(false),
+                    0 => return Err(snappy_error("块头被截断")),
+                    n => filled += n,
+                }
+            }
+            let len = u32::from_le_bytes([header[1], header[2], header[3], 0]) as usize;
+            let mut body = vec![0u8; len];
+            self.input.read_exact(&mut body).map_err(|_| snappy_error("数据块被截断"))?;
+            let data = match header[0] {
+                0xFF if body == SNAPPY_STREAM_ID[4..] => continue,
+                0xFF => return Err(snappy_error("流标识不正确")),
+                0x00 | 0x01 if len < 4 => return Err(snappy_error("数据块过短")),
+                0x00 => snappy_decompress_block(&body[4..])?,
+                0x01 => body.split_off(4),
+                // 0x80 以上为可跳过的块, 0x02-0x7F 保留且不可跳过
+                0x80.. => continue,
+                kind => return Err(snappy_error(&format!("未知的块类型 0x{:02x}", kind))),
+            };
+            let masked = u32::from_le_bytes(body[..4].try_into().unwrap());
+            if data.len() > SNAPPY_BLOCK_SIZE {
+                return Err(snappy_error("数据块过大"));
+            }
+            if masked != crc32c(&data).rotate_right(15).wrapping_add(0xA282_EAD8) {
+                return Err(snappy_error("CRC-32C 不符"));
+            }
+            self.block = data;
+            self.pos = 0;
+            return Ok(true);
+        }
+    }
+}
+
+impl<R: Read> Read for SnappyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.block.len() {
+            if buf.is_empty() || !self.next_block()? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.block.len() - self.pos);
+        buf[..n].copy_from_slice(&self.block[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// 在 PATH 中查找命令行工具
+pub fn find_tool(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?).map(|dir| dir.join(name)).find(|path| path.is_file())
+}
+
+// 调用系统中的命令行工具, 数据经标准输入传入, 返回标准输出
+pub fn run_tool(program: &str, args: &[&str], data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("无法启动 {}: {}", program, e)))?;
+
+    // 另起线程写入, 避免输出管道写满时双方互相等待
+    let mut stdin = child.stdin.take().unwrap();
+    let output = thread::scope(|scope| {
+        let writer = scope.spawn(move || stdin.write_all(data));
+        let output = child.wait_with_output();
+        writer.join().unwrap()?;
+        output
+    })?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!("{} 执行失败: {}", program, output.status)));
+    }
+    Ok(output.stdout)
+}
+
+// 从命令行工具的标准输出读取; 读到末尾时检查退出状态, 下载失败不会被当成数据结束
+pub struct ToolReader {
+    program: String,
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl ToolReader {
+    pub fn spawn(program: &str, args: &[&str]) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("无法启动 {}: {}", program, e)))?;
+        let stdout = child.stdout.take().unwrap();
+        Ok(ToolReader { program: program.to_string(), child, stdout })
+    }
+
+    // 经标准输入把 input 交给工具处理, 读取其输出; 工具提前退出时写入端的错误不必理会, 以退出状态为准
+    pub fn pipe(program: &str, args: &[&str], mut input: impl Read + Send + 'static) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("无法启动 {}: {}", program, e)))?;
+        let mut stdin = child.stdin.take().unwrap();
+        thread::spawn(move || io::copy(&mut input, &mut stdin));
+        let stdout = child.stdout.take().unwrap();
+        Ok(ToolReader { program: program.to_string(), child, stdout })
+    }
+}
+
+impl Read for ToolReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("{} 执行失败: {}", self.program, status)));
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for ToolReader {
+    fn drop(&mut self) {
+        // 提前放弃读取时不留下子进程
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+
+// gzip 输入: 流式解压, 首尾相接的多个成员(轮转后拼接的日志)依次解压拼接, 每个成员分别校验
+// CRC 与长度; 需要时把每个成员在解压后数据中的结束偏移记录到 member_ends
+pub struct GzipReader<R: Read> {
+    input: R,
+    inbuf: Box<[u8]>,
+    inpos: usize,
+    inlen: usize,
+    bitbuf: u64,
+    bitcnt: u32,
+    // 已解压的数据: out[pos..] 尚未交给调用方, 之前保留 32KB 作为回溯窗口;
+    // out_base 为 out[0] 在解压后数据中的偏移, crc_pos 之前的部分已计入 crc
+    out: Vec<u8>,
+    pos: usize,
+    out_base: usize,
+    crc_pos: usize,
+    state: GzipState,
+    last_block: bool,
+    member_start: usize,
+    crc: u32,
+    // 已读完至少一个成员, 之后的 0 填充不再当作成员头
+    after_member: bool,
+    member_ends: Option<MemberEnds>,
+}
+
+enum GzipState {
+    Header,
+    BlockStart,
+    Stored(usize),
+    Huffman(Box<(Huffman, Huffman)>),
+    Trailer,
+    Done,
+}
+
+const GZIP_WINDOW: usize = 32 * 1024;
+const GZIP_BATCH: usize = 256 * 1024; // 每次解压至少产出的字节数
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn gzip_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("gzip 数据无效: {}", message))
+}
+
+// 改写一个 gzip 成员头中的文件名(gunzip -N 据此恢复文件名)与修改时间(Unix 秒, 0 表示没有);
+// 保留额外字段与注释, 头部 CRC 随之失效, 一并去掉
+pub fn set_gzip_header(member: &[u8], name: Option<&str>, mtime: u32) -> io::Result<Vec<u8>> {
+    if member.len() < 10 || member[..3] != [0x1F, 0x8B, 0x08] {
+        return Err(gzip_error("成员头无效"));
+    }
+    let truncated = || gzip_error("成员头被截断");
+    let flags = member[3];
+    let mut pos = 10;
+    if flags & 0x04 != 0 {
+        let len = member.get(pos..pos + 2).ok_or_else(truncated)?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    let extra = member.get(10..pos).ok_or_else(truncated)?;
+    // 以 0 结尾的字段之后的位置
+    let field_end = |pos: usize| member.get(pos..).and_then(|rest| rest.iter().position(|&b| b == 0)).map(|i| pos + i + 1).ok_or_else(truncated);
+    if flags & 0x08 != 0 {
+        pos = field_end(pos)?;
+    }
+    let comment_start = pos;
+    if flags & 0x10 != 0 {
+        pos = field_end(pos)?;
+    }
+    let comment = &member[comment_start..pos];
+    if flags & 0x02 != 0 {
+        pos += 2;
+    }
+    let body = member.get(pos..).ok_or_else(truncated)?;
+    if name.is_some_and(|name| name.contains('\0')) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "gzip 成员头中的文件名不能包含 NUL"));
+    }
+
+    let flags = flags & (0x01 | 0x04 | 0x10) | if name.is_some() { 0x08 } else { 0 };
+    let mut out = Vec::with_capacity(member.len() + name.map_or(0, |name| name.len() + 1));
+    out.extend_from_slice(&[0x1F, 0x8B, 0x08, flags]);
+    out.extend_from_slice(&mtime.to_le_bytes());
+    out.extend_from_slice(&member[8..10]);
+    out.extend_from_slice(extra);
+    if let Some(name) = name {
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+    }
+    out.extend_from_slice(comment);
+    out.extend_from_slice(body);
+    Ok(out)
+}
+
+// 范式 Huffman 码的查找表: 下标为接下来 bits 位(按读取顺序), 值为 (符号 << 4) | 码长, 0 表示无效码
+struct Huffman {
+    table: Vec<u16>,
+    bits: u32,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let bits = lengths.iter().copied().max().unwrap_or(0).max(1) as u32;
+        let mut count = [0u16; 16];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+        count[0] = 0;
+
+        let mut left = 1i32;
+        let mut next = [0u16; 16];
+        for len in 1..16 {
+            left = (left << 1) - count[len] as i32;
+            if left < 0 {
+                return Err(gzip_error("Huffman 码长超额"));
+            }
+            next[len] = (next[len - 1] + count[len - 1]) << 1;
+        }
+
+        let mut table = vec![0u16; 1 << bits];
+        for (symbol, &len) in lengths.iter().enumerate().filter(|(_, &len)| len > 0) {
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            // 码字从高位起写入, 读取时是反过来的
+            let mut i = (code.reverse_bits() >> (16 - len)) as usize;
+            while i < table.len() {
+                table[i] = ((symbol as u16) << 4) | len as u16;
+                i += 1 << len;
+            }
+        }
+        Ok(Huffman { table, bits })
+    }
+
+    fn fixed() -> (Self, Self) {
+        let mut lengths = [8u8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        (Huffman::new(&lengths).unwrap(), Huffman::new(&[5; 30]).unwrap())
+    }
+}
+
+impl<R: Read> GzipReader<R> {
+    pub fn new(input: R, member_ends: Option<MemberEnds>) -> Self {
+        GzipReader {
+            input,
+            inbuf: vec![0; 64 * 1024].into_boxed_slice(),
+            inpos: 0,
+            inlen: 0,
+            bitbuf: 0,
+            bitcnt: 0,
+            out: Vec::with_capacity(GZIP_WINDOW + 2 * GZIP_BATCH),
+            pos: 0,
+            out_base: 0,
+            crc_pos: 0,
+            state: GzipState::Header,
+            last_block: false,
+            member_start: 0,
+            crc: 0,
+            after_member: false,
+            member_ends,
+        }
+    }
+
+    // 补充位缓冲至少到 n 位; 输入已经结束时返回 false
+    fn refill(&mut self, n: u32) -> io::Result<bool> {
+        while self.bitcnt < n {
+            if self.inpos == self.inlen {
+                self.inlen = self.input.read(&mut self.inbuf)?;
+                self.inpos = 0;
+                if self.inlen == 0 {
+                    return Ok(false);
+                }
+            }
+            self.bitbuf |= (self.inbuf[self.inpos] as u64) << self.bitcnt;
+            self.inpos += 1;
+            self.bitcnt += 8;
+        }
+        Ok(true)
+    }
+
+    fn bits(&mut self, n: u32) -> io::Result<usize> {
+        if !self.refill(n)? {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "gzip 数据被截断"));
+        }
+        let value = (self.bitbuf & ((1 << n) - 1)) as usize;
+        self.bitbuf >>= n;
+        self.bitcnt -= n;
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.bits(8)? as u8)
+    }
+
+    fn u32_le(&mut self) -> io::Result<u32> {
+        Ok(self.bits(16)? as u32 | (self.bits(16)? as u32) << 16)
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> io::Result<usize> {
+        // 流末尾可能不足 bits 位, 只要够当前码字的长度即可
+        self.refill(huffman.bits)?;
+        let entry = huffman.table[(self.bitbuf & ((1 << huffman.bits) - 1)) as usize];
+        let len = (entry & 0x0F) as u32;
+        if len == 0 {
+            return Err(gzip_error("无效的 Huffman 码"));
+        }
+        if len > self.bitcnt {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "gzip 数据被截断"));
+        }
+        self.bitbuf >>= len;
+        self.bitcnt -= len;
+        Ok((entry >> 4) as usize)
+    }
+
+    // 读取成员头; 输入在成员之间正常结束时返回 false. 与 gzip 一样忽略最后一个成员之后的 0 填充
+    // (按块写入磁带或块设备时补齐的部分), 填充之后再有数据则报错
+    fn read_header(&mut self) -> io::Result<bool> {
+        if !self.refill(8)? {
+            return Ok(false);
+        }
+        if self.after_member && self.bitbuf & 0xFF == 0 {
+            while self.refill(8)? {
+                if self.byte()? != 0 {
+                    return Err(gzip_error("最后一个成员之后的 0 填充中有其他数据"));
+                }
+            }
+            return Ok(false);
+        }
+        let magic = [self.byte()?, self.byte()?, self.byte()?];
+        if magic != [0x1F, 0x8B, 0x08] {
+            return Err(gzip_error("成员头无效"));
+        }
+        let flags = self.byte()?;
+        self.bits(16)?; // MTIME
+        self.bits(16)?;
+        self.bits(16)?; // XFL, OS
+        if flags & 0x04 != 0 {
+            let len = self.bits(16)?;
+            for _ in 0..len {
+                self.byte()?;
+            }
+        }
+        // 以 0 结尾的文件名与注释
+        for flag in [0x08, 0x10] {
+            if flags & flag != 0 {
+                while self.byte()? != 0 {}
+            }
+        }
+        if flags & 0x02 != 0 {
+            self.bits(16)?; // 头部 CRC16
+        }
+        Ok(true)
+    }
+
+    fn read_dynamic_tables(&mut self) -> io::Result<(Huffman, Huffman)> {
+        let literals = self.bits(5)? + 257;
+        let distances = self.bits(5)? + 1;
+        let code_lengths = self.bits(4)? + 4;
+        let mut lengths = [0u8; 19];
+        for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+            lengths[i] = self.bits(3)? as u8;
+        }
+        let code_length_huffman = Huffman::new(&lengths)?;
+
+        let mut lengths = vec![0u8; literals + distances];
+        let mut i = 0;
+        while i < lengths.len() {
+            let symbol = self.decode(&code_length_huffman)?;
+            let (value, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 if i > 0 => (lengths[i - 1], 3 + self.bits(2)?),
+                17 => (0, 3 + self.bits(3)?),
+                18 => (0, 11 + self.bits(7)?),
+                _ => return Err(gzip_error("码长序列无效")),
+            };
+            if i + repeat > lengths.len() {
+                return Err(gzip_error("码长序列过长"));
+            }
+            lengths[i..i + repeat].fill(value);
+            i += repeat;
+        }
+        if lengths[256] == 0 {
+            return Err(gzip_error("缺少块结束符"));
+        }
+        Ok((Huffman::new(&lengths[..literals])?, Huffman::new(&lengths[literals..])?))
+    }
+
+    // 解压一个 Huffman 块直到产出足够的数据; 块结束时返回 true
+    fn inflate_block(&mut self, tables: &(Huffman, Huffman), target: usize) -> io::Result<bool> {
+        while self.out.len() < target {
+            let symbol = self.decode(&tables.0)?;
+            if symbol < 256 {
+                self.out.push(symbol as u8);
+                continue;
+            }
+            if symbol == 256 {
+                return Ok(true);
+            }
+            let index = symbol - 257;
+            if index >= LENGTH_BASE.len() {
+                return Err(gzip_error("长度码无效"));
+            }
+            let len = LENGTH_BASE[index] as usize + self.bits(LENGTH_EXTRA[index] as u32)?;
+            let index = self.decode(&tables.1)?;
+            if index >= DIST_BASE.len() {
+                return Err(gzip_error("距离码无效"));
+            }
+            let distance = DIST_BASE[index] as usize + self.bits(DIST_EXTRA[index] as u32)?;
+            if distance > self.out_base + self.out.len() - self.member_start {
+                return Err(gzip_error("回溯距离超出已解压的数据"));
+            }
+            let start = self.out.len() - distance;
+            if distance >= len {
+                self.out.extend_from_within(start..start + len);
+            } else {
+                // 与待写出部分重叠, 逐字节复制
+                for i in start..start + len {
+                    let byte = self.out[i];
+                    self.out.push(byte);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn update_crc(&mut self) {
+        self.crc = crc32_update(self.crc, &self.out[self.crc_pos..]);
+        self.crc_pos = self.out.len();
+    }
+
+    fn end_block(&self) -> GzipState {
+        if self.last_block { GzipState::Trailer } else { GzipState::BlockStart }
+    }
+
+    // 解压直到产出 GZIP_BATCH 字节或整个输入结束; 成员的最后一块解压完时接着读取尾部,
+    // 保证交出成员末尾的数据之前已经记录了它的结束位置
+    fn inflate_some(&mut self) -> io::Result<()> {
+        let target = self.out.len() + GZIP_BATCH;
+        while self.out.len() < target || matches!(self.state, GzipState::Trailer) {
+            self.state = match std::mem::replace(&mut self.state, GzipState::Done) {
+                GzipState::Header => {
+                    if !self.read_header()? {
+                        return Ok(());
+                    }
+                    self.member_start = self.out_base + self.out.len();
+                    self.crc = 0;
+                    GzipState::BlockStart
+                }
+                GzipState::BlockStart => {
+                    self.last_block = self.bits(1)? == 1;
+                    match self.bits(2)? {
+                        0 => {
+                            // 存储块从字节边界开始
+                            self.bits(self.bitcnt % 8)?;
+                            let len = self.bits(16)?;
+                            if self.bits(16)? != !len & 0xFFFF {
+                                return Err(gzip_error("存储块长度校验失败"));
+                            }
+                            GzipState::Stored(len)
+                        }
+                        1 => GzipState::Huffman(Box::new(Huffman::fixed())),
+                        2 => GzipState::Huffman(Box::new(self.read_dynamic_tables()?)),
+                        _ => return Err(gzip_error("块类型无效")),
+                    }
+                }
+                GzipState::Stored(mut remaining) => {
+                    while remaining > 0 && self.out.len() < target {
+                        if self.bitcnt == 0 && self.inpos < self.inlen {
+                            let n = remaining.min(self.inlen - self.inpos);
+                            self.out.extend_from_slice(&self.inbuf[self.inpos..self.inpos + n]);
+                            self.inpos += n;
+                            remaining -= n;
+                        } else {
+                            let byte = self.byte()?;
+                            self.out.push(byte);
+                            remaining -= 1;
+                        }
+                    }
+                    if remaining > 0 { GzipState::Stored(remaining) } else { self.end_block() }
+                }
+                GzipState::Huffman(tables) => {
+                    if self.inflate_block(&tables, target)? {
+                        self.end_block()
+                    } else {
+                        GzipState::Huffman(tables)
+                    }
+                }
+                GzipState::Trailer => {
+                    self.bits(self.bitcnt % 8)?;
+                    self.update_crc();
+                    let end = self.out_base + self.out.len();
+                    if self.u32_le()? != self.crc {
+                        return Err(gzip_error(&format!("解压后偏移 {} 处结束的成员 CRC 校验失败", end)));
+                    }
+                    if self.u32_le()? != (end - self.member_start) as u32 {
+                        return Err(gzip_error(&format!("解压后偏移 {} 处结束的成员长度不符", end)));
+                    }
+                    if let Some(ends) = &self.member_ends {
+                        ends.lock().unwrap().push_back(end);
+                    }
+                    self.after_member = true;
+                    GzipState::Header
+                }
+                GzipState::Done => return Ok(()),
+            };
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for GzipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.out.len() {
+            if matches!(self.state, GzipState::Done) {
+                return Ok(0);
+            }
+            // 已交出的数据只保留回溯窗口
+            if self.out.len() > GZIP_WINDOW {
+                self.update_crc();
+                let drop = self.out.len() - GZIP_WINDOW;
+                self.out.drain(..drop);
+                self.out_base += drop;
+                self.pos -= drop;
+                self.crc_pos -= drop;
+            }
+            self.inflate_some()?;
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn bzip2_streams_concatenate() {
+        let mut data = compress(b"first\n", Format::Bzip2, ZstdParams::default()).unwrap();
+        data.extend(compress(b"second\n", Format::Bzip2, ZstdParams::default()).unwrap());
+        let mut child = Command::new("bzip2").arg("-dc").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(&data).unwrap();
+        assert_eq!(child.wait_with_output().unwrap().stdout, b"first\nsecond\n");
+    }
+
+    #[test]
+    fn snappy_framed_roundtrip() {
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+
+        // xorshift64*, 固定种子
+        let mut state = 7u64;
+        let mut next = || {
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        };
+        let mut repetitive = Vec::new();
+        while repetitive.len() < 200_000 {
+            repetitive.extend_from_slice(format!("2024-01-01 INFO request {} ok\n", next() % 100).as_bytes());
+        }
+        let random: Vec<u8> = (0..70_000).map(|_| next() as u8).collect();
+        let long_literal: Vec<u8> = (0..300u32).map(|i| (i * 7 % 256) as u8).collect();
+
+        for data in [&b""[..], b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", &long_literal, &random, &repetitive] {
+            let mut out = Vec::new();
+            SnappyReader::new(&snappy_framed(data)[..]).read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+        }
+        assert!(snappy_framed(&repetitive).len() < repetitive.len() / 3);
+
+        // 校验和不符或块被截断时报错
+        let mut framed = snappy_framed(&repetitive);
+        framed[20] ^= 0x01;
+        assert!(SnappyReader::new(&framed[..]).read_to_end(&mut Vec::new()).is_err());
+        let framed = snappy_framed(&repetitive);
+        assert!(SnappyReader::new(&framed[..framed.len() - 1]).read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn formats_roundtrip_and_are_detected() {
+        let data = b"2024-01-01 INFO request ok\n".repeat(1000);
+        for format in [Format::Zstd, Format::Gzip, Format::Xz, Format::Lz4, Format::Snappy] {
+            let compressed = compress(&data, format, ZstdParams::default()).unwrap();
+            let (name, mut decoder) = open_decoder(Box::new(io::Cursor::new(compressed))).unwrap();
+            assert_eq!(name, format.name());
+            let mut out = Vec::new();
+            decoder.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+        }
+        assert!(open_decoder(Box::new(io::Cursor::new(b"plain text".to_vec()))).is_err());
+    }
+
+    #[test]
+    fn gzip_headers_carry_name_and_mtime() {
+        let data = b"hello gzip\n".repeat(50);
+        let member = compress(&data, Format::Gzip, ZstdParams::default()).unwrap();
+        let named = set_gzip_header(&member, Some("out.001"), 1_700_000_000).unwrap();
+        assert_eq!(named[3], 0x08);
+        assert_eq!(u32::from_le_bytes(named[4..8].try_into().unwrap()), 1_700_000_000);
+        assert_eq!(&named[10..18], b"out.001\0");
+        // 再次改写时替换原有的文件名
+        let renamed = set_gzip_header(&named, None, 0).unwrap();
+        assert_eq!(renamed, member.iter().take(4).chain(&[0; 4]).chain(&member[8..]).copied().collect::<Vec<_>>());
+        for member in [named, renamed] {
+            let mut out = Vec::new();
+            GzipReader::new(&member[..], None).read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+        }
+        assert!(set_gzip_header(&member[..5], None, 0).is_err());
+    }
+
+    #[test]
+    fn embedded_dictionary_decodes_the_whole_set() {
+        let dictionary = b"2024-03-01 12:00:00 INFO request served path=/api/v1/items status=200\n".repeat(4);
+        let compressor = ZstdDictionary { params: ZstdParams::default(), dictionary: &dictionary };
+        let mut first = dictionary_frame(&dictionary);
+        first.extend(compressor.compress(b"2024-03-01 12:00:01 INFO request served path=/api/v1/items status=200\n").unwrap());
+        let second = compressor.compress(b"2024-03-01 12:00:02 INFO request served path=/api/v1/users status=404\n").unwrap();
+
+        let read = |data: &[u8], dictionary: &mut Option<Vec<u8>>| -> io::Result<Vec<u8>> {
+            let mut out = Vec::new();
+            open_decoder_with(Box::new(io::Cursor::new(data.to_vec())), dictionary)?.1.read_to_end(&mut out)?;
+            Ok(out)
+        };
+        let mut shared = None;
+        assert!(read(&first, &mut shared).unwrap().ends_with(b"12:00:01 INFO request served path=/api/v1/items status=200\n"));
+        assert_eq!(shared.as_deref(), Some(&dictionary[..]));
+        assert!(read(&second, &mut shared).unwrap().ends_with(b"users status=404\n"));
+        assert!(read(&second, &mut None).is_err());
+
+        let (none, rest) = take_dictionary(Box::new(io::Cursor::new(b"plain".to_vec()))).unwrap();
+        assert!(none.is_none());
+        assert_eq!(io::read_to_string(rest).unwrap(), "plain");
+    }
+}
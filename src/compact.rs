@@ -0,0 +1,232 @@
+// compact 子命令: 把相邻的小分卷首尾相接合并, 不重新压缩
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::config::parse_size_mb;
+use crate::hash::{to_hex, Sha256};
+use crate::manifest::{read_manifest, sibling, ManifestEntry, ManifestTable};
+use crate::merge::CheckedWriter;
+use crate::naming::temp_path;
+use crate::prompt::confirm;
+use crate::source::Source;
+use crate::{chunk_name, codec, Format};
+
+// 按顺序把相邻分卷分组, 每组的压缩后大小不超过 target; 单个分卷超过 target 时自成一组
+fn plan_compaction(sizes: &[u64], target: u64) -> Vec<std::ops::Range<usize>> {
+    let mut groups = Vec::new();
+    let (mut start, mut total) = (0, 0);
+    for (i, &size) in sizes.iter().enumerate() {
+        if i > start && total + size > target {
+            groups.push(start..i);
+            (start, total) = (i, 0);
+        }
+        total += size;
+    }
+    if start < sizes.len() {
+        groups.push(start..sizes.len());
+    }
+    groups
+}
+
+// 压缩流可以首尾相接的分卷扩展名; 7z 归档与 brotli 流不行
+fn concatenable_extensions() -> Vec<&'static str> {
+    let formats = [
+        Format::Zstd,
+        Format::Gzip,
+        Format::Xz,
+        Format::Lz4,
+        Format::Snappy,
+        #[cfg(feature = "bzip2")]
+        Format::Bzip2,
+    ];
+    formats.into_iter().map(Format::extension).collect()
+}
+
+// 把许多小分卷(如按时间或持续跟随切分得到的)合并为接近目标大小的大分卷: 压缩流直接首尾相接, 不重新压缩,
+// 也不把数据解压到磁盘. 新分卷从 1 起按编号命名, manifest 中的行随之重写; 有 sha256 列时流式解压重新计算摘要.
+// 新分卷与 manifest 先写临时文件, 全部写完才替换旧分卷
+pub fn run_compact(prefix: &str, args: &[String]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let mut target = None;
+    let mut yes = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target-size" => {
+                let value = args.next().ok_or_else(|| invalid("选项 --target-size 缺少参数".to_string()))?;
+                target = Some(parse_size_mb(value, "目标大小").map_err(invalid)? as u64);
+            }
+            "--yes" => yes = true,
+            _ => return Err(invalid(format!("未知选项: {}", arg))),
+        }
+    }
+    let target = target.ok_or_else(|| invalid("compact 需要 --target-size <MB>".to_string()))?;
+    if !matches!(Source::parse(prefix), Source::Local(_)) {
+        return Err(invalid("compact 只支持本地分卷".to_string()));
+    }
+
+    let table = read_manifest(prefix)?;
+    let entries = match &table {
+        Some(table) => {
+            if table.column("line_merkle").is_some() {
+                return Err(invalid("manifest 含 line_merkle 列, 合并后各行的 Merkle 根无法保持, 不能合并".to_string()));
+            }
+            if table.column("header_bytes").is_some() {
+                return Err(invalid("分卷开头带有复制的 CSV 表头或 XML 首尾, 首尾相接后会混入数据, 不能合并".to_string()));
+            }
+            let mut entries = table.entries()?;
+            for entry in &mut entries {
+                entry.file = sibling(prefix, &entry.file);
+            }
+            entries
+        }
+        // 没有 manifest 时按第一个分卷的扩展名依次探测编号分卷
+        None => {
+            let extension = concatenable_extensions().into_iter().find(|extension| Path::new(&chunk_name(prefix, 1, extension)).exists()).unwrap_or("zst");
+            let numbered = |n| ManifestEntry { file: chunk_name(prefix, n, extension), ..ManifestEntry::numbered(prefix, n) };
+            (1..).map(numbered).take_while(|entry| Path::new(&entry.file).exists()).collect()
+        }
+    };
+    if entries.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("找不到分卷 {}", chunk_name(prefix, 1, "zst"))));
+    }
+    let extension = entries[0].file.rsplit_once('.').map_or("", |(_, extension)| extension).to_string();
+    if entries.iter().any(|entry| !entry.file.ends_with(&format!(".{}", extension))) {
+        return Err(invalid("分卷的扩展名不一致, 不能合并".to_string()));
+    }
+    if !concatenable_extensions().contains(&extension.as_str()) {
+        return Err(invalid(format!("扩展名为 .{} 的分卷不能首尾相接合并", extension)));
+    }
+
+    let sizes = entries.iter().map(|entry| std::fs::metadata(&entry.file).map(|m| m.len())).collect::<io::Result<Vec<_>>>()?;
+    let groups = plan_compaction(&sizes, target);
+    if groups.len() == entries.len() {
+        println!("{} 个分卷都无法在 {} 字节内与相邻分卷合并, 无需整理", entries.len(), target);
+        return Ok(());
+    }
+    confirm(&format!("将把 {} 个分卷合并为 {} 个并重新编号", entries.len(), groups.len()), yes)?;
+
+    // 镜像目标中的仍是旧分卷, 合并后不再记录它们的状态
+    // 合并后的分卷都写在 prefix 所在目录, 不再需要 dir 列
+    let known = ["chunk", "file", "bytes", "raw_bytes", "lines", "sha256"];
+    let columns: Vec<String> = table.iter().flat_map(|t| t.columns.iter()).filter(|c| known.contains(&c.as_str())).cloned().collect();
+    if table.as_ref().is_some_and(|t| t.columns.iter().filter(|c| *c != "dir").count() > columns.len()) {
+        eprintln!("警告: manifest 中镜像目标的状态列不适用于合并后的分卷, 已删除");
+    }
+
+    // 合并后分卷的行数为原分卷之和
+    let lines: Vec<Option<u64>> = match table.as_ref().and_then(|t| Some((t, t.column("lines")?))) {
+        Some((table, i)) => table.rows.iter().map(|row| row.get(i).and_then(|n| n.parse().ok())).collect(),
+        None => vec![None; entries.len()],
+    };
+
+    let mut written: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut dictionary = None;
+    let result = (|| {
+        let mut rows = Vec::new();
+        for (i, group) in groups.iter().enumerate() {
+            let path = PathBuf::from(chunk_name(prefix, i + 1, &extension));
+            let tmp = temp_path(&path);
+            written.push((tmp.clone(), path.clone()));
+            let mut out = File::create(&tmp)?;
+            for entry in &entries[group.clone()] {
+                io::copy(&mut File::open(&entry.file)?, &mut out)?;
+            }
+            out.sync_all()?;
+
+            let bytes: u64 = sizes[group.clone()].iter().sum();
+            let mut raw_bytes = entries[group.clone()].iter().map(|entry| entry.raw_bytes).sum::<Option<u64>>();
+            let mut sha256 = None;
+            if columns.iter().any(|c| c == "sha256") {
+                let mut sink = io::sink();
+                let mut checked = CheckedWriter::new(&mut sink, Some(Sha256::new()));
+                let decoded = codec::open_decoder_with(Box::new(File::open(&tmp)?), &mut dictionary).and_then(|(_, mut decoder)| io::copy(&mut decoder, &mut checked));
+                raw_bytes = Some(decoded.map_err(|e| io::Error::new(e.kind(), format!("合并后的分卷 {} 解压失败: {}", i + 1, e)))?);
+                sha256 = checked.hasher.map(|hasher| to_hex(&hasher.finish()));
+            }
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let row = columns.iter().map(|column| match column.as_str() {
+                "chunk" => (i + 1).to_string(),
+                "file" => name.clone(),
+                "bytes" => bytes.to_string(),
+                "raw_bytes" => raw_bytes.map(|n| n.to_string()).unwrap_or_default(),
+                "lines" => lines[group.clone()].iter().copied().sum::<Option<u64>>().map(|n| n.to_string()).unwrap_or_default(),
+                _ => sha256.clone().unwrap_or_default(),
+            });
+            rows.push(row.collect::<Vec<_>>());
+            println!("分卷 {}: 合并原分卷 {}-{}, {} 字节", i + 1, entries[group.start].chunk, entries[group.end - 1].chunk, bytes);
+        }
+        if let Some(table) = &table {
+            let mut lineage = table.lineage.clone();
+            lineage.push(("compacted".to_string(), format!("chunks={} target_bytes={} volumes={}", entries.len(), target, groups.len())));
+            let compacted = ManifestTable { version: table.version, columns: columns.clone(), rows, lineage };
+            let path = PathBuf::from(format!("{}.manifest", prefix));
+            std::fs::write(temp_path(&path), compacted.to_text())?;
+            written.push((temp_path(&path), path));
+        }
+        Ok(())
+    })();
+    if let Err(e) = result {
+        for (tmp, _) in &written {
+            let _ = std::fs::remove_file(tmp);
+        }
+        return Err(e);
+    }
+
+    for (tmp, path) in &written {
+        std::fs::rename(tmp, path)?;
+    }
+    for entry in &entries {
+        if !written.iter().any(|(_, path)| path == Path::new(&entry.file)) {
+            std::fs::remove_file(&entry.file)?;
+        }
+    }
+    // 日志记录的是旧分卷, 留着会让 --recover 按旧文件名删除合并后的分卷; JSON 清单同样只描述旧分卷
+    for stale in ["journal", "manifest.json"] {
+        match std::fs::remove_file(format!("{}.{}", prefix, stale)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    println!("整理完成: {} 个分卷合并为 {} 个", entries.len(), groups.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+use crate::hash::sha256;
+    use super::*;
+    use crate::config::Config;
+    use crate::manifest::chunk_list;
+    use crate::merge::{merge_chunks, MergeOptions};
+    use crate::profiler::Profiler;
+    use crate::testing::Scratch;
+    use crate::writer::{ChunkWriter, Output};
+
+    #[test]
+    fn compaction_concatenates_neighbours_and_rewrites_manifest() {
+        assert_eq!(plan_compaction(&[3, 3, 3, 9, 1, 1], 6), [0..2, 2..3, 3..4, 4..6]);
+        assert!(plan_compaction(&[], 6).is_empty());
+
+        let dir = Scratch::new("compact");
+        let prefix = dir.join("part").display().to_string();
+        let config = Config::parse(["zstd_compressor", "in.log", &prefix, "--content-addressed"].map(String::from)).unwrap();
+        let mut writer = ChunkWriter::new(&config, Output::open(&config).unwrap());
+        for (n, line) in ["a\n", "b\n", "c\n"].iter().enumerate() {
+            writer.write(line.as_bytes(), n * 2, &Profiler::new(false)).unwrap();
+        }
+        writer.finish().unwrap();
+        std::fs::write(format!("{}.journal", prefix), "").unwrap();
+
+        run_compact(&prefix, &["--target-size".to_string(), "1".to_string(), "--yes".to_string()]).unwrap();
+        assert!(!Path::new(&chunk_name(&prefix, 2, "zst")).exists());
+        assert!(!Path::new(&format!("{}.journal", prefix)).exists());
+        let entries = chunk_list(&prefix).unwrap().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].raw_bytes, entries[0].sha256), (Some(6), Some(sha256(b"a\nb\nc\n"))));
+        let mut merged = Vec::new();
+        merge_chunks(&prefix, &mut merged, &MergeOptions::default()).unwrap();
+        assert_eq!(merged, b"a\nb\nc\n");
+    }
+}
@@ -0,0 +1,202 @@
+// 分卷的压缩: 采样估算压缩率与训练内嵌字典, 压缩时内存不足则把分块大小减半重试
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use crate::codec::{self, Compressor};
+use crate::config::{inject_failure, Config, FailureStage, SevenZipCodec};
+use crate::header::{copied_footer_for, copied_header_for};
+use crate::profiler::Profiler;
+use crate::split::{check_ratio, filter_lines, Rejects};
+use crate::writer::ChunkWriter;
+use crate::{CompressionModel, Format, MIN_SHRUNK_CHUNK_SIZE};
+#[cfg(feature = "dictionary")]
+use crate::merkle::split_lines;
+
+const SAMPLE_SIZE: usize = 64 * 1024; // 压缩率采样时每段压缩的字节数
+
+#[cfg(feature = "dictionary")]
+const DICTIONARY_SIZE: usize = 110 * 1024; // 内嵌字典的大小上限, 与 zstd --train 的默认值相同
+
+// 把文件按 segment_size 均匀分段, 依次读出每段开头的 SAMPLE_SIZE 字节; 采样按原始文件进行, 不支持 gzip 输入
+fn for_each_sample(config: &Config, segment_size: usize, option: &str, mut each: impl FnMut(&[u8]) -> io::Result<()>) -> io::Result<usize> {
+    let mut file = File::open(&config.input_path)?;
+    let file_len = file.metadata()?.len() as usize;
+    let mut magic = [0u8; 2];
+    if file.read(&mut magic)? == 2 && magic == [0x1F, 0x8B] {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} 按原始文件采样, 不支持 gzip 输入", option)));
+    }
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    for start in (0..file_len).step_by(segment_size) {
+        sample.clear();
+        file.seek(SeekFrom::Start(start as u64))?;
+        Read::by_ref(&mut file).take(SAMPLE_SIZE as u64).read_to_end(&mut sample)?;
+        each(&sample)?;
+    }
+    Ok(file_len)
+}
+
+// 采样遍: 把文件均匀分段, 每段开头取一小块压缩, 估算各段的压缩率
+pub(crate) fn sample_compression_model(config: &Config) -> io::Result<CompressionModel> {
+    let segment_size = (config.chunk_size / 8).max(SAMPLE_SIZE);
+    let mut ratios = Vec::new();
+    let file_len = for_each_sample(config, segment_size, "--balance-compressed", |sample| {
+        let compressed = chunk_compressor(config).compress(sample)?;
+        ratios.push(compressed.len() as f64 / sample.len().max(1) as f64);
+        Ok(())
+    })?;
+    Ok(CompressionModel::new(segment_size, ratios, file_len, config.chunk_size))
+}
+
+// 从整个文件均匀采样训练字典, 每行(二进制输入为每 4 KB)作为一个样本; 样本总量约为字典大小的 100 倍.
+// 样本太少无法训练时给出警告, 分卷不使用字典
+#[cfg(feature = "dictionary")]
+pub(crate) fn train_inline_dictionary(config: &Config) -> io::Result<Option<Vec<u8>>> {
+    let file_len = std::fs::metadata(&config.input_path)?.len() as usize;
+    let segment_size = (file_len / (DICTIONARY_SIZE * 100 / SAMPLE_SIZE)).max(SAMPLE_SIZE);
+    let delimiter = config.encoding.encode(&config.line_ending).0;
+    let (mut samples, mut sizes) = (Vec::new(), Vec::new());
+    for_each_sample(config, segment_size, "--inline-dictionary", |block| {
+        let records = if config.binary { block.chunks(4096).collect() } else { split_lines(block, &delimiter) };
+        sizes.extend(records.iter().map(|record| record.len()));
+        samples.extend_from_slice(block);
+        Ok(())
+    })?;
+    match codec::train_dictionary(&samples, &sizes, DICTIONARY_SIZE) {
+        Ok(dictionary) => Ok(Some(dictionary)),
+        Err(e) => {
+            let message = format!("无法从 {} 个样本训练字典: {}; 分卷不使用字典", sizes.len(), e);
+            if config.fail_on_warning {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+            eprintln!("警告: {}", message);
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(not(feature = "dictionary"))]
+pub(crate) fn train_inline_dictionary(_config: &Config) -> io::Result<Option<Vec<u8>>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "此构建未启用 dictionary feature, 不能训练字典"))
+}
+
+// 压缩一个分卷并检查压缩比, 与输出无关, 流水线中在压缩线程进行
+pub(crate) fn compress_chunk(chunk: &[u8], config: &Config, chunk_number: usize, profiler: &Profiler) -> io::Result<Vec<u8>> {
+    let span = profiler.span("compress");
+    if inject_failure(config, FailureStage::Compress, chunk_number) {
+        return Err(io::Error::other(format!("注入的故障: 压缩分卷 {} 时失败", chunk_number)));
+    }
+    let mut compressed = compress_shrinking(chunk, config, chunk_number)?;
+    drop(span);
+    check_ratio(chunk.len(), compressed.len(), config, chunk_number)?;
+    if let Some(dictionary) = config.dictionary.get().filter(|_| chunk_number == 1) {
+        compressed.splice(0..0, codec::dictionary_frame(dictionary));
+    }
+    if inject_failure(config, FailureStage::Truncate, chunk_number) {
+        compressed.truncate(compressed.len() / 2);
+    }
+    Ok(compressed)
+}
+
+// 分卷的编码器; 7z 归档按 --7z-codec 选择
+fn chunk_compressor(config: &Config) -> Box<dyn Compressor> {
+    match (config.format, config.seven_zip_codec) {
+        (Format::SevenZip, SevenZipCodec::Zstd) => codec::compressor(Format::Zstd, config.zstd),
+        (format, _) => codec::compressor(format, config.zstd),
+    }
+}
+
+// 压缩时内存不足(如超大分卷配合高压缩级别或多线程)时不中止运行: 分块大小减半, 之后的分卷按减半后的大小切分,
+// 这个分卷则按新的大小分段压缩成首尾相接的多个帧, 解压结果不变. 只用于可以直接拼接的格式
+fn compress_shrinking(chunk: &[u8], config: &Config, chunk_number: usize) -> io::Result<Vec<u8>> {
+    let compress = |data: &[u8]| match config.dictionary.get() {
+        Some(dictionary) => codec::ZstdDictionary { params: config.zstd, dictionary }.compress(data),
+        None => chunk_compressor(config).compress(data),
+    };
+    let first = if inject_failure(config, FailureStage::OutOfMemory, chunk_number) {
+        Err(io::Error::new(io::ErrorKind::OutOfMemory, format!("注入的故障: 压缩分卷 {} 时内存不足", chunk_number)))
+    } else {
+        compress(chunk)
+    };
+    let mut error = match first {
+        Err(e) if is_out_of_memory(&e) && config.format.concatenable() => e,
+        result => return result,
+    };
+    let mut size = config.chunk_limit.load(Ordering::Relaxed);
+    loop {
+        size = halve_chunk_limit(config, size, &format!("压缩分卷 {}", chunk_number), &error)?;
+        match chunk.chunks(size).map(compress).collect::<io::Result<Vec<_>>>() {
+            Ok(frames) => return Ok(frames.concat()),
+            Err(e) if is_out_of_memory(&e) => error = e,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// 内存不足时把分块大小从 size 减半, 之后的分卷按此切分; 减到下限以下时报错
+pub(crate) fn halve_chunk_limit(config: &Config, size: usize, action: &str, error: &dyn std::fmt::Display) -> io::Result<usize> {
+    let size = size / 2;
+    if size < MIN_SHRUNK_CHUNK_SIZE {
+        return Err(io::Error::new(io::ErrorKind::OutOfMemory, format!("{} 时内存不足, 分块大小减到 {} 字节以下仍然失败: {}", action, MIN_SHRUNK_CHUNK_SIZE, error)));
+    }
+    if config.chunk_limit.fetch_min(size, Ordering::Relaxed) > size {
+        eprintln!("警告: {} 时内存不足, 分块大小减半为 {} 字节, 之后的分卷按此切分", action, size);
+    }
+    Ok(size)
+}
+
+// libzstd 分配内存失败时返回 "Allocation error : not enough memory"
+fn is_out_of_memory(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::OutOfMemory || error.to_string().contains("Allocation error")
+}
+
+// 写出一个分卷前按配置过滤数据
+pub(crate) fn emit_chunk(chunk: &[u8], config: &Config, chunk_offset: usize, rejects: &mut Rejects, writer: &mut ChunkWriter, profiler: &Profiler) -> io::Result<Vec<PathBuf>> {
+    let header = copied_header_for(chunk, config, writer.next_number);
+    let footer = copied_footer_for(chunk, config, writer.next_number);
+    if config.drop_invalid || config.validate_json.is_some() {
+        let span = profiler.span("filter");
+        let kept = filter_lines(chunk, config, chunk_offset, rejects)?;
+        drop(span);
+        writer.write(&[header, &kept, footer].concat(), chunk_offset, profiler)
+    } else if !header.is_empty() || !footer.is_empty() {
+        writer.write(&[header, chunk, footer].concat(), chunk_offset, profiler)
+    } else {
+        writer.write(chunk, chunk_offset, profiler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_name;
+    use crate::frames::scan_frames;
+    use crate::merge::{merge_chunks, MergeOptions};
+    use crate::split::run_split;
+    use crate::testing::Scratch;
+
+    #[test]
+    fn chunk_size_is_halved_when_compression_runs_out_of_memory() {
+        let dir = Scratch::new("oom");
+        let input = dir.join("in.log");
+        let text: String = (0..1_200_000).map(|i| format!("{:09}\n", i)).collect();
+        std::fs::write(&input, &text).unwrap();
+        let prefix = dir.join("part").display().to_string();
+        let args = ["zstd_compressor", &input.display().to_string(), &prefix, "4", "--buffer-size", "1", "--pipeline-depth", "0", "--inject-failure", "oom:chunk=1", "--yes", "--porcelain"];
+        let config = Config::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+        run_split(&config).unwrap();
+        assert_eq!(config.chunk_limit.load(Ordering::Relaxed), 2 << 20);
+        // 出错的分卷在 4 MiB 之后的第一个换行处结束, 分段压缩成两个 2 MiB 的帧和剩余几个字节的帧;
+        // 之后的分卷按减半后的大小切分
+        let first = std::fs::read(chunk_name(&prefix, 1, "zst")).unwrap();
+        let frames = scan_frames(&mut io::Cursor::new(&first), first.len() as u64).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(zstd::decode_all(&first[..frames[0].len as usize]).unwrap().len(), 2 << 20);
+        let second = zstd::decode_all(File::open(chunk_name(&prefix, 2, "zst")).unwrap()).unwrap();
+        assert!(second.len() < 3 << 20, "{}", second.len());
+        let mut merged = Vec::new();
+        merge_chunks(&prefix, &mut merged, &MergeOptions::default()).unwrap();
+        assert_eq!(merged, text.as_bytes());
+    }
+}
@@ -0,0 +1,1532 @@
+// 切分模式的配置: 命令行参数按领域分别解析(输入, 切分, 输出, 运行), 之后统一检查选项之间的冲突.
+// 选项依次来自配置文件(--config FILE 或环境变量 ZSTD_COMPRESSOR_CONFIG)、环境变量 ZSTD_COMPRESSOR_OPTIONS
+// 与命令行, 按此顺序拼接后交给 Config::parse: 后出现的单值选项覆盖先出现的, 可重复的选项(--output, --field)累加.
+// Config::parse 收集所有问题后一次报告, 未知选项附上最接近的已知选项
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+use encoding_rs::{Encoding, UTF_8, GBK};
+use crate::checkpoint::ResumePoint;
+use crate::codec::find_tool;
+use crate::hash::parse_input_sha256;
+use crate::jobs::{parse_yaml_entry, push_option, strip_yaml_comment, unquote_yaml, JobValue};
+use crate::manifest::{new_run_id, quote_arg};
+use crate::naming::{parse_field, NameTemplate};
+use crate::partition::{TimeFormat, MAX_PARTITIONS};
+use crate::regex::Regex;
+use crate::source::{is_device, Destination, Source};
+use crate::units::{self, NumberFormat, UnitSystem};
+use crate::{boundary, ChunkLimit, Format, LongLinePolicy, NoBoundaryPolicy, ZstdParams};
+
+pub const DEFAULT_CHUNK_SIZE: usize = 100 * 1024 * 1024; // 100MB default
+
+pub(crate) const DEFAULT_LINE_ENDING: &str = "\n"; // 默认换行符
+
+const DEFAULT_PIPELINE_DEPTH: usize = 2; // 切分、压缩与写出线程之间积压的分卷数
+
+// 检查点间隔: 按时间或按分卷数
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum CheckpointInterval {
+    Every(Duration),
+    Chunks(usize),
+}
+
+// gzip 分卷成员头中的文件名: 分卷文件名去掉 .gz(与对分卷文件执行 gzip 相同), 输入文件名, 或不写
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum GzipName {
+    Chunk,
+    Input,
+    Omit,
+}
+
+// 7z 归档中各分卷的编码器: LZMA2(由系统中的 xz 编码, 任何 7-Zip 都能解压) 或 zstd(进程内编码,
+// 需要 7-Zip-zstd、NanaZip 或 libarchive 等支持 zstd 的解压工具)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SevenZipCodec {
+    Lzma2,
+    Zstd,
+}
+
+impl SevenZipCodec {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "lzma2" => Ok(SevenZipCodec::Lzma2),
+            "zstd" => Ok(SevenZipCodec::Zstd),
+            _ => Err(format!("无效的 7z 编码器: {}. 请使用 lzma2 或 zstd", value)),
+        }
+    }
+}
+
+// gzip 分卷成员头中的修改时间: 输入文件的修改时间, 写出时刻, 不写(0), 或给定的 Unix 秒数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum GzipMtime {
+    Input,
+    Now,
+    Omit,
+    At(u32),
+}
+
+impl GzipName {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "chunk" => Ok(GzipName::Chunk),
+            "input" => Ok(GzipName::Input),
+            "none" => Ok(GzipName::Omit),
+            _ => Err(format!("无效的 gzip 文件名来源: {}. 请使用 chunk, input 或 none", value)),
+        }
+    }
+}
+
+impl GzipMtime {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "input" => Ok(GzipMtime::Input),
+            "now" => Ok(GzipMtime::Now),
+            "none" => Ok(GzipMtime::Omit),
+            _ => value.parse().map(GzipMtime::At).map_err(|_| format!("无效的 gzip 修改时间: {}. 请使用 input, now, none 或 Unix 秒数", value)),
+        }
+    }
+}
+
+// --validate-json: 不是有效 JSON 的行只告警, 或移入 <output_prefix>.rejects
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum JsonCheck {
+    Report,
+    Quarantine,
+}
+
+impl JsonCheck {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "report" => Ok(JsonCheck::Report),
+            "quarantine" => Ok(JsonCheck::Quarantine),
+            _ => Err(format!("无效的 JSON 校验方式: {}. 请使用 report 或 quarantine", value)),
+        }
+    }
+}
+
+// --csv-header: 自动判断第一条记录是否为表头, 第一条记录就是表头, 或给出输入中没有的表头文本
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CsvHeader {
+    Auto,
+    First,
+    Text(String),
+}
+
+impl CsvHeader {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "auto" => Ok(CsvHeader::Auto),
+            "first" => Ok(CsvHeader::First),
+            _ => match value.strip_prefix("text:") {
+                Some(text) if !text.is_empty() => Ok(CsvHeader::Text(text.to_string())),
+                _ => Err(format!("无效的 CSV 表头: {}. 请使用 auto, first 或 text:<表头>", value)),
+            },
+        }
+    }
+
+    // 从这个编号起的分卷开头加上表头; 表头取自输入时第一个分卷本来就以它开头
+    pub(crate) fn first_added(&self) -> usize {
+        match self {
+            CsvHeader::Text(_) => 1,
+            _ => 2,
+        }
+    }
+}
+
+// --tee-plain: 压缩的同时写出未压缩的副本, 每个分卷一个文本文件放在目录中, 或按顺序拼成一个文件
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TeePlain {
+    Chunks(PathBuf),
+    Single(PathBuf),
+}
+
+impl TeePlain {
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        match value.strip_prefix("single:") {
+            Some(path) if !path.is_empty() => Ok(TeePlain::Single(PathBuf::from(path))),
+            None if !value.is_empty() => Ok(TeePlain::Chunks(PathBuf::from(value))),
+            _ => Err(format!("无效的未压缩副本位置: {}. 请使用 <DIR> 或 single:<FILE>", value)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub(crate) input_path: String,
+    pub(crate) output_prefix: String,
+    pub(crate) chunk_size: usize,
+    // 压缩或缓存分卷数据时内存不足后减半的分块大小, 之后切出的分卷按它切分
+    pub(crate) chunk_limit: ChunkLimit,
+    pub(crate) line_ending: String,
+    pub(crate) encoding: &'static Encoding,
+    pub(crate) drop_invalid: bool,
+    // 二进制输入: 按分块大小整块切分, 不检查字符编码, 不自动解压 gzip; 设备输入默认如此
+    pub(crate) binary: bool,
+    // 定长二进制记录的长度, 分块大小已取整为它的整数倍
+    pub(crate) record_bytes: Option<usize>,
+    // 最多读取的输入字节数, 以及读取之前跳过的字节数
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) skip_bytes: u64,
+    // 跳过开头的行数与最多读取的行数, 按解压后的数据计
+    pub(crate) skip_lines: u64,
+    pub(crate) max_lines: Option<u64>,
+    pub(crate) expect_ratio: Option<f64>,
+    pub(crate) ratio_tolerance: f64,
+    pub(crate) ratio_abort: bool,
+    pub(crate) checkpoint_interval: Option<CheckpointInterval>,
+    // 运行超过这么久时在分卷边界处停下, 记下进度后以 EXIT_TIMEOUT 退出
+    pub(crate) max_runtime: Option<Duration>,
+    // 从 <output_prefix>.state 记录的进度继续, 输入从记录的偏移读起(存入 skip_bytes)
+    pub(crate) resume: Option<ResumePoint>,
+    pub(crate) readahead: usize,
+    // 切分、压缩与写出之间通道的容量(以分卷计), 0 表示在主线程中依次进行
+    pub(crate) pipeline_depth: usize,
+    // 流水线中并行压缩分卷的线程数
+    pub(crate) jobs: usize,
+    // 边切分边压缩写出, 不在内存中缓存整个分卷
+    pub(crate) stream: bool,
+    // 本地文件按位置分段, 并行查找切分点、读取与压缩
+    pub(crate) parallel_split: bool,
+    pub(crate) gzip_name: GzipName,
+    pub(crate) seven_zip_codec: SevenZipCodec,
+    pub(crate) gzip_mtime: GzipMtime,
+    // 从输入采样训练 zstd 字典, 内嵌在第一个分卷开头; 训练出的字典在切分开始前存入 dictionary
+    pub(crate) inline_dictionary: bool,
+    pub(crate) dictionary: OnceLock<Vec<u8>>,
+    pub(crate) buffer_size: Option<usize>,
+    // 分卷大小上限, 设置后 chunk_size 作为目标大小
+    pub(crate) max_size: Option<usize>,
+    // 单条记录超过上限仍未结束时 grow, error 或 split; 上限默认为分块大小, 有 max_size 时即为 max_size
+    pub(crate) long_line_policy: LongLinePolicy,
+    pub(crate) long_line_cap: Option<usize>,
+    // 读入 NO_BOUNDARY_CHUNKS 倍分块大小仍找不到换行符时 warn, fallback-binary 或 error
+    pub(crate) no_boundary: NoBoundaryPolicy,
+    // 每个分卷正好包含的记录数, 设置后不再按分块大小切分
+    pub(crate) lines: Option<usize>,
+    // 按输入文件的大小均分为这么多个分卷, 设置后不再按分块大小切分
+    pub(crate) parts: Option<usize>,
+    // 按内容定义的切分点(FastCDC)切分, 分卷平均约为分块大小
+    pub(crate) cdc: bool,
+    // 按第几列(从 1 开始)的值把记录分到各自的分区, 列以 field_delimiter 分隔; 有 shards 时按这一列的哈希分片
+    pub(crate) partition_by: Option<usize>,
+    pub(crate) shards: Option<usize>,
+    // 把记录依次轮流分给这么多个分区, 各分区的记录数至多相差 1
+    pub(crate) round_robin: Option<usize>,
+    // 按第 time_field 列时间戳所在的时间窗口分区, 窗口由格式中的字段决定, 如 %Y-%m-%dT%H 为每小时一个
+    pub(crate) split_by_time: Option<TimeFormat>,
+    pub(crate) time_field: usize,
+    pub(crate) field_delimiter: char,
+    pub(crate) balance_compressed: bool,
+    pub(crate) single_output: Option<PathBuf>,
+    // 分卷依次轮流写到这些目录, 目录记录在 manifest 中
+    pub(crate) output_dirs: Vec<PathBuf>,
+    pub(crate) format: Format,
+    pub(crate) mirrors: Vec<Destination>,
+    pub(crate) align_gz_members: bool,
+    pub(crate) input_sha256: Option<[u8; 32]>,
+    // 在 manifest 中记录每个分卷原始内容的 SHA-256, name_by_hash 时还以它命名分卷文件
+    pub(crate) content_addressed: bool,
+    pub(crate) name_by_hash: bool,
+    // 计算各分卷原始内容的 SHA-256, 记入 JSON 清单供合并时校验; --no-chunk-sha256 时关闭
+    pub(crate) chunk_sha256: bool,
+    // 分卷文件名模板, 设置后文件名与编号的对应记录在 manifest 中
+    pub(crate) name_template: Option<NameTemplate>,
+    // 在 manifest 中记录每个分卷各行哈希的 Merkle 根
+    pub(crate) line_merkle: bool,
+    pub(crate) journal: bool,
+    pub(crate) profile_out: Option<PathBuf>,
+    // 读取线程与压缩线程的 nice 值, 未设置时沿用进程的优先级
+    pub(crate) read_nice: Option<i32>,
+    pub(crate) compress_nice: Option<i32>,
+    pub(crate) porcelain: bool,
+    // 报告中大小、耗时与计数的格式
+    pub(crate) numbers: NumberFormat,
+    // 把告警升级为失败
+    pub(crate) fail_on_warning: bool,
+    // 不询问直接覆盖已有的分卷
+    pub(crate) yes: bool,
+    pub(crate) zstd: ZstdParams,
+    // 按其他格式的记录切分(csv, fixed:N, regex:PATTERN), 未设置时按换行符
+    pub(crate) records: Option<String>,
+    // 在每个分卷开头复制 CSV 表头; 表头在切出第一个分卷时确定并存入 header, 没有表头时为空
+    pub(crate) csv_header: Option<CsvHeader>,
+    // 按 XML 元素切分时在分卷开头复制第一条记录之前的部分(存入 header), 以记录结束的分卷结尾补上根元素的
+    // 结束标签 footer, 补过的分卷编号记在 footed 中, 各分卷都是完整的 XML 文档
+    pub(crate) xml_wrap: bool,
+    pub(crate) header: OnceLock<Vec<u8>>,
+    pub(crate) footer: OnceLock<Vec<u8>>,
+    pub(crate) footed: Mutex<HashSet<usize>>,
+    // 按换行符切分时跳过这个引号字符之间的换行符
+    pub(crate) quote_char: Option<char>,
+    // 检查 JSON Lines 的每一行是否为有效的 JSON
+    pub(crate) validate_json: Option<JsonCheck>,
+    pub(crate) tee_plain: Option<TeePlain>,
+    // 隐藏选项 --inject-failure 指定的故障注入点
+    inject_failures: Vec<InjectedFailure>,
+    // 每次运行的唯一 ID 与原始命令行参数, 随来历信息记录在 manifest 中
+    pub(crate) run_id: String,
+    pub(crate) settings: String,
+}
+
+// 读取输入的选项: 读取的范围, 二进制与定长记录, 换行符与编码, 读取缓冲
+#[derive(Default)]
+struct InputOptions {
+    drop_invalid: bool,
+    binary: bool,
+    record_bytes: Option<usize>,
+    max_bytes: Option<u64>,
+    skip_bytes: u64,
+    skip_lines: u64,
+    max_lines: Option<u64>,
+    line_ending: Option<String>,
+    encoding: Option<String>,
+    input_sha256: Option<String>,
+    readahead: usize,
+    buffer_size: Option<usize>,
+}
+
+impl InputOptions {
+    fn parse_flag(&mut self, flag: &str, value: impl FnOnce() -> Result<String, String>) -> Result<bool, String> {
+        match flag {
+            "drop-invalid" => self.drop_invalid = true,
+            "binary" => self.binary = true,
+            "record-bytes" => self.record_bytes = Some(parse_byte_count(&value()?)? as usize),
+            "max-bytes" => self.max_bytes = Some(parse_byte_count(&value()?)?),
+            "skip-bytes" => self.skip_bytes = parse_byte_count(&value()?)?,
+            "skip-lines" => self.skip_lines = value()?.parse().map_err(|_| "无效的跳过行数")?,
+            "max-lines" => {
+                let value = value()?;
+                self.max_lines = Some(value.parse().ok().filter(|&n: &u64| n > 0).ok_or_else(|| format!("无效的最多读取行数: {}. 请使用正整数", value))?);
+            }
+            "line-ending" => self.line_ending = Some(value()?),
+            "encoding" => self.encoding = Some(value()?),
+            "input-sha256" => self.input_sha256 = Some(value()?),
+            "readahead" => self.readahead = value()?.parse().map_err(|_| "无效的预读深度")?,
+            "buffer-size" => {
+                let value = value()?;
+                if !value.eq_ignore_ascii_case("auto") {
+                    let mb = value.parse::<usize>().map_err(|_| "无效的缓冲区大小")?;
+                    if mb == 0 {
+                        return Err("缓冲区大小必须大于 0".to_string());
+                    }
+                    self.buffer_size = Some(mb * 1024 * 1024);
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // 按行截取输入
+    fn line_window(&self) -> bool {
+        self.skip_lines > 0 || self.max_lines.is_some()
+    }
+}
+
+// 记录格式的选项: 按换行符以外的记录切分, 复制的表头与 XML 根元素, 引号, JSON Lines 校验
+#[derive(Default)]
+struct RecordOptions {
+    records: Option<String>,
+    csv_header: Option<CsvHeader>,
+    xml_wrap: bool,
+    quote_char: Option<char>,
+    validate_json: Option<JsonCheck>,
+}
+
+impl RecordOptions {
+    fn parse_flag(&mut self, flag: &str, value: impl FnOnce() -> Result<String, String>) -> Result<bool, String> {
+        match flag {
+            "records" => self.records = Some(value()?),
+            "record-sep-regex" => self.records = Some(format!("regex:{}", value()?)),
+            "csv-header" => self.csv_header = Some(CsvHeader::parse(&value()?)?),
+            "xml-wrap" => self.xml_wrap = true,
+            "validate-json" => self.validate_json = Some(JsonCheck::parse(&value()?)?),
+            "quote-char" => {
+                let value = value()?;
+                let mut chars = value.chars();
+                self.quote_char = match (chars.next(), chars.next()) {
+                    (Some(quote), None) => Some(quote),
+                    _ => return Err(format!("无效的引号字符: {}. 请给出单个字符", value)),
+                };
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+// 决定切分点的选项: 目标大小与上限, 长记录, 按记录数、分卷数或内容切分, 以及分区
+#[derive(Default)]
+struct ChunkingOptions {
+    target_size: Option<usize>,
+    hard_limit: bool,
+    max_size: Option<usize>,
+    long_line_policy: Option<LongLinePolicy>,
+    long_line_cap: Option<usize>,
+    no_boundary: Option<NoBoundaryPolicy>,
+    lines: Option<usize>,
+    parts: Option<usize>,
+    cdc: bool,
+    balance_compressed: bool,
+    partition_by: Option<usize>,
+    shard_by_key: Option<usize>,
+    shards: Option<usize>,
+    round_robin: Option<usize>,
+    split_by_time: Option<TimeFormat>,
+    time_field: Option<usize>,
+    field_delimiter: Option<char>,
+}
+
+impl ChunkingOptions {
+    fn parse_flag(&mut self, flag: &str, value: impl FnOnce() -> Result<String, String>) -> Result<bool, String> {
+        match flag {
+            "hard-limit" => self.hard_limit = true,
+            "target-size" | "chunk-size" => self.target_size = Some(parse_size_mb(&value()?, "目标大小")?),
+            "max-size" => self.max_size = Some(parse_size_mb(&value()?, "大小上限")?),
+            "long-line-policy" => self.long_line_policy = Some(LongLinePolicy::parse(&value()?)?),
+            "long-line-cap" => self.long_line_cap = Some(parse_size_mb(&value()?, "长记录上限")?),
+            "no-boundary" => self.no_boundary = Some(NoBoundaryPolicy::parse(&value()?)?),
+            "lines" => {
+                let value = value()?;
+                self.lines = Some(value.parse().ok().filter(|&n: &usize| n > 0).ok_or_else(|| format!("无效的每卷行数: {}. 请使用正整数", value))?);
+            }
+            "parts" => {
+                let value = value()?;
+                self.parts = Some(value.parse().ok().filter(|&n: &usize| n > 0).ok_or_else(|| format!("无效的分卷数: {}. 请使用正整数", value))?);
+            }
+            "cdc" => self.cdc = true,
+            "balance-compressed" => self.balance_compressed = true,
+            "partition-by" => {
+                let value = value()?;
+                self.partition_by = Some(value.parse().ok().filter(|&n: &usize| n > 0).ok_or_else(|| format!("无效的分区列: {}. 请使用从 1 开始的列号", value))?);
+            }
+            "shard-by-key" => {
+                let value = value()?;
+                self.shard_by_key = Some(value.parse().ok().filter(|&n: &usize| n > 0).ok_or_else(|| format!("无效的分片列: {}. 请使用从 1 开始的列号", value))?);
+            }
+            "shards" => {
+                let value = value()?;
+                self.shards = Some(
+                    value.parse().ok().filter(|n: &usize| (1..=MAX_PARTITIONS).contains(n)).ok_or_else(|| format!("无效的分片数: {}. 请使用 1 到 {} 之间的整数", value, MAX_PARTITIONS))?,
+                );
+            }
+            "round-robin" => {
+                let value = value()?;
+                self.round_robin = Some(
+                    value.parse().ok().filter(|n: &usize| (1..=MAX_PARTITIONS).contains(n)).ok_or_else(|| format!("无效的轮流分区数: {}. 请使用 1 到 {} 之间的整数", value, MAX_PARTITIONS))?,
+                );
+            }
+            "split-by-time" => self.split_by_time = Some(TimeFormat::parse(&value()?)?),
+            "time-field" => {
+                let value = value()?;
+                self.time_field = Some(value.parse().ok().filter(|&n: &usize| n > 0).ok_or_else(|| format!("无效的时间戳列: {}. 请使用从 1 开始的列号", value))?);
+            }
+            "delimiter" => self.field_delimiter = Some(parse_field_delimiter(&value()?)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn long_lines_set(&self) -> bool {
+        self.long_line_policy.is_some() || self.long_line_cap.is_some()
+    }
+}
+
+// 写出分卷的选项: 格式与输出位置, gzip 与 7z 的细节, 内嵌字典, 分卷命名与清单中的记录
+#[derive(Default)]
+struct OutputOptions {
+    format: Option<Format>,
+    single_output: Option<PathBuf>,
+    output_dirs: Vec<PathBuf>,
+    mirrors: Vec<Destination>,
+    align_gz_members: bool,
+    gzip_name: Option<GzipName>,
+    gzip_mtime: Option<GzipMtime>,
+    seven_zip_codec: Option<SevenZipCodec>,
+    inline_dictionary: bool,
+    content_addressed: bool,
+    name_by_hash: bool,
+    no_chunk_sha256: bool,
+    name_template: Option<String>,
+    fields: Vec<(String, Regex)>,
+    line_merkle: bool,
+    journal: bool,
+    tee_plain: Option<TeePlain>,
+}
+
+impl OutputOptions {
+    fn parse_flag(&mut self, flag: &str, value: impl FnOnce() -> Result<String, String>) -> Result<bool, String> {
+        match flag {
+            "format" => self.format = Some(Format::parse(&value()?)?),
+            "single-output" => self.single_output = Some(PathBuf::from(value()?)),
+            "output-dirs" => {
+                let value = value()?;
+                self.output_dirs = value.split(',').filter(|dir| !dir.is_empty()).map(PathBuf::from).collect();
+                if self.output_dirs.is_empty() {
+                    return Err(format!("无效的输出目录列表: {}. 请用逗号分隔多个目录", value));
+                }
+            }
+            "output" => self.mirrors.push(Destination::parse(&value()?)?),
+            "align-gz-members" => self.align_gz_members = true,
+            "gzip-name" => self.gzip_name = Some(GzipName::parse(&value()?)?),
+            "gzip-mtime" => self.gzip_mtime = Some(GzipMtime::parse(&value()?)?),
+            "7z-codec" => self.seven_zip_codec = Some(SevenZipCodec::parse(&value()?)?),
+            "inline-dictionary" => {
+                if !cfg!(feature = "dictionary") {
+                    return Err("此构建未启用 dictionary feature, 不能训练字典".to_string());
+                }
+                self.inline_dictionary = true;
+            }
+            "content-addressed" => self.content_addressed = true,
+            "name-by-hash" => self.name_by_hash = true,
+            "no-chunk-sha256" => self.no_chunk_sha256 = true,
+            "name-template" => self.name_template = Some(value()?),
+            "field" => self.fields.push(parse_field(&value()?)?),
+            "line-merkle" => self.line_merkle = true,
+            "journal" => self.journal = true,
+            "tee-plain" => self.tee_plain = Some(TeePlain::parse(&value()?)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn format(&self) -> Format {
+        self.format.unwrap_or(Format::Zstd)
+    }
+
+    // 所有分卷写进一个文件: --single-output 或 7z 归档
+    fn single_file(&self) -> bool {
+        self.single_output.is_some() || self.format() == Format::SevenZip
+    }
+}
+
+// 运行方式的选项: 压缩比检查, 检查点与续跑, 流水线与线程, 报告与确认
+struct RunOptions {
+    expect_ratio: Option<f64>,
+    ratio_tolerance: f64,
+    ratio_abort: bool,
+    checkpoint_interval: Option<CheckpointInterval>,
+    max_runtime: Option<Duration>,
+    resume: bool,
+    pipeline_depth: usize,
+    jobs: usize,
+    stream: bool,
+    parallel_split: bool,
+    profile_out: Option<PathBuf>,
+    read_nice: Option<i32>,
+    compress_nice: Option<i32>,
+    porcelain: bool,
+    unit_system: UnitSystem,
+    locale: Option<String>,
+    fail_on_warning: bool,
+    yes: bool,
+    inject_failures: Vec<InjectedFailure>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            expect_ratio: None,
+            ratio_tolerance: 0.5,
+            ratio_abort: false,
+            checkpoint_interval: None,
+            max_runtime: None,
+            resume: false,
+            pipeline_depth: DEFAULT_PIPELINE_DEPTH,
+            jobs: 1,
+            stream: false,
+            parallel_split: false,
+            profile_out: None,
+            read_nice: None,
+            compress_nice: None,
+            porcelain: false,
+            unit_system: UnitSystem::Binary,
+            locale: None,
+            fail_on_warning: false,
+            yes: false,
+            inject_failures: Vec::new(),
+        }
+    }
+}
+
+impl RunOptions {
+    fn parse_flag(&mut self, flag: &str, value: impl FnOnce() -> Result<String, String>) -> Result<bool, String> {
+        match flag {
+            "expect-ratio" => self.expect_ratio = Some(parse_ratio(&value()?)?),
+            "ratio-tolerance" => self.ratio_tolerance = parse_percent(&value()?)?,
+            "ratio-policy" => {
+                self.ratio_abort = match value()?.to_lowercase().as_str() {
+                    "warn" => false,
+                    "abort" => true,
+                    _ => return Err("无效的压缩比策略. 请使用 warn 或 abort".to_string()),
+                }
+            }
+            "checkpoint-interval" => self.checkpoint_interval = Some(parse_checkpoint_interval(&value()?)?),
+            "max-runtime" => self.max_runtime = Some(parse_runtime(&value()?)?),
+            "resume" => self.resume = true,
+            "pipeline-depth" => self.pipeline_depth = value()?.parse().map_err(|_| "无效的流水线深度")?,
+            "jobs" => {
+                let value = value()?;
+                self.jobs = if value.eq_ignore_ascii_case("auto") {
+                    thread::available_parallelism().map_or(1, |n| n.get())
+                } else {
+                    value.parse().ok().filter(|&n| n > 0).ok_or_else(|| format!("无效的压缩线程数: {}. 请使用正整数或 auto", value))?
+                };
+            }
+            "stream" => self.stream = true,
+            "parallel-split" => self.parallel_split = true,
+            "profile-out" => self.profile_out = Some(PathBuf::from(value()?)),
+            "priority" => {
+                for (stage, nice) in parse_priorities(&value()?)? {
+                    match stage {
+                        "read" => self.read_nice = Some(nice),
+                        _ => self.compress_nice = Some(nice),
+                    }
+                }
+            }
+            "porcelain" => self.porcelain = true,
+            "units" => self.unit_system = UnitSystem::parse(&value()?)?,
+            "locale" => self.locale = Some(value()?),
+            "fail-on-warning" => self.fail_on_warning = true,
+            "yes" => self.yes = true,
+            "inject-failure" => self.inject_failures.push(InjectedFailure::parse(&value()?)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+// 命令行中的全部切分选项, 按领域分组; 检查选项之间的冲突时已换成派生后的值
+// (定长记录与设备输入即二进制输入, --hard-limit 即大小上限, --name-by-hash 即按内容寻址, 以及默认的记录格式)
+#[derive(Default)]
+struct Options {
+    input: InputOptions,
+    records: RecordOptions,
+    chunking: ChunkingOptions,
+    output: OutputOptions,
+    run: RunOptions,
+    zstd: ZstdParams,
+}
+
+impl Options {
+    // 依次交给各领域解析, 都不认识时返回 false
+    fn parse_flag(&mut self, flag: &str, mut value: impl FnMut() -> Result<String, String>) -> Result<bool, String> {
+        Ok(self.input.parse_flag(flag, &mut value)?
+            || self.records.parse_flag(flag, &mut value)?
+            || self.chunking.parse_flag(flag, &mut value)?
+            || self.output.parse_flag(flag, &mut value)?
+            || self.run.parse_flag(flag, &mut value)?
+            || self.zstd.parse_flag(flag, &mut value)?)
+    }
+
+    // 只支持本地输入文件的选项: 需要采样、按位置读取或输入的大小
+    fn local_input(input_path: &str) -> bool {
+        matches!(Source::parse(input_path), Source::Local(_)) && !is_device(input_path)
+    }
+
+    fn check_input(&self, input_path: &str, chunk_size: usize, problems: &mut Vec<String>) {
+        let (input, records, output) = (&self.input, &self.records, &self.output);
+        if let Some(n) = input.record_bytes {
+            if records.records.is_some() {
+                problems.push("--record-bytes 不能与 --records 同时使用".to_string());
+            }
+            // 从记录中间开始或结束会使之后的每个分卷都错位
+            for (bytes, option) in [(input.skip_bytes, "--skip-bytes"), (input.max_bytes.unwrap_or(0), "--max-bytes")] {
+                if bytes % n as u64 != 0 {
+                    problems.push(format!("{} 需要是记录长度 {} 的整数倍", option, n));
+                }
+            }
+        }
+        if self.chunking.balance_compressed && !Self::local_input(input_path) {
+            problems.push("--balance-compressed 需要采样, 只支持本地输入文件".to_string());
+        }
+        if input.binary && (input.drop_invalid || output.line_merkle || output.align_gz_members || input.line_window()) {
+            problems.push("二进制输入不按行处理, 不能与 --drop-invalid, --line-merkle, --align-gz-members, --skip-lines 或 --max-lines 同时使用".to_string());
+        }
+        if output.align_gz_members && (input.skip_bytes > 0 || input.line_window()) {
+            problems.push("--align-gz-members 需要从头读取完整的 gzip 成员, 不能与 --skip-bytes, --skip-lines 或 --max-lines 同时使用".to_string());
+        }
+        if self.chunking.max_size.is_some_and(|max| max < chunk_size) {
+            problems.push("大小上限不能小于目标大小".to_string());
+        }
+    }
+
+    // 从记录的输入偏移读起, 之前的输入不再经过这次运行; 依赖第一个分卷或整个输入的选项无法接续
+    fn check_resume(&self, input_path: &str, problems: &mut Vec<String>) {
+        let (input, records, chunking, output, run) = (&self.input, &self.records, &self.chunking, &self.output, &self.run);
+        if run.resume {
+            let unsupported = [
+                (input.skip_bytes > 0 || input.line_window(), "--skip-bytes, --skip-lines 与 --max-lines"),
+                (input.max_bytes.is_some(), "--max-bytes"),
+                (input.input_sha256.is_some(), "--input-sha256"),
+                (chunking.parts.is_some() || chunking.balance_compressed, "--parts 与 --balance-compressed"),
+                (records.csv_header.is_some() || records.xml_wrap, "--csv-header 与 --xml-wrap"),
+                (output.inline_dictionary, "--inline-dictionary"),
+                (output.tee_plain.is_some(), "--tee-plain"),
+                (output.single_file(), "--single-output 与 --format 7z"),
+                (run.parallel_split, "--parallel-split"),
+            ];
+            for (_, option) in unsupported.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("--resume 从上次停下的位置继续, 不能与 {} 同时使用", option));
+            }
+            // 偏移按解压前的输入计, 自动解压的 gzip 输入无法从中间读起
+            let mut magic = [0u8; 2];
+            let sniffed = matches!(Source::parse(input_path), Source::Local(_)) && File::open(input_path).and_then(|mut file| file.read_exact(&mut magic)).is_ok();
+            if !input.binary && sniffed && magic == [0x1F, 0x8B] {
+                problems.push("--resume 按输入偏移继续, 不支持自动解压的 gzip 输入".to_string());
+            }
+        }
+        if run.max_runtime.is_some() && run.parallel_split {
+            problems.push("--max-runtime 需要按顺序记录进度, 不能与 --parallel-split 同时使用".to_string());
+        }
+    }
+
+    // 记录格式之间, 以及与按行处理的选项之间的冲突; 在补上默认的记录格式之前检查
+    fn check_records(&self, problems: &mut Vec<String>) {
+        let (input, records, run, output) = (&self.input, &self.records, &self.run, &self.output);
+        if records.csv_header.is_some() {
+            if records.records.as_deref().is_some_and(|records| !records.eq_ignore_ascii_case("csv")) {
+                problems.push("--csv-header 只用于 CSV 记录, 不能与其他 --records 同时使用".to_string());
+            }
+            // 合并时按 manifest 中每个分卷的 header_bytes 去掉复制的表头
+            let unsupported = [
+                (input.binary, "--binary"),
+                (run.stream, "--stream"),
+                (run.parallel_split, "--parallel-split"),
+                (output.single_file(), "--single-output 与 --format 7z"),
+            ];
+            for (_, option) in unsupported.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("--csv-header 需要在 manifest 中记录复制的表头, 不能与 {} 同时使用", option));
+            }
+        }
+        if records.xml_wrap {
+            if !records.records.as_deref().is_some_and(|records| records.starts_with("xml:")) {
+                problems.push("--xml-wrap 需要 --records xml:<元素名>".to_string());
+            }
+            // 合并时按 manifest 中每个分卷的 header_bytes 与 footer_bytes 去掉复制的首尾
+            let unsupported = [
+                (run.stream, "--stream"),
+                (run.parallel_split, "--parallel-split"),
+                (output.single_file(), "--single-output 与 --format 7z"),
+            ];
+            for (_, option) in unsupported.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("--xml-wrap 需要在 manifest 中记录复制的首尾, 不能与 {} 同时使用", option));
+            }
+        }
+        if records.quote_char.is_some() {
+            // 引号只改变换行符的查找, 其他记录格式与按行处理的选项会把带引号的多行字段拆开
+            let line_based = [
+                (input.binary, "--binary"),
+                (records.records.is_some(), "--records"),
+                (records.csv_header.is_some(), "--csv-header"),
+                (run.parallel_split, "--parallel-split"),
+                (output.align_gz_members, "--align-gz-members"),
+                (input.drop_invalid, "--drop-invalid"),
+                (input.line_window(), "--skip-lines 与 --max-lines"),
+            ];
+            for (_, option) in line_based.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("--quote-char 不能与 {} 同时使用", option));
+            }
+        }
+        if let Some(check) = records.validate_json {
+            if records.records.as_deref().is_some_and(|records| !records.eq_ignore_ascii_case("jsonl")) {
+                problems.push("--validate-json 只用于 JSON Lines, 不能与其他 --records 同时使用".to_string());
+            }
+            // 校验在分卷写出之前逐行进行, 不经过这一步的模式无法校验
+            let unchecked = [
+                (input.binary, "--binary"),
+                (run.stream, "--stream"),
+                (run.parallel_split, "--parallel-split"),
+                (input.drop_invalid, "--drop-invalid"),
+                (records.csv_header.is_some(), "--csv-header"),
+                (records.quote_char.is_some(), "--quote-char"),
+                (check == JsonCheck::Quarantine && self.chunking.lines.is_some(), "--lines"),
+            ];
+            for (_, option) in unchecked.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("--validate-json 不能与 {} 同时使用", option));
+            }
+        }
+    }
+
+    // 挑选切分点的方式之间的冲突: 大小上限, 长记录, 按记录数、内容或分卷数切分, 分区
+    fn check_chunking(&self, input_path: &str, problems: &mut Vec<String>) {
+        let (input, records, chunking, output, run) = (&self.input, &self.records, &self.chunking, &self.output, &self.run);
+        let binary = input.binary;
+        if output.align_gz_members && (chunking.max_size.is_some() || chunking.balance_compressed) {
+            problems.push("--align-gz-members 只在成员边界切分, 不能与 --max-size, --hard-limit 或 --balance-compressed 同时使用".to_string());
+        }
+        if chunking.max_size.is_some() && !binary && (chunking.long_line_policy == Some(LongLinePolicy::Grow) || chunking.long_line_cap.is_some()) {
+            problems.push("有 --max-size 或 --hard-limit 时长记录以大小上限为限, 不能与 --long-line-policy grow 或 --long-line-cap 同时使用".to_string());
+        }
+        if chunking.long_lines_set() && (output.align_gz_members || (chunking.balance_compressed && chunking.max_size.is_none())) {
+            problems.push("--long-line-policy 与 --long-line-cap 不能与 --align-gz-members 或不带 --max-size 的 --balance-compressed 同时使用".to_string());
+        }
+        if chunking.no_boundary.is_some() {
+            // 成员边界与并行查找不经过 Chunker 的换行符查找; 按二进制切分会把行数与按行过滤打乱
+            let fallback = chunking.no_boundary == Some(NoBoundaryPolicy::FallbackBinary);
+            let unsupported = [
+                (output.align_gz_members, "--align-gz-members"),
+                (run.parallel_split, "--parallel-split"),
+                (fallback && chunking.lines.is_some(), "--lines"),
+                (fallback && chunking.cdc, "--cdc"),
+                (fallback && input.drop_invalid, "--drop-invalid"),
+            ];
+            let name = if fallback { "--no-boundary fallback-binary" } else { "--no-boundary" };
+            for (_, option) in unsupported.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("{} 不能与 {} 同时使用", name, option));
+            }
+        }
+        if chunking.lines.is_some() {
+            // 按记录数切分时分卷大小不定, 与按大小挑选切分点的选项冲突
+            let sized = [
+                (chunking.target_size.is_some(), "--chunk-size 与 --target-size"),
+                (binary, "--binary"),
+                (chunking.max_size.is_some() && !binary, "--max-size 与 --hard-limit"),
+                (chunking.balance_compressed, "--balance-compressed"),
+                (output.align_gz_members, "--align-gz-members"),
+                (chunking.long_lines_set(), "--long-line-policy 与 --long-line-cap"),
+                (run.stream, "--stream"),
+                (run.parallel_split, "--parallel-split"),
+                (input.drop_invalid, "--drop-invalid"),
+            ];
+            for (_, option) in sized.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("--lines 按记录数切分, 不能与 {} 同时使用", option));
+            }
+        }
+        if chunking.cdc {
+            // 切分点由内容决定, 与按大小或记录数挑选切分点的选项冲突; 二进制输入按字节切分, 不受整块切分的上限约束
+            let sized = [
+                (chunking.hard_limit || (chunking.max_size.is_some() && !binary), "--max-size 与 --hard-limit"),
+                (chunking.lines.is_some(), "--lines"),
+                (chunking.parts.is_some(), "--parts"),
+                (chunking.balance_compressed, "--balance-compressed"),
+                (output.align_gz_members, "--align-gz-members"),
+                (chunking.long_lines_set(), "--long-line-policy 与 --long-line-cap"),
+                (run.stream, "--stream"),
+                (run.parallel_split, "--parallel-split"),
+                (input.drop_invalid, "--drop-invalid"),
+            ];
+            for (_, option) in sized.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("--cdc 按内容切分, 不能与 {} 同时使用", option));
+            }
+        }
+        if chunking.shard_by_key.is_some() != chunking.shards.is_some() {
+            problems.push("--shard-by-key 与 --shards 需要一起使用".to_string());
+        }
+        let modes = [
+            (chunking.partition_by.is_some(), "--partition-by"),
+            (chunking.shard_by_key.is_some(), "--shard-by-key"),
+            (chunking.round_robin.is_some(), "--round-robin"),
+            (chunking.split_by_time.is_some(), "--split-by-time"),
+        ];
+        let modes: Vec<&str> = modes.iter().filter(|(set, _)| *set).map(|(_, name)| *name).collect();
+        if modes.len() > 1 {
+            problems.push(format!("{} 不能同时使用", modes.join(", ")));
+        }
+        if chunking.field_delimiter.is_some() && chunking.partition_by.is_none() && chunking.shard_by_key.is_none() && chunking.split_by_time.is_none() {
+            problems.push("--delimiter 只用于 --partition-by, --shard-by-key 与 --split-by-time".to_string());
+        }
+        if chunking.time_field.is_some() && chunking.split_by_time.is_none() {
+            problems.push("--time-field 只用于 --split-by-time".to_string());
+        }
+        if let Some(name) = modes.first() {
+            // 分区各自缓冲记录, 直接写出编号分卷, 不经过 manifest、日志与检查点
+            let unsupported = [
+                (binary, "--binary"),
+                (run.stream, "--stream"),
+                (run.parallel_split, "--parallel-split"),
+                (run.jobs > 1, "--jobs"),
+                (chunking.lines.is_some(), "--lines"),
+                (chunking.parts.is_some(), "--parts"),
+                (chunking.cdc, "--cdc"),
+                (chunking.max_size.is_some(), "--max-size 与 --hard-limit"),
+                (chunking.balance_compressed, "--balance-compressed"),
+                (output.align_gz_members, "--align-gz-members"),
+                (records.records.is_some(), "--records"),
+                (records.quote_char.is_some(), "--quote-char"),
+                (records.csv_header.is_some(), "--csv-header"),
+                (records.validate_json.is_some(), "--validate-json"),
+                (output.single_file(), "--single-output 与 --format 7z"),
+                (!output.mirrors.is_empty(), "--output"),
+                (!output.output_dirs.is_empty(), "--output-dirs"),
+                (output.content_addressed, "--content-addressed 与 --name-by-hash"),
+                (output.name_template.is_some(), "--name-template"),
+                (output.line_merkle, "--line-merkle"),
+                (run.checkpoint_interval.is_some(), "--checkpoint-interval"),
+                (run.max_runtime.is_some() || run.resume, "--max-runtime 与 --resume"),
+                (output.inline_dictionary, "--inline-dictionary"),
+                (output.tee_plain.is_some(), "--tee-plain"),
+            ];
+            for (_, option) in unsupported.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("{} 不能与 {} 同时使用", name, option));
+            }
+        }
+        if chunking.parts.is_some() {
+            let sized = [
+                (binary, "--binary"),
+                (chunking.max_size.is_some() && !binary, "--max-size 与 --hard-limit"),
+                (chunking.balance_compressed, "--balance-compressed"),
+                (output.align_gz_members, "--align-gz-members"),
+                (chunking.long_lines_set(), "--long-line-policy 与 --long-line-cap"),
+                (run.stream, "--stream"),
+                (run.parallel_split, "--parallel-split"),
+                (chunking.lines.is_some(), "--lines"),
+                (input.line_window(), "--skip-lines 与 --max-lines"),
+            ];
+            for (_, option) in sized.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("--parts 按输入大小均分, 不能与 {} 同时使用", option));
+            }
+            if !Self::local_input(input_path) {
+                problems.push("--parts 需要输入的大小, 只支持本地输入文件".to_string());
+            }
+        }
+    }
+
+    // 输出位置、分卷命名与格式细节之间的冲突
+    fn check_output(&self, input_path: &str, problems: &mut Vec<String>) {
+        let output = &self.output;
+        let format = output.format();
+        if output.single_output.is_some() && format == Format::SevenZip {
+            problems.push("--format 7z 本身就输出单个归档文件, 不能与 --single-output 同时使用".to_string());
+        }
+        if !output.mirrors.is_empty() && output.single_file() {
+            problems.push("--output 镜像只支持逐个分卷输出".to_string());
+        }
+        if !output.output_dirs.is_empty() && output.single_file() {
+            problems.push("--output-dirs 只支持逐个分卷输出".to_string());
+        }
+        if output.content_addressed && output.single_file() {
+            problems.push("--content-addressed 与 --name-by-hash 只支持逐个分卷输出".to_string());
+        }
+        if output.content_addressed && output.no_chunk_sha256 {
+            problems.push("--no-chunk-sha256 不能与 --content-addressed 或 --name-by-hash 同时使用".to_string());
+        }
+        if !output.fields.is_empty() && output.name_template.is_none() {
+            problems.push("--field 只在 --name-template 中使用".to_string());
+        }
+        if output.name_template.is_some() && (output.name_by_hash || output.single_file()) {
+            problems.push("--name-template 只支持逐个分卷输出, 不能与 --name-by-hash 同时使用".to_string());
+        }
+        if output.line_merkle && output.single_file() {
+            problems.push("--line-merkle 记录在 manifest 中, 只支持逐个分卷输出".to_string());
+        }
+        if output.journal && output.single_file() {
+            problems.push("--journal 只支持逐个分卷输出".to_string());
+        }
+        if output.single_output.is_some() && !format.concatenable() {
+            problems.push(format!("{} 数据流不能直接拼接, 不支持 --single-output", format.name()));
+        }
+        if (output.gzip_name.is_some() || output.gzip_mtime.is_some()) && format != Format::Gzip {
+            problems.push("--gzip-name 与 --gzip-mtime 只用于 gzip 格式".to_string());
+        }
+        if output.inline_dictionary && (format != Format::Zstd || output.single_output.is_some()) {
+            problems.push("--inline-dictionary 只支持逐个写出 zstd 分卷".to_string());
+        }
+        if output.inline_dictionary && !Self::local_input(input_path) {
+            problems.push("--inline-dictionary 需要采样, 只支持本地输入文件".to_string());
+        }
+        if output.seven_zip_codec.is_some() && format != Format::SevenZip {
+            problems.push("--7z-codec 只用于 7z 格式".to_string());
+        }
+        let seven_zip_codec = output.seven_zip_codec.unwrap_or(SevenZipCodec::Lzma2);
+        let zstd_codec = format == Format::Zstd || (format == Format::SevenZip && seven_zip_codec == SevenZipCodec::Zstd);
+        if self.zstd.is_set() && !zstd_codec {
+            problems.push("--level 与 --threads 只用于 zstd 格式(或 --7z-codec zstd)".to_string());
+        }
+        // 缺少 xz 时在开始前报告, 而不是写了一半归档才失败
+        if format == Format::SevenZip && seven_zip_codec == SevenZipCodec::Lzma2 && find_tool("xz").is_none() {
+            problems.push("7z 的 LZMA2 编码需要系统中的 xz, 没有找到; 可改用 --7z-codec zstd".to_string());
+        }
+    }
+
+    // 流式写出、并行切分与流水线需要的条件
+    fn check_run(&self, input_path: &str, problems: &mut Vec<String>) {
+        let (input, records, chunking, output, run) = (&self.input, &self.records, &self.chunking, &self.output, &self.run);
+        let binary = input.binary;
+        if run.jobs > 1 && run.pipeline_depth == 0 {
+            problems.push("--jobs 需要流水线, 不能与 --pipeline-depth 0 同时使用".to_string());
+        }
+        if run.stream {
+            // 这些选项需要完整的分卷: 挑选切分点、过滤、按内容命名或计算摘要, 以及写到单个文件
+            let buffered = [
+                (output.format() != Format::Zstd, "--format"),
+                (chunking.max_size.is_some(), if binary { "二进制输入" } else { "--max-size 与 --hard-limit" }),
+                (chunking.balance_compressed, "--balance-compressed"),
+                (output.align_gz_members, "--align-gz-members"),
+                (input.drop_invalid, "--drop-invalid"),
+                (output.single_output.is_some(), "--single-output"),
+                (!output.mirrors.is_empty(), "--output"),
+                (output.content_addressed, "--content-addressed 与 --name-by-hash"),
+                (output.name_template.is_some(), "--name-template"),
+                (output.line_merkle, "--line-merkle"),
+                (output.inline_dictionary, "--inline-dictionary"),
+                (run.jobs > 1, "--jobs"),
+            ];
+            for (_, option) in buffered.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("--stream 只支持逐个写出 zstd 分卷, 不能与 {} 同时使用", option));
+            }
+        }
+        if run.parallel_split {
+            // 切分点由各线程按固定规则分头查找, 分卷不经过顺序的 Chunker
+            let sequential = [
+                (run.stream, "--stream"),
+                (chunking.max_size.is_some() && !binary, "--max-size 与 --hard-limit"),
+                (chunking.balance_compressed, "--balance-compressed"),
+                (output.align_gz_members, "--align-gz-members"),
+                (input.drop_invalid, "--drop-invalid"),
+                (records.records.is_some() && !binary, "--records"),
+                (chunking.long_lines_set(), "--long-line-policy 与 --long-line-cap"),
+                (run.pipeline_depth == 0, "--pipeline-depth 0"),
+                (input.skip_bytes > 0 || input.line_window(), "--skip-bytes, --skip-lines 与 --max-lines"),
+            ];
+            for (_, option) in sequential.iter().filter(|(conflict, _)| *conflict) {
+                problems.push(format!("--parallel-split 并行查找切分点, 不能与 {} 同时使用", option));
+            }
+            if !Self::local_input(input_path) {
+                problems.push("--parallel-split 需要按位置读取, 只支持本地输入文件".to_string());
+            }
+        }
+        if run.read_nice.is_some() && input.readahead == 0 {
+            problems.push("--priority read=N 需要 --readahead, 读取才在独立线程中进行".to_string());
+        }
+    }
+
+    // 依赖换行符与编码的检查: 并行查找换行符, JSON 的编码, 引号与记录格式本身
+    fn check_delimiters(&self, line_ending: &str, encoding: &'static Encoding, problems: &mut Vec<String>) {
+        let (input, records) = (&self.input, &self.records);
+        if self.run.parallel_split && !input.binary && !boundary::Delimiter::new(line_ending, encoding).self_synchronizing() {
+            problems.push(format!("--parallel-split 需要能从任意位置查找的换行符, {} 可能与自身重叠", line_ending.escape_default()));
+        }
+        if records.validate_json.is_some() && encoding != UTF_8 {
+            problems.push("JSON Lines 必须是 UTF-8 编码, --validate-json 不能与其他 --encoding 同时使用".to_string());
+        }
+        if let Some(Err(e)) = records.quote_char.map(|quote| boundary::Quoted::new(line_ending, quote, encoding)) {
+            problems.push(e);
+        }
+        if let Some(records) = &records.records {
+            if let Err(e) = boundary::parse(records, encoding) {
+                problems.push(e);
+            }
+            if self.output.align_gz_members && !input.binary {
+                problems.push("--records 与 --align-gz-members 不能同时使用".to_string());
+            }
+        }
+    }
+}
+
+impl Config {
+    // 第一个参数为程序名, 与命令行一致; 批量任务把每个任务转换成同样的参数列表.
+    // 各领域的选项分别解析, 补上派生的值后按领域检查冲突
+    pub fn parse(raw_args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let raw_args: Vec<String> = raw_args.into_iter().collect();
+        let settings = raw_args.iter().skip(1).map(|arg| quote_arg(arg)).collect::<Vec<_>>().join(" ");
+        let mut options = Options::default();
+        let mut args: Vec<String> = Vec::new();
+        let mut problems = Vec::new();
+
+        // 分离 --xxx 选项与位置参数
+        let mut raw_args = raw_args.into_iter();
+        while let Some(arg) = raw_args.next() {
+            if let Some(flag) = arg.strip_prefix("--") {
+                let value = || raw_args.next().ok_or(format!("选项 {} 缺少参数", arg));
+                // 出错的选项记下后继续解析, 最后一次报告所有问题
+                match options.parse_flag(flag, value) {
+                    Ok(true) => {}
+                    Ok(false) => problems.push(unknown_option(&arg)),
+                    Err(problem) => problems.push(problem),
+                }
+            } else {
+                args.push(arg);
+            }
+        }
+        if args.len() < 3 {
+            return Err(format!("缺少参数 <input_file> <output_prefix>; 不带参数运行 {} 查看用法", args[0]));
+        }
+
+        let input_path = args[1].clone();
+        let output_prefix = args[2].clone();
+        let chunk_size = if let Some(target_size) = options.chunking.target_size {
+            target_size
+        } else if args.len() >= 4 {
+            args[3].parse::<usize>().map(|mb| mb * 1024 * 1024).unwrap_or_else(|_| {
+                problems.push("无效的块大小".to_string());
+                DEFAULT_CHUNK_SIZE
+            })
+        } else {
+            DEFAULT_CHUNK_SIZE
+        };
+        // 定长记录按二进制输入整块切分, 分块大小向下取整为记录长度的整数倍(至少一条记录)
+        let chunk_size = options.input.record_bytes.map_or(chunk_size, |n| (chunk_size / n).max(1) * n);
+        // 命名选项优先于位置参数
+        let line_ending = match options.input.line_ending.as_ref().or(args.get(4)) {
+            Some(value) => parse_line_ending(value).unwrap_or_else(|e| {
+                problems.push(e);
+                String::from(DEFAULT_LINE_ENDING)
+            }),
+            None => String::from(DEFAULT_LINE_ENDING),
+        };
+        let encoding = match options.input.encoding.as_ref().or(args.get(5)) {
+            Some(value) => parse_encoding(value).unwrap_or_else(|e| {
+                problems.push(e);
+                UTF_8
+            }),
+            None => UTF_8,
+        };
+        let input_sha256 = options.input.input_sha256.as_ref().and_then(|value| parse_input_sha256(value, &input_path).map_err(|e| problems.push(e)).ok());
+
+        // --hard-limit 相当于上限等于目标大小; 二进制输入同样整块切分
+        let (input, chunking, output) = (&mut options.input, &mut options.chunking, &mut options.output);
+        input.binary = input.binary || input.record_bytes.is_some() || is_device(&input_path);
+        chunking.max_size = chunking.max_size.or((chunking.hard_limit || input.binary).then_some(chunk_size));
+        output.content_addressed = output.content_addressed || output.name_by_hash;
+        options.check_input(&input_path, chunk_size, &mut problems);
+        options.check_resume(&input_path, &mut problems);
+        options.check_records(&mut problems);
+        // 二进制输入没有换行符, 每个分卷正好是分块大小; CSV 表头意味着按 CSV 记录切分, JSON 校验意味着按 JSON Lines
+        let records = &mut options.records;
+        records.records = records
+            .records
+            .take()
+            .or_else(|| options.input.binary.then(|| format!("fixed:{}", chunk_size)))
+            .or_else(|| records.csv_header.is_some().then(|| "csv".to_string()))
+            .or_else(|| records.validate_json.is_some().then(|| "jsonl".to_string()));
+        options.check_chunking(&input_path, &mut problems);
+        options.check_output(&input_path, &mut problems);
+        options.check_run(&input_path, &mut problems);
+        options.check_delimiters(&line_ending, encoding, &mut problems);
+
+        let resume = options.run.resume.then(|| ResumePoint::read(&output_prefix, &input_path).map_err(|e| problems.push(e)).ok()).flatten();
+        let name_template = options.output.name_template.take().and_then(|template| NameTemplate::parse(&template, std::mem::take(&mut options.output.fields)).map_err(|e| problems.push(e)).ok());
+        if !problems.is_empty() {
+            return Err(report(problems));
+        }
+
+        let Options { input, records, chunking, output, run, zstd } = options;
+        Ok(Config {
+            input_path,
+            output_prefix,
+            chunk_size,
+            chunk_limit: Arc::new(AtomicUsize::new(chunk_size)),
+            line_ending,
+            encoding,
+            drop_invalid: input.drop_invalid,
+            binary: input.binary,
+            record_bytes: input.record_bytes,
+            max_bytes: input.max_bytes,
+            skip_bytes: resume.map_or(input.skip_bytes, |point| point.input_offset),
+            skip_lines: input.skip_lines,
+            max_lines: input.max_lines,
+            expect_ratio: run.expect_ratio,
+            ratio_tolerance: run.ratio_tolerance,
+            ratio_abort: run.ratio_abort,
+            checkpoint_interval: run.checkpoint_interval,
+            max_runtime: run.max_runtime,
+            resume,
+            readahead: input.readahead,
+            pipeline_depth: run.pipeline_depth,
+            jobs: run.jobs,
+            stream: run.stream,
+            parallel_split: run.parallel_split,
+            gzip_name: output.gzip_name.unwrap_or(GzipName::Chunk),
+            seven_zip_codec: output.seven_zip_codec.unwrap_or(SevenZipCodec::Lzma2),
+            gzip_mtime: output.gzip_mtime.unwrap_or(GzipMtime::Input),
+            inline_dictionary: output.inline_dictionary,
+            dictionary: OnceLock::new(),
+            buffer_size: input.buffer_size,
+            max_size: chunking.max_size,
+            long_line_policy: chunking.long_line_policy.unwrap_or(LongLinePolicy::Grow),
+            long_line_cap: chunking.long_line_cap,
+            no_boundary: chunking.no_boundary.unwrap_or(NoBoundaryPolicy::Warn),
+            lines: chunking.lines,
+            cdc: chunking.cdc,
+            parts: chunking.parts,
+            partition_by: chunking.partition_by.or(chunking.shard_by_key),
+            shards: chunking.shards,
+            round_robin: chunking.round_robin,
+            split_by_time: chunking.split_by_time,
+            time_field: chunking.time_field.unwrap_or(1),
+            field_delimiter: chunking.field_delimiter.unwrap_or(','),
+            balance_compressed: chunking.balance_compressed,
+            format: output.format(),
+            single_output: output.single_output,
+            output_dirs: output.output_dirs,
+            mirrors: output.mirrors,
+            align_gz_members: output.align_gz_members,
+            input_sha256,
+            content_addressed: output.content_addressed,
+            name_by_hash: output.name_by_hash,
+            chunk_sha256: !output.no_chunk_sha256,
+            name_template,
+            line_merkle: output.line_merkle,
+            journal: output.journal,
+            profile_out: run.profile_out,
+            read_nice: run.read_nice,
+            compress_nice: run.compress_nice,
+            porcelain: run.porcelain,
+            numbers: NumberFormat::for_locale(&run.locale.unwrap_or_else(units::env_locale), run.unit_system),
+            fail_on_warning: run.fail_on_warning,
+            yes: run.yes,
+            zstd,
+            records: records.records,
+            csv_header: records.csv_header,
+            xml_wrap: records.xml_wrap,
+            quote_char: records.quote_char,
+            validate_json: records.validate_json,
+            tee_plain: output.tee_plain,
+            header: OnceLock::new(),
+            footer: OnceLock::new(),
+            footed: Mutex::new(HashSet::new()),
+            inject_failures: run.inject_failures,
+            run_id: new_run_id(),
+            settings,
+        })
+    }
+
+    // 记录经 Partitions 分到各自的一组分卷, 而不是依次写成一组分卷
+    pub(crate) fn partitioned(&self) -> bool {
+        self.partition_by.is_some() || self.round_robin.is_some() || self.split_by_time.is_some()
+    }
+}
+
+// 解析 "5:1" 或 "5" 形式的压缩比
+fn parse_ratio(value: &str) -> Result<f64, String> {
+    let invalid = || format!("无效的压缩比: {}", value);
+    let (raw, compressed) = value.split_once(':').unwrap_or((value, "1"));
+    let raw = raw.trim().parse::<f64>().map_err(|_| invalid())?;
+    let compressed = compressed.trim().parse::<f64>().map_err(|_| invalid())?;
+    if raw <= 0.0 || compressed <= 0.0 {
+        return Err(invalid());
+    }
+    Ok(raw / compressed)
+}
+
+// 解析 "2h", "90m", "30s" 形式的运行时间上限, 不带单位时为秒
+pub(crate) fn parse_runtime(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("无效的运行时间上限: {}. 请使用如 2h, 90m 或 30s", value);
+    let value = value.trim().to_lowercase();
+    let (number, unit) = match value.strip_suffix('h') {
+        Some(hours) => (hours, 3600),
+        None => match value.strip_suffix('m') {
+            Some(mins) => (mins, 60),
+            None => (value.strip_suffix('s').unwrap_or(&value), 1),
+        },
+    };
+    match number.parse::<u64>() {
+        Ok(n) if n > 0 => Ok(Duration::from_secs(n.saturating_mul(unit))),
+        _ => Err(invalid()),
+    }
+}
+
+// 解析 "10"(分卷数) 或 "30s"/"5m"(时间) 形式的检查点间隔
+pub(crate) fn parse_checkpoint_interval(value: &str) -> Result<CheckpointInterval, String> {
+    let invalid = || format!("无效的检查点间隔: {}", value);
+    let value = value.trim().to_lowercase();
+    let interval = if let Some(secs) = value.strip_suffix('s') {
+        CheckpointInterval::Every(Duration::from_secs(secs.parse().map_err(|_| invalid())?))
+    } else if let Some(mins) = value.strip_suffix('m') {
+        CheckpointInterval::Every(Duration::from_secs(mins.parse::<u64>().map_err(|_| invalid())? * 60))
+    } else {
+        CheckpointInterval::Chunks(value.parse().map_err(|_| invalid())?)
+    };
+    match interval {
+        CheckpointInterval::Every(d) if d.is_zero() => Err(invalid()),
+        CheckpointInterval::Chunks(0) => Err(invalid()),
+        interval => Ok(interval),
+    }
+}
+
+// 解析以 MB 为单位的大小
+// 分区列的分隔符: 单个字符, 制表符可写作 tab 或 \t
+pub(crate) fn parse_field_delimiter(value: &str) -> Result<char, String> {
+    let mut chars = value.chars();
+    match (value, chars.next(), chars.next()) {
+        ("tab" | "\\t", _, _) => Ok('\t'),
+        (_, Some(c), None) => Ok(c),
+        _ => Err(format!("无效的列分隔符: {}. 请使用单个字符, 制表符可写作 tab", value)),
+    }
+}
+
+pub fn parse_line_ending(value: &str) -> Result<String, String> {
+    match value.to_uppercase().as_str() {
+        "LF" => Ok(String::from("\n")),
+        "CRLF" => Ok(String::from("\r\n")),
+        "CR" => Ok(String::from("\r")),
+        custom if custom.starts_with("CUSTOM:") => {
+            let custom_ending = custom[7..].to_string()
+                .replace("\\n", "\n")
+                .replace("\\r", "\r");
+            if custom_ending.is_empty() {
+                return Err("自定义换行符不能为空".to_string());
+            }
+            Ok(custom_ending)
+        }
+        _ => Err("无效的换行符选项. 请使用 LF, CRLF, CR 或 custom:xxx".to_string())
+    }
+}
+
+pub fn parse_encoding(value: &str) -> Result<&'static Encoding, String> {
+    match value.to_uppercase().as_str() {
+        "UTF-8" => Ok(UTF_8),
+        "GBK" => Ok(GBK),
+        _ => Err("不支持的编码. 目前支持: UTF-8, GBK".to_string())
+    }
+}
+
+// 字节数, 可带 K/M/G 后缀(按 1024 计)
+pub(crate) fn parse_byte_count(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let (number, unit) = match trimmed.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&trimmed[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&trimmed[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&trimmed[..i], 1 << 30),
+        _ => (trimmed, 1),
+    };
+    number.parse::<u64>().ok().filter(|&n| n > 0).and_then(|n| n.checked_mul(unit)).ok_or_else(|| format!("无效的字节数: {}", value))
+}
+
+pub(crate) fn parse_size_mb(value: &str, name: &str) -> Result<usize, String> {
+    match value.trim().parse::<usize>() {
+        Ok(mb) if mb > 0 => Ok(mb * 1024 * 1024),
+        _ => Err(format!("无效的{}: {}", name, value)),
+    }
+}
+
+// 故障注入的位置: 压缩前出错, 写出一半后出错, 或把截断的分卷当作完整分卷写出(留给 --verify 发现)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FailureStage {
+    Compress,
+    Write,
+    Truncate,
+    // 第一次压缩整个分卷时报告内存不足
+    OutOfMemory,
+}
+
+// 隐藏选项 --inject-failure <compress|write|truncate|oom>:chunk=<N>, 不出现在用法说明中;
+// 在指定分卷处故意出错, 供用户在真正使用前演练续传、校验与告警流程
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct InjectedFailure {
+    stage: FailureStage,
+    chunk: usize,
+}
+
+impl InjectedFailure {
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("无效的故障注入点: {}. 格式为 compress|write|truncate|oom:chunk=N", value);
+        let (stage, chunk) = value.split_once(":chunk=").ok_or_else(invalid)?;
+        let stage = match stage {
+            "compress" => FailureStage::Compress,
+            "write" => FailureStage::Write,
+            "truncate" => FailureStage::Truncate,
+            "oom" => FailureStage::OutOfMemory,
+            _ => return Err(invalid()),
+        };
+        let chunk = chunk.parse().ok().filter(|&n| n > 0).ok_or_else(invalid)?;
+        Ok(InjectedFailure { stage, chunk })
+    }
+}
+
+pub(crate) fn inject_failure(config: &Config, stage: FailureStage, chunk_number: usize) -> bool {
+    config.inject_failures.contains(&InjectedFailure { stage, chunk: chunk_number })
+}
+
+// 解析 "read=-5,compress=10" 形式的阶段优先级
+fn parse_priorities(value: &str) -> Result<Vec<(&'static str, i32)>, String> {
+    let invalid = || format!("无效的优先级: {}. 请使用 read=N,compress=N, N 为 -20 到 19", value);
+    let mut priorities = Vec::new();
+    for item in value.split(',') {
+        let (stage, nice) = item.split_once('=').ok_or_else(invalid)?;
+        let stage = match stage.trim() {
+            "read" => "read",
+            "compress" => "compress",
+            _ => return Err(invalid()),
+        };
+        let nice = nice.trim().parse::<i32>().ok().filter(|n| (-20..=19).contains(n)).ok_or_else(invalid)?;
+        priorities.push((stage, nice));
+    }
+    Ok(priorities)
+}
+
+// 解析 "50%" 或 "50" 形式的百分比
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent = value.trim().trim_end_matches('%').parse::<f64>()
+        .map_err(|_| format!("无效的百分比: {}", value))?;
+    if percent < 0.0 {
+        return Err(format!("无效的百分比: {}", value));
+    }
+    Ok(percent / 100.0)
+}
+
+pub const CONFIG_ENV: &str = "ZSTD_COMPRESSOR_CONFIG";
+pub const OPTIONS_ENV: &str = "ZSTD_COMPRESSOR_OPTIONS";
+
+// 切分模式接受的选项(去掉 --), 用于检查配置文件的键与给未知选项找建议
+pub const OPTIONS: &[&str] = &[
+    "drop-invalid", "binary", "max-bytes", "skip-bytes", "skip-lines", "max-lines", "expect-ratio", "ratio-tolerance",
+    "ratio-policy", "checkpoint-interval", "max-runtime", "resume", "readahead",
+    "pipeline-depth", "buffer-size", "hard-limit", "target-size", "chunk-size", "line-ending", "encoding", "max-size",
+    "balance-compressed", "single-output", "format", "output", "align-gz-members", "records", "input-sha256",
+    "content-addressed", "name-by-hash", "no-chunk-sha256", "name-template", "field", "line-merkle", "journal", "porcelain",
+    "fail-on-warning", "yes", "profile-out", "priority", "level", "fast", "best", "threads", "compress-threads",
+    "jobs", "stream", "parallel-split", "gzip-name", "gzip-mtime", "7z-codec", "inline-dictionary", "long-line-policy", "long-line-cap",
+    "lines", "parts", "cdc", "csv-header", "xml-wrap", "quote-char", "no-boundary",
+    "validate-json", "tee-plain", "output-dirs", "partition-by", "delimiter",
+    "shard-by-key", "shards", "round-robin", "units", "locale", "split-by-time", "time-field",
+    "record-sep-regex", "record-bytes",
+];
+
+// 在命令行参数(第一个为程序名)的选项之前插入配置文件与环境变量中的选项;
+// 命令行中的 --config FILE 取代环境变量指定的配置文件
+pub fn layered_args(args: Vec<String>) -> Result<Vec<String>, String> {
+    let mut args = args.into_iter();
+    let mut merged: Vec<String> = args.next().into_iter().collect();
+    let mut config_path = env::var(CONFIG_ENV).ok().filter(|path| !path.is_empty());
+    let mut cli = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            config_path = Some(args.next().ok_or("选项 --config 缺少参数")?);
+        } else {
+            cli.push(arg);
+        }
+    }
+
+    if let Some(path) = config_path {
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("无法读取配置文件 {}: {}", path, e))?;
+        merged.extend(file_options(&text).map_err(|e| format!("配置文件 {}: {}", path, e))?);
+    }
+    if let Ok(options) = env::var(OPTIONS_ENV) {
+        let options: Vec<String> = options.split_whitespace().map(str::to_string).collect();
+        if options.first().is_some_and(|first| !first.starts_with("--")) {
+            return Err(format!("环境变量 {} 只能包含选项, 不能给出位置参数", OPTIONS_ENV));
+        }
+        merged.extend(options);
+    }
+    merged.extend(cli);
+    Ok(merged)
+}
+
+// 配置文件是一层 key: value, 键为去掉 -- 的选项名(可用 _ 代替 -), 开关的值为 true/false;
+// 可重复的选项写成 [a, b] 或缩进的 "- item" 列表. 输入文件与前缀只能在命令行给出
+fn file_options(text: &str) -> Result<Vec<String>, String> {
+    let mut entries: Vec<(String, JobValue)> = Vec::new();
+    for (n, raw) in text.lines().enumerate() {
+        let error = |message: String| format!("第 {} 行: {}", n + 1, message);
+        let line = strip_yaml_comment(raw).trim_end();
+        let content = line.trim_start();
+        if content.is_empty() || content == "---" {
+            continue;
+        }
+        if content.len() < line.len() {
+            let item = content.strip_prefix('-').ok_or_else(|| error("只有列表项可以缩进".to_string()))?;
+            match entries.last_mut() {
+                Some((_, JobValue::List(items))) => items.push(unquote_yaml(item)),
+                _ => return Err(error("列表项不属于任何键".to_string())),
+            }
+            continue;
+        }
+        let (key, value) = parse_yaml_entry(content).map_err(error)?;
+        let key = key.replace('_', "-");
+        if !OPTIONS.contains(&key.as_str()) {
+            return Err(error(unknown_option(&format!("--{}", key))));
+        }
+        if entries.iter().any(|(k, _)| *k == key) {
+            return Err(error(format!("重复的键: {}", key)));
+        }
+        entries.push((key, value));
+    }
+
+    let mut args = Vec::new();
+    for (key, value) in &entries {
+        push_option(&mut args, format!("--{}", key), value);
+    }
+    Ok(args)
+}
+
+// 未知选项的提示, 拼写接近某个已知选项时附上它
+pub fn unknown_option(arg: &str) -> String {
+    let name = arg.trim_start_matches('-');
+    let closest = OPTIONS.iter().map(|option| (edit_distance(name, option), *option)).min();
+    match closest {
+        Some((distance, option)) if distance <= (name.chars().count() / 3).max(2) => format!("未知选项: {} (是否想用 --{}?)", arg, option),
+        _ => format!("未知选项: {}", arg),
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+// 只有一个问题时原样返回, 多个问题逐行列出
+pub fn report(problems: Vec<String>) -> String {
+    if problems.len() == 1 {
+        return problems.into_iter().next().unwrap_or_default();
+    }
+    let lines: Vec<String> = problems.iter().map(|problem| format!("  - {}", problem)).collect();
+    format!("发现 {} 个问题:\n{}", problems.len(), lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn every_listed_option_is_accepted() {
+        for option in OPTIONS {
+            let flag = format!("--{}", option);
+            if let Err(e) = Config::parse(args(&["zstd_compressor", "a.log", "out/a", &flag, "x"])) {
+                assert!(!e.contains("未知选项"), "{}: {}", flag, e);
+            }
+        }
+    }
+
+    #[test]
+    fn problems_are_reported_together_with_suggestions() {
+        let error = Config::parse(args(&["zstd_compressor", "a.log", "out/a", "x", "--max-sise", "--format", "7z", "--single-output", "o"])).unwrap_err();
+        assert!(error.starts_with("发现 3 个问题:"), "{}", error);
+        assert!(error.contains("无效的块大小"));
+        assert!(error.contains("未知选项: --max-sise (是否想用 --max-size?)"));
+        assert!(error.contains("不能与 --single-output 同时使用"));
+        assert_eq!(unknown_option("--verbose"), "未知选项: --verbose");
+        assert_eq!(Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--readahed", "2"])).unwrap_err(), "未知选项: --readahed (是否想用 --readahead?)");
+    }
+
+    #[test]
+    fn config_files_become_options() {
+        let text = "# 默认设置\nformat: gzip   # 注释\ndrop_invalid: true\nhard-limit: false\noutput:\n  - /mnt/a\n  - 's3://b/p'\nfield: [date=^(\\d+)]\n";
+        assert_eq!(file_options(text).unwrap(), args(&["--format", "gzip", "--drop-invalid", "--output", "/mnt/a", "--output", "s3://b/p", "--field", "date=^(\\d+)"]));
+        assert!(file_options("input: a.log\n").is_err());
+        assert!(file_options("formt: gzip\n").unwrap_err().contains("--format"));
+        assert!(file_options("yes: true\nyes: false\n").is_err());
+        assert!(file_options("  - a\n").is_err());
+    }
+
+    #[test]
+    fn stage_priorities() {
+        assert_eq!(parse_priorities("read=-5, compress=10"), Ok(vec![("read", -5), ("compress", 10)]));
+        assert!(parse_priorities("read=20").is_err());
+        assert!(parse_priorities("write=1").is_err());
+        assert!(parse_priorities("read").is_err());
+    }
+}
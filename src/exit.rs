@@ -0,0 +1,70 @@
+// 退出码约定与带退出码的错误, 供调度系统区分失败原因
+
+use std::io;
+use crate::InvalidEncoding;
+
+// 退出码约定, 供调度系统区分失败原因
+const EXIT_IO: u8 = 1; // 读写失败等运行时错误
+
+pub const EXIT_CONFIG: u8 = 2; // 参数或配置无效
+
+const EXIT_ENCODING: u8 = 3; // 遇到无效的字符编码 (--fail-on-warning)
+
+pub(crate) const EXIT_VERIFY: u8 = 4; // 校验失败: 摘要或大小不符, 分卷损坏, 压缩比异常
+
+pub(crate) const EXIT_PARTIAL: u8 = 5; // 部分成功: 有镜像写入失败, 或批量任务中部分失败
+
+pub(crate) const EXIT_TIMEOUT: u8 = 6; // 到达 --max-runtime 的时间上限, 已在分卷边界处停下并记录进度, 可用 --resume 继续
+
+// 带退出码的错误, 用于 io::ErrorKind 区分不了的失败
+#[derive(Debug)]
+struct Failure {
+    code: u8,
+    message: String,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+pub(crate) fn failure(code: u8, message: String) -> io::Error {
+    io::Error::other(Failure { code, message })
+}
+
+pub fn exit_code(error: &io::Error) -> u8 {
+    if let Some(failure) = error.get_ref().and_then(|e| e.downcast_ref::<Failure>()) {
+        return failure.code;
+    }
+    if error.get_ref().is_some_and(|e| e.is::<InvalidEncoding>()) {
+        return EXIT_ENCODING;
+    }
+    match error.kind() {
+        io::ErrorKind::InvalidInput => EXIT_CONFIG,
+        io::ErrorKind::InvalidData => EXIT_VERIFY,
+        _ => EXIT_IO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::UTF_8;
+    use crate::Chunker;
+
+    #[test]
+    fn errors_map_to_exit_codes() {
+        assert_eq!(exit_code(&io::Error::new(io::ErrorKind::InvalidInput, "无效的块大小")), EXIT_CONFIG);
+        assert_eq!(exit_code(&io::Error::new(io::ErrorKind::InvalidData, "SHA-256 不符")), EXIT_VERIFY);
+        assert_eq!(exit_code(&io::Error::from(io::ErrorKind::NotFound)), EXIT_IO);
+        assert_eq!(exit_code(&failure(EXIT_PARTIAL, "镜像写入失败".to_string())), EXIT_PARTIAL);
+
+        let mut chunker = Chunker::new(4, "\n", UTF_8);
+        chunker.fail_on_invalid = true;
+        let error = chunker.push(b"ok\na\xFFb\n", &mut |_, _| Ok(())).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_ENCODING);
+    }
+}
@@ -0,0 +1,126 @@
+// export 子命令: 把分卷集导出为只需要 sh, tail 与 gzip 的自解压脚本
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use crate::hash::{to_hex, Sha256};
+use crate::merge::{merge_chunks, CheckedWriter, MergeOptions};
+
+// sh 单引号字面量
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+// 自解压脚本的头部: 用 tail 取出脚本后面的 gzip 数据解压, 再核对大小与 SHA-256(有 sha256sum 时).
+// 数据起始位置写在头部里, 反复生成直到位置的位数不再变化
+fn self_extracting_header(name: &str, bytes: u64, sha256: &str) -> String {
+    let mut offset = 1;
+    loop {
+        let header = format!(
+            "#!/bin/sh
+# 自解压分卷集, 由 zstd_compressor export 生成; 只需要 sh, tail 与 gzip
+# 用法: sh <本文件> [输出文件]
+set -e
+out=${{1:-{name}}}
+if [ -e \"$out\" ]; then echo \"$out 已存在\" >&2; exit 1; fi
+tail -c +{offset} \"$0\" | gzip -dc > \"$out\"
+if [ \"$(wc -c < \"$out\" | tr -d ' ')\" != {bytes} ]; then echo \"$out 的大小不符\" >&2; exit 1; fi
+if command -v sha256sum > /dev/null 2>&1; then
+  echo \"{sha256}  $out\" | sha256sum -c - > /dev/null || {{ echo \"$out 的 SHA-256 不符\" >&2; exit 1; }}
+fi
+echo \"已还原 $out ({bytes} 字节)\" >&2
+exit 0
+",
+            name = shell_quote(name),
+        );
+        if header.len() + 1 == offset {
+            return header;
+        }
+        offset = header.len() + 1;
+    }
+}
+
+// 把分卷集导出为自解压脚本: 解压各分卷后用 gzip 重新压缩并附在脚本末尾, 接收方不需要本程序或 zstd
+pub fn run_export(prefix: &str, args: &[String]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let [flag, output, rest @ ..] = args else {
+        return Err(invalid("export 需要 --self-extracting <out.sh>".to_string()));
+    };
+    if flag != "--self-extracting" {
+        return Err(invalid(format!("未知选项: {}", flag)));
+    }
+    let options = MergeOptions::parse(rest)?;
+
+    let payload_path = format!("{}.tmp", output);
+    let payload = File::create(&payload_path)?;
+    let result = (|| {
+        let mut child = Command::new("gzip")
+            .arg("-c")
+            .stdin(Stdio::piped())
+            .stdout(payload)
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("无法启动 gzip: {}", e)))?;
+        let mut stdin = io::BufWriter::new(child.stdin.take().unwrap());
+        let mut checked = CheckedWriter::new(&mut stdin, Some(Sha256::new()));
+        let merged = merge_chunks(prefix, &mut checked, &options);
+        let sha256 = checked.hasher.take().unwrap().finish();
+        let flushed = stdin.flush();
+        drop(stdin);
+        let status = child.wait()?;
+        let (chunks, bytes) = merged?;
+        flushed?;
+        if !status.success() {
+            return Err(io::Error::other(format!("gzip 执行失败: {}", status)));
+        }
+
+        let name = Path::new(prefix).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| prefix.to_string());
+        let mut out = File::create(output)?;
+        out.write_all(self_extracting_header(&name, bytes, &to_hex(&sha256)).as_bytes())?;
+        io::copy(&mut File::open(&payload_path)?, &mut out)?;
+        out.sync_all()?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(output, std::fs::Permissions::from_mode(0o755))?;
+        }
+        Ok((chunks, bytes))
+    })();
+    let _ = std::fs::remove_file(&payload_path);
+    let (chunks, bytes) = result?;
+    eprintln!("导出 {} 个分卷 ({} 字节) 到自解压脚本 {}, 用 sh {} 还原", chunks, bytes, output, output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::profiler::Profiler;
+    use crate::testing::Scratch;
+    use crate::writer::{ChunkWriter, Output};
+
+    #[test]
+    fn self_extracting_export_restores_chunks() {
+        let header = self_extracting_header("it's.log", 12, &to_hex(&[0; 32]));
+        assert!(header.contains(&format!("tail -c +{} ", header.len() + 1)));
+        assert!(header.contains("out=${1:-'it'\\''s.log'}"));
+
+        let dir = Scratch::new("export");
+        let prefix = dir.join("part").display().to_string();
+        let config = Config::parse(["zstd_compressor", "in.log", &prefix].map(String::from)).unwrap();
+        let mut writer = ChunkWriter::new(&config, Output::open(&config).unwrap());
+        writer.write(b"first\n", 0, &Profiler::new(false)).unwrap();
+        writer.write(b"second\n", 6, &Profiler::new(false)).unwrap();
+        writer.finish().unwrap();
+
+        let script = dir.join("out.sh").display().to_string();
+        run_export(&prefix, &["--self-extracting".to_string(), script.clone()]).unwrap();
+        let restored = dir.join("restored");
+        let status = Command::new("sh").arg(&script).arg(&restored).status().unwrap();
+        assert!(status.success());
+        assert_eq!(std::fs::read(&restored).unwrap(), b"first\nsecond\n");
+        // 已存在的输出文件不会被覆盖
+        assert!(!Command::new("sh").arg(&script).arg(&restored).stderr(Stdio::null()).status().unwrap().success());
+    }
+}
@@ -0,0 +1,687 @@
+// compress/decompress 子命令: 整个文件或目录树的压缩与解压, 与切分无关
+
+use std::fs::File;
+use std::io::{self, IsTerminal, Write, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::exit::EXIT_PARTIAL;
+use crate::exit::exit_code;
+use crate::exit::failure;
+use crate::prompt::confirm;
+use crate::source::Source;
+use crate::units::{self, NumberFormat, UnitSystem};
+use crate::{codec, memory, Format, ZstdParams};
+
+// compress 与 decompress 子命令的参数
+struct FileCommand {
+    paths: Vec<String>,
+    format: Option<Format>,
+    yes: bool,
+    // 递归处理目录时按相对路径过滤, 同时处理 parallel 个文件
+    recursive: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    parallel: usize,
+    // 成功后删除原文件; 默认保留
+    remove_source: bool,
+    zstd: ZstdParams,
+    // 报告以 JSON 输出到标准输出
+    json: bool,
+    numbers: NumberFormat,
+    // 单个输入写到标准输出, 与 zstd -c 相同; force 允许把压缩数据写到终端
+    stdout: bool,
+    force: bool,
+    // 递归处理时无法读取的子目录记为失败并继续, 不中止整个运行
+    keep_going: bool,
+}
+
+impl FileCommand {
+    fn parse(args: &[String]) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let mut command = FileCommand {
+            paths: Vec::new(),
+            format: None,
+            yes: false,
+            recursive: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            parallel: 1,
+            remove_source: false,
+            zstd: ZstdParams::default(),
+            json: false,
+            numbers: NumberFormat::default(),
+            stdout: false,
+            force: false,
+            keep_going: false,
+        };
+        let mut keep = false;
+        let mut unit_system = UnitSystem::Binary;
+        let mut locale = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| invalid(format!("选项 {} 缺少参数", arg)));
+            match arg.as_str() {
+                "--format" => command.format = Some(Format::parse(value()?).map_err(invalid)?),
+                "--yes" => command.yes = true,
+                "--recursive" | "-r" => command.recursive = true,
+                "--keep" | "-k" => keep = true,
+                "--rm" => command.remove_source = true,
+                "--json" => command.json = true,
+                "--units" => unit_system = UnitSystem::parse(value()?).map_err(invalid)?,
+                "--locale" => locale = Some(value()?.clone()),
+                "--stdout" | "-c" => command.stdout = true,
+                "--force" | "-f" => command.force = true,
+                "--keep-going" => command.keep_going = true,
+                "--include" => command.include.push(value()?.clone()),
+                "--exclude" => command.exclude.push(value()?.clone()),
+                "--parallel" => {
+                    let value = value()?;
+                    command.parallel = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| invalid(format!("无效的并行数: {}", value)))?;
+                }
+                flag if flag.starts_with("--") => {
+                    let value = || value().cloned().map_err(|e| e.to_string());
+                    if !command.zstd.parse_flag(&flag[2..], value).map_err(invalid)? {
+                        return Err(invalid(format!("未知选项: {}", flag)));
+                    }
+                }
+                path => command.paths.push(path.to_string()),
+            }
+        }
+        command.numbers = NumberFormat::for_locale(&locale.unwrap_or_else(units::env_locale), unit_system);
+        if keep && command.remove_source {
+            return Err(invalid("--keep 与 --rm 不能同时使用".to_string()));
+        }
+        if command.recursive && (command.stdout || command.paths.iter().any(|path| path == "-")) {
+            return Err(invalid("--recursive 不能与标准输入输出同时使用".to_string()));
+        }
+        if !command.recursive && (!command.include.is_empty() || !command.exclude.is_empty() || command.keep_going) {
+            return Err(invalid("--include, --exclude 与 --keep-going 只用于 --recursive".to_string()));
+        }
+        Ok(command)
+    }
+
+    fn is_tree(&self) -> bool {
+        self.recursive && self.paths.first().is_some_and(|path| Path::new(path).is_dir())
+    }
+
+    // 目录下所有通过过滤的普通文件; rename 把相对路径映射为输出的相对路径, 返回 None 的文件跳过.
+    // 给出输出根目录时在其下重建目录结构, 否则输出在原文件旁边; 另外返回 --keep-going 时跳过的无法读取的子目录
+    fn tree_tasks(&self, rename: impl Fn(&str) -> Option<String>) -> io::Result<(Vec<FileTask>, Unreadable)> {
+        let (root, output_root) = match &self.paths[..] {
+            [root] => (Path::new(root), Path::new(root)),
+            [root, output_root] => (Path::new(root), Path::new(output_root)),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "--recursive 用法: <dir> [output_dir]")),
+        };
+        let mut files = Vec::new();
+        let mut unreadable = Vec::new();
+        walk_files(root, &mut files, self.keep_going.then_some(&mut unreadable))?;
+        files.sort();
+        let mut tasks = Vec::new();
+        for file in files {
+            let relative = file.strip_prefix(root).unwrap().to_string_lossy().replace(std::path::MAIN_SEPARATOR, "/");
+            let included = self.include.is_empty() || self.include.iter().any(|pattern| glob_match(pattern, &relative));
+            if !included || self.exclude.iter().any(|pattern| glob_match(pattern, &relative)) {
+                continue;
+            }
+            if let Some(renamed) = rename(&relative) {
+                tasks.push(FileTask { input: file.display().to_string(), output: output_root.join(renamed).display().to_string() });
+            }
+        }
+        Ok((tasks, unreadable))
+    }
+}
+
+// 一个文件的压缩或解压
+struct FileTask {
+    input: String,
+    output: String,
+}
+
+// --keep-going 时跳过的无法读取的目录及原因
+type Unreadable = Vec<(String, io::Error)>;
+
+struct FileStats {
+    format: &'static str,
+    raw_bytes: u64,
+    // 远程输入解压时不知道压缩后的大小
+    compressed_bytes: Option<u64>,
+}
+
+// 递归列出目录下的普通文件, 不跟随符号链接; 给出 unreadable 时无法读取的目录记入其中并继续
+fn walk_files(dir: &Path, files: &mut Vec<PathBuf>, mut unreadable: Option<&mut Unreadable>) -> io::Result<()> {
+    let listed = std::fs::read_dir(dir).and_then(|entries| {
+        let mut dirs = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+        Ok(dirs)
+    });
+    let dirs = match (listed, unreadable.as_deref_mut()) {
+        (Ok(dirs), _) => dirs,
+        (Err(e), Some(unreadable)) => {
+            eprintln!("错误: 无法读取目录 {}: {}, 继续处理其他文件", dir.display(), e);
+            unreadable.push((dir.display().to_string(), e));
+            return Ok(());
+        }
+        (Err(e), None) => return Err(e),
+    };
+    for dir in dirs {
+        walk_files(&dir, files, unreadable.as_deref_mut())?;
+    }
+    Ok(())
+}
+
+// 通配符匹配: * 与 ? 不跨越 /, ** 可以跨越目录(**/ 也匹配零层目录); 不含 / 的模式只与文件名比较
+fn glob_match(pattern: &str, path: &str) -> bool {
+    let path = if pattern.contains('/') { path } else { path.rsplit('/').next().unwrap_or(path) };
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    glob_match_chars(&pattern, &path)
+}
+
+fn glob_match_chars(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            (0..=path.len()).filter(|&i| i == 0 || path[i - 1] == '/').any(|i| glob_match_chars(rest, &path[i..]))
+        }
+        ['*', '*', rest @ ..] => (0..=path.len()).any(|i| glob_match_chars(rest, &path[i..])),
+        ['*', rest @ ..] => (0..=path.len()).take_while(|&i| i == 0 || path[i - 1] != '/').any(|i| glob_match_chars(rest, &path[i..])),
+        ['?', rest @ ..] => path.first().is_some_and(|&c| c != '/') && glob_match_chars(rest, &path[1..]),
+        [c, rest @ ..] => path.first() == Some(c) && glob_match_chars(rest, &path[1..]),
+    }
+}
+
+// 执行压缩或解压任务: 先一次确认所有要覆盖的文件; 单个文件时直接报告,
+// 多个文件时由 parallel 个线程处理, 某个文件失败不影响其他文件, 最后汇总(包括 --keep-going 跳过的目录)
+fn run_file_tasks(command: &FileCommand, tasks: &[FileTask], unreadable: &[(String, io::Error)], process: impl Fn(&FileTask) -> io::Result<FileStats> + Sync) -> io::Result<()> {
+    let existing: Vec<&str> = tasks.iter().map(|task| task.output.as_str()).filter(|&output| output != "-" && Path::new(output).exists()).collect();
+    match existing[..] {
+        [] => {}
+        [output] => confirm(&format!("将覆盖已有的 {}", output), command.yes)?,
+        [first, ..] => confirm(&format!("将覆盖已有的 {} 个文件(如 {})", existing.len(), first), command.yes)?,
+    }
+
+    if let Some(task) = tasks.iter().find(|task| task.input == task.output && task.input != "-") {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("输入与输出是同一个文件: {}", task.input)));
+    }
+    if command.remove_source {
+        if let Some(task) = tasks.iter().find(|task| task.input == "-" || !matches!(Source::parse(&task.input), Source::Local(_))) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("--rm 只用于本地文件: {}", task.input)));
+        }
+        if !tasks.is_empty() {
+            confirm(&format!("处理成功后将删除 {} 个原文件", tasks.len()), command.yes)?;
+        }
+    }
+
+    // 失败时不留下残缺的输出; 成功后沿用原文件的修改时间与权限, 再按需删除原文件
+    let run = |task: &FileTask| -> io::Result<FileStats> {
+        let to_file = task.output != "-";
+        if let Some(parent) = Path::new(&task.output).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let stats = process(task).inspect_err(|_| {
+            if to_file {
+                let _ = std::fs::remove_file(&task.output);
+            }
+        })?;
+        if let (Source::Local(input), false) = (Source::parse(&task.input), task.input == "-") {
+            if to_file {
+                copy_file_metadata(&input, Path::new(&task.output))?;
+            }
+            if command.remove_source {
+                std::fs::remove_file(&input)?;
+            }
+        }
+        Ok(stats)
+    };
+    let start_time = Instant::now();
+    let throughput = |bytes: u64, seconds: f64| bytes as f64 / 1024.0 / 1024.0 / seconds.max(1e-9);
+    let optional_json = |bytes: Option<u64>| bytes.map_or("null".to_string(), |bytes| bytes.to_string());
+    let numbers = &command.numbers;
+    // JSON 中的数值字段保持原始数值, 另附按 --units 与 --locale 格式化的文本
+    let formatted_json = |raw: u64, compressed: Option<u64>, seconds: f64, separator: &str| {
+        let fields = [
+            ("raw_size", json_string(&numbers.size(raw))),
+            ("compressed_size", compressed.map_or("null".to_string(), |bytes| json_string(&numbers.size(bytes)))),
+            ("duration", json_string(&numbers.duration(seconds))),
+            ("throughput", json_string(&numbers.rate(raw, seconds))),
+            ("peak_buffer", json_string(&numbers.size(memory::peak_heap()))),
+            ("peak_rss", memory::peak_rss().map_or("null".to_string(), |bytes| json_string(&numbers.size(bytes)))),
+        ];
+        fields.map(|(key, value)| format!("\"{}\": {}", key, value)).join(separator)
+    };
+    if let ([task], false) = (tasks, command.is_tree()) {
+        let stats = run(task)?;
+        // 数据写到标准输出时报告改写到标准错误
+        let report = |line: String| if task.output == "-" { eprintln!("{}", line) } else { println!("{}", line) };
+        let seconds = start_time.elapsed().as_secs_f64();
+        let ratio = stats.compressed_bytes.map(|compressed_bytes| stats.raw_bytes as f64 / compressed_bytes.max(1) as f64);
+        if command.json {
+            report(format!(
+                "{{\"input\": {}, \"output\": {}, \"format\": \"{}\", \"raw_bytes\": {}, \"compressed_bytes\": {}, \"ratio\": {}, \"seconds\": {:.3}, \"mb_per_sec\": {:.2}, \"peak_buffer_bytes\": {}, \"peak_rss_bytes\": {}, {}}}",
+                json_string(&task.input),
+                json_string(&task.output),
+                stats.format,
+                stats.raw_bytes,
+                optional_json(stats.compressed_bytes),
+                ratio.map_or("null".to_string(), |ratio| format!("{:.2}", ratio)),
+                seconds,
+                throughput(stats.raw_bytes, seconds),
+                memory::peak_heap(),
+                optional_json(memory::peak_rss()),
+                formatted_json(stats.raw_bytes, stats.compressed_bytes, seconds, ", ")
+            ));
+            return Ok(());
+        }
+        report(format!("{} -> {} ({})", task.input, task.output, stats.format));
+        report(format!("- 原始大小: {} ({} 字节)", numbers.size(stats.raw_bytes), numbers.integer(stats.raw_bytes)));
+        if let (Some(compressed_bytes), Some(ratio)) = (stats.compressed_bytes, ratio) {
+            report(format!("- 压缩后: {} ({} 字节, {}:1)", numbers.size(compressed_bytes), numbers.integer(compressed_bytes), numbers.decimal(ratio, 2)));
+        }
+        report(format!("- 处理耗时: {}", numbers.duration(seconds)));
+        report(format!("- 吞吐: {} (按原始大小)", numbers.rate(stats.raw_bytes, seconds)));
+        report(format!("- 内存峰值: {}", peak_memory(numbers)));
+        return Ok(());
+    }
+
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<io::Result<FileStats>>>> = Mutex::new(tasks.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..command.parallel.min(tasks.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(task) = tasks.get(i) else { break };
+                let result = run(task);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    let (mut raw_total, mut compressed_total, mut failed, mut first_code) = (0, 0, 0, None);
+    let mut rows = Vec::new();
+    for (dir, e) in unreadable {
+        failed += 1;
+        first_code.get_or_insert(exit_code(e));
+        if command.json {
+            rows.push(format!("    {{\"input\": {}, \"error\": {}}}", json_string(dir), json_string(&e.to_string())));
+        } else {
+            println!("- {}: 无法读取目录: {}", dir, e);
+        }
+    }
+    for (task, result) in tasks.iter().zip(results.into_inner().unwrap()) {
+        let (input, output) = (json_string(&task.input), json_string(&task.output));
+        match result.expect("每个文件都已处理") {
+            Ok(stats) => {
+                raw_total += stats.raw_bytes;
+                compressed_total += stats.compressed_bytes.unwrap_or_default();
+                if command.json {
+                    rows.push(format!(
+                        "    {{\"input\": {}, \"output\": {}, \"raw_bytes\": {}, \"compressed_bytes\": {}}}",
+                        input, output, stats.raw_bytes, optional_json(stats.compressed_bytes)
+                    ));
+                    continue;
+                }
+                match stats.compressed_bytes {
+                    Some(compressed_bytes) => println!("- {} -> {}: 原始 {}, 压缩后 {}", task.input, task.output, numbers.size(stats.raw_bytes), numbers.size(compressed_bytes)),
+                    None => println!("- {} -> {}: 原始 {}", task.input, task.output, numbers.size(stats.raw_bytes)),
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                first_code.get_or_insert(exit_code(&e));
+                if command.json {
+                    rows.push(format!("    {{\"input\": {}, \"output\": {}, \"error\": {}}}", input, output, json_string(&e.to_string())));
+                    continue;
+                }
+                println!("- {} -> {}: 失败: {}", task.input, task.output, e);
+            }
+        }
+    }
+    let seconds = start_time.elapsed().as_secs_f64();
+    if command.json {
+        println!(
+            "{{\n  \"files\": [\n{}\n  ],\n  \"failed\": {},\n  \"raw_bytes\": {},\n  \"compressed_bytes\": {},\n  \"seconds\": {:.3},\n  \"mb_per_sec\": {:.2},\n  \"peak_buffer_bytes\": {},\n  \"peak_rss_bytes\": {},\n  {}\n}}",
+            rows.join(",\n"),
+            failed,
+            raw_total,
+            compressed_total,
+            seconds,
+            throughput(raw_total, seconds),
+            memory::peak_heap(),
+            optional_json(memory::peak_rss()),
+            formatted_json(raw_total, Some(compressed_total), seconds, ",\n  ")
+        );
+    } else {
+        println!(
+            "共 {} 个文件 ({} 个失败): 原始 {}, 压缩后 {}, 耗时 {}, {}",
+            numbers.integer((tasks.len() + unreadable.len()) as u64),
+            failed,
+            numbers.size(raw_total),
+            numbers.size(compressed_total),
+            numbers.duration(seconds),
+            numbers.rate(raw_total, seconds)
+        );
+        println!("内存峰值: {}", peak_memory(numbers));
+    }
+    batch_result(first_code, failed, tasks.len() + unreadable.len(), "个文件处理失败")
+}
+
+// 汇总中的内存峰值: 堆上的缓冲, 以及(Linux 上)进程的常驻内存
+pub(crate) fn peak_memory(numbers: &NumberFormat) -> String {
+    match memory::peak_rss() {
+        Some(rss) => format!("缓冲 {}, 常驻内存 {}", numbers.size(memory::peak_heap()), numbers.size(rss)),
+        None => format!("缓冲 {}", numbers.size(memory::peak_heap())),
+    }
+}
+
+// JSON 字符串字面量, 转义引号、反斜杠与控制字符
+pub(crate) fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// 把原文件的修改时间与权限应用到输出文件, 与 gzip/zstd 命令行一致
+fn copy_file_metadata(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = std::fs::metadata(from)?;
+    let file = File::options().write(true).open(to)?;
+    file.set_modified(metadata.modified()?)?;
+    file.set_permissions(metadata.permissions())
+}
+
+// 批量执行的结果: 全部失败时沿用第一个失败的退出码, 否则为部分成功
+pub(crate) fn batch_result(first_code: Option<u8>, failed: usize, total: usize, what: &str) -> io::Result<()> {
+    match first_code {
+        None => Ok(()),
+        Some(code) => Err(failure(if failed < total { EXIT_PARTIAL } else { code }, format!("{} {}", failed, what))),
+    }
+}
+
+// 不切分, 把整个文件压缩为一个文件; 与切分共用压缩格式、覆盖确认与退出码
+pub fn run_compress(args: &[String]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let command = FileCommand::parse(args)?;
+    let format = command.format.unwrap_or(Format::Zstd);
+    if format == Format::SevenZip {
+        return Err(invalid("7z 只用于分卷集, 整个文件压缩请使用其他格式".to_string()));
+    }
+    if command.zstd.is_set() && format != Format::Zstd {
+        return Err(invalid("--level 与 --threads 只用于 zstd 格式".to_string()));
+    }
+    let extension = format!(".{}", format.extension());
+    let (tasks, unreadable) = if command.is_tree() {
+        // 已是目标格式的文件不再压缩
+        command.tree_tasks(|name| (!name.ends_with(&extension)).then(|| format!("{}{}", name, extension)))?
+    } else {
+        let tasks = match &command.paths[..] {
+            [input] if input == "-" || command.stdout => vec![FileTask { input: input.clone(), output: "-".to_string() }],
+            [input] => {
+                // 远程输入的压缩结果写到当前目录
+                let name = input.rsplit('/').next().filter(|name| !name.is_empty()).unwrap_or("output");
+                let base = if matches!(Source::parse(input), Source::Local(_)) { input } else { name };
+                vec![FileTask { input: input.clone(), output: format!("{}{}", base, extension) }]
+            }
+            [input, output] if !command.stdout => vec![FileTask { input: input.clone(), output: output.clone() }],
+            _ => return Err(invalid("用法: compress <input_file|dir> [output_file|output_dir] [选项]".to_string())),
+        };
+        (tasks, Vec::new())
+    };
+    if tasks.iter().any(|task| task.output == "-") && io::stdout().is_terminal() && !command.force {
+        return Err(invalid("拒绝把压缩数据写到终端; 请重定向标准输出, 或用 --force 强制写出".to_string()));
+    }
+    run_file_tasks(&command, &tasks, &unreadable, |task| {
+        let input = open_input(&task.input)?;
+        let (raw_bytes, compressed_bytes) = write_output(&task.output, |out| compress_stream(input, out, format, command.zstd))?;
+        Ok(FileStats { format: format.name(), raw_bytes, compressed_bytes: Some(compressed_bytes) })
+    })
+}
+
+// 按魔数识别格式解压单个文件, 见 codec::decompressors
+pub fn run_decompress(args: &[String]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let command = FileCommand::parse(args)?;
+    if command.format.is_some() {
+        return Err(invalid("decompress 按文件头识别格式, 不需要 --format".to_string()));
+    }
+    if command.zstd.is_set() {
+        return Err(invalid("--level 与 --threads 只用于压缩".to_string()));
+    }
+    // 去掉压缩格式的扩展名得到输出文件名
+    let strip = |name: &str| {
+        let stem = [".zst", ".gz", ".xz", ".lz4", ".bz2"].iter().find_map(|ext| name.strip_suffix(ext));
+        stem.filter(|stem| !stem.is_empty() && !stem.ends_with('/')).map(str::to_string)
+    };
+    let (tasks, unreadable) = if command.is_tree() {
+        command.tree_tasks(strip)?
+    } else {
+        let tasks = match &command.paths[..] {
+            [input] if input == "-" || command.stdout => vec![FileTask { input: input.clone(), output: "-".to_string() }],
+            [input] => {
+                let name = input.rsplit('/').next().unwrap_or(input);
+                let base = if matches!(Source::parse(input), Source::Local(_)) { input.as_str() } else { name };
+                let output = strip(base).ok_or_else(|| invalid(format!("无法从 {} 推断输出文件名, 请指定 output_file", input)))?;
+                vec![FileTask { input: input.clone(), output }]
+            }
+            [input, output] if !command.stdout => vec![FileTask { input: input.clone(), output: output.clone() }],
+            _ => return Err(invalid("用法: decompress <file.zst|.gz|.xz|.lz4|.bz2|dir> [output_file|output_dir] [选项]".to_string())),
+        };
+        (tasks, Vec::new())
+    };
+    // 单个文件在终端上显示进度; 批量处理与 --json 时只有最后的报告
+    let progress = !command.is_tree() && !command.json && io::stderr().is_terminal();
+    run_file_tasks(&command, &tasks, &unreadable, |task| {
+        let mut input = open_input(&task.input)?;
+        if progress {
+            let total = std::fs::metadata(&task.input).ok().filter(|_| task.input != "-").map(|metadata| metadata.len());
+            input = Box::new(ProgressReader::new(input, "解压", total, command.numbers));
+        }
+        let (format, mut decoder) = codec::open_decoder(input)?;
+        let (_, raw_bytes) = write_output(&task.output, |out| io::copy(&mut decoder, out))?;
+        let compressed_bytes = std::fs::metadata(&task.input).ok().filter(|_| task.input != "-").map(|metadata| metadata.len());
+        Ok(FileStats { format, raw_bytes, compressed_bytes })
+    })
+}
+
+// 压缩整个输入写到 output, 返回原始字节数
+fn compress_stream(reader: impl Read, mut output: impl Write, format: Format, zstd: ZstdParams) -> io::Result<u64> {
+    let mut counted = ReadCounter { inner: reader, bytes: 0 };
+    codec::compressor(format, zstd).compress_stream(&mut counted, &mut output)?;
+    Ok(counted.bytes)
+}
+
+// 打开输入, - 为标准输入
+fn open_input(input: &str) -> io::Result<Box<dyn Read + Send>> {
+    if input == "-" {
+        Ok(Box::new(io::stdin()))
+    } else {
+        Source::parse(input).open()
+    }
+}
+
+// 把 write 的结果写到 output(- 为标准输出), 返回 write 的结果与写出的字节数; 写到文件时落盘后才返回
+fn write_output<T>(output: &str, write: impl FnOnce(&mut dyn Write) -> io::Result<T>) -> io::Result<(T, u64)> {
+    if output == "-" {
+        let mut out = WriteCounter { inner: io::BufWriter::new(io::stdout().lock()), bytes: 0 };
+        let result = write(&mut out)?;
+        out.flush()?;
+        return Ok((result, out.bytes));
+    }
+    let mut out = WriteCounter { inner: io::BufWriter::new(File::create(output)?), bytes: 0 };
+    let result = write(&mut out)?;
+    let file = out.inner.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    Ok((result, out.bytes))
+}
+
+// 统计写出的字节数
+struct WriteCounter<W> {
+    inner: W,
+    bytes: u64,
+}
+
+impl<W: Write> Write for WriteCounter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// 统计读过的字节数
+struct ReadCounter<R> {
+    inner: R,
+    bytes: u64,
+}
+
+impl<R: Read> Read for ReadCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+}
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+// 在终端的同一行上刷新已读取的字节数与百分比, 结束时清除该行
+struct ProgressReader<R> {
+    inner: R,
+    label: &'static str,
+    total: Option<u64>,
+    numbers: NumberFormat,
+    bytes: u64,
+    shown: Instant,
+    printed: bool,
+}
+
+impl<R> ProgressReader<R> {
+    fn new(inner: R, label: &'static str, total: Option<u64>, numbers: NumberFormat) -> Self {
+        ProgressReader { inner, label, total, numbers, bytes: 0, shown: Instant::now(), printed: false }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes += n as u64;
+        if self.shown.elapsed() >= PROGRESS_INTERVAL {
+            self.shown = Instant::now();
+            self.printed = true;
+            // 单位可能变短, 清除上一次输出的行尾
+            let numbers = &self.numbers;
+            match self.total.filter(|&total| total > 0) {
+                Some(total) => eprint!("\r{} {} / {} ({:.0}%)\x1b[K", self.label, numbers.size(self.bytes), numbers.size(total), self.bytes as f64 * 100.0 / total as f64),
+                None => eprint!("\r{} {}\x1b[K", self.label, numbers.size(self.bytes)),
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<R> Drop for ProgressReader<R> {
+    fn drop(&mut self) {
+        if self.printed {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::GBK;
+    use crate::codec;
+    use crate::config::{inject_failure, parse_byte_count, Config, FailureStage, InjectedFailure};
+
+    #[test]
+    fn file_command_options() {
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--best"])).unwrap().zstd.level(), 19);
+        let threads = Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--level", "19", "--threads", "4"]));
+        if cfg!(feature = "zstdmt") {
+            let config = threads.unwrap();
+            assert_eq!((config.zstd.level(), config.zstd.threads()), (19, 4));
+            let config = Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--compress-threads", "auto"])).unwrap();
+            assert!(config.zstd.threads() >= 1);
+            let command = FileCommand::parse(&args(&["a.log", "--level", "-5", "--threads", "2"])).unwrap();
+            assert_eq!((command.zstd.level(), command.zstd.threads()), (-5, 2));
+        } else {
+            assert!(threads.unwrap_err().contains("zstdmt"));
+        }
+        let config = Config::parse(args(&["zstd_compressor", "/dev/null", "out/a", "1", "--max-bytes", "2G"])).unwrap();
+        assert!(config.binary && config.records.as_deref() == Some("fixed:1048576"));
+        assert_eq!(config.max_bytes, Some(2 << 30));
+        let config = Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--record-sep-regex", "^----- BEGIN"])).unwrap();
+        assert_eq!(config.records.as_deref(), Some("regex:^----- BEGIN"));
+        assert!(Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--record-sep-regex", "(a"])).unwrap_err().contains("正则"));
+        assert!(Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--binary", "--drop-invalid"])).is_err());
+        assert_eq!(parse_byte_count("512"), Ok(512));
+        assert!(parse_byte_count("0").is_err() && parse_byte_count("1T").is_err());
+        let config = Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--inject-failure", "write:chunk=5"])).unwrap();
+        assert!(inject_failure(&config, FailureStage::Write, 5) && !inject_failure(&config, FailureStage::Write, 4));
+        assert!(InjectedFailure::parse("write:chunk=0").is_err());
+        assert!(InjectedFailure::parse("read:chunk=1").is_err());
+        assert_eq!(Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--best", "--fast"])).unwrap().zstd.level(), 1);
+        assert!(Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--level", "99"])).is_err());
+        assert!(Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--format", "snappy", "--level", "5"])).is_err());
+
+        let config = Config::parse(args(&["zstd_compressor", "a.log", "out/a", "--chunk-size", "2", "--line-ending", "CRLF", "--encoding", "GBK"])).unwrap();
+        assert_eq!((config.chunk_size, config.line_ending.as_str(), config.encoding), (2 * 1024 * 1024, "\r\n", GBK));
+        let config = Config::parse(args(&["zstd_compressor", "a.log", "out/a", "4", "CR", "--line-ending", "LF"])).unwrap();
+        assert_eq!((config.chunk_size, config.line_ending.as_str()), (4 * 1024 * 1024, "\n"));
+
+        assert!(FileCommand::parse(&args(&["a.log", "--threads", "x"])).is_err());
+
+        let command = FileCommand::parse(&args(&["a.log", "-c", "-f"])).unwrap();
+        assert!(command.stdout && command.force);
+        assert!(FileCommand::parse(&args(&["-r", "-"])).is_err());
+
+        let data = b"line\n".repeat(10000);
+        let compressed = codec::compress(&data, Format::Zstd, command.zstd).unwrap();
+        assert_eq!(codec::decode_all(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("a b/c.log"), "\"a b/c.log\"");
+        assert_eq!(json_string("say \"hi\"\\\n\u{1}"), "\"say \\\"hi\\\"\\\\\\n\\u0001\"");
+    }
+
+    #[test]
+    fn glob_patterns_filter_relative_paths() {
+        assert!(glob_match("*.log", "a/b/x.log"));
+        assert!(!glob_match("*.log", "a/b/x.log.zst"));
+        assert!(glob_match("a/*.log", "a/x.log"));
+        assert!(!glob_match("a/*.log", "a/b/x.log"));
+        assert!(glob_match("a/**/x.log", "a/x.log"));
+        assert!(glob_match("a/**/x.log", "a/b/c/x.log"));
+        assert!(!glob_match("a/**/x.log", "a/b/cx.log"));
+        assert!(glob_match("**", "a/b"));
+        assert!(glob_match("日志-??.txt", "日志-01.txt"));
+        assert!(!glob_match("a?b/c", "a/b/c"));
+    }
+}
@@ -0,0 +1,283 @@
+// zstd 帧的扫描: info, split-frames, 以及按单文件输出的索引只读取所需的帧(extract)
+
+use std::fs::File;
+use std::io::{self, Write, Read, Seek, SeekFrom};
+use crate::codec::{self, SKIPPABLE_MAGIC, ZSTD_MAGIC};
+use crate::naming::chunk_path;
+use crate::source::Source;
+
+// 已有 .zst 文件中的一个帧
+#[derive(Debug, PartialEq)]
+pub(crate) struct FrameInfo {
+    offset: u64,
+    pub(crate) len: u64,
+    // 帧头中记录的原始大小, 流式压缩的帧可能没有
+    content_size: Option<u64>,
+    skippable: bool,
+}
+
+fn read_le<R: Read>(reader: &mut R, n: usize) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes[..n])?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+// 只读取帧头与块头来定位帧边界, 不解压数据
+pub(crate) fn scan_frames<R: Read + Seek>(reader: &mut R, file_len: u64) -> io::Result<Vec<FrameInfo>> {
+    let invalid = |offset: u64, what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("偏移 {} 处{}", offset, what));
+    let mut frames = Vec::new();
+    let mut offset = 0;
+
+    while offset < file_len {
+        reader.seek(SeekFrom::Start(offset))?;
+        let magic = read_le(reader, 4)? as u32;
+        let frame = if SKIPPABLE_MAGIC.contains(&magic) {
+            FrameInfo { offset, len: 8 + read_le(reader, 4)?, content_size: None, skippable: true }
+        } else if magic == ZSTD_MAGIC {
+            let descriptor = read_le(reader, 1)? as u8;
+            if descriptor & 0x08 != 0 {
+                return Err(invalid(offset, "的帧头保留位不为 0"));
+            }
+            let single_segment = descriptor & 0x20 != 0;
+            let window_len = if single_segment { 0 } else { 1 };
+            let dict_len = [0, 1, 2, 4][(descriptor & 0x03) as usize];
+            let size_len = match descriptor >> 6 {
+                0 => single_segment as usize,
+                1 => 2,
+                2 => 4,
+                _ => 8,
+            };
+
+            reader.seek(SeekFrom::Current(window_len + dict_len))?;
+            let content_size = match size_len {
+                0 => None,
+                // 两字节的原始大小以 256 为起点
+                2 => Some(read_le(reader, 2)? + 256),
+                n => Some(read_le(reader, n)?),
+            };
+
+            let mut pos = offset + 5 + (window_len + dict_len) as u64 + size_len as u64;
+            loop {
+                reader.seek(SeekFrom::Start(pos))?;
+                let header = read_le(reader, 3)?;
+                let block_len = match (header >> 1) & 0x03 {
+                    // RLE 块只存一个字节
+                    1 => 1,
+                    3 => return Err(invalid(pos, "的块类型无效")),
+                    _ => header >> 3,
+                };
+                pos += 3 + block_len;
+                if header & 1 == 1 {
+                    break;
+                }
+            }
+            if descriptor & 0x04 != 0 {
+                pos += 4;
+            }
+            FrameInfo { offset, len: pos - offset, content_size, skippable: false }
+        } else {
+            return Err(invalid(offset, "不是 zstd 帧"));
+        };
+
+        if frame.offset + frame.len > file_len {
+            return Err(invalid(offset, "的帧被截断"));
+        }
+        offset += frame.len;
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+pub fn run_info(path: &str) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let frames = scan_frames(&mut file, file_len)?;
+
+    println!("{}: {} 字节, {} 个帧", path, file_len, frames.len());
+    for (i, frame) in frames.iter().enumerate() {
+        let content = match (frame.skippable, frame.content_size) {
+            (true, _) => "可跳过帧".to_string(),
+            (false, Some(size)) => format!("原始 {} 字节", size),
+            (false, None) => "原始大小未知".to_string(),
+        };
+        println!("- 帧 {}: 偏移 {}, 压缩后 {} 字节, {}", i + 1, frame.offset, frame.len, content);
+    }
+    Ok(())
+}
+
+// 按帧原样拷贝到各分卷文件; 可跳过帧只含元数据, 不输出
+pub fn run_split_frames(path: &str, output_prefix: &str) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let frames = scan_frames(&mut file, file_len)?;
+
+    let mut chunk_number = 1;
+    for frame in frames.iter().filter(|f| !f.skippable) {
+        file.seek(SeekFrom::Start(frame.offset))?;
+        let mut output_file = File::create(chunk_path(output_prefix, chunk_number, "zst"))?;
+        io::copy(&mut Read::by_ref(&mut file).take(frame.len), &mut output_file)?;
+        println!("写入分卷 {} (压缩后 {} 字节)", chunk_number, frame.len);
+        chunk_number += 1;
+    }
+    println!("共拆分出 {} 个分卷", chunk_number - 1);
+    Ok(())
+}
+
+// --single-output 索引中的一行
+#[derive(Debug, PartialEq)]
+struct IndexEntry {
+    chunk: usize,
+    frame_offset: u64,
+    frame_len: u64,
+    raw_len: u64,
+}
+
+fn parse_index(text: &str) -> io::Result<Vec<IndexEntry>> {
+    let mut entries = Vec::new();
+    for line in text.lines().filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let fields: Vec<u64> = line.split('\t').map(|f| f.parse().ok()).collect::<Option<_>>().unwrap_or_default();
+        let [chunk, frame_offset, frame_len, _input_offset, raw_len] = fields[..] else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("无效的索引行: {}", line)));
+        };
+        entries.push(IndexEntry { chunk: chunk as usize, frame_offset, frame_len, raw_len });
+    }
+    Ok(entries)
+}
+
+// 与合并后数据中 [start, end) 重叠的帧, 附带各帧在合并后数据中的起始位置
+fn overlapping_frames(entries: &[IndexEntry], start: u64, end: u64) -> Vec<(&IndexEntry, u64)> {
+    let mut pos = 0;
+    let mut frames = Vec::new();
+    for entry in entries {
+        if pos < end && pos + entry.raw_len > start {
+            frames.push((entry, pos));
+        }
+        pos += entry.raw_len;
+    }
+    frames
+}
+
+// 解析 "A-B"(含两端) 或 "A-"(到末尾) 形式的字节范围, 返回左闭右开区间
+fn parse_byte_range(value: &str) -> Result<(u64, u64), String> {
+    let invalid = || format!("无效的字节范围: {}", value);
+    let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+    let start = start.trim().parse::<u64>().map_err(|_| invalid())?;
+    let end = match end.trim() {
+        "" => u64::MAX,
+        end => end.parse::<u64>().map_err(|_| invalid())?.checked_add(1).ok_or_else(invalid)?,
+    };
+    if end <= start {
+        return Err(invalid());
+    }
+    Ok((start, end))
+}
+
+// 按索引只下载与范围重叠的帧, 解压后截取所需部分
+pub fn run_extract(path: &str, range: &str, output: &str) -> io::Result<()> {
+    let (start, end) = parse_byte_range(range).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut index = String::new();
+    Source::parse(&format!("{}.idx", path)).open()?.read_to_string(&mut index)?;
+    let entries = parse_index(&index)?;
+    let source = Source::parse(path);
+
+    let mut out: Box<dyn Write> = if output == "-" {
+        Box::new(io::BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(io::BufWriter::new(File::create(output)?))
+    };
+    let frames = overlapping_frames(&entries, start, end);
+    let (mut downloaded, mut written) = (0, 0);
+    for (entry, frame_start) in &frames {
+        let data = codec::decode_all(&source.read_range(entry.frame_offset, entry.frame_len)?)?;
+        let from = start.saturating_sub(*frame_start) as usize;
+        let to = (end.min(frame_start + entry.raw_len) - frame_start) as usize;
+        out.write_all(&data[from..to])?;
+        eprintln!("读取分卷 {} (压缩后 {} 字节)", entry.chunk, entry.frame_len);
+        downloaded += entry.frame_len;
+        written += (to - from) as u64;
+    }
+    out.flush()?;
+
+    let total: u64 = entries.iter().map(|e| e.frame_len).sum();
+    eprintln!("提取 {} 字节, 读取 {}/{} 个分卷共 {}/{} 字节压缩数据", written, frames.len(), entries.len(), downloaded, total);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+    use crate::split::run_split;
+    use crate::testing::{split_config, Scratch};
+
+    #[test]
+    fn scan_frames_finds_every_frame_boundary() {
+        let large: Vec<u8> = (0..400_000u32).flat_map(|i| (i % 251).to_le_bytes()).collect();
+        let mut data = zstd::encode_all(&b"hello\n"[..], 3).unwrap();
+        let first_len = data.len() as u64;
+        data.extend_from_slice(&0x184D_2A50u32.to_le_bytes());
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(b"abc");
+        data.extend(zstd::bulk::compress(&large, 1).unwrap());
+
+        let frames = scan_frames(&mut io::Cursor::new(&data), data.len() as u64).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!((frames[0].offset, frames[0].len), (0, first_len));
+        assert_eq!(frames[1], FrameInfo { offset: first_len, len: 11, content_size: None, skippable: true });
+        assert_eq!(frames[2].offset, first_len + 11);
+        assert_eq!(frames[2].content_size, Some(large.len() as u64));
+
+        let last = &data[frames[2].offset as usize..];
+        assert_eq!(frames[2].len, last.len() as u64);
+        assert_eq!(zstd::decode_all(last).unwrap(), large);
+
+        let truncated = &data[..data.len() - 1];
+        assert!(scan_frames(&mut io::Cursor::new(truncated), truncated.len() as u64).is_err());
+    }
+
+    #[test]
+    fn byte_range_selects_only_overlapping_frames() {
+        assert_eq!(parse_byte_range("10-19"), Ok((10, 20)));
+        assert_eq!(parse_byte_range("5-"), Ok((5, u64::MAX)));
+        assert!(parse_byte_range("9-3").is_err());
+        assert!(parse_byte_range("abc").is_err());
+
+        let index = "# chunk\tframe_offset\tframe_len\tinput_offset\traw_len\n1\t0\t7\t0\t10\n2\t7\t5\t10\t10\n3\t12\t9\t20\t10\n";
+        let entries = parse_index(index).unwrap();
+        assert_eq!(entries[1], IndexEntry { chunk: 2, frame_offset: 7, frame_len: 5, raw_len: 10 });
+
+        let chunks = |start, end| overlapping_frames(&entries, start, end).iter().map(|(e, pos)| (e.chunk, *pos)).collect::<Vec<_>>();
+        assert_eq!(chunks(0, 10), [(1, 0)]);
+        assert_eq!(chunks(9, 11), [(1, 0), (2, 10)]);
+        assert_eq!(chunks(25, u64::MAX), [(3, 20)]);
+        assert_eq!(chunks(30, 40), []);
+        assert!(parse_index("1\t2\n").is_err());
+    }
+
+    #[test]
+    fn single_output_frames_are_indexed() {
+        let dir = Scratch::new("single_output");
+        let (input, text) = dir.lines("in.log", 20_000);
+        for format in ["zstd", "gzip"] {
+            let single = dir.arg(&format!("all.{}", format));
+            run_split(&split_config(&[&input, &dir.arg("part"), "--format", format, "--single-output", &single], 32 * 1024)).unwrap();
+
+            // 整个文件按普通的多帧/多成员文件解压
+            let data = std::fs::read(&single).unwrap();
+            assert!(codec::decode_all(&data).unwrap() == text.as_bytes(), "{}", format);
+
+            // 索引中的帧首尾相接, 每一帧单独解压得到对应的一段输入
+            let entries = parse_index(&std::fs::read_to_string(format!("{}.idx", single)).unwrap()).unwrap();
+            assert!(entries.len() > 2, "{}", format);
+            let (mut frame_offset, mut raw_offset) = (0, 0);
+            for entry in &entries {
+                assert_eq!(entry.frame_offset, frame_offset);
+                let frame = codec::decode_all(&data[frame_offset as usize..(frame_offset + entry.frame_len) as usize]).unwrap();
+                assert!(frame == text.as_bytes()[raw_offset as usize..(raw_offset + entry.raw_len) as usize]);
+                frame_offset += entry.frame_len;
+                raw_offset += entry.raw_len;
+            }
+            assert_eq!(frame_offset, data.len() as u64);
+        }
+    }
+}
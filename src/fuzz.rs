@@ -0,0 +1,135 @@
+// 隐藏的开发者模式 --fuzz-roundtrip: 随机生成输入与选项, 检查切分后合并能还原
+
+use std::io;
+use encoding_rs::{Encoding, UTF_8, GBK};
+use crate::{codec, Chunker, Format, ZstdParams};
+
+// 开发者模式 --fuzz-roundtrip: 随机生成各种编码/换行符/大小的输入, 在进程内切分、压缩、
+// 解压合并后校验逐字节一致, 用于在新平台上做浸泡测试
+struct Rng(u64);
+
+impl Rng {
+    // xorshift64*
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+struct FuzzCase {
+    encoding: &'static Encoding,
+    line_ending: String,
+    data: Vec<u8>,
+    buffer_size: usize,
+    chunk_size: usize,
+}
+
+fn random_case(rng: &mut Rng) -> FuzzCase {
+    let encoding = rng.pick(&[UTF_8, GBK]);
+    let line_ending = rng.pick(&["\n", "\r\n", "\r", "\r\n\r\n", "@@", "。", "分隔"]).to_string();
+    let pieces = ["a", "Z", "0", " ", "@", "\r", "\n", "中", "文", "丂", "。", "分", "隔", "€", line_ending.as_str()];
+
+    let mut data = Vec::new();
+    for _ in 0..rng.below(200) {
+        for _ in 0..rng.below(20) {
+            data.extend_from_slice(&encoding.encode(rng.pick(&pieces)).0);
+        }
+        // 偶尔混入非法字节与截断的多字节字符
+        if rng.below(10) == 0 {
+            data.extend_from_slice(rng.pick(&[&b"\xff"[..], b"\x81", b"\xe4\xb8", b"\x81\x30"]));
+        }
+        if rng.below(4) != 0 {
+            data.extend_from_slice(&encoding.encode(&line_ending).0);
+        }
+    }
+
+    FuzzCase {
+        encoding,
+        line_ending,
+        data,
+        buffer_size: 1 + rng.below(64),
+        chunk_size: 1 + rng.below(256),
+    }
+}
+
+fn check_roundtrip(case: &FuzzCase) -> Result<(), String> {
+    let delimiter = case.encoding.encode(&case.line_ending).0;
+    let mut chunker = Chunker::new(case.chunk_size, &case.line_ending, case.encoding);
+    chunker.warn_invalid = false;
+
+    let mut compressed = Vec::new();
+    let mut emit = |chunk: &[u8], _offset: usize| -> io::Result<()> {
+        compressed.push((codec::compress(chunk, Format::Zstd, ZstdParams { level: Some(1), threads: None })?, chunk.ends_with(&delimiter)));
+        Ok(())
+    };
+    for buffer in case.data.chunks(case.buffer_size) {
+        chunker.push(buffer, &mut emit).map_err(|e| e.to_string())?;
+    }
+    chunker.finish(&mut emit).map_err(|e| e.to_string())?;
+
+    let mut joined = Vec::with_capacity(case.data.len());
+    for (i, (frame, ends_on_boundary)) in compressed.iter().enumerate() {
+        if i + 1 < compressed.len() && !ends_on_boundary {
+            return Err(format!("分卷 {} 没有在换行符处结束", i + 1));
+        }
+        joined.extend(codec::decode_all(frame).map_err(|e| e.to_string())?);
+    }
+    if joined != case.data {
+        return Err(format!("合并结果与输入不一致 ({} 字节 vs {} 字节)", joined.len(), case.data.len()));
+    }
+    Ok(())
+}
+
+// iterations 为 0 时一直运行直到出错
+fn fuzz_roundtrip(iterations: u64, seed: u64) -> Result<(), String> {
+    let mut i = 0;
+    while iterations == 0 || i < iterations {
+        let case_seed = seed.wrapping_add(i);
+        let case = random_case(&mut Rng(case_seed | 1));
+        check_roundtrip(&case).map_err(|e| {
+            format!(
+                "种子 {} 失败: {} (编码 {}, 换行符 {}, 缓冲区 {}, 分块 {})",
+                case_seed, e, case.encoding.name(), case.line_ending.escape_default(), case.buffer_size, case.chunk_size
+            )
+        })?;
+        i += 1;
+        if i % 10000 == 0 {
+            println!("已通过 {} 轮", i);
+        }
+    }
+    Ok(())
+}
+
+pub fn run_fuzz_roundtrip(args: &[String]) -> io::Result<()> {
+    let iterations = args.first().and_then(|v| v.parse().ok()).unwrap_or(10000);
+    let seed = args.get(1).and_then(|v| v.parse().ok()).unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(1)
+    });
+    println!("往返测试: 种子 {}, 轮数 {}", seed, iterations);
+    fuzz_roundtrip(iterations, seed).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    println!("往返测试全部通过");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_roundtrips() {
+        fuzz_roundtrip(2000, 0x5eed).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+// gRPC 服务模式: 双向流的 zstd_compressor.Splitter/Split. 客户端先发一条带 options 的消息, 之后的消息只带 data;
+// 服务端每切出一个分卷就流式返回压缩后的数据与元数据, 客户端关闭发送方向后返回最后一个分卷并结束.
+// 对应的 proto(客户端生成代码用):
+//
+//   syntax = "proto3";
+//   package zstd_compressor;
+//   service Splitter { rpc Split(stream SplitRequest) returns (stream SplitChunk); }
+//   message SplitOptions {
+//     uint64 chunk_size = 1;   // 目标分卷大小(字节), 0 为默认的 100 MB
+//     uint64 lines = 2;        // 非 0 时每个分卷正好这么多行
+//     string line_ending = 3;  // LF, CRLF, CR 或 custom:xxx, 空为 LF
+//     string encoding = 4;     // UTF-8 或 GBK, 空为 UTF-8
+//     string format = 5;       // zstd, gzip, xz, lz4, snappy, 空为 zstd
+//     int32 level = 6;         // zstd 级别, 0 为默认
+//   }
+//   message SplitRequest { SplitOptions options = 1; bytes data = 2; }
+//   message SplitChunk {
+//     uint64 number = 1; uint64 offset = 2; uint64 raw_bytes = 3; bytes sha256 = 4; string extension = 5; bytes data = 6;
+//   }
+//
+// 切分与压缩在每个调用各自的线程中进行, 两个方向都经有界通道传递, 客户端发送过快或读取过慢时互相限速
+
+use std::io;
+use std::net::SocketAddr;
+use std::thread;
+
+use tokio::sync::mpsc;
+use tonic::codegen::{http, tokio_stream, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::server::{Grpc, NamedService, StreamingService};
+use tonic::{Request, Response, Status, Streaming};
+use zstd_compressor::config;
+use zstd_compressor::hash::sha256;
+use zstd_compressor::{Chunk, Format, SplitConfig, Splitter};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SplitOptions {
+    #[prost(uint64, tag = "1")]
+    pub chunk_size: u64,
+    #[prost(uint64, tag = "2")]
+    pub lines: u64,
+    #[prost(string, tag = "3")]
+    pub line_ending: String,
+    #[prost(string, tag = "4")]
+    pub encoding: String,
+    #[prost(string, tag = "5")]
+    pub format: String,
+    #[prost(int32, tag = "6")]
+    pub level: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SplitRequest {
+    #[prost(message, optional, tag = "1")]
+    pub options: Option<SplitOptions>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SplitChunk {
+    #[prost(uint64, tag = "1")]
+    pub number: u64,
+    #[prost(uint64, tag = "2")]
+    pub offset: u64,
+    #[prost(uint64, tag = "3")]
+    pub raw_bytes: u64,
+    // 原始内容的 SHA-256
+    #[prost(bytes = "vec", tag = "4")]
+    pub sha256: Vec<u8>,
+    #[prost(string, tag = "5")]
+    pub extension: String,
+    #[prost(bytes = "vec", tag = "6")]
+    pub data: Vec<u8>,
+}
+
+const SPLIT_PATH: &str = "/zstd_compressor.Splitter/Split";
+
+// 通道中最多积压的消息数
+const QUEUE_DEPTH: usize = 4;
+
+fn status(e: io::Error) -> Status {
+    match e.kind() {
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => Status::invalid_argument(e.to_string()),
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+// 第一条消息中的选项, 与命令行同名参数的取值相同
+fn split_config(options: &SplitOptions) -> Result<SplitConfig, String> {
+    let mut config = SplitConfig::new(if options.chunk_size == 0 { config::DEFAULT_CHUNK_SIZE } else { options.chunk_size as usize });
+    config.lines = (options.lines > 0).then_some(options.lines as usize);
+    if !options.line_ending.is_empty() {
+        config.line_ending = config::parse_line_ending(&options.line_ending)?;
+    }
+    if !options.encoding.is_empty() {
+        config.encoding = config::parse_encoding(&options.encoding)?;
+    }
+    if !options.format.is_empty() {
+        config.format = Format::parse(&options.format)?;
+    }
+    // 7z 的分卷是同一个归档的各段, 单独不能解压
+    if config.format == Format::SevenZip {
+        return Err("gRPC 服务不支持 7z 格式".to_string());
+    }
+    if options.level != 0 {
+        config.zstd.parse_flag("level", || Ok(options.level.to_string()))?;
+    }
+    // 服务端不在标准错误上告警, 无效编码直接作为错误返回
+    config.fail_on_invalid = true;
+    Ok(config)
+}
+
+fn split_chunk(chunk: Chunk, extension: &str) -> SplitChunk {
+    SplitChunk {
+        number: chunk.number as u64,
+        offset: chunk.offset as u64,
+        raw_bytes: chunk.raw.len() as u64,
+        sha256: sha256(chunk.raw).to_vec(),
+        extension: extension.to_string(),
+        data: chunk.compressed,
+    }
+}
+
+// 一次调用的切分: 依次取出客户端的数据, 切出的分卷交给 emit; 客户端的流出错时不写出剩余数据
+fn split_stream(options: &SplitOptions, inbound: impl IntoIterator<Item = io::Result<Vec<u8>>>, mut emit: impl FnMut(SplitChunk) -> io::Result<()>) -> io::Result<()> {
+    let config = split_config(options).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let extension = config.format.extension();
+    let mut splitter = Splitter::new(config)?;
+    let mut emit = |chunk: Chunk| emit(split_chunk(chunk, extension));
+    for data in inbound {
+        splitter.push(&data?, &mut emit)?;
+    }
+    splitter.finish(&mut emit)?;
+    Ok(())
+}
+
+#[derive(Clone)]
+struct SplitterService;
+
+impl SplitterService {
+    async fn split(mut inbound: Streaming<SplitRequest>) -> Result<tokio_stream::wrappers::ReceiverStream<Result<SplitChunk, Status>>, Status> {
+        let first = inbound.message().await?.ok_or_else(|| Status::invalid_argument("请求为空, 第一条消息需要带 options"))?;
+        let options = first.options.unwrap_or_default();
+        split_config(&options).map_err(Status::invalid_argument)?;
+
+        let (data_tx, mut data_rx) = mpsc::channel::<io::Result<Vec<u8>>>(QUEUE_DEPTH);
+        let (chunk_tx, chunk_rx) = mpsc::channel(QUEUE_DEPTH);
+        thread::spawn(move || {
+            let inbound = std::iter::from_fn(|| data_rx.blocking_recv());
+            let send = |chunk| chunk_tx.blocking_send(Ok(chunk)).map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "客户端已断开"));
+            if let Err(e) = split_stream(&options, inbound, send) {
+                let _ = chunk_tx.blocking_send(Err(status(e)));
+            }
+        });
+        tokio::spawn(async move {
+            if data_tx.send(Ok(first.data)).await.is_err() {
+                return;
+            }
+            loop {
+                let message = match inbound.message().await {
+                    Ok(Some(request)) => Ok(request.data),
+                    Ok(None) => return,
+                    Err(e) => Err(io::Error::new(io::ErrorKind::ConnectionAborted, format!("客户端的请求流出错: {}", e.message()))),
+                };
+                let failed = message.is_err();
+                if data_tx.send(message).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+        Ok(tokio_stream::wrappers::ReceiverStream::new(chunk_rx))
+    }
+}
+
+struct SplitCall;
+
+impl StreamingService<SplitRequest> for SplitCall {
+    type Response = SplitChunk;
+    type ResponseStream = tokio_stream::wrappers::ReceiverStream<Result<SplitChunk, Status>>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<Streaming<SplitRequest>>) -> Self::Future {
+        Box::pin(async move { SplitterService::split(request.into_inner()).await.map(Response::new) })
+    }
+}
+
+impl<B> Service<http::Request<B>> for SplitterService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if request.uri().path() != SPLIT_PATH {
+            let unknown = Status::unimplemented(format!("未知的方法: {}", request.uri().path()));
+            return Box::pin(async move { Ok(unknown.into_http()) });
+        }
+        Box::pin(async move {
+            let mut grpc = Grpc::new(tonic::codec::ProstCodec::default());
+            Ok(grpc.streaming(SplitCall, request).await)
+        })
+    }
+}
+
+impl NamedService for SplitterService {
+    const NAME: &'static str = "zstd_compressor.Splitter";
+}
+
+// 在 address 上提供服务, 直到进程被终止
+pub fn serve(address: &str) -> io::Result<()> {
+    let address: SocketAddr = address.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("无效的监听地址: {}", address)))?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    eprintln!("gRPC 服务监听 {}", address);
+    runtime.block_on(tonic::transport::Server::builder().add_service(SplitterService).serve(address)).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::codegen::http::uri::PathAndQuery;
+
+    fn options(lines: u64) -> SplitOptions {
+        SplitOptions { lines, ..SplitOptions::default() }
+    }
+
+    #[test]
+    fn client_stream_errors_drop_the_tail() {
+        let mut chunks = Vec::new();
+        let inbound = [Ok(b"a\nb\nc".to_vec()), Err(io::ErrorKind::ConnectionAborted.into()), Ok(b"d\n".to_vec())];
+        let e = split_stream(&options(1), inbound, |chunk| {
+            chunks.push(chunk.raw_bytes);
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+        assert_eq!(chunks, [2, 2]);
+
+        let e = split_stream(&SplitOptions { format: "7z".to_string(), ..options(1) }, [], |_| Ok(())).unwrap_err();
+        assert_eq!(status(e).code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn serves_chunks_over_a_bidirectional_stream() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener);
+            tokio::spawn(tonic::transport::Server::builder().add_service(SplitterService).serve_with_incoming(incoming));
+
+            let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", address)).unwrap().connect().await.unwrap();
+            let client = tonic::client::Grpc::new(channel);
+            let split = |requests: Vec<SplitRequest>| {
+                let mut client = client.clone();
+                async move {
+                    client.ready().await.unwrap();
+                    let path = PathAndQuery::from_static(SPLIT_PATH);
+                    let mut chunks = client.streaming(Request::new(tokio_stream::iter(requests)), path, tonic::codec::ProstCodec::default()).await?.into_inner();
+                    let mut received: Vec<SplitChunk> = Vec::new();
+                    while let Some(chunk) = chunks.message().await? {
+                        received.push(chunk);
+                    }
+                    Ok::<_, Status>(received)
+                }
+            };
+
+            let data = |text: &str| SplitRequest { options: None, data: text.as_bytes().to_vec() };
+            let first = SplitRequest { options: Some(options(2)), data: b"a\nb".to_vec() };
+            let chunks = split(vec![first, data("\nc\nd"), data("\ne")]).await.unwrap();
+            let expected = ["a\nb\n", "c\nd\n", "e"];
+            assert_eq!(chunks.len(), expected.len());
+            for (i, (chunk, raw)) in chunks.iter().zip(expected).enumerate() {
+                assert_eq!((chunk.number, chunk.offset, chunk.extension.as_str()), (i as u64 + 1, 4 * i as u64, "zst"));
+                assert_eq!(zstd::decode_all(&chunk.data[..]).unwrap(), raw.as_bytes());
+                assert_eq!(chunk.sha256, sha256(raw.as_bytes()));
+            }
+
+            let bad = SplitRequest { options: Some(SplitOptions { format: "nope".to_string(), ..options(2) }), data: Vec::new() };
+            assert_eq!(split(vec![bad]).await.unwrap_err().code(), tonic::Code::InvalidArgument);
+        });
+    }
+}
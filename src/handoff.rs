@@ -0,0 +1,167 @@
+// 把分卷直接交给同机的其他进程(如上传程序), 接收方不必再从磁盘读回: 分卷内容放入 memfd 并密封后,
+// 每个分卷连接一次接收方监听的 Unix 套接字, 发送一行 "<文件名>\t<字节数>\n" 并以 SCM_RIGHTS 附带 memfd.
+// 接收方从描述符读取或 mmap 分卷, 关闭描述符即释放内存. 只支持 Linux
+
+use std::io;
+use std::path::Path;
+
+#[cfg(target_os = "linux")]
+pub fn send(socket: &Path, name: &str, data: &[u8]) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::fs::File;
+    use std::io::{Seek, Write};
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::net::UnixStream;
+
+    extern "C" {
+        fn memfd_create(name: *const std::ffi::c_char, flags: u32) -> i32;
+        fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+        fn sendmsg(fd: i32, msg: *const sys::MsgHdr, flags: i32) -> isize;
+    }
+    const MFD_CLOEXEC: u32 = 1;
+    const MFD_ALLOW_SEALING: u32 = 2;
+    const F_ADD_SEALS: i32 = 1033;
+    // F_SEAL_SEAL | F_SEAL_SHRINK | F_SEAL_GROW | F_SEAL_WRITE: 交出后内容不会再变
+    const SEALS: i32 = 1 | 2 | 4 | 8;
+    const MSG_NOSIGNAL: i32 = 0x4000;
+
+    let memfd_name = CString::new(name.replace('\0', "_")).map_err(io::Error::other)?;
+    // SAFETY: 名字是以 0 结尾的有效字符串
+    let fd = unsafe { memfd_create(memfd_name.as_ptr(), MFD_CLOEXEC | MFD_ALLOW_SEALING) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd 是刚创建的描述符, 此后只由 memfd 持有
+    let mut memfd = unsafe { File::from_raw_fd(fd) };
+    memfd.write_all(data)?;
+    // 接收方拿到的描述符与这里共享读写位置, 交出前回到开头
+    memfd.rewind()?;
+    // SAFETY: 只传入整数参数
+    if unsafe { fcntl(memfd.as_raw_fd(), F_ADD_SEALS, SEALS) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut stream = UnixStream::connect(socket)?;
+    let header = format!("{}\t{}\n", name, data.len());
+    let iov = sys::IoVec { base: header.as_ptr() as *mut u8, len: header.len() };
+    let mut control = sys::FdControl::new(memfd.as_raw_fd());
+    let message = sys::MsgHdr {
+        name: std::ptr::null_mut(),
+        name_len: 0,
+        iov: &iov as *const sys::IoVec as *mut sys::IoVec,
+        iov_len: 1,
+        control: &mut control as *mut sys::FdControl as *mut std::ffi::c_void,
+        control_len: std::mem::size_of::<sys::FdControl>(),
+        flags: 0,
+    };
+    // SAFETY: message 引用的缓冲区在调用期间都有效, 长度与实际大小一致
+    let sent = unsafe { sendmsg(stream.as_raw_fd(), &message, MSG_NOSIGNAL) };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // 描述符随第一个字节送出, 余下的表头照常写完
+    stream.write_all(&header.as_bytes()[sent as usize..])?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn send(_socket: &Path, _name: &str, _data: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "unix: 输出目标只支持 Linux"))
+}
+
+// sendmsg/recvmsg 用到的结构, 布局与 Linux 的 struct msghdr, iovec 和只带一个描述符的 cmsghdr 相同
+#[cfg(target_os = "linux")]
+mod sys {
+    pub const SOL_SOCKET: i32 = 1;
+    pub const SCM_RIGHTS: i32 = 1;
+
+    #[repr(C)]
+    pub struct IoVec {
+        pub base: *mut u8,
+        pub len: usize,
+    }
+
+    #[repr(C)]
+    pub struct MsgHdr {
+        pub name: *mut std::ffi::c_void,
+        pub name_len: u32,
+        pub iov: *mut IoVec,
+        pub iov_len: usize,
+        pub control: *mut std::ffi::c_void,
+        pub control_len: usize,
+        pub flags: i32,
+    }
+
+    // CMSG_SPACE(sizeof(int)) 字节的控制消息, fd 之后的填充使整体按 cmsghdr 对齐
+    #[repr(C)]
+    pub struct FdControl {
+        pub len: usize,
+        pub level: i32,
+        pub kind: i32,
+        pub fd: i32,
+        padding: i32,
+    }
+
+    impl FdControl {
+        pub fn new(fd: i32) -> Self {
+            // CMSG_LEN(sizeof(int))
+            let len = std::mem::offset_of!(FdControl, fd) + std::mem::size_of::<i32>();
+            FdControl { len, level: SOL_SOCKET, kind: SCM_RIGHTS, fd, padding: 0 }
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::net::UnixListener;
+
+    // 接收方: 读出表头与随之送来的描述符
+    fn receive(listener: &UnixListener) -> (String, File) {
+        extern "C" {
+            fn recvmsg(fd: i32, msg: *mut sys::MsgHdr, flags: i32) -> isize;
+        }
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0u8; 256];
+        let mut iov = sys::IoVec { base: buffer.as_mut_ptr(), len: buffer.len() };
+        let mut control = sys::FdControl::new(-1);
+        let mut message = sys::MsgHdr {
+            name: std::ptr::null_mut(),
+            name_len: 0,
+            iov: &mut iov,
+            iov_len: 1,
+            control: &mut control as *mut sys::FdControl as *mut std::ffi::c_void,
+            control_len: std::mem::size_of::<sys::FdControl>(),
+            flags: 0,
+        };
+        // SAFETY: 缓冲区与控制消息在调用期间有效
+        let n = unsafe { recvmsg(stream.as_raw_fd(), &mut message, 0) };
+        assert!(n > 0 && control.kind == sys::SCM_RIGHTS && control.fd >= 0);
+        let mut header = buffer[..n as usize].to_vec();
+        stream.read_to_end(&mut header).unwrap();
+        (String::from_utf8(header).unwrap(), unsafe { File::from_raw_fd(control.fd) })
+    }
+
+    #[test]
+    fn chunks_are_handed_over_as_sealed_memfds() {
+        let socket = std::env::temp_dir().join(format!("handoff_{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let sender = {
+            let socket = socket.clone();
+            std::thread::spawn(move || send(&socket, "part.001.zst", b"compressed"))
+        };
+        let (header, mut memfd) = receive(&listener);
+        sender.join().unwrap().unwrap();
+        assert_eq!(header, "part.001.zst\t10\n");
+        let mut data = Vec::new();
+        memfd.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"compressed");
+        assert!(memfd.set_len(0).is_err(), "memfd 应已密封");
+        assert!(send(&socket.with_extension("missing"), "part.002.zst", b"x").is_err());
+        std::fs::remove_file(&socket).unwrap();
+    }
+}
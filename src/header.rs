@@ -0,0 +1,192 @@
+// 在每个分卷中复制的首尾: CSV 表头, 以及按 XML 元素切分时补上的根元素
+
+use crate::boundary::{self, Boundary};
+use crate::config::{Config, CsvHeader};
+
+// 分卷开头要加上的 CSV 表头或 XML 前导部分, 没有时为空; 切出第一个分卷时确定
+pub(crate) fn copied_header_for<'c>(chunk: &[u8], config: &'c Config, chunk_number: usize) -> &'c [u8] {
+    if config.xml_wrap {
+        let header = config.header.get_or_init(|| {
+            let (header, footer) = xml_wrapper(chunk, config);
+            let _ = config.footer.set(footer);
+            header
+        });
+        // 第一个分卷本来就以前导部分开头
+        return if chunk_number >= 2 { header } else { &[] };
+    }
+    let Some(mode) = &config.csv_header else { return &[] };
+    let header = config.header.get_or_init(|| {
+        let header = detect_csv_header(chunk, mode, config);
+        match &header[..] {
+            [] => info!(config, "未检测到 CSV 表头, 分卷开头不加表头"),
+            header => info!(config, "CSV 表头: {}", config.encoding.decode_without_bom_handling(header).0.trim_end()),
+        }
+        header
+    });
+    if chunk_number >= mode.first_added() { header } else { &[] }
+}
+
+// 分卷开头复制的表头长度, 合并时去掉
+pub(crate) fn copied_header_len(config: &Config, chunk_number: usize) -> usize {
+    match (&config.csv_header, config.header.get()) {
+        (Some(mode), Some(header)) if chunk_number >= mode.first_added() => header.len(),
+        (None, Some(header)) if config.xml_wrap && chunk_number >= 2 => header.len(),
+        _ => 0,
+    }
+}
+
+// 以记录结束的分卷(通常是除最后一个外的所有分卷)结尾要补上的根元素结束标签; 最后一个分卷带有输入原本的结尾
+pub(crate) fn copied_footer_for<'c>(chunk: &[u8], config: &'c Config, chunk_number: usize) -> &'c [u8] {
+    let Some(footer) = config.footer.get().filter(|footer| config.xml_wrap && !footer.is_empty()) else { return &[] };
+    let Some(Ok(mut records)) = config.records.as_deref().map(|records| boundary::parse(records, config.encoding)) else { return &[] };
+    if records.last_end(chunk, &mut 0, false) != Some(chunk.len()) {
+        return &[];
+    }
+    config.footed.lock().unwrap().insert(chunk_number);
+    footer
+}
+
+// 分卷结尾补上的结束标签长度, 合并时去掉
+pub(crate) fn copied_footer_len(config: &Config, chunk_number: usize) -> usize {
+    match config.footer.get() {
+        Some(footer) if config.footed.lock().unwrap().contains(&chunk_number) => footer.len(),
+        _ => 0,
+    }
+}
+
+// --xml-wrap 复制的首尾: 输入中第一条记录所在行之前的全部内容(XML 声明, 根元素的开始标签等),
+// 与其中第一个元素(根元素)的结束标签加换行符; 找不到根元素时都为空
+fn xml_wrapper(first_chunk: &[u8], config: &Config) -> (Vec<u8>, Vec<u8>) {
+    let name = config.records.as_deref().and_then(|records| records.strip_prefix("xml:")).unwrap_or_default();
+    let open = format!("<{}", name).into_bytes();
+    let record_start = (0..first_chunk.len())
+        .find(|&i| first_chunk[i..].starts_with(&open) && first_chunk.get(i + open.len()).is_some_and(|b| b.is_ascii_whitespace() || b"/>".contains(b)))
+        .unwrap_or(first_chunk.len());
+    let prolog = &first_chunk[..first_chunk[..record_start].iter().rposition(|&b| b == b'\n').map_or(record_start, |i| i + 1)];
+    let root = prolog.windows(2).enumerate().find(|(_, pair)| pair[0] == b'<' && (pair[1].is_ascii_alphabetic() || pair[1] == b'_')).map(|(i, _)| {
+        let name = &prolog[i + 1..];
+        &name[..name.iter().position(|b| b.is_ascii_whitespace() || b"/>".contains(b)).unwrap_or(name.len())]
+    });
+    match root {
+        Some(root) => {
+            let root = String::from_utf8_lossy(root);
+            info!(config, "XML 根元素: <{}>, 各分卷开头复制 {} 字节的前导部分", root, prolog.len());
+            (prolog.to_vec(), format!("</{}>{}", root, config.line_ending).into_bytes())
+        }
+        None => {
+            info!(config, "第一条 <{}> 之前没有根元素, 分卷不加首尾", name);
+            (Vec::new(), Vec::new())
+        }
+    }
+}
+
+// 第一个分卷中的表头: 第一条记录, 给出的文本(补上换行符), 或自动判断时第一条记录没有数值字段、
+// 第二条记录有数值字段时把第一条记录当作表头
+fn detect_csv_header(first_chunk: &[u8], mode: &CsvHeader, config: &Config) -> Vec<u8> {
+    let mut records = boundary::Csv::default();
+    let mut pos = 0;
+    let first_end = records.next_end(first_chunk, &mut pos, false).unwrap_or(first_chunk.len());
+    match mode {
+        CsvHeader::Text(text) => {
+            let mut header = config.encoding.encode(text).0.into_owned();
+            let line_ending = config.encoding.encode(&config.line_ending).0;
+            if !header.ends_with(&line_ending) {
+                header.extend_from_slice(&line_ending);
+            }
+            header
+        }
+        CsvHeader::First => first_chunk[..first_end].to_vec(),
+        CsvHeader::Auto => {
+            let Some(second_end) = records.next_end(first_chunk, &mut pos, false) else { return Vec::new() };
+            let fields = |record: &[u8]| csv_fields(&config.encoding.decode_without_bom_handling(record).0);
+            let numeric = |fields: &[String]| fields.iter().any(|field| field.trim().parse::<f64>().is_ok());
+            let (first, second) = (fields(&first_chunk[..first_end]), fields(&first_chunk[first_end..second_end]));
+            if first.len() == second.len() && !numeric(&first) && numeric(&second) {
+                first_chunk[..first_end].to_vec()
+            } else {
+                Vec::new()
+            }
+        }
+    }
+}
+
+// 一条 CSV 记录的各字段, 去掉引号与结尾的换行符
+fn csv_fields(record: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = record.trim_end_matches(['\r', '\n']).chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+use std::fs::File;
+    use super::*;
+    use crate::chunk_name;
+    use crate::merge::{run_join, MergeOptions};
+    use crate::split::run_split;
+    use crate::testing::Scratch;
+
+    #[test]
+    fn csv_headers_are_repeated_in_every_chunk_and_dropped_on_merge() {
+        let dir = Scratch::new("csv_header");
+        let input = dir.join("in.csv");
+        std::fs::write(&input, "name,age\n\"a\nb\",1\nc,2\nd,3\n").unwrap();
+        let prefix = dir.join("part").display().to_string();
+        for (header, first) in [("auto", "\"a\nb\",1\n"), ("first", "\"a\nb\",1\n"), ("text:n,a", "n,a\nname,age\n")] {
+            let args = ["zstd_compressor", &input.display().to_string(), &prefix, "--lines", "2", "--csv-header", header, "--yes", "--porcelain"];
+            let config = Config::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+            run_split(&config).unwrap();
+            let chunk = |n| zstd::decode_all(File::open(chunk_name(&prefix, n, "zst")).unwrap()).unwrap();
+            let expected_header = if header == "text:n,a" { "n,a\n" } else { "name,age\n" };
+            assert!(String::from_utf8(chunk(2)).unwrap().starts_with(expected_header), "{}", header);
+            assert!(String::from_utf8(chunk(1)).unwrap().contains(first), "{}", header);
+            let merged = dir.join("merged");
+            run_join(&prefix, &merged.display().to_string(), &MergeOptions::parse(&[]).unwrap()).unwrap();
+            assert_eq!(std::fs::read(&merged).unwrap(), std::fs::read(&input).unwrap());
+        }
+
+        // 第一条记录有数值字段时 auto 不认为有表头
+        let config = Config::parse(["zstd_compressor", "in.csv", "out/a"].map(String::from)).unwrap();
+        assert!(detect_csv_header(b"1,2\n3,4\n", &CsvHeader::Auto, &config).is_empty());
+        assert_eq!(detect_csv_header(b"id,\"x,y\"\n1,2\n", &CsvHeader::Auto, &config), b"id,\"x,y\"\n");
+        assert!(Config::parse(["zstd_compressor", "in.csv", "out/a", "--csv-header", "first", "--records", "fixed:4"].map(String::from)).is_err());
+        // --lines 决定切分点, 同时给出的分块大小不会生效
+        let error = Config::parse(["zstd_compressor", "in.txt", "l2", "1", "--lines", "50000", "--chunk-size", "1"].map(String::from)).unwrap_err();
+        assert!(error.contains("--lines 按记录数切分, 不能与 --chunk-size 与 --target-size 同时使用"), "{}", error);
+    }
+
+    #[test]
+    fn xml_chunks_are_wrapped_in_the_root_element_and_unwrapped_on_merge() {
+        let dir = Scratch::new("xml_wrap");
+        let input = dir.join("in.xml");
+        let xml = "<?xml version=\"1.0\"?>\n<feed xmlns=\"urn:x\">\n  <row id=\"1\"><b/></row>\n  <!-- <row> -->\n  <row id=\"2\">a</row>\n  <row id=\"3\"/>\n  <row>b</row>\n</feed>\n";
+        std::fs::write(&input, xml).unwrap();
+        let prefix = dir.join("part").display().to_string();
+        let args = ["zstd_compressor", &input.display().to_string(), &prefix, "--lines", "2", "--records", "xml:row", "--xml-wrap", "--yes", "--porcelain"];
+        let config = Config::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+        run_split(&config).unwrap();
+        let chunk = |n| String::from_utf8(zstd::decode_all(File::open(chunk_name(&prefix, n, "zst")).unwrap()).unwrap()).unwrap();
+        let prolog = "<?xml version=\"1.0\"?>\n<feed xmlns=\"urn:x\">\n";
+        assert_eq!(chunk(1), format!("{}  <row id=\"1\"><b/></row>\n  <!-- <row> -->\n  <row id=\"2\">a</row>\n</feed>\n", prolog));
+        assert_eq!(chunk(2), format!("{}  <row id=\"3\"/>\n  <row>b</row>\n</feed>\n", prolog));
+        // 最后一条记录之后的结尾单独成卷时带有输入原本的结束标签, 不再补上
+        assert_eq!(chunk(3), format!("{}</feed>\n", prolog));
+        let merged = dir.join("merged");
+        run_join(&prefix, &merged.display().to_string(), &MergeOptions::parse(&[]).unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(&merged).unwrap(), xml);
+
+        assert!(Config::parse(["zstd_compressor", "in.xml", "out/a", "--xml-wrap"].map(String::from)).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+// 批量任务: YAML 任务文件中的每个任务转换成一条切分命令行
+
+use std::io;
+use std::sync::Mutex;
+use std::thread;
+use crate::config::{Config, DEFAULT_CHUNK_SIZE};
+use crate::exit::exit_code;
+use crate::files::{batch_result, peak_memory};
+use crate::split::{confirm_overwrite, run_split, SplitStats};
+use crate::units::NumberFormat;
+
+// 任务文件中一个键的值: 标量, 或对应可重复选项的列表
+#[derive(Debug, PartialEq)]
+pub(crate) enum JobValue {
+    Scalar(String),
+    List(Vec<String>),
+}
+
+type Job = Vec<(String, JobValue)>;
+
+// 去掉引号外 '#' 开始的注释
+pub(crate) fn strip_yaml_comment(line: &str) -> &str {
+    let (mut single, mut double) = (false, false);
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match c {
+            '\'' if !double => single = !single,
+            '"' if !single && previous != '\\' => double = !double,
+            '#' if !single && !double && previous.is_whitespace() => return &line[..i],
+            _ => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+pub(crate) fn unquote_yaml(value: &str) -> String {
+    let value = value.trim();
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        value[1..value.len() - 1].replace("\\\"", "\"").replace("\\\\", "\\")
+    } else if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        value[1..value.len() - 1].replace("''", "'")
+    } else {
+        value.to_string()
+    }
+}
+
+// "key: value" 一行; 值为空时等待后续的 "- item" 列表
+pub(crate) fn parse_yaml_entry(content: &str) -> Result<(String, JobValue), String> {
+    let colon = content
+        .char_indices()
+        .find(|&(i, c)| c == ':' && content[i + 1..].chars().next().is_none_or(char::is_whitespace))
+        .map(|(i, _)| i)
+        .ok_or("应为 key: value")?;
+    let key = content[..colon].trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(format!("无效的键: {}", key));
+    }
+    let value = content[colon + 1..].trim();
+    let value = if value.is_empty() {
+        JobValue::List(Vec::new())
+    } else if let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        JobValue::List(items.split(',').map(unquote_yaml).filter(|item| !item.is_empty()).collect())
+    } else {
+        JobValue::Scalar(unquote_yaml(value))
+    };
+    Ok((key.to_string(), value))
+}
+
+// 任务文件只用到 YAML 的一个子集: 可选的顶层 jobs: 键下是任务列表, 每个任务是一层 key: value,
+// 值为标量、[a, b] 或缩进的 "- item" 列表
+fn parse_jobs(text: &str) -> Result<Vec<Job>, String> {
+    let mut jobs: Vec<Job> = Vec::new();
+    let mut item_indent = None;
+    // 当前任务中键所在的列, 任务以单独一行 "-" 开始时由下一行确定
+    let mut key_indent = None;
+    for (n, raw) in text.lines().enumerate() {
+        let error = |message: String| format!("第 {} 行: {}", n + 1, message);
+        let line = strip_yaml_comment(raw).trim_end();
+        let content = line.trim_start();
+        if content.is_empty() || content == "---" {
+            continue;
+        }
+        if line.starts_with('\t') {
+            return Err(error("不能用制表符缩进".to_string()));
+        }
+        let indent = line.len() - content.len();
+        if indent == 0 && content == "jobs:" && item_indent.is_none() {
+            continue;
+        }
+
+        let item = content.strip_prefix('-').filter(|rest| rest.is_empty() || rest.starts_with(' '));
+        if let Some(rest) = item {
+            if item_indent.is_none() || item_indent == Some(indent) {
+                item_indent = Some(indent);
+                jobs.push(Vec::new());
+                let rest_trimmed = rest.trim_start();
+                if rest_trimmed.is_empty() {
+                    key_indent = None;
+                } else {
+                    key_indent = Some(indent + content.len() - rest_trimmed.len());
+                    jobs.last_mut().unwrap().push(parse_yaml_entry(rest_trimmed).map_err(error)?);
+                }
+                continue;
+            }
+            // 键下缩进的列表项
+            let entry = jobs.last_mut().and_then(|job| job.last_mut()).filter(|_| key_indent.is_some_and(|k| indent >= k));
+            match entry {
+                Some((_, JobValue::List(items))) => items.push(unquote_yaml(rest)),
+                _ => return Err(error("列表项不属于任何键".to_string())),
+            }
+            continue;
+        }
+
+        let Some(job) = jobs.last_mut() else {
+            return Err(error("任务需要以 \"- \" 开始的列表给出".to_string()));
+        };
+        if *key_indent.get_or_insert(indent) != indent {
+            return Err(error("缩进与同一任务中的其他键不一致".to_string()));
+        }
+        let (key, value) = parse_yaml_entry(content).map_err(error)?;
+        if job.iter().any(|(k, _)| *k == key) {
+            return Err(error(format!("重复的键: {}", key)));
+        }
+        job.push((key, value));
+    }
+    Ok(jobs)
+}
+
+// 把任务转换成与命令行相同的参数: input、prefix、line_ending、encoding 对应位置参数,
+// chunk_size 对应 --target-size, 其余键 xxx_yyy 对应选项 --xxx-yyy, true/false 表示开关
+fn job_args(job: &Job) -> Result<Vec<String>, String> {
+    let scalar = |name: &str| match job.iter().find(|(k, _)| k == name) {
+        Some((_, JobValue::Scalar(value))) => Ok(Some(value.clone())),
+        Some(_) => Err(format!("{} 应为单个值", name)),
+        None => Ok(None),
+    };
+    let input = scalar("input")?.ok_or("缺少 input")?;
+    let prefix = scalar("prefix")?.ok_or("缺少 prefix")?;
+    let mut args = vec!["zstd_compressor".to_string(), input, prefix];
+    let (line_ending, encoding) = (scalar("line_ending")?, scalar("encoding")?);
+    if line_ending.is_some() || encoding.is_some() {
+        args.push((DEFAULT_CHUNK_SIZE / 1024 / 1024).to_string());
+        args.push(line_ending.unwrap_or_else(|| "LF".to_string()));
+        args.extend(encoding);
+    }
+
+    for (key, value) in job {
+        let flag = match key.as_str() {
+            "input" | "prefix" | "line_ending" | "encoding" => continue,
+            "chunk_size" => "--target-size".to_string(),
+            key => format!("--{}", key.replace('_', "-")),
+        };
+        push_option(&mut args, flag, value);
+    }
+    Ok(args)
+}
+
+// 一个键对应的命令行选项: true/false 表示开关, 列表对应重复给出的选项
+pub(crate) fn push_option(args: &mut Vec<String>, flag: String, value: &JobValue) {
+    match value {
+        JobValue::Scalar(value) if value == "true" => args.push(flag),
+        JobValue::Scalar(value) if value == "false" => {}
+        JobValue::Scalar(value) => args.extend([flag, value.clone()]),
+        JobValue::List(items) => {
+            for item in items {
+                args.extend([flag.clone(), item.clone()]);
+            }
+        }
+    }
+}
+
+// 批量执行任务文件: 所有任务的配置先全部检查通过才开始(--keep-going 时配置无效或不允许覆盖的任务记为失败,
+// 其余照常执行), 由固定数量的工作线程依次领取任务, 最后输出一份汇总报告; 有任务失败时整体返回错误
+pub fn run_jobs(path: &str, options: &[String]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let mut workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut yes = false;
+    let mut keep_going = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--parallel" => {
+                let value = options.next().ok_or_else(|| invalid("选项 --parallel 缺少参数".to_string()))?;
+                workers = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| invalid(format!("无效的并行数: {}", value)))?;
+            }
+            "--yes" => yes = true,
+            "--keep-going" => keep_going = true,
+            _ => return Err(invalid(format!("未知选项: {}", option))),
+        }
+    }
+    let jobs = parse_jobs(&std::fs::read_to_string(path)?).map_err(|e| invalid(format!("{} {}", path, e)))?;
+    if jobs.is_empty() {
+        return Err(invalid(format!("{} 中没有任务", path)));
+    }
+    let mut configs: Vec<io::Result<Config>> = jobs.iter().map(|job| job_args(job).and_then(Config::parse).map_err(|e| invalid(format!("配置无效: {}", e)))).collect();
+    if !keep_going {
+        if let Some((i, Err(e))) = configs.iter().enumerate().find(|(_, config)| config.is_err()) {
+            return Err(invalid(format!("任务 {} {}", i + 1, e)));
+        }
+    }
+    // 开始执行前逐个确认, 工作线程中不再询问
+    for config in configs.iter_mut().filter(|_| !yes) {
+        let refused = config.as_ref().ok().and_then(|config| confirm_overwrite(config).err());
+        match refused {
+            Some(e) if keep_going => *config = Err(e),
+            Some(e) => return Err(e),
+            None => {}
+        }
+    }
+
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<io::Result<SplitStats>>>> = Mutex::new(configs.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..workers.min(configs.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(config) = configs.get(i) else { break };
+                let Ok(config) = config else { continue };
+                let result = run_split(config);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    println!("\n批量任务汇总 ({} 个任务, {} 个工作线程):", configs.len(), workers.min(configs.len()));
+    let mut failed = 0;
+    let mut first_code = None;
+    for (i, (config, result)) in configs.iter().zip(results.into_inner().unwrap()).enumerate() {
+        let config = match config {
+            Ok(config) => config,
+            Err(e) => {
+                failed += 1;
+                first_code.get_or_insert(exit_code(e));
+                println!("- 任务 {}: 未执行: {}", i + 1, e);
+                continue;
+            }
+        };
+        match result.expect("每个任务都已执行") {
+            Ok(stats) => println!(
+                "- 任务 {}: {} -> {}: 完成, {} 个分卷, {}, {}",
+                i + 1,
+                config.input_path,
+                config.output_prefix,
+                config.numbers.integer(stats.chunks as u64),
+                config.numbers.size(stats.bytes as u64),
+                config.numbers.duration(stats.duration.as_secs_f64())
+            ),
+            Err(e) => {
+                failed += 1;
+                first_code.get_or_insert(exit_code(&e));
+                println!("- 任务 {}: {} -> {}: 失败: {}", i + 1, config.input_path, config.output_prefix, e);
+            }
+        }
+    }
+    let numbers = configs.iter().flatten().next().map_or_else(NumberFormat::default, |config| config.numbers);
+    println!("内存峰值 (所有任务): {}", peak_memory(&numbers));
+    batch_result(first_code, failed, configs.len(), "个任务失败")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exit::EXIT_PARTIAL;
+    use crate::testing::Scratch;
+
+    #[test]
+    fn job_files_become_command_lines() {
+        let text = "# 每日任务\njobs:\n  - input: a.log   # 注释\n    prefix: \"out/a #1\"\n    chunk_size: 64\n    drop_invalid: true\n    hard_limit: false\n    output:\n      - /mnt/backup\n      - 's3://bucket/a'\n  -\n    input: b.log\n    prefix: out/b\n    encoding: GBK\n    format: snappy\n    output: [d1, d2]\n";
+        let jobs = parse_jobs(text).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(
+            job_args(&jobs[0]).unwrap(),
+            ["zstd_compressor", "a.log", "out/a #1", "--target-size", "64", "--drop-invalid", "--output", "/mnt/backup", "--output", "s3://bucket/a"]
+        );
+        assert_eq!(
+            job_args(&jobs[1]).unwrap(),
+            ["zstd_compressor", "b.log", "out/b", "100", "LF", "GBK", "--format", "snappy", "--output", "d1", "--output", "d2"]
+        );
+
+        assert!(parse_jobs("input: a.log\n").is_err());
+        assert!(parse_jobs("- input: a\n   prefix: b\n").is_err());
+        assert!(parse_jobs("- input: a\n  input: b\n").is_err());
+        assert!(job_args(&parse_jobs("- prefix: b\n").unwrap()[0]).is_err());
+    }
+
+    #[test]
+    fn keep_going_runs_the_jobs_that_are_valid() {
+        let dir = Scratch::new("keep_going");
+        std::fs::write(dir.join("a.log"), "a\n").unwrap();
+        let jobs = dir.join("jobs.yaml");
+        let text = format!("- input: {0}/a.log\n  prefix: {0}/a\n  porcelain: true\n- input: {0}/a.log\n  prefix: {0}/b\n  format: nope\n", dir.display());
+        std::fs::write(&jobs, text).unwrap();
+        let jobs = jobs.display().to_string();
+
+        let error = run_jobs(&jobs, &["--yes".to_string()]).unwrap_err();
+        assert!(error.to_string().starts_with("任务 2 配置无效"), "{}", error);
+        assert!(!dir.join("a.001.zst").exists());
+        let error = run_jobs(&jobs, &["--yes".to_string(), "--keep-going".to_string()]).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_PARTIAL);
+        assert!(dir.join("a.001.zst").exists());
+    }
+}
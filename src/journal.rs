@@ -0,0 +1,69 @@
+// 写出日志: 分卷开始写与写完时各记一行, recover 按它删除未写完的分卷
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use crate::naming::temp_path;
+use crate::prompt::confirm;
+
+// 分卷写入的预写日志: 开始写与落盘完成各记一行并立即同步, 崩溃后据此区分完整与残缺的分卷
+pub(crate) struct Journal {
+    file: File,
+}
+
+impl Journal {
+    pub(crate) fn create(output_prefix: &str) -> io::Result<Self> {
+        let mut file = File::create(format!("{}.journal", output_prefix))?;
+        writeln!(file, "# event\tchunk\tfile\tbytes")?;
+        file.sync_data()?;
+        Ok(Journal { file })
+    }
+
+    pub(crate) fn record(&mut self, event: &str, chunk_number: usize, path: &Path, bytes: Option<usize>) -> io::Result<()> {
+        let bytes = bytes.map(|b| b.to_string()).unwrap_or_default();
+        writeln!(self.file, "{}\t{}\t{}\t{}", event, chunk_number, path.display(), bytes)?;
+        self.file.sync_data()
+    }
+}
+
+// 按日志恢复: 开始写但没有完成记录的分卷是崩溃时写了一半的, 删除后记为 rolled-back;
+// 已完成的分卷保留, 可以再次执行
+pub fn run_recover(prefix: &str, yes: bool) -> io::Result<()> {
+    let path = format!("{}.journal", prefix);
+    let text = std::fs::read_to_string(&path)?;
+    let mut unfinished: Vec<(String, String)> = Vec::new();
+    let mut done = 0;
+    for line in text.lines().filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [event, chunk, file, ..] = fields[..] else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("无效的日志行: {}", line)));
+        };
+        let key = (chunk.to_string(), file.to_string());
+        match event {
+            "begin" => unfinished.push(key),
+            "done" | "rolled-back" => {
+                unfinished.retain(|k| *k != key);
+                done += (event == "done") as usize;
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("无效的日志事件: {}", line))),
+        }
+    }
+
+    if !unfinished.is_empty() {
+        confirm(&format!("将删除 {} 个未完成的分卷", unfinished.len()), yes)?;
+    }
+    let mut journal = std::fs::OpenOptions::new().append(true).open(&path)?;
+    for (chunk, file) in &unfinished {
+        // 崩溃时可能只留下了临时文件
+        let _ = std::fs::remove_file(temp_path(Path::new(file)));
+        match std::fs::remove_file(file) {
+            Ok(()) => println!("删除未完成的分卷 {}: {}", chunk, file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => println!("未完成的分卷 {} 尚未创建: {}", chunk, file),
+            Err(e) => return Err(e),
+        }
+        writeln!(journal, "rolled-back\t{}\t{}\t", chunk, file)?;
+    }
+    journal.sync_data()?;
+    println!("恢复完成: {} 个分卷完整, 回滚 {} 个", done, unfinished.len());
+    Ok(())
+}
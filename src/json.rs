@@ -0,0 +1,406 @@
+// JSON Lines 校验用的 JSON 语法检查: 只判断文本是否恰好是一个合法的 JSON 值(前后可以有空白),
+// 不构建值; 嵌套层数有上限, 畸形输入不会耗尽栈. parse 按同样的语法构建值, 用于读回清单等小文件
+
+const MAX_DEPTH: usize = 512;
+
+// 不合法时返回出错的字节位置与原因
+pub fn validate(text: &[u8]) -> Result<(), String> {
+    let text = std::str::from_utf8(text).map_err(|e| format!("第 {} 字节: 不是有效的 UTF-8", e.valid_up_to()))?;
+    let mut parser = JsonParser { bytes: text.as_bytes(), pos: 0 };
+    parser.whitespace();
+    parser.value(0)?;
+    parser.whitespace();
+    if parser.pos < parser.bytes.len() {
+        return Err(parser.error("值之后还有多余的内容"));
+    }
+    Ok(())
+}
+
+// 解析出的 JSON 值; 数字保留原文, 由调用方按需要的类型解析, 对象保留键的顺序
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    // 对象中键对应的值, 键重复时取第一个
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+}
+
+// 解析恰好一个 JSON 值, 错误与 validate 相同
+pub fn parse(text: &[u8]) -> Result<Value, String> {
+    let text = std::str::from_utf8(text).map_err(|e| format!("第 {} 字节: 不是有效的 UTF-8", e.valid_up_to()))?;
+    let mut parser = JsonParser { bytes: text.as_bytes(), pos: 0 };
+    parser.whitespace();
+    let value = parser.parsed(0)?;
+    parser.whitespace();
+    if parser.pos < parser.bytes.len() {
+        return Err(parser.error("值之后还有多余的内容"));
+    }
+    Ok(value)
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, message: &str) -> String {
+        match self.bytes.get(self.pos) {
+            Some(_) => format!("第 {} 字节: {}", self.pos, message),
+            None => format!("第 {} 字节(结尾): {}", self.pos, message),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8, message: &str) -> Result<(), String> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(message));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self, depth: usize) -> Result<(), String> {
+        if depth >= MAX_DEPTH {
+            return Err(self.error("嵌套层数过多"));
+        }
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                self.whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                loop {
+                    self.whitespace();
+                    self.string()?;
+                    self.whitespace();
+                    self.expect(b':', "缺少 :")?;
+                    self.whitespace();
+                    self.value(depth + 1)?;
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(());
+                        }
+                        _ => return Err(self.error("对象中缺少 , 或 }")),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                self.whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                loop {
+                    self.whitespace();
+                    self.value(depth + 1)?;
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(());
+                        }
+                        _ => return Err(self.error("数组中缺少 , 或 ]")),
+                    }
+                }
+            }
+            Some(b'"') => self.string(),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b't') => self.literal("true"),
+            Some(b'f') => self.literal("false"),
+            Some(b'n') => self.literal("null"),
+            _ => Err(self.error("缺少值")),
+        }
+    }
+
+    fn literal(&mut self, word: &str) -> Result<(), String> {
+        if !self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            return Err(self.error("无效的字面量"));
+        }
+        self.pos += word.len();
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<(), String> {
+        self.expect(b'"', "缺少字符串")?;
+        loop {
+            match self.peek() {
+                None => return Err(self.error("字符串没有结束")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => self.pos += 1,
+                        Some(b'u') => {
+                            let hex = self.bytes.get(self.pos + 1..self.pos + 5);
+                            if !hex.is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit)) {
+                                return Err(self.error("无效的 \\u 转义"));
+                            }
+                            self.pos += 5;
+                        }
+                        _ => return Err(self.error("无效的转义")),
+                    }
+                }
+                Some(0..=0x1f) => return Err(self.error("字符串中有未转义的控制字符")),
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    // -?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?
+    fn number(&mut self) -> Result<(), String> {
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.error("无效的数字")),
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if !self.peek().is_some_and(|b| b.is_ascii_digit()) {
+                return Err(self.error("小数点后缺少数字"));
+            }
+            self.digits();
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !self.peek().is_some_and(|b| b.is_ascii_digit()) {
+                return Err(self.error("指数缺少数字"));
+            }
+            self.digits();
+        }
+        Ok(())
+    }
+
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+    }
+
+    // 与 value 相同的语法, 同时构建值; 字符串与数字先按 value 的规则检查, 再从检查过的原文取值
+    fn parsed(&mut self, depth: usize) -> Result<Value, String> {
+        if depth >= MAX_DEPTH {
+            return Err(self.error("嵌套层数过多"));
+        }
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(entries));
+                }
+                loop {
+                    self.whitespace();
+                    let key = self.parsed_string()?;
+                    self.whitespace();
+                    self.expect(b':', "缺少 :")?;
+                    self.whitespace();
+                    entries.push((key, self.parsed(depth + 1)?));
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(entries));
+                        }
+                        _ => return Err(self.error("对象中缺少 , 或 }")),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    self.whitespace();
+                    items.push(self.parsed(depth + 1)?);
+                    self.whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(items));
+                        }
+                        _ => return Err(self.error("数组中缺少 , 或 ]")),
+                    }
+                }
+            }
+            Some(b'"') => self.parsed_string().map(Value::String),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                self.number()?;
+                Ok(Value::Number(String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned()))
+            }
+            Some(b't') => self.literal("true").map(|()| Value::Bool(true)),
+            Some(b'f') => self.literal("false").map(|()| Value::Bool(false)),
+            Some(b'n') => self.literal("null").map(|()| Value::Null),
+            _ => Err(self.error("缺少值")),
+        }
+    }
+
+    // 还原转义; 不成对的 UTF-16 代理项换成 U+FFFD
+    fn parsed_string(&mut self) -> Result<String, String> {
+        let start = self.pos + 1;
+        self.string()?;
+        let raw = std::str::from_utf8(&self.bytes[start..self.pos - 1]).map_err(|e| e.to_string())?;
+        let mut value = String::with_capacity(raw.len());
+        let mut chars = raw.chars();
+        let mut surrogate = None;
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                value.extend(surrogate.take().map(|_| char::REPLACEMENT_CHARACTER));
+                value.push(c);
+                continue;
+            }
+            let escaped = match chars.next() {
+                Some('u') => {
+                    let code = u32::from_str_radix(&chars.by_ref().take(4).collect::<String>(), 16).unwrap_or(0xFFFD);
+                    match (surrogate.take(), code) {
+                        (None, 0xD800..=0xDBFF) => {
+                            surrogate = Some(code);
+                            continue;
+                        }
+                        (Some(high), 0xDC00..=0xDFFF) => char::from_u32(0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00)),
+                        (high, _) => {
+                            value.extend(high.map(|_| char::REPLACEMENT_CHARACTER));
+                            char::from_u32(code)
+                        }
+                    }
+                }
+                other => {
+                    value.extend(surrogate.take().map(|_| char::REPLACEMENT_CHARACTER));
+                    match other {
+                        Some('n') => Some('\n'),
+                        Some('t') => Some('\t'),
+                        Some('r') => Some('\r'),
+                        Some('b') => Some('\u{8}'),
+                        Some('f') => Some('\u{c}'),
+                        other => other,
+                    }
+                }
+            };
+            value.push(escaped.unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+        value.extend(surrogate.map(|_| char::REPLACEMENT_CHARACTER));
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_json_values_and_rejects_partial_objects() {
+        for valid in [
+            r#"{"a": [1, -2.5e3, true, false, null], "b": {"c": "x\"é\n"}}"#,
+            " 0 \r",
+            r#""中文""#,
+            "[]",
+            "{}",
+        ] {
+            assert!(validate(valid.as_bytes()).is_ok(), "{}", valid);
+        }
+        for invalid in [r#"{"a": 1"#, r#"{"a" 1}"#, "[1,]", "01", "1.", "tru", r#""a\x""#, "{} {}", "", "\"a\tb\""] {
+            assert!(validate(invalid.as_bytes()).is_err(), "{}", invalid);
+        }
+        assert_eq!(validate(br#"{"a": 1"#).unwrap_err(), "第 7 字节(结尾): 对象中缺少 , 或 }");
+        assert!(validate(&[b'['; 10000]).unwrap_err().contains("嵌套层数过多"));
+        assert!(validate(b"\"\xff\"").is_err());
+    }
+
+    #[test]
+    fn parses_values_regardless_of_layout() {
+        let compact = parse(br#"{"b":[1,-2.5e3,true,null],"a":{"c":"x\"\u00e9\n\ud83d\ude00\ud800"}}"#).unwrap();
+        let spaced = parse(b"{\n  \"b\" : [ 1 , -2.5e3 , true , null ] ,\n  \"a\" : { \"c\" : \"x\\\"\xc3\xa9\\n\xf0\x9f\x98\x80\\ud800\" }\n}\n").unwrap();
+        assert_eq!(compact, spaced);
+        assert_eq!(compact.get("a").and_then(|a| a.get("c")).and_then(Value::as_str), Some("x\"é\n😀\u{fffd}"));
+        let items = compact.get("b").and_then(Value::as_array).unwrap();
+        assert_eq!((items[0].as_u64(), items[1].as_u64(), items[2].as_bool(), items[3].is_null()), (Some(1), None, Some(true), true));
+        assert_eq!(compact.as_object().unwrap()[0].0, "b");
+        assert_eq!(parse(br#"{"a": 1"#).unwrap_err(), validate(br#"{"a": 1"#).unwrap_err());
+        assert!(parse(&[b'['; 10000]).is_err());
+    }
+}
@@ -0,0 +1,1730 @@
+//! 按换行符把大文本切成各自独立压缩的分卷.
+//!
+//! 命令行程序 zstd_compressor 的切分、换行符查找与压缩都在这里; 其他 Rust 程序可以用
+//! [`Splitter`] 直接嵌入切分流程, 不必调用命令行程序; 数据已在内存中时用 [`split_bytes`] 或
+//! [`SplitBytes`], 不经过文件系统. 命令行程序的各子命令(切分流水线, 合并与校验, 清单, 归档等)
+//! 也都在下面的模块中, 程序本身只解析参数并分派到这里.
+
+use std::io::{self, Read, Write};
+use std::collections::{TryReserveError, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use encoding_rs::{Encoding, UTF_8, GBK};
+use boundary::{Boundary, ContentDefined, Delimiter, FixedWidth};
+
+// 面向人的进度信息; --porcelain 时标准输出只留机器可读的行
+macro_rules! info {
+    ($config:expr, $($arg:tt)*) => {
+        if !$config.porcelain {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub mod archive;
+pub mod boundary;
+pub mod capabilities;
+pub mod checkpoint;
+pub mod codec;
+pub mod compact;
+pub mod compress;
+pub mod config;
+pub mod exit;
+pub mod export;
+pub mod files;
+pub mod frames;
+pub mod fuzz;
+pub mod handoff;
+pub mod hash;
+pub mod header;
+pub mod jobs;
+pub mod journal;
+pub mod json;
+pub mod manifest;
+pub mod memory;
+pub mod merge;
+pub mod merkle;
+pub mod naming;
+pub mod partition;
+pub mod profiler;
+pub mod prompt;
+pub mod query;
+pub mod regex;
+pub mod seven_zip;
+pub mod source;
+pub mod split;
+pub mod tier;
+pub mod units;
+pub mod writer;
+#[cfg(test)]
+mod testing;
+
+// 输出格式; gzip, xz 与 lz4 调用系统中的同名命令压缩; brotli 与 bzip2 还需要以同名 cargo feature 构建
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Zstd,
+    Gzip,
+    Xz,
+    Lz4,
+    Snappy,
+    SevenZip,
+    #[cfg(feature = "brotli")]
+    Brotli,
+    #[cfg(feature = "bzip2")]
+    Bzip2,
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "zstd" => Ok(Format::Zstd),
+            "gzip" | "gz" => Ok(Format::Gzip),
+            "xz" => Ok(Format::Xz),
+            "lz4" => Ok(Format::Lz4),
+            "snappy" => Ok(Format::Snappy),
+            "7z" => Ok(Format::SevenZip),
+            #[cfg(feature = "brotli")]
+            "brotli" => Ok(Format::Brotli),
+            #[cfg(feature = "bzip2")]
+            "bzip2" => Ok(Format::Bzip2),
+            #[allow(unreachable_patterns)]
+            name @ ("brotli" | "bzip2") => Err(format!("此构建未启用 {0} 格式, 请使用 cargo build --features {0}", name)),
+            _ => Err(format!("不支持的输出格式: {}", value)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Zstd => "zstd",
+            Format::Gzip => "gzip",
+            Format::Xz => "xz",
+            Format::Lz4 => "lz4",
+            Format::Snappy => "snappy",
+            Format::SevenZip => "7z",
+            #[cfg(feature = "brotli")]
+            Format::Brotli => "brotli",
+            #[cfg(feature = "bzip2")]
+            Format::Bzip2 => "bzip2",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Zstd => "zst",
+            Format::Gzip => "gz",
+            Format::Xz => "xz",
+            Format::Lz4 => "lz4",
+            Format::Snappy => "sz",
+            Format::SevenZip => "7z",
+            #[cfg(feature = "brotli")]
+            Format::Brotli => "br",
+            #[cfg(feature = "bzip2")]
+            Format::Bzip2 => "bz2",
+        }
+    }
+
+    // 多个压缩流首尾相接后能否整体解压
+    pub fn concatenable(self) -> bool {
+        match self {
+            #[cfg(feature = "brotli")]
+            Format::Brotli => false,
+            _ => true,
+        }
+    }
+}
+
+// zstd 的压缩级别与工作线程数, 切分与 compress 子命令共用; 未设置时为级别 3, 单线程
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZstdParams {
+    pub level: Option<i32>,
+    pub threads: Option<u32>,
+}
+
+impl ZstdParams {
+    pub const DEFAULT_LEVEL: i32 = 3;
+    pub const BEST_LEVEL: i32 = 19;
+
+    // 处理 --level, --fast, --best 与 --threads(也写作 --compress-threads; flag 不含 --), 不是这些选项时返回 false
+    pub fn parse_flag(&mut self, flag: &str, value: impl FnOnce() -> Result<String, String>) -> Result<bool, String> {
+        match flag {
+            "level" => {
+                let value = value()?;
+                let range = zstd::compression_level_range();
+                let level = value.trim().parse::<i32>().ok().filter(|level| range.contains(level))
+                    .ok_or_else(|| format!("无效的压缩级别: {}. 请使用 {} 到 {}", value, range.start(), range.end()))?;
+                self.level = Some(level);
+            }
+            // 与 zstd 命令行的 -1 和 -19 相同; 更高的级别需要明确给出 --level
+            "fast" => self.level = Some(1),
+            "best" => self.level = Some(Self::BEST_LEVEL),
+            // auto 为 CPU 数
+            "threads" | "compress-threads" => {
+                let value = value()?;
+                let threads = if value.trim().eq_ignore_ascii_case("auto") {
+                    std::thread::available_parallelism().map_or(1, |n| n.get() as u32).min(ZSTD_MAX_THREADS)
+                } else {
+                    value.trim().parse::<u32>().ok().filter(|&n| n <= ZSTD_MAX_THREADS)
+                        .ok_or_else(|| format!("无效的线程数: {}. 请使用 0 到 {} 或 auto", value, ZSTD_MAX_THREADS))?
+                };
+                if threads > 0 && !cfg!(feature = "zstdmt") {
+                    return Err("此构建未启用 zstdmt feature, 不支持 zstd 库内的工作线程".to_string());
+                }
+                self.threads = Some(threads);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.level.is_some() || self.threads.is_some()
+    }
+
+    pub fn level(&self) -> i32 {
+        self.level.unwrap_or(Self::DEFAULT_LEVEL)
+    }
+
+    // 0 表示在调用线程内压缩, 不启用库内的工作线程
+    pub fn threads(&self) -> u32 {
+        self.threads.unwrap_or(0)
+    }
+
+    pub fn encoder<W: Write>(&self, writer: W) -> io::Result<zstd::stream::Encoder<'static, W>> {
+        self.encoder_with_dictionary(writer, &[])
+    }
+
+    // 字典为空时与不用字典相同
+    pub fn encoder_with_dictionary<W: Write>(&self, writer: W, dictionary: &[u8]) -> io::Result<zstd::stream::Encoder<'static, W>> {
+        #[cfg_attr(not(feature = "zstdmt"), allow(unused_mut))]
+        let mut encoder = zstd::stream::Encoder::with_dictionary(writer, self.level(), dictionary)?;
+        #[cfg(feature = "zstdmt")]
+        if self.threads() > 0 {
+            encoder.multithread(self.threads())?;
+        }
+        Ok(encoder)
+    }
+}
+
+const ZSTD_MAX_THREADS: u32 = 200; // libzstd 在 64 位平台上的工作线程数上限
+
+// gzip 成员在解压后数据中的结束偏移, 由解压端写入, Chunker 按它切分
+pub type MemberEnds = Arc<Mutex<VecDeque<usize>>>;
+
+// 运行中可以调小的分块大小, 如压缩时内存不足由压缩端减半; Chunker 每次追加数据时取两者中较小的
+pub type ChunkLimit = Arc<AtomicUsize>;
+
+// 内存不足时分块大小减半的下限, 减到此以下仍然失败时报错
+pub const MIN_SHRUNK_CHUNK_SIZE: usize = 64 * 1024;
+
+// 输入中出现无效字符编码, 且要求报错而不是告警 (Chunker::fail_on_invalid)
+#[derive(Debug)]
+pub struct InvalidEncoding {
+    pub offset: usize,
+}
+
+impl std::fmt::Display for InvalidEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "输入偏移 {} 之后发现无效的字符编码", self.offset)
+    }
+}
+
+impl std::error::Error for InvalidEncoding {}
+
+// 不解码地检查字节序列是否合法, 返回 (是否含非法字节, 完整字符部分的长度);
+// 末尾被缓冲区截断的不完整字符不算错误, 留待与后续数据拼接后再检查
+pub fn check_encoding(data: &[u8], encoding: &'static Encoding) -> (bool, usize) {
+    let mut invalid = false;
+    if encoding == UTF_8 {
+        let mut pos = 0;
+        loop {
+            match std::str::from_utf8(&data[pos..]) {
+                Ok(_) => return (invalid, data.len()),
+                Err(e) => match e.error_len() {
+                    Some(len) => {
+                        invalid = true;
+                        pos += e.valid_up_to() + len;
+                    }
+                    None => return (invalid, pos + e.valid_up_to()),
+                },
+            }
+        }
+    }
+    if encoding != GBK {
+        return (false, data.len());
+    }
+
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            0x00..=0x80 => i += 1,
+            0xFF => {
+                invalid = true;
+                i += 1;
+            }
+            _ => {
+                let Some(&second) = data.get(i + 1) else { return (invalid, i) };
+                match second {
+                    0x40..=0x7E | 0x80..=0xFE => i += 2,
+                    // GB18030 四字节序列
+                    0x30..=0x39 => match (data.get(i + 2), data.get(i + 3)) {
+                        (Some(0x81..=0xFE), Some(0x30..=0x39)) => i += 4,
+                        (Some(0x81..=0xFE), None) | (None, _) => return (invalid, i),
+                        _ => {
+                            invalid = true;
+                            i += 1;
+                        }
+                    },
+                    _ => {
+                        invalid = true;
+                        i += 1;
+                    }
+                }
+            }
+        }
+    }
+    (invalid, data.len())
+}
+
+// 流式切分的接收端: 分卷的数据分段到达, 每个分卷以 end 结束
+pub trait ChunkSink {
+    // 当前分卷的下一段数据, offset 为它在输入中的偏移
+    fn data(&mut self, data: &[u8], offset: usize) -> io::Result<()>;
+    fn end(&mut self) -> io::Result<()>;
+}
+
+// 单条记录超过上限仍未结束时的处理
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LongLinePolicy {
+    // 分卷随记录增大, 记录超过分块大小时告警; 设置了上限时超过上限报错
+    Grow,
+    // 报错, 指出所在的分卷与记录的偏移
+    Error,
+    // 在上限处(字符边界)强行切分, 该分卷结束于记录中间
+    Split,
+}
+
+impl LongLinePolicy {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "grow" => Ok(LongLinePolicy::Grow),
+            "error" => Ok(LongLinePolicy::Error),
+            "split" => Ok(LongLinePolicy::Split),
+            _ => Err(format!("无效的长记录策略: {}. 请使用 grow, error 或 split", value)),
+        }
+    }
+}
+
+// 读入这么多倍分块大小的数据仍未找到任何记录结束时, 按 NoBoundaryPolicy 处理
+pub const NO_BOUNDARY_CHUNKS: usize = 4;
+
+// 输入中一直找不到记录结束(如换行符设置错误或输入其实是二进制)时的处理
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoBoundaryPolicy {
+    // 在标准错误上醒目地告警一次, 仍按记录切分, 整个输入可能成为一个分卷
+    Warn,
+    // 告警后改为按二进制输入切分, 每个分卷正好是分块大小
+    FallbackBinary,
+    // 报错
+    Error,
+}
+
+impl NoBoundaryPolicy {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "warn" => Ok(NoBoundaryPolicy::Warn),
+            "fallback-binary" => Ok(NoBoundaryPolicy::FallbackBinary),
+            "error" => Ok(NoBoundaryPolicy::Error),
+            _ => Err(format!("无效的无记录边界策略: {}. 请使用 warn, fallback-binary 或 error", value)),
+        }
+    }
+}
+
+// 在 pending 中查找记录结束的结果
+enum Scanned {
+    // 目标处或之后的第一个记录结束的位置; 没有时为最后一个记录结束的位置
+    End(Option<usize>),
+    // Split 策略下超长记录的强制切分位置, 应立即在此切分
+    Forced(usize),
+}
+
+// 把读入的数据按换行符切成分卷, 与读取和写出解耦
+pub struct Chunker {
+    chunk_size: usize,
+    encoding: &'static Encoding,
+    // 记录边界的查找策略, 默认按换行符
+    records: Box<dyn Boundary>,
+    pending: Vec<u8>,
+    // 按 gzip 成员切分时 pending 中最后一个成员结束的位置
+    boundary: Option<usize>,
+    // pending 起始处在输入中的偏移
+    offset: usize,
+    // pending 中已检查过编码的长度
+    checked: usize,
+    pub warn_invalid: bool,
+    // 发现无效编码时报错而不是告警
+    pub fail_on_invalid: bool,
+    // 设置后分卷不超过 max_size, chunk_size 作为目标大小
+    pub max_size: Option<usize>,
+    // 查找记录边界的进度, 以及有上限时目标之内的最后一个、目标之后的第一个记录结束位置
+    scan_pos: usize,
+    below: Option<usize>,
+    above: Option<usize>,
+    // 设置后每切出一个分卷, 按模型重新计算下一个分卷的目标大小
+    balance: Option<CompressionModel>,
+    // 设置后把总长 total 的输入均分为 parts 个分卷, 见 set_parts
+    parts: Option<(usize, usize)>,
+    // 设置后只在 gzip 成员结束处切分, 不再按换行符
+    pub member_ends: Option<MemberEnds>,
+    pub chunk_limit: Option<ChunkLimit>,
+    // 流式切分时当前分卷已经交给接收端的字节数
+    streamed: usize,
+    // 单条记录超过上限时的处理; 上限未设置时, Grow 没有上限, 其余策略以分块大小为上限.
+    // 有 max_size 时上限即为 max_size, Grow 视同 Error
+    pub long_line_policy: LongLinePolicy,
+    pub long_line_cap: Option<usize>,
+    // pending 中当前(未结束)记录的开始位置, 已切出的分卷数, 以及是否已对当前记录告警
+    record_start: usize,
+    chunks: usize,
+    long_warned: bool,
+    // Grow 策略下告警过的过长记录数
+    pub long_records: usize,
+    // 设置后每个分卷正好包含这么多条记录(最后一个可能更少), 不再按分块大小; counted 为当前分卷已有的记录数
+    pub lines: Option<usize>,
+    counted: usize,
+    // 一直找不到记录结束时的处理, 以及是否已经处理过
+    pub no_boundary: NoBoundaryPolicy,
+    no_boundary_handled: bool,
+}
+
+impl Chunker {
+    pub fn new(chunk_size: usize, line_ending: &str, encoding: &'static Encoding) -> Self {
+        Chunker {
+            chunk_size,
+            encoding,
+            records: Box::new(Delimiter::new(line_ending, encoding)),
+            pending: Vec::new(),
+            boundary: None,
+            offset: 0,
+            checked: 0,
+            warn_invalid: true,
+            fail_on_invalid: false,
+            max_size: None,
+            scan_pos: 0,
+            below: None,
+            above: None,
+            balance: None,
+            parts: None,
+            member_ends: None,
+            chunk_limit: None,
+            streamed: 0,
+            long_line_policy: LongLinePolicy::Grow,
+            long_line_cap: None,
+            record_start: 0,
+            chunks: 0,
+            long_warned: false,
+            long_records: 0,
+            lines: None,
+            counted: 0,
+            no_boundary: NoBoundaryPolicy::Warn,
+            no_boundary_handled: false,
+        }
+    }
+
+    // 按其他格式的记录切分, 如 CSV 或定长记录
+    pub fn set_records(&mut self, records: Box<dyn Boundary>) {
+        self.records = records;
+    }
+
+    // 按内容定义的切分点切分, 分卷平均约为分块大小; byte_level 时(二进制输入)按字节而不是在记录结束处切分.
+    // 每个切分点结束一个分卷, 与 lines 为 1 时相同
+    pub fn set_content_defined(&mut self, byte_level: bool) {
+        let records = std::mem::replace(&mut self.records, Box::new(FixedWidth::new(1)));
+        self.records = Box::new(ContentDefined::new(self.chunk_size, (!byte_level).then_some(records)));
+        self.lines = Some(1);
+    }
+
+    pub fn set_balance(&mut self, model: CompressionModel) {
+        self.balance = Some(model);
+        self.update_target();
+    }
+
+    // 把总长 total 的输入均分为 parts 个分卷: 第 k 个分卷在离输入的 k/parts 处最近的记录结束处结束,
+    // 前面分卷的偏差不会累积; 最后一个分卷包含剩余的全部数据
+    pub fn set_parts(&mut self, parts: usize, total: usize) {
+        self.parts = Some((parts, total));
+        self.update_target();
+    }
+
+    fn update_target(&mut self) {
+        if let Some((parts, total)) = self.parts {
+            let next = self.chunks + 1;
+            let end = (next as u128 * total as u128 / parts as u128) as usize;
+            self.chunk_size = if next >= parts { usize::MAX } else { end.saturating_sub(self.offset).max(1) };
+        } else if let Some(model) = &self.balance {
+            self.chunk_size = model.raw_size_from(self.offset).min(self.max_size.unwrap_or(usize::MAX));
+        }
+    }
+
+    // 追加一个缓冲区; 在分块大小处或之后的第一个记录结束处切出分卷, 切分位置与每次追加多少数据无关
+    pub fn push<F>(&mut self, data: &[u8], emit: &mut F) -> io::Result<()>
+    where
+        F: FnMut(&[u8], usize) -> io::Result<()>,
+    {
+        self.reserve(data.len(), emit, |pending, additional| pending.try_reserve(additional))?;
+        self.append(data)?;
+        self.split_pending(emit)?;
+        if self.check_no_boundary()? {
+            while self.pending.len() >= self.chunk_size {
+                self.cut(self.chunk_size, emit)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn split_pending<F>(&mut self, emit: &mut F) -> io::Result<()>
+    where
+        F: FnMut(&[u8], usize) -> io::Result<()>,
+    {
+        if let Some(lines) = self.lines {
+            return self.push_counted(lines, emit);
+        }
+
+        // 分卷大小各不相同时, 一次追加的数据里可能要切出多个分卷, 同样逐个挑选换行符
+        if self.max_size.is_some() || self.balance.is_some() || self.parts.is_some() {
+            return self.push_bounded(self.max_size.unwrap_or(usize::MAX), emit);
+        }
+
+        if let Some(member_ends) = &self.member_ends {
+            // 解压端可能已经记录了尚未交到这里的成员结束位置, 留到数据到齐后再用
+            let available = self.offset + self.pending.len();
+            let mut member_ends = member_ends.lock().unwrap();
+            while let Some(end) = member_ends.front().copied().filter(|&end| end <= available) {
+                member_ends.pop_front();
+                if end > self.offset {
+                    self.boundary = Some(end - self.offset);
+                }
+            }
+            drop(member_ends);
+            if self.pending.len() >= self.chunk_size {
+                if let Some(split_pos) = self.boundary.take() {
+                    self.cut(split_pos, emit)?;
+                }
+            }
+            return Ok(());
+        }
+
+        loop {
+            match self.scan_records(self.chunk_size)? {
+                Scanned::End(Some(end)) if end >= self.chunk_size => self.cut(end, emit)?,
+                Scanned::End(_) => return Ok(()),
+                Scanned::Forced(split_pos) => self.cut(split_pos, emit)?,
+            }
+        }
+    }
+
+    // 按记录数切分: 数满 lines 条记录就在该记录结束处切出一个分卷. 一个缓冲区里可能切出许多分卷,
+    // 全部交出后才一次性移除, 扫描状态随之平移, 不必从头重新扫描
+    fn push_counted<F>(&mut self, lines: usize, emit: &mut F) -> io::Result<()>
+    where
+        F: FnMut(&[u8], usize) -> io::Result<()>,
+    {
+        let mut start = 0;
+        while let Some(end) = self.records.next_end(&self.pending, &mut self.scan_pos, true) {
+            self.counted += 1;
+            if self.counted == lines {
+                emit(&self.pending[start..end], self.offset + start)?;
+                self.counted = 0;
+                self.chunks += 1;
+                start = end;
+            }
+        }
+        if start > 0 {
+            self.pending.drain(..start);
+            self.offset += start;
+            self.checked = self.checked.saturating_sub(start);
+            self.scan_pos -= start;
+        }
+        Ok(())
+    }
+
+    // 流式切分: 完整的记录立即交给接收端, 只有最后一个记录结束之后的数据留在内存中,
+    // 内存占用与分块大小无关; 切分位置与 push 相同. 不支持 max_size, balance 与 member_ends
+    pub fn push_streaming(&mut self, data: &[u8], sink: &mut impl ChunkSink) -> io::Result<()> {
+        self.append(data)?;
+        loop {
+            let target = self.chunk_size.saturating_sub(self.streamed).max(1);
+            let (end, forced) = match self.scan_records(target)? {
+                Scanned::End(end) => (end, false),
+                Scanned::Forced(split_pos) => (Some(split_pos), true),
+            };
+            let full = end.is_some_and(|end| end >= target);
+            if let Some(end) = end {
+                sink.data(&self.pending[..end], self.offset)?;
+                self.pending.drain(..end);
+                self.offset += end;
+                self.streamed += end;
+                self.checked = self.checked.saturating_sub(end);
+                self.scan_pos = 0;
+                self.record_start = 0;
+                self.records.reset();
+            }
+            if (full || forced) && self.streamed > 0 {
+                sink.end()?;
+                self.streamed = 0;
+                self.chunks += 1;
+            }
+            if !(full || forced) {
+                break;
+            }
+        }
+        if self.check_no_boundary()? {
+            // 还没有找到过记录结束, 接收端中没有未结束的分卷
+            while self.pending.len() >= self.chunk_size {
+                sink.data(&self.pending[..self.chunk_size], self.offset)?;
+                sink.end()?;
+                self.pending.drain(..self.chunk_size);
+                self.offset += self.chunk_size;
+                self.checked = self.checked.saturating_sub(self.chunk_size);
+                self.chunks += 1;
+            }
+        }
+        Ok(())
+    }
+
+    // 流式切分的输入结束, 剩余数据作为最后一个分卷的结尾
+    pub fn finish_streaming(&mut self, sink: &mut impl ChunkSink) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            sink.data(&self.pending, self.offset)?;
+            self.offset += self.pending.len();
+            self.streamed += self.pending.len();
+            self.pending.clear();
+        }
+        if self.streamed > 0 {
+            sink.end()?;
+            self.streamed = 0;
+            self.chunks += 1;
+        }
+        self.checked = 0;
+        self.scan_pos = 0;
+        self.record_start = 0;
+        self.records.reset();
+        Ok(())
+    }
+
+    // 找出 pending 中 target 处或之后的第一个记录结束的位置, 没有时为最后一个记录结束的位置.
+    // 长记录有上限时逐条检查记录长度, 超过上限的记录按策略报错或强制切分; 没有上限时目标之前的部分
+    // 只需找最后一个记录结束, 只在未结束的记录超过分块大小时告警一次
+    fn scan_records(&mut self, target: usize) -> io::Result<Scanned> {
+        let cap = match self.long_line_policy {
+            LongLinePolicy::Grow => self.long_line_cap,
+            _ => Some(self.long_line_cap.unwrap_or(self.chunk_size)),
+        };
+        let mut last = None;
+        let window = self.pending.len().min(target);
+        if cap.is_none() && self.scan_pos < window {
+            // 窗口不超过分块大小, 其中结束的记录都不会过长
+            last = self.records.last_end(&self.pending[..window], &mut self.scan_pos, true);
+            if let Some(end) = last {
+                self.record_start = end;
+                self.long_warned = false;
+            }
+        }
+        while last.is_none_or(|end| end < target) {
+            let end = self.records.next_end(&self.pending, &mut self.scan_pos, true);
+            if let Some(cap) = cap {
+                if end.unwrap_or(self.pending.len()) - self.record_start > cap {
+                    if self.long_line_policy != LongLinePolicy::Split {
+                        return Err(self.long_record_error(cap));
+                    }
+                    return Ok(Scanned::Forced(self.char_boundary(self.record_start + cap)));
+                }
+            }
+            let Some(end) = end else { break };
+            if end - self.record_start > self.chunk_size {
+                self.warn_long_record();
+            }
+            last = Some(end);
+            self.record_start = end;
+            self.long_warned = false;
+        }
+        // 只按已扫描的部分判断未结束的记录, 缓冲区比分块大时尚未扫描的数据不算
+        if last.is_none_or(|end| end < target) && self.scan_pos.saturating_sub(self.record_start) > self.chunk_size {
+            self.warn_long_record();
+        }
+        Ok(Scanned::End(last))
+    }
+
+    // 还没有找到过任何记录结束而积累的数据已达 NO_BOUNDARY_CHUNKS 倍分块大小时按策略处理, 只处理一次;
+    // 返回 true 表示已改为按二进制切分, 调用方应把 pending 按分块大小切出
+    fn check_no_boundary(&mut self) -> io::Result<bool> {
+        let found = self.chunks > 0 || self.streamed > 0 || self.boundary.is_some() || self.record_start > 0 || self.below.is_some() || self.above.is_some() || self.counted > 0;
+        if self.no_boundary_handled || found || self.pending.len() < NO_BOUNDARY_CHUNKS.saturating_mul(self.chunk_size) {
+            return Ok(false);
+        }
+        self.no_boundary_handled = true;
+        let message = format!("已读取 {} 字节(分块大小的 {} 倍)仍未找到任何记录结束, 请检查换行符设置或输入是否为二进制", self.pending.len(), NO_BOUNDARY_CHUNKS);
+        match self.no_boundary {
+            NoBoundaryPolicy::Error => Err(io::Error::new(io::ErrorKind::InvalidData, message)),
+            NoBoundaryPolicy::Warn => {
+                eprintln!("警告: {}; 整个输入可能成为一个分卷, 可用 --no-boundary fallback-binary 改为按固定大小切分", message);
+                Ok(false)
+            }
+            NoBoundaryPolicy::FallbackBinary => {
+                eprintln!("警告: {}; 改为按二进制输入切分, 每个分卷 {} 字节", message, self.chunk_size);
+                self.records = Box::new(FixedWidth::new(self.chunk_size));
+                self.scan_pos = 0;
+                self.warn_invalid = false;
+                self.fail_on_invalid = false;
+                Ok(true)
+            }
+        }
+    }
+
+    // Grow 策略下记录超过分块大小时告警, 每条记录只告警一次
+    fn warn_long_record(&mut self) {
+        if self.long_line_policy != LongLinePolicy::Grow || self.long_warned {
+            return;
+        }
+        eprintln!(
+            "警告: 分卷 {} 中偏移 {} 处的记录超过分块大小 {} 字节仍未结束, 分卷随之增大",
+            self.chunks + 1,
+            self.offset + self.record_start,
+            self.chunk_size
+        );
+        self.long_warned = true;
+        self.long_records += 1;
+    }
+
+    fn long_record_error(&self, cap: usize) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("分卷 {} 中偏移 {} 处的记录超过 {} 字节仍未结束", self.chunks + 1, self.offset + self.record_start, cap),
+        )
+    }
+
+    // pos 之前(含)最近的字符边界; 当前记录的开始总在字符边界上, 从那里逐字符前进.
+    // 末尾被缓冲区截断的字符还不完整, 不越过已检查过编码的部分
+    fn char_boundary(&self, pos: usize) -> usize {
+        let mut i = self.record_start;
+        loop {
+            let next = i + boundary::char_len(&self.pending, i, self.encoding);
+            if next > pos.min(self.checked) {
+                return i.max(self.record_start + 1);
+            }
+            i = next;
+        }
+    }
+
+    // 为追加的数据预留空间. 内存不足时分块大小减半(经 chunk_limit 同样用于之后的压缩), 先按减半后的大小
+    // 切出已有的完整记录腾出空间再重试; 流式切分只缓存未结束的记录, 不经过这里
+    fn reserve<F, R>(&mut self, additional: usize, emit: &mut F, mut try_reserve: R) -> io::Result<()>
+    where
+        F: FnMut(&[u8], usize) -> io::Result<()>,
+        R: FnMut(&mut Vec<u8>, usize) -> Result<(), TryReserveError>,
+    {
+        while let Err(error) = try_reserve(&mut self.pending, additional) {
+            let size = self.chunk_size / 2;
+            if size < MIN_SHRUNK_CHUNK_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::OutOfMemory,
+                    format!("缓存分卷 {} 的数据时内存不足, 分块大小减到 {} 字节以下仍然失败: {}", self.chunks + 1, MIN_SHRUNK_CHUNK_SIZE, error),
+                ));
+            }
+            eprintln!("警告: 缓存分卷 {} 的数据时内存不足, 分块大小减半为 {} 字节, 之后的分卷按此切分", self.chunks + 1, size);
+            if let Some(limit) = &self.chunk_limit {
+                limit.fetch_min(size, Ordering::Relaxed);
+            }
+            self.shrink(size);
+            self.split_pending(emit)?;
+        }
+        Ok(())
+    }
+
+    // 运行中调小分块大小. 长记录的上限原本跟随分块大小, 不因分块变小而收紧; 已扫描过新的大小时
+    // 其中的记录结束没有保留, 从 pending 开头(总是记录开始处)重新扫描
+    fn shrink(&mut self, size: usize) {
+        if self.long_line_policy != LongLinePolicy::Grow && self.long_line_cap.is_none() {
+            self.long_line_cap = Some(self.chunk_size);
+        }
+        self.chunk_size = size;
+        if self.lines.is_none() && self.scan_pos > size {
+            self.scan_pos = 0;
+            self.record_start = 0;
+            self.records.reset();
+            self.below = None;
+            self.above = None;
+        }
+    }
+
+    // 追加数据并检查字符编码
+    fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        if let Some(limit) = self.chunk_limit.as_ref().map(|limit| limit.load(Ordering::Relaxed)).filter(|&limit| limit < self.chunk_size) {
+            self.shrink(limit);
+        }
+        self.pending.extend_from_slice(data);
+
+        // 从上次检查到的字符边界继续, 跨缓冲区的多字节字符不会被误报
+        let checked_from = self.offset + self.checked;
+        let (invalid, complete) = check_encoding(&self.pending[self.checked..], self.encoding);
+        self.checked += complete;
+        if invalid && self.fail_on_invalid {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, InvalidEncoding { offset: checked_from }));
+        }
+        if invalid && self.warn_invalid {
+            eprintln!("警告: 发现无效的字符编码");
+        }
+        Ok(())
+    }
+
+    // 有上限时: 找到目标之后的第一个换行符, 或数据超过上限时, 从目标两侧的换行符中
+    // 选离目标最近的切分; 上限之内没有换行符说明单行超过上限
+    fn push_bounded<F>(&mut self, max_size: usize, emit: &mut F) -> io::Result<()>
+    where
+        F: FnMut(&[u8], usize) -> io::Result<()>,
+    {
+        loop {
+            // 数据等于上限时还不能确定是否该在此处切分, 超过上限才是最终窗口
+            let full = self.pending.len() > max_size;
+            let window = &self.pending[..self.pending.len().min(max_size)];
+            while self.above.is_none() {
+                let Some(end) = self.records.next_end(window, &mut self.scan_pos, !full) else { break };
+                if end <= self.chunk_size {
+                    self.below = Some(end);
+                } else {
+                    self.above = Some(end);
+                }
+            }
+
+            let split_pos = match (self.below, self.above) {
+                (Some(below), Some(above)) if self.chunk_size - below <= above - self.chunk_size => below,
+                (_, Some(above)) => above,
+                (Some(below), None) if full => below,
+                // 上限之内没有记录结束, 有上限时只能报错或强行切分
+                (None, None) if full && self.long_line_policy == LongLinePolicy::Split => self.char_boundary(max_size),
+                (None, None) if full => return Err(self.long_record_error(max_size)),
+                _ => return Ok(()),
+            };
+            self.cut(split_pos, emit)?;
+        }
+    }
+
+    // 把 pending 的前 split_pos 字节作为一个分卷写出
+    fn cut<F>(&mut self, split_pos: usize, emit: &mut F) -> io::Result<()>
+    where
+        F: FnMut(&[u8], usize) -> io::Result<()>,
+    {
+        emit(&self.pending[..split_pos], self.offset)?;
+        self.pending.drain(..split_pos);
+        self.offset += split_pos;
+        self.checked = self.checked.saturating_sub(split_pos);
+        self.scan_pos = 0;
+        self.record_start = 0;
+        self.chunks += 1;
+        self.long_warned = false;
+        self.records.reset();
+        self.below = None;
+        self.above = None;
+        self.update_target();
+        Ok(())
+    }
+
+    // 输入结束时写出剩余数据; 没有剩余数据时不产生空分卷
+    pub fn finish<F>(&mut self, emit: &mut F) -> io::Result<()>
+    where
+        F: FnMut(&[u8], usize) -> io::Result<()>,
+    {
+        if !self.pending.is_empty() {
+            emit(&self.pending, self.offset)?;
+            self.offset += self.pending.len();
+            self.pending.clear();
+            self.chunks += 1;
+        }
+        self.boundary = None;
+        self.checked = 0;
+        self.scan_pos = 0;
+        self.record_start = 0;
+        self.long_warned = false;
+        self.counted = 0;
+        self.records.reset();
+        self.below = None;
+        self.above = None;
+        Ok(())
+    }
+}
+
+// 压缩率模型: 把文件均匀分段, 每段开头取一小块压缩, 估算各段压缩后的大小
+pub struct CompressionModel {
+    segment_size: usize,
+    // 各段压缩后与压缩前大小之比
+    ratios: Vec<f64>,
+    file_len: usize,
+    // 每个分卷压缩后的目标大小
+    target_compressed: f64,
+}
+
+impl CompressionModel {
+    // 由各段的采样压缩率建立模型; 分卷数与按原始大小切分时相同, 总压缩后大小平均分给各分卷
+    pub fn new(segment_size: usize, ratios: Vec<f64>, file_len: usize, chunk_size: usize) -> Self {
+        let mut model = CompressionModel {
+            segment_size,
+            ratios,
+            file_len,
+            target_compressed: 0.0,
+        };
+        let chunks = file_len.div_ceil(chunk_size).max(1);
+        model.target_compressed = model.compressed_between(0, file_len) / chunks as f64;
+        model
+    }
+
+    // 整个文件压缩后的估算大小, 以及每个分卷压缩后的目标大小
+    pub fn total_compressed(&self) -> f64 {
+        self.compressed_between(0, self.file_len)
+    }
+
+    pub fn target_compressed(&self) -> f64 {
+        self.target_compressed
+    }
+
+    fn ratio_at(&self, pos: usize) -> f64 {
+        self.ratios.get(pos / self.segment_size).copied().unwrap_or(1.0).max(1e-6)
+    }
+
+    // 估算 [start, end) 压缩后的大小
+    fn compressed_between(&self, start: usize, end: usize) -> f64 {
+        let mut total = 0.0;
+        let mut pos = start;
+        while pos < end {
+            let segment_end = ((pos / self.segment_size + 1) * self.segment_size).min(end);
+            total += (segment_end - pos) as f64 * self.ratio_at(pos);
+            pos = segment_end;
+        }
+        total
+    }
+
+    // 从 offset 开始, 压缩后达到目标大小所需的原始字节数
+    fn raw_size_from(&self, offset: usize) -> usize {
+        let mut remaining = self.target_compressed;
+        let mut pos = offset;
+        while pos < self.file_len {
+            let segment_end = ((pos / self.segment_size + 1) * self.segment_size).min(self.file_len);
+            let ratio = self.ratio_at(pos);
+            let available = (segment_end - pos) as f64 * ratio;
+            if available >= remaining {
+                return (pos - offset + (remaining / ratio) as usize).max(1);
+            }
+            remaining -= available;
+            pos = segment_end;
+        }
+        (pos - offset).max(1)
+    }
+}
+
+// 输入中各种行结束符的出现次数; CRLF 不重复计入 LF 与 CR, 跨缓冲区的 CRLF 也只算一次
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LineEndings {
+    pub lf: u64,
+    pub crlf: u64,
+    pub cr: u64,
+    // 上一个缓冲区以 CR 结尾, 要看下一个字节才知道是 CR 还是 CRLF
+    after_cr: bool,
+}
+
+impl LineEndings {
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            if self.after_cr {
+                self.after_cr = false;
+                if byte == b'\n' {
+                    self.crlf += 1;
+                    continue;
+                }
+                self.cr += 1;
+            }
+            match byte {
+                b'\n' => self.lf += 1,
+                b'\r' => self.after_cr = true,
+                _ => {}
+            }
+        }
+    }
+
+    // 接上紧随其后的一段数据单独的计数(未调用 finish), first 为该段的第一个字节;
+    // 前一段以 CR 结尾而该段以 LF 开头时, 两者合起来是一个 CRLF
+    pub fn append(&mut self, next: &LineEndings, first: Option<u8>) {
+        self.lf += next.lf;
+        self.crlf += next.crlf;
+        self.cr += next.cr;
+        if std::mem::replace(&mut self.after_cr, next.after_cr) {
+            if first == Some(b'\n') {
+                self.lf -= 1;
+                self.crlf += 1;
+            } else {
+                self.cr += 1;
+            }
+        }
+    }
+
+    // 输入结束, 结尾的 CR 单独计数
+    pub fn finish(&mut self) {
+        if std::mem::take(&mut self.after_cr) {
+            self.cr += 1;
+        }
+    }
+
+    // 出现最多的行结束符, 次数相同时依次优先 LF, CRLF, CR; 没有任何行结束符时为 None
+    pub fn dominant(&self) -> Option<&'static str> {
+        // max_by_key 在相同时取最后一个
+        let counts = [(self.cr, "CR"), (self.crlf, "CRLF"), (self.lf, "LF")];
+        counts.iter().filter(|(count, _)| *count > 0).max_by_key(|(count, _)| *count).map(|(_, name)| *name)
+    }
+}
+
+pub fn chunk_name(output_prefix: &str, chunk_number: usize, extension: &str) -> String {
+    format!("{}.{:03}.{}", output_prefix, chunk_number, extension)
+}
+
+
+// Splitter 的参数; new 给出与命令行相同的默认值: LF 换行, UTF-8, zstd 级别 3
+#[derive(Debug, Clone)]
+pub struct SplitConfig {
+    // 目标分卷大小(字节); 设置 max_size 时分卷在两者之间选离目标最近的换行符切分
+    pub chunk_size: usize,
+    pub max_size: Option<usize>,
+    pub line_ending: String,
+    pub encoding: &'static Encoding,
+    pub format: Format,
+    pub zstd: ZstdParams,
+    // 发现无效编码时报错(错误中带 InvalidEncoding), 否则在标准错误上告警
+    pub fail_on_invalid: bool,
+    // 单条记录超过上限时的处理, 见 Chunker
+    pub long_line_policy: LongLinePolicy,
+    pub long_line_cap: Option<usize>,
+    // 设置后每个分卷正好包含这么多行, chunk_size 不再起作用
+    pub lines: Option<usize>,
+    // 一直找不到换行符时的处理, 见 Chunker
+    pub no_boundary: NoBoundaryPolicy,
+}
+
+impl SplitConfig {
+    pub fn new(chunk_size: usize) -> Self {
+        SplitConfig {
+            chunk_size,
+            max_size: None,
+            line_ending: "\n".to_string(),
+            encoding: UTF_8,
+            format: Format::Zstd,
+            zstd: ZstdParams::default(),
+            fail_on_invalid: false,
+            long_line_policy: LongLinePolicy::Grow,
+            long_line_cap: None,
+            lines: None,
+            no_boundary: NoBoundaryPolicy::Warn,
+        }
+    }
+
+    // 检查参数并建立相应的 Chunker
+    fn chunker(&self) -> io::Result<Chunker> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+        if self.chunk_size == 0 {
+            return Err(invalid("分卷大小必须大于 0"));
+        }
+        if self.max_size.is_some_and(|max| max < self.chunk_size) {
+            return Err(invalid("大小上限不能小于目标大小"));
+        }
+        if self.line_ending.is_empty() {
+            return Err(invalid("换行符不能为空"));
+        }
+        if self.lines == Some(0) {
+            return Err(invalid("每卷行数必须大于 0"));
+        }
+        if self.lines.is_some() && self.max_size.is_some() {
+            return Err(invalid("按行数切分时不能设置大小上限"));
+        }
+        let mut chunker = Chunker::new(self.chunk_size, &self.line_ending, self.encoding);
+        chunker.max_size = self.max_size;
+        chunker.fail_on_invalid = self.fail_on_invalid;
+        chunker.long_line_policy = self.long_line_policy;
+        chunker.long_line_cap = self.long_line_cap;
+        chunker.lines = self.lines;
+        chunker.no_boundary = self.no_boundary;
+        Ok(chunker)
+    }
+}
+
+// 切出并压缩好的一个分卷; number 从 1 开始, offset 为原始内容在输入中的偏移
+pub struct Chunk<'a> {
+    pub number: usize,
+    pub offset: usize,
+    pub raw: &'a [u8],
+    pub compressed: Vec<u8>,
+}
+
+// 按换行符切分并逐卷压缩: 用 push 逐段喂入数据, 最后调用 finish; 或用 split 直接读完一个 Read
+pub struct Splitter {
+    format: Format,
+    zstd: ZstdParams,
+    chunker: Chunker,
+    chunks: usize,
+}
+
+impl Splitter {
+    pub fn new(config: SplitConfig) -> io::Result<Self> {
+        let chunker = config.chunker()?;
+        Ok(Splitter { format: config.format, zstd: config.zstd, chunker, chunks: 0 })
+    }
+
+    // 追加一段数据, 每切出一个分卷就压缩后交给 emit
+    pub fn push(&mut self, data: &[u8], emit: &mut impl FnMut(Chunk) -> io::Result<()>) -> io::Result<()> {
+        let Splitter { format, zstd, chunker, chunks } = self;
+        chunker.push(data, &mut |raw: &[u8], offset: usize| compress_chunk(raw, offset, *format, *zstd, chunks, emit))
+    }
+
+    // 输入结束: 写出剩余数据, 返回分卷总数
+    pub fn finish(&mut self, emit: &mut impl FnMut(Chunk) -> io::Result<()>) -> io::Result<usize> {
+        let Splitter { format, zstd, chunker, chunks } = self;
+        chunker.finish(&mut |raw: &[u8], offset: usize| compress_chunk(raw, offset, *format, *zstd, chunks, emit))?;
+        Ok(*chunks)
+    }
+
+    // 读完 reader 并切分, 返回分卷总数
+    pub fn split(&mut self, mut reader: impl Read, mut emit: impl FnMut(Chunk) -> io::Result<()>) -> io::Result<usize> {
+        let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+        loop {
+            let n = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.push(&buffer[..n], &mut emit)?;
+        }
+        self.finish(&mut emit)
+    }
+
+    // 与命令行相同, 把分卷写成 <output_prefix>.001.zst 起的文件, 返回各文件路径
+    pub fn split_to_files(&mut self, reader: impl Read, output_prefix: &str) -> io::Result<Vec<PathBuf>> {
+        let extension = self.format.extension();
+        let mut paths = Vec::new();
+        self.split(reader, |chunk| {
+            let path = PathBuf::from(chunk_name(output_prefix, chunk.number, extension));
+            let mut file = std::fs::File::create(&path)?;
+            file.write_all(&chunk.compressed)?;
+            file.sync_all()?;
+            paths.push(path);
+            Ok(())
+        })?;
+        Ok(paths)
+    }
+}
+
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+// 切分并压缩内存中的全部数据, 不读写文件
+pub fn split_bytes<'a>(data: &'a [u8], config: &SplitConfig) -> io::Result<Vec<Chunk<'a>>> {
+    SplitBytes::new(data, config)?.collect()
+}
+
+// split_bytes 的迭代器形式: 每次取下一个分卷时才继续切分并压缩, 同时只有一个压缩好的分卷在内存中;
+// 分卷的 raw 直接引用输入. 出错后不再返回分卷
+pub struct SplitBytes<'a> {
+    data: &'a [u8],
+    // 已交给 Chunker 的字节数
+    fed: usize,
+    format: Format,
+    zstd: ZstdParams,
+    chunker: Chunker,
+    // 已切出、尚未压缩的分卷在输入中的范围
+    pending: VecDeque<(usize, usize)>,
+    chunks: usize,
+    done: bool,
+}
+
+impl<'a> SplitBytes<'a> {
+    pub fn new(data: &'a [u8], config: &SplitConfig) -> io::Result<Self> {
+        let chunker = config.chunker()?;
+        Ok(SplitBytes { data, fed: 0, format: config.format, zstd: config.zstd, chunker, pending: VecDeque::new(), chunks: 0, done: false })
+    }
+}
+
+impl<'a> Iterator for SplitBytes<'a> {
+    type Item = io::Result<Chunk<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.done {
+            let pending = &mut self.pending;
+            let mut emit = |raw: &[u8], offset: usize| -> io::Result<()> {
+                pending.push_back((offset, raw.len()));
+                Ok(())
+            };
+            let result = if self.fed < self.data.len() {
+                let end = (self.fed + READ_BUFFER_SIZE).min(self.data.len());
+                let result = self.chunker.push(&self.data[self.fed..end], &mut emit);
+                self.fed = end;
+                result
+            } else {
+                self.done = true;
+                self.chunker.finish(&mut emit)
+            };
+            if let Err(e) = result {
+                self.done = true;
+                self.pending.clear();
+                return Some(Err(e));
+            }
+        }
+        let (offset, len) = self.pending.pop_front()?;
+        let raw = &self.data[offset..offset + len];
+        self.chunks += 1;
+        Some(codec::compress(raw, self.format, self.zstd).map(|compressed| Chunk { number: self.chunks, offset, raw, compressed }))
+    }
+}
+
+fn compress_chunk(raw: &[u8], offset: usize, format: Format, zstd: ZstdParams, chunks: &mut usize, emit: &mut impl FnMut(Chunk) -> io::Result<()>) -> io::Result<()> {
+    let compressed = codec::compress(raw, format, zstd)?;
+    *chunks += 1;
+    emit(Chunk { number: *chunks, offset, raw, compressed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按 buffer_size 分批喂给 Chunker, 返回 (偏移, 分卷) 列表
+    fn split(data: &[u8], buffer_size: usize, chunk_size: usize, line_ending: &str) -> Vec<(usize, Vec<u8>)> {
+        let mut chunker = Chunker::new(chunk_size, line_ending, UTF_8);
+        let mut chunks = Vec::new();
+        let mut emit = |chunk: &[u8], offset: usize| -> io::Result<()> {
+            chunks.push((offset, chunk.to_vec()));
+            Ok(())
+        };
+        for buffer in data.chunks(buffer_size) {
+            chunker.push(buffer, &mut emit).unwrap();
+        }
+        chunker.finish(&mut emit).unwrap();
+        chunks
+    }
+
+    // 把流式切分交出的各段拼回分卷
+    #[derive(Default)]
+    struct Collect {
+        chunks: Vec<(usize, Vec<u8>)>,
+        open: bool,
+    }
+
+    impl ChunkSink for Collect {
+        fn data(&mut self, data: &[u8], offset: usize) -> io::Result<()> {
+            match self.chunks.last_mut() {
+                Some((_, chunk)) if self.open => chunk.extend_from_slice(data),
+                _ => self.chunks.push((offset, data.to_vec())),
+            }
+            self.open = true;
+            Ok(())
+        }
+
+        fn end(&mut self) -> io::Result<()> {
+            assert!(self.open, "空分卷");
+            self.open = false;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn streaming_cuts_where_push_does() {
+        let data = b"ab\ncdef\n\ng\nhijklmn\nop\nq";
+        for buffer_size in 1..=data.len() {
+            for chunk_size in 1..=data.len() + 1 {
+                let mut chunker = Chunker::new(chunk_size, "\n", UTF_8);
+                let mut sink = Collect::default();
+                for buffer in data.chunks(buffer_size) {
+                    chunker.push_streaming(buffer, &mut sink).unwrap();
+                }
+                chunker.finish_streaming(&mut sink).unwrap();
+                assert!(!sink.open);
+                assert_eq!(sink.chunks, split(data, buffer_size, chunk_size, "\n"), "buffer={} chunk={}", buffer_size, chunk_size);
+            }
+        }
+    }
+
+    #[test]
+    fn cut_points_do_not_depend_on_buffer_size() {
+        let data = b"ab\r\ncdef\r\n\r\ng\r\nhijklmn\r\nop\r\nq";
+        for line_ending in ["\n", "\r\n"] {
+            for chunk_size in 1..=data.len() + 1 {
+                let whole = split(data, data.len(), chunk_size, line_ending);
+                // 除最后一个外, 每个分卷都在达到分块大小后的第一个记录结束处结束
+                for (_, chunk) in &whole[..whole.len() - 1] {
+                    let body = &chunk[..chunk.len() - line_ending.len()];
+                    let last_record = body.windows(line_ending.len()).rposition(|w| w == line_ending.as_bytes()).map_or(0, |i| i + line_ending.len());
+                    assert!(chunk.len() >= chunk_size && last_record < chunk_size, "chunk={} {:?}", chunk_size, chunk);
+                }
+                for buffer_size in 1..data.len() {
+                    assert_eq!(split(data, buffer_size, chunk_size, line_ending), whole, "buffer={} chunk={}", buffer_size, chunk_size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_size_is_halved_when_buffering_runs_out_of_memory() {
+        let data: Vec<u8> = (0..40_000).flat_map(|i| format!("{:09}\n", i).into_bytes()).collect();
+        let out_of_memory = || Vec::<u8>::new().try_reserve(usize::MAX).unwrap_err();
+        let mut chunker = Chunker::new(256 * 1024, "\n", UTF_8);
+        let limit: ChunkLimit = Arc::new(AtomicUsize::new(256 * 1024));
+        chunker.chunk_limit = Some(limit.clone());
+        let mut chunks = Vec::new();
+        let mut emit = |chunk: &[u8], offset: usize| -> io::Result<()> {
+            chunks.push((offset, chunk.to_vec()));
+            Ok(())
+        };
+        chunker.push(&data[..200_000], &mut emit).unwrap();
+        // 追加下一段时预留失败一次: 减半后先切出已缓存的数据, 再追加
+        let mut failures = 1;
+        let flaky = |pending: &mut Vec<u8>, additional: usize| match failures {
+            0 => pending.try_reserve(additional),
+            _ => {
+                failures -= 1;
+                Err(out_of_memory())
+            }
+        };
+        chunker.reserve(200_000, &mut emit, flaky).unwrap();
+        chunker.push(&data[200_000..], &mut emit).unwrap();
+        chunker.finish(&mut emit).unwrap();
+        assert_eq!(limit.load(Ordering::Relaxed), 128 * 1024);
+        assert!(chunks[..chunks.len() - 1].iter().all(|(_, chunk)| chunk.len() == 131_080), "{:?}", chunks.iter().map(|(_, chunk)| chunk.len()).collect::<Vec<_>>());
+        assert_eq!(chunks.iter().flat_map(|(_, chunk)| chunk.clone()).collect::<Vec<u8>>(), data);
+
+        // 减到下限以下仍然失败时报错
+        let mut chunker = Chunker::new(100 * 1024, "\n", UTF_8);
+        let error = chunker.reserve(1, &mut |_: &[u8], _: usize| Ok(()), |_: &mut Vec<u8>, _: usize| Err(out_of_memory())).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::OutOfMemory);
+    }
+
+    #[test]
+    fn line_endings_are_counted_across_buffers() {
+        let data = b"a\r\nb\nc\rd\r\n\r\r\ne\r";
+        for buffer_size in 1..=data.len() {
+            let mut endings = LineEndings::default();
+            for buffer in data.chunks(buffer_size) {
+                endings.update(buffer);
+            }
+            endings.finish();
+            assert_eq!((endings.lf, endings.crlf, endings.cr), (1, 3, 3), "buffer={}", buffer_size);
+            assert_eq!(endings.dominant(), Some("CRLF"));
+            endings.cr += 1;
+            assert_eq!(endings.dominant(), Some("CR"));
+
+            // 各段单独计数后按顺序接上, 结果与连续计数相同
+            let (head, tail) = data.split_at(buffer_size);
+            let (mut joined, mut later) = (LineEndings::default(), LineEndings::default());
+            joined.update(head);
+            later.update(tail);
+            joined.append(&later, tail.first().copied());
+            joined.finish();
+            assert_eq!((joined.lf, joined.crlf, joined.cr), (1, 3, 3), "split={}", buffer_size);
+        }
+        assert_eq!(LineEndings::default().dominant(), None);
+    }
+
+    #[test]
+    fn chunks_reassemble_at_every_buffer_and_chunk_boundary() {
+        let inputs: [&[u8]; 7] = [
+            b"",
+            b"\n",
+            b"a\n",
+            b"abc\ndef\n",
+            b"abc\ndef\ngh",
+            b"\n\n\nxy\n\nz",
+            b"0123456789\n012\n45\n7\n",
+        ];
+        for data in inputs {
+            for buffer_size in 1..=data.len().max(1) + 1 {
+                for chunk_size in 1..=data.len() + 2 {
+                    let chunks = split(data, buffer_size, chunk_size, "\n");
+                    let joined: Vec<u8> = chunks.iter().flat_map(|(_, c)| c.clone()).collect();
+                    assert_eq!(joined, data, "buffer={} chunk={}", buffer_size, chunk_size);
+
+                    let mut expected_offset = 0;
+                    for (i, (offset, chunk)) in chunks.iter().enumerate() {
+                        assert!(!chunk.is_empty());
+                        assert_eq!(*offset, expected_offset);
+                        expected_offset += chunk.len();
+                        if i + 1 < chunks.len() {
+                            assert!(chunk.ends_with(b"\n"));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // 多字节分隔符被读缓冲区或分卷大小从中间截断时, 切分点仍是从前向后逐个匹配得到的记录结束位置
+    #[test]
+    fn multi_byte_delimiters_straddling_buffers_and_chunks() {
+        let delimiter = b"\r\n\r\n";
+        let data = b"ab\r\n\r\ncd\r\n\r\n\r\nef\r\ng\r\n\r\nh\r\n\r";
+        let mut ends = Vec::new();
+        let mut i = 0;
+        while i + delimiter.len() <= data.len() {
+            if data[i..].starts_with(delimiter) {
+                i += delimiter.len();
+                ends.push(i);
+            } else {
+                i += 1;
+            }
+        }
+        assert_eq!(ends, [6, 12, 23]);
+
+        let cuts = |chunks: &[(usize, Vec<u8>)]| chunks.iter().skip(1).map(|(offset, _)| *offset).collect::<Vec<_>>();
+        for buffer_size in 1..=data.len() {
+            for chunk_size in 1..=data.len() + 1 {
+                let chunks = split(data, buffer_size, chunk_size, "\r\n\r\n");
+                let joined: Vec<u8> = chunks.iter().flat_map(|(_, c)| c.clone()).collect();
+                assert_eq!(joined, data, "buffer={} chunk={}", buffer_size, chunk_size);
+                assert!(cuts(&chunks).iter().all(|cut| ends.contains(cut)), "buffer={} chunk={}: {:?}", buffer_size, chunk_size, cuts(&chunks));
+
+                let mut chunker = Chunker::new(chunk_size, "\r\n\r\n", UTF_8);
+                let mut sink = Collect::default();
+                for buffer in data.chunks(buffer_size) {
+                    chunker.push_streaming(buffer, &mut sink).unwrap();
+                }
+                chunker.finish_streaming(&mut sink).unwrap();
+                assert_eq!(sink.chunks, chunks, "buffer={} chunk={}", buffer_size, chunk_size);
+
+                let mut chunker = Chunker::new(chunk_size, "\r\n\r\n", UTF_8);
+                chunker.max_size = Some(data.len());
+                let mut bounded = Vec::new();
+                let mut emit = |chunk: &[u8], offset: usize| -> io::Result<()> {
+                    bounded.push((offset, chunk.to_vec()));
+                    Ok(())
+                };
+                for buffer in data.chunks(buffer_size) {
+                    chunker.push(buffer, &mut emit).unwrap();
+                }
+                chunker.finish(&mut emit).unwrap();
+                assert!(cuts(&bounded).iter().all(|cut| ends.contains(cut)), "max_size buffer={} chunk={}", buffer_size, chunk_size);
+            }
+        }
+        // 每次只到达一个字节且分卷大小为 1 时, 每个记录结束处都切分
+        assert_eq!(cuts(&split(data, 1, 1, "\r\n\r\n")), ends);
+    }
+
+    #[test]
+    fn line_counts_cut_after_every_n_records() {
+        let data = b"a\nb\n\"c\nc\"\nd\ne";
+        for buffer_size in 1..=data.len() {
+            let mut chunker = Chunker::new(1, "\n", UTF_8);
+            chunker.lines = Some(2);
+            chunker.set_records(Box::new(boundary::Csv::default()));
+            let mut chunks = Vec::new();
+            let mut emit = |chunk: &[u8], offset: usize| -> io::Result<()> {
+                chunks.push((offset, chunk.to_vec()));
+                Ok(())
+            };
+            for buffer in data.chunks(buffer_size) {
+                chunker.push(buffer, &mut emit).unwrap();
+            }
+            chunker.finish(&mut emit).unwrap();
+            // 引号中的换行不结束记录, 最后一个分卷只剩一条未结束的记录
+            let expected: [(usize, &[u8]); 3] = [(0, b"a\nb\n"), (4, b"\"c\nc\"\nd\n"), (12, b"e")];
+            assert_eq!(chunks, expected.map(|(offset, chunk)| (offset, chunk.to_vec())), "buffer={}", buffer_size);
+        }
+    }
+
+    #[test]
+    fn inputs_without_boundaries_follow_policy() {
+        let data = b"abcdefghijk\nlm";
+        for buffer_size in [1, 2, data.len()] {
+            let run = |policy| {
+                let mut chunker = Chunker::new(2, "\n", UTF_8);
+                chunker.no_boundary = policy;
+                let mut chunks = Vec::new();
+                let mut emit = |chunk: &[u8], _: usize| -> io::Result<()> {
+                    chunks.push(chunk.to_vec());
+                    Ok(())
+                };
+                for buffer in data.chunks(buffer_size) {
+                    chunker.push(buffer, &mut emit)?;
+                }
+                chunker.finish(&mut emit)?;
+                Ok::<_, io::Error>(chunks)
+            };
+            // 积累到 4 倍分块大小仍没有换行符时才按策略处理, 之后每个分卷正好是分块大小;
+            // 一次读入全部数据时已经找到了换行符
+            let chunks = run(NoBoundaryPolicy::FallbackBinary).unwrap();
+            assert_eq!(chunks.concat(), data, "buffer={}", buffer_size);
+            let sizes: Vec<usize> = chunks.iter().map(Vec::len).collect();
+            assert_eq!(sizes, if buffer_size < 8 { vec![2; 7] } else { vec![12, 2] }, "buffer={}", buffer_size);
+            assert_eq!(run(NoBoundaryPolicy::Error).is_err(), buffer_size < 8, "buffer={}", buffer_size);
+            assert_eq!(run(NoBoundaryPolicy::Warn).unwrap().concat(), data);
+
+            let mut chunker = Chunker::new(2, "\n", UTF_8);
+            chunker.no_boundary = NoBoundaryPolicy::FallbackBinary;
+            let mut sink = Collect::default();
+            for buffer in data.chunks(buffer_size) {
+                chunker.push_streaming(buffer, &mut sink).unwrap();
+            }
+            chunker.finish_streaming(&mut sink).unwrap();
+            assert_eq!(sink.chunks.into_iter().map(|(_, chunk)| chunk).collect::<Vec<_>>(), chunks, "buffer={}", buffer_size);
+        }
+    }
+
+    #[test]
+    fn parts_end_nearest_to_even_shares_of_the_input() {
+        let data: Vec<u8> = (0..40).flat_map(|n| format!("{}\n", "x".repeat(n % 7)).into_bytes()).collect();
+        for buffer_size in [1, 5, 64, data.len()] {
+            let mut chunker = Chunker::new(1, "\n", UTF_8);
+            chunker.set_parts(4, data.len());
+            let mut chunks = Vec::new();
+            let mut emit = |chunk: &[u8], offset: usize| -> io::Result<()> {
+                chunks.push((offset, chunk.to_vec()));
+                Ok(())
+            };
+            for buffer in data.chunks(buffer_size) {
+                chunker.push(buffer, &mut emit).unwrap();
+            }
+            chunker.finish(&mut emit).unwrap();
+            assert_eq!(chunks.len(), 4, "buffer={}", buffer_size);
+            // 每个分卷在离均分点最近的换行符处结束, 偏差不超过半行
+            for (k, (offset, chunk)) in chunks.iter().enumerate().take(3) {
+                let end = offset + chunk.len();
+                assert!(chunk.ends_with(b"\n"));
+                assert!(end.abs_diff((k + 1) * data.len() / 4) <= 4, "buffer={} end={}", buffer_size, end);
+            }
+            let joined: Vec<u8> = chunks.iter().flat_map(|(_, chunk)| chunk.clone()).collect();
+            assert_eq!(joined, data);
+        }
+    }
+
+    #[test]
+    fn long_record_warnings_do_not_depend_on_buffer_size() {
+        // 最长 95 字节的行, 分块 1000 字节: 缓冲区比分块大也不告警
+        let data: Vec<u8> = (0..2000).flat_map(|i| format!("{}{}\n", i, "x".repeat(i % 90)).into_bytes()).collect();
+        let mut long = data.clone();
+        long.extend_from_slice(&[b'y'; 3000]);
+        long.extend_from_slice(b"\nend\n");
+        for buffer_size in [1, 100, 999, 4096, 1 << 20] {
+            let warnings = |data: &[u8]| {
+                let mut chunker = Chunker::new(1000, "\n", UTF_8);
+                let mut emit = |_: &[u8], _: usize| -> io::Result<()> { Ok(()) };
+                for buffer in data.chunks(buffer_size) {
+                    chunker.push(buffer, &mut emit).unwrap();
+                }
+                chunker.finish(&mut emit).unwrap();
+                chunker.long_records
+            };
+            assert_eq!(warnings(&data), 0, "buffer={}", buffer_size);
+            assert_eq!(warnings(&long), 1, "buffer={}", buffer_size);
+        }
+    }
+
+    #[test]
+    fn long_records_follow_policy() {
+        let data = "ab\n一二三四五六七八\ncd\n".as_bytes();
+        let run = |policy, cap: Option<usize>, max_size: Option<usize>, buffer_size: usize| -> io::Result<Vec<(usize, Vec<u8>)>> {
+            let mut chunker = Chunker::new(4, "\n", UTF_8);
+            chunker.long_line_policy = policy;
+            chunker.long_line_cap = cap;
+            chunker.max_size = max_size;
+            let mut chunks = Vec::new();
+            let mut emit = |chunk: &[u8], offset: usize| -> io::Result<()> {
+                chunks.push((offset, chunk.to_vec()));
+                Ok(())
+            };
+            for buffer in data.chunks(buffer_size) {
+                chunker.push(buffer, &mut emit)?;
+            }
+            chunker.finish(&mut emit)?;
+            Ok(chunks)
+        };
+        for buffer_size in 1..=data.len() {
+            let grown = run(LongLinePolicy::Grow, None, None, buffer_size).unwrap();
+            let record = "一二三四五六七八\n".as_bytes();
+            assert!(grown.iter().any(|(_, chunk)| chunk.windows(record.len()).any(|w| w == record)), "buffer={}", buffer_size);
+            for (policy, cap, max_size) in [(LongLinePolicy::Error, None, None), (LongLinePolicy::Grow, Some(20), None), (LongLinePolicy::Error, None, Some(8))] {
+                let error = run(policy, cap, max_size, buffer_size).unwrap_err().to_string();
+                assert!(error.contains("中偏移 3 处的记录"), "{:?} buffer={}: {}", policy, buffer_size, error);
+            }
+
+            for (cap, max_size) in [(Some(7), None), (None, None), (None, Some(8))] {
+                let chunks = run(LongLinePolicy::Split, cap, max_size, buffer_size).unwrap();
+                let joined: Vec<u8> = chunks.iter().flat_map(|(_, c)| c.clone()).collect();
+                assert_eq!(joined, data);
+                // 强行切分也落在字符边界上
+                assert!(chunks.iter().all(|(_, chunk)| std::str::from_utf8(chunk).is_ok()), "buffer={}: {:?}", buffer_size, chunks);
+                assert!(chunks.len() >= 4);
+            }
+
+            let mut chunker = Chunker::new(4, "\n", UTF_8);
+            chunker.long_line_policy = LongLinePolicy::Split;
+            let mut sink = Collect::default();
+            for buffer in data.chunks(buffer_size) {
+                chunker.push_streaming(buffer, &mut sink).unwrap();
+            }
+            chunker.finish_streaming(&mut sink).unwrap();
+            assert_eq!(sink.chunks, run(LongLinePolicy::Split, None, None, buffer_size).unwrap(), "buffer={}", buffer_size);
+        }
+    }
+
+    #[test]
+    fn input_ending_exactly_on_chunk_boundary_has_no_trailing_chunk() {
+        let chunks = split(b"abc\ndef\n", 4, 4, "\n");
+        assert_eq!(chunks, vec![(0, b"abc\n".to_vec()), (4, b"def\n".to_vec())]);
+
+        let chunks = split(b"abc\ndef\n", 8, 8, "\n");
+        assert_eq!(chunks, vec![(0, b"abc\ndef\n".to_vec())]);
+    }
+
+    #[test]
+    fn csv_records_are_never_split() {
+        let data = b"1,\"a\nb\nc\"\n2,x\n3,\"\n\n\"\"\"\n4,y\n";
+        for max_size in [None, Some(16)] {
+            for buffer_size in 1..=data.len() {
+                let mut chunker = Chunker::new(4, "\n", UTF_8);
+                chunker.set_records(Box::new(boundary::Csv::default()));
+                chunker.max_size = max_size;
+                let mut chunks: Vec<Vec<u8>> = Vec::new();
+                let mut emit = |chunk: &[u8], _: usize| -> io::Result<()> {
+                    chunks.push(chunk.to_vec());
+                    Ok(())
+                };
+                for buffer in data.chunks(buffer_size) {
+                    chunker.push(buffer, &mut emit).unwrap();
+                }
+                chunker.finish(&mut emit).unwrap();
+                assert_eq!(chunks.concat(), data);
+                for chunk in &chunks {
+                    assert!(chunk.ends_with(b"\n") && chunk.iter().filter(|&&b| b == b'"').count() % 2 == 0, "{:?}", chunk);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tail_without_line_ending_is_written_once() {
+        let chunks = split(b"abc\ndef", 4, 4, "\n");
+        assert_eq!(chunks, vec![(0, b"abc\n".to_vec()), (4, b"def".to_vec())]);
+
+        let chunks = split(b"abcdef", 2, 3, "\n");
+        assert_eq!(chunks, vec![(0, b"abcdef".to_vec())]);
+    }
+
+    #[test]
+    fn hard_limit_never_exceeds_chunk_size() {
+        let data = b"ab\ncd\nefgh\ni\n\njk";
+        for buffer_size in 1..=data.len() {
+            let mut chunker = Chunker::new(6, "\n", UTF_8);
+            chunker.max_size = Some(chunker.chunk_size);
+            let mut chunks = Vec::new();
+            let mut emit = |chunk: &[u8], _offset: usize| -> io::Result<()> {
+                chunks.push(chunk.to_vec());
+                Ok(())
+            };
+            for buffer in data.chunks(buffer_size) {
+                chunker.push(buffer, &mut emit).unwrap();
+            }
+            chunker.finish(&mut emit).unwrap();
+            assert_eq!(chunks, vec![b"ab\ncd\n".to_vec(), b"efgh\n".to_vec(), b"i\n\njk".to_vec()]);
+        }
+
+        let mut chunker = Chunker::new(4, "\n", UTF_8);
+        chunker.max_size = Some(chunker.chunk_size);
+        let mut emit = |_: &[u8], _: usize| -> io::Result<()> { Ok(()) };
+        let err = chunker.push(b"ab\nlong line\n", &mut emit).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn target_size_picks_nearest_boundary_under_max() {
+        // 目标 10, 上限 14: 两侧换行符取离目标近的, 超过上限的换行符不可选
+        let cases: [(&[u8], &[&[u8]]); 2] = [
+            (b"aaaaaaa\nb\nccccccc\ndddddd\ne\n", &[b"aaaaaaa\nb\n", b"ccccccc\n", b"dddddd\ne\n"]),
+            (b"aaaa\nbbbbbb\ncc\n", &[b"aaaa\nbbbbbb\n", b"cc\n"]),
+        ];
+        for (data, expected) in cases {
+            for buffer_size in 1..=data.len() {
+                let mut chunker = Chunker::new(10, "\n", UTF_8);
+                chunker.max_size = Some(14);
+                let mut chunks = Vec::new();
+                let mut emit = |chunk: &[u8], _offset: usize| -> io::Result<()> {
+                    chunks.push(chunk.to_vec());
+                    Ok(())
+                };
+                for buffer in data.chunks(buffer_size) {
+                    chunker.push(buffer, &mut emit).unwrap();
+                }
+                chunker.finish(&mut emit).unwrap();
+                assert_eq!(chunks, expected, "buffer={}", buffer_size);
+            }
+        }
+    }
+
+    #[test]
+    fn balanced_chunks_get_more_raw_data_where_it_compresses_well() {
+        // 前两段压缩到 1/10, 后两段几乎不可压缩
+        let model = CompressionModel {
+            segment_size: 100,
+            ratios: vec![0.1, 0.1, 1.0, 1.0],
+            file_len: 400,
+            target_compressed: 55.0,
+        };
+        assert_eq!(model.compressed_between(0, 400), 220.0);
+        assert_eq!(model.raw_size_from(0), 200 + 35);
+        assert_eq!(model.raw_size_from(235), 55);
+        assert_eq!(model.raw_size_from(390), 10);
+    }
+
+    #[test]
+    fn splitter_compresses_numbered_chunks() {
+        let data = b"alpha\nbeta\ngamma\ndelta\n".repeat(50);
+        let mut config = SplitConfig::new(200);
+        config.max_size = Some(300);
+        let mut splitter = Splitter::new(config).unwrap();
+        let mut joined = Vec::new();
+        let mut next_offset = 0;
+        let count = splitter.split(&data[..], |chunk| {
+            assert_eq!(chunk.number, joined.len() + 1);
+            assert_eq!(chunk.offset, next_offset);
+            assert!(chunk.raw.len() <= 300 && chunk.raw.ends_with(b"\n"));
+            next_offset += chunk.raw.len();
+            joined.push(zstd::decode_all(&chunk.compressed[..])?);
+            Ok(())
+        }).unwrap();
+        assert_eq!(count, joined.len());
+        assert_eq!(joined.concat(), data);
+
+        let mut config = SplitConfig::new(10);
+        config.max_size = Some(5);
+        assert!(Splitter::new(config).is_err());
+    }
+
+    #[test]
+    fn in_memory_split_matches_the_splitter() {
+        let data = b"alpha\nbeta\ngamma\ndelta\n".repeat(200);
+        let mut config = SplitConfig::new(300);
+        config.max_size = Some(400);
+        let mut expected = Vec::new();
+        Splitter::new(config.clone()).unwrap().split(&data[..], |chunk| {
+            expected.push((chunk.number, chunk.offset, chunk.raw.to_vec(), chunk.compressed));
+            Ok(())
+        }).unwrap();
+        let chunks = split_bytes(&data, &config).unwrap();
+        let actual: Vec<_> = chunks.into_iter().map(|chunk| (chunk.number, chunk.offset, chunk.raw.to_vec(), chunk.compressed)).collect();
+        assert_eq!(actual, expected);
+        assert!(split_bytes(b"", &config).unwrap().is_empty());
+
+        // 无效编码报错后迭代结束
+        let mut config = SplitConfig::new(4);
+        config.fail_on_invalid = true;
+        let mut chunks = SplitBytes::new(b"ab\n\xff\xfe\ncd\n", &config).unwrap();
+        assert!(chunks.any(|chunk| chunk.is_err()));
+        assert!(chunks.next().is_none());
+    }
+
+    #[test]
+    fn truncated_trailing_character_is_not_an_error() {
+        assert_eq!(check_encoding(b"abc\n\xe4\xb8", UTF_8), (false, 4));
+        assert_eq!(check_encoding(b"abc\n\xc4", GBK), (false, 4));
+        assert_eq!(check_encoding(b"abc\xff\n", UTF_8), (true, 5));
+        assert_eq!(check_encoding(b"abc\xff\n\x81\x30", GBK), (true, 5));
+    }
+}
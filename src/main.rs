@@ -1,216 +1,580 @@
+#[cfg(feature = "grpc")]
+mod grpc;
+
 use std::env;
-use std::fs::File;
-use std::io::{self, Write, BufReader, Read};
-use std::path::PathBuf;
-use std::time::Instant;
-use encoding_rs::{Encoding, UTF_8, GBK};
-
-const DEFAULT_CHUNK_SIZE: usize = 100 * 1024 * 1024; // 100MB default
-const BUFFER_SIZE: usize = 8 * 1024 * 1024; // 8MB read buffer
-const DEFAULT_LINE_ENDING: &str = "\n"; // 默认换行符
-
-#[derive(Debug)]
-struct Config {
-    input_path: String,
-    output_prefix: String,
-    chunk_size: usize,
-    line_ending: String,
-    encoding: &'static Encoding,
+use std::io;
+use std::process::ExitCode;
+use zstd_compressor::archive::run_archive;
+use zstd_compressor::capabilities::run_capabilities;
+use zstd_compressor::compact::run_compact;
+use zstd_compressor::config::{self, Config};
+use zstd_compressor::exit::exit_code;
+use zstd_compressor::export::run_export;
+use zstd_compressor::files::{run_compress, run_decompress};
+use zstd_compressor::frames::{run_extract, run_info, run_split_frames};
+use zstd_compressor::fuzz::run_fuzz_roundtrip;
+use zstd_compressor::jobs::run_jobs;
+use zstd_compressor::journal::run_recover;
+use zstd_compressor::manifest::run_manifest_upgrade;
+use zstd_compressor::memory;
+use zstd_compressor::merge::{run_join, run_verify, MergeOptions};
+use zstd_compressor::merkle::run_prove_line;
+use zstd_compressor::query::run_query;
+use zstd_compressor::split::{confirm_overwrite, run_split};
+use zstd_compressor::tier::run_tier;
+
+// 记录堆内存峰值, 汇总中报告
+#[global_allocator]
+static ALLOCATOR: memory::CountingAllocator = memory::CountingAllocator;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("错误: {}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
 }
 
-impl Config {
-    fn from_args() -> Result<Self, String> {
-        let args: Vec<String> = env::args().collect();
-        
-        if args.len() < 3 {
-            return Err(format!(
-                "用法: {} <input_file> <output_prefix> [chunk_size_mb] [line_ending] [encoding]
-                选项:
-                chunk_size_mb: 分块大小(MB)
-                line_ending:
-                  LF     - Unix 风格 (\\n)
-                  CRLF   - Windows 风格 (\\r\\n)
-                  CR     - 经典 Mac 风格 (\\r)
-                  custom - 自定义换行符(例如: custom:\\r\\n\\r\\n)
-                encoding:
-                  UTF-8  - UTF-8 编码
-                  GBK    - GBK 编码", 
-                args[0]
-            ));
+fn run() -> io::Result<()> {
+    // 隐藏的开发者模式, 不出现在用法说明中
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("--fuzz-roundtrip") => return run_fuzz_roundtrip(&args[2..]),
+        // 不带参数时报告完整的用法说明
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, usage(&args[0]))),
+        Some("--help") => {
+            println!("{}", usage(&args[0]));
+            return Ok(());
         }
+        _ => {}
+    }
 
-        let input_path = args[1].clone();
-        let output_prefix = args[2].clone();
-        
-        let chunk_size = if args.len() >= 4 {
-            args[3].parse::<usize>()
-                .map_err(|_| "无效的块大小")?
-                * 1024 * 1024
-        } else {
-            DEFAULT_CHUNK_SIZE
-        };
+    let Some(command) = args.get(1).and_then(|name| subcommand(name)) else {
+        // 不带子命令时为切分
+        return run_split_args(&args[0], &args[1..]);
+    };
+    let mode = ModeArgs { program: &args[0], command, args: &args[2..] };
+    if mode.args == ["--help"] {
+        println!("用法: {}", mode.usage());
+        return Ok(());
+    }
+    (command.run)(&mode)
+}
 
-        let line_ending = if args.len() >= 5 {
-            match args[4].to_uppercase().as_str() {
-                "LF" => String::from("\n"),
-                "CRLF" => String::from("\r\n"),
-                "CR" => String::from("\r"),
-                custom if custom.starts_with("CUSTOM:") => {
-                    let custom_ending = custom[7..].to_string()
-                        .replace("\\n", "\n")
-                        .replace("\\r", "\r");
-                    if custom_ending.is_empty() {
-                        return Err("自定义换行符不能为空".to_string());
-                    }
-                    custom_ending
+// 子命令: 名字, 早先以选项形式给出的写法(保留为别名, 如 --join 同 merge), 用法, 入口
+struct Subcommand {
+    name: &'static str,
+    alias: Option<&'static str>,
+    usage: &'static [&'static str],
+    run: fn(&ModeArgs) -> io::Result<()>,
+}
+
+// 各子命令只解析自己的选项; 切分选项只属于 split
+const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "split",
+        alias: None,
+        usage: &["[split] <input_file> <output_prefix> [chunk_size_mb] [line_ending] [encoding] [选项]"],
+        run: |mode| run_split_args(mode.program, mode.args),
+    },
+    Subcommand {
+        name: "compress",
+        alias: None,
+        usage: &[
+            "compress <input_file|-> [output_file|-] [-c] [--force] [--format F] [--level N|--fast|--best] [--threads N] [--keep|--rm] [--json] [--yes]",
+            "compress --recursive <dir> [output_dir] [--include GLOB] [--exclude GLOB] [--parallel N] [--keep-going]",
+        ],
+        run: |mode| run_compress(mode.args),
+    },
+    Subcommand {
+        name: "decompress",
+        alias: None,
+        usage: &[
+            "decompress <file.zst|.gz|.xz|.lz4|.bz2|-> [output_file|-] [-c] [--keep|--rm] [--json] [--yes]",
+            "decompress --recursive <dir> [output_dir] [--include GLOB] [--exclude GLOB] [--parallel N] [--keep-going]",
+        ],
+        run: |mode| run_decompress(mode.args),
+    },
+    Subcommand {
+        name: "info",
+        alias: Some("--info"),
+        usage: &["info <file.zst>"],
+        run: |mode| {
+            let [path] = mode.exactly()?;
+            run_info(path)
+        },
+    },
+    Subcommand { name: "capabilities", alias: Some("--capabilities"), usage: &["capabilities"], run: |mode| mode.exactly::<0>().and_then(|_| run_capabilities()) },
+    Subcommand {
+        name: "serve",
+        alias: None,
+        usage: &["serve <ADDR>"],
+        run: |mode| {
+            let [address] = mode.exactly()?;
+            run_serve(address)
+        },
+    },
+    Subcommand {
+        name: "job",
+        alias: Some("--job"),
+        usage: &["job <jobs.yaml> [--parallel N] [--yes] [--keep-going]"],
+        run: |mode| {
+            let ([path], options) = mode.leading()?;
+            run_jobs(path, options)
+        },
+    },
+    Subcommand {
+        name: "split-frames",
+        alias: Some("--split-frames"),
+        usage: &["split-frames <file.zst> <output_prefix>"],
+        run: |mode| {
+            let [path, prefix] = mode.exactly()?;
+            run_split_frames(path, prefix)
+        },
+    },
+    Subcommand {
+        name: "tier",
+        alias: Some("--tier"),
+        usage: &["tier <output_prefix> <days> <DEST> [--yes]"],
+        run: |mode| {
+            let ([prefix, days, destination], options) = mode.leading()?;
+            run_tier(prefix, days, destination, parse_yes(options)?)
+        },
+    },
+    Subcommand {
+        name: "merge",
+        alias: Some("--join"),
+        usage: &["merge <prefix> <output_file|-> [--prefetch K] [--check-source]"],
+        run: |mode| {
+            let ([prefix, output], options) = mode.leading()?;
+            run_join(prefix, output, &MergeOptions::parse(options)?)
+        },
+    },
+    Subcommand {
+        name: "verify",
+        alias: Some("--verify"),
+        usage: &["verify <prefix> [--prefetch K] [--check-source]"],
+        run: |mode| {
+            let ([prefix], options) = mode.leading()?;
+            run_verify(prefix, &MergeOptions::parse(options)?)
+        },
+    },
+    Subcommand {
+        name: "export",
+        alias: None,
+        usage: &["export <prefix> --self-extracting <out.sh> [--prefetch K] [--check-source]"],
+        run: |mode| {
+            let ([prefix], options) = mode.leading()?;
+            run_export(prefix, options)
+        },
+    },
+    Subcommand {
+        name: "compact",
+        alias: None,
+        usage: &["compact <prefix> --target-size <MB> [--yes]"],
+        run: |mode| {
+            let ([prefix], options) = mode.leading()?;
+            run_compact(prefix, options)
+        },
+    },
+    Subcommand {
+        name: "archive",
+        alias: None,
+        usage: &["archive <input_file> <output_prefix> [--upload DEST] [--keep] [切分选项]"],
+        run: |mode| {
+            let ([input, prefix], options) = mode.leading()?;
+            run_archive(input, prefix, options)
+        },
+    },
+    Subcommand { name: "query", alias: None, usage: &["query <prefix>... --run <chunk>|--bytes-per-day|--unverified [--json]"], run: |mode| run_query(mode.args) },
+    Subcommand {
+        name: "manifest-upgrade",
+        alias: Some("--manifest-upgrade"),
+        usage: &["manifest-upgrade <prefix>"],
+        run: |mode| {
+            let [prefix] = mode.exactly()?;
+            run_manifest_upgrade(prefix)
+        },
+    },
+    Subcommand {
+        name: "recover",
+        alias: Some("--recover"),
+        usage: &["recover <prefix> [--yes]"],
+        run: |mode| {
+            let ([prefix], options) = mode.leading()?;
+            run_recover(prefix, parse_yes(options)?)
+        },
+    },
+    Subcommand {
+        name: "prove-line",
+        alias: Some("--prove-line"),
+        usage: &["prove-line <prefix> <chunk> <line> [line_ending] [encoding]"],
+        run: |mode| {
+            let ([prefix, chunk, line], rest) = mode.leading()?;
+            if let Some(extra) = rest.get(2).or_else(|| rest.iter().find(|arg| arg.starts_with("--"))) {
+                return Err(mode.usage_error(&format!("多余的参数: {}", extra)));
+            }
+            let options: Vec<String> = std::iter::once(line.to_string()).chain(rest.iter().cloned()).collect();
+            run_prove_line(prefix, chunk, &options)
+        },
+    },
+    Subcommand {
+        name: "extract",
+        alias: Some("--extract"),
+        usage: &["extract <file.zst> --bytes <A-B> [output_file|-]"],
+        run: |mode| {
+            let ([path], rest) = mode.leading()?;
+            let (mut range, mut output) = (None, None);
+            let mut rest = rest.iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--bytes" => range = Some(rest.next().ok_or_else(|| mode.usage_error("选项 --bytes 缺少参数"))?),
+                    flag if flag.starts_with("--") => return Err(mode.usage_error(&format!("未知选项: {}", flag))),
+                    _ if output.is_none() => output = Some(arg.as_str()),
+                    _ => return Err(mode.usage_error(&format!("多余的参数: {}", arg))),
                 }
-                _ => return Err("无效的换行符选项. 请使用 LF, CRLF, CR 或 custom:xxx".to_string())
             }
-        } else {
-            String::from(DEFAULT_LINE_ENDING)
-        };
+            let range = range.ok_or_else(|| mode.usage_error("extract 需要 --bytes <A-B>"))?;
+            run_extract(path, range, output.unwrap_or("-"))
+        },
+    },
+];
 
-        let encoding = if args.len() >= 6 {
-            match args[5].to_uppercase().as_str() {
-                "UTF-8" => UTF_8,
-                "GBK" => GBK,
-                _ => return Err("不支持的编码. 目前支持: UTF-8, GBK".to_string())
-            }
-        } else {
-            UTF_8
-        };
+// 按名字或旧写法找子命令; split 的选项本身以 -- 开头, 不作为别名
+fn subcommand(name: &str) -> Option<&'static Subcommand> {
+    SUBCOMMANDS.iter().find(|command| command.name == name || command.alias == Some(name))
+}
 
-        Ok(Config {
-            input_path,
-            output_prefix,
-            chunk_size,
-            line_ending,
-            encoding,
+// 用法说明中除 split 以外的各子命令, 以及保留的旧写法
+fn subcommand_usage(program: &str) -> String {
+    let mut lines: Vec<String> = SUBCOMMANDS[1..].iter().flat_map(|command| command.usage).map(|usage| format!("{} {}", program, usage)).collect();
+    let aliases: Vec<String> = SUBCOMMANDS
+        .iter()
+        .filter_map(|command| match command.alias? {
+            alias if alias == format!("--{}", command.name) => Some(alias.to_string()),
+            alias => Some(format!("{} (同 {})", alias, command.name)),
         })
-    }
+        .collect();
+    lines.push(format!("旧写法 {} 仍可使用, 与对应的子命令相同; <子命令> --help 只显示其用法", aliases.join(", ")));
+    lines.join("\n                      ")
+}
+
+// 子命令收到的参数(不含子命令本身); 参数个数不对时的错误附上该子命令的用法
+struct ModeArgs<'a> {
+    program: &'a str,
+    command: &'a Subcommand,
+    args: &'a [String],
 }
 
-fn find_last_line_ending(data: &[u8], line_ending: &str, encoding: &'static Encoding) -> Option<usize> {
-    if data.is_empty() {
-        return None;
+impl<'a> ModeArgs<'a> {
+    fn usage(&self) -> String {
+        let lines: Vec<String> = self.command.usage.iter().map(|usage| format!("{} {}", self.program, usage)).collect();
+        lines.join("\n      ")
     }
 
-    // 解码数据
-    let (decoded, _, had_errors) = encoding.decode(data);
-    if had_errors {
-        eprintln!("警告: 发现无效的字符编码");
+    fn usage_error(&self, message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{}\n用法: {}", message, self.usage()))
     }
 
-    // 在解码后的文本中查找换行符
-    if let Some(last_pos) = decoded.rfind(line_ending) {
-        // 将字符位置转换回字节位置
-        let byte_pos = encoding
-            .encode(&decoded[..last_pos])
-            .0
-            .len();
-        Some(byte_pos)
-    } else {
-        None
+    // 开头的 N 个位置参数与其后的选项
+    fn leading<const N: usize>(&self) -> io::Result<([&'a str; N], &'a [String])> {
+        match self.args.get(..N) {
+            Some(head) if !head.iter().any(|arg| arg.starts_with("--")) => Ok((std::array::from_fn(|i| head[i].as_str()), &self.args[N..])),
+            _ => Err(self.usage_error(&format!("{} 缺少参数", self.command.name))),
+        }
+    }
+
+    // 正好 N 个位置参数, 没有选项
+    fn exactly<const N: usize>(&self) -> io::Result<[&'a str; N]> {
+        let (head, rest) = self.leading()?;
+        match rest.first() {
+            Some(extra) => Err(self.usage_error(&format!("多余的参数: {}", extra))),
+            None => Ok(head),
+        }
     }
 }
 
-fn write_compressed_chunk(chunk: &[u8], output_prefix: &str, chunk_number: usize) -> io::Result<()> {
-    // 创建输出文件路径
-    let output_path = PathBuf::from(format!("{}.{:03}.zst", output_prefix, chunk_number));
-    
-    // 压缩数据
-    let compressed = zstd::encode_all(chunk, 3)?;
-    
-    // 写入文件
-    let mut output_file = File::create(output_path.clone())?;
-    output_file.write_all(&compressed)?;
-    
-    println!("写入分卷 {} (压缩后 {} 字节)", chunk_number, compressed.len());
+// 切分: 参数之前依次插入配置文件与环境变量中的选项
+fn run_split_args(program: &str, args: &[String]) -> io::Result<()> {
+    let args = std::iter::once(program.to_string()).chain(args.iter().cloned()).collect();
+    let config = config::layered_args(args).and_then(Config::parse).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    confirm_overwrite(&config)?;
+    run_split(&config)?;
     Ok(())
 }
 
-fn main() -> io::Result<()> {
-    let start_time = Instant::now();
-    
-    // 解析配置
-    let config = match Config::from_args() {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            eprintln!("错误: {}", e);
-            return Ok(());
-        }
-    };
 
-    println!("使用配置:");
-    println!("- 编码: {}", config.encoding.name());
-    println!("- 换行符: {}", config.line_ending.escape_default());
-    println!("- 分块大小: {} MB", config.chunk_size / 1024 / 1024);
-
-    // 初始化文件读取
-    let file = File::open(&config.input_path)?;
-    let mut reader = BufReader::with_capacity(BUFFER_SIZE, file);
-    let mut current_chunk = Vec::with_capacity(config.chunk_size + BUFFER_SIZE);
-    let mut buffer = Vec::with_capacity(BUFFER_SIZE);
-    let mut chunk_number = 1;
-    let mut last_newline_pos = 0;
-    let mut total_bytes = 0;
-    
-    loop {
-        buffer.clear();
-        let n = reader.by_ref().take(BUFFER_SIZE as u64).read_to_end(&mut buffer)?;
-        if n == 0 && current_chunk.is_empty() {
-            break;
-        }
+// 完整的用法说明: 切分的参数与选项, 以及其他子命令; 不带参数或以 --help 运行时显示
+fn usage(program: &str) -> String {
+    format!(
+        "用法: {} [split] <input_file> <output_prefix> [chunk_size_mb] [line_ending] [encoding] [选项]
+                      {3}
+                参数:
+                chunk_size_mb: 分块大小(MB); 分卷在达到分块大小后的第一个记录结束处结束, 与读取缓冲区大小无关
+                line_ending:
+                  LF     - Unix 风格 (\\n)
+                  CRLF   - Windows 风格 (\\r\\n)
+                  CR     - 经典 Mac 风格 (\\r)
+                  custom - 自定义换行符(例如: custom:\\r\\n\\r\\n)
+                encoding:
+                  UTF-8  - UTF-8 编码
+                  GBK    - GBK 编码
+                以上三者也可以用 --chunk-size <MB>, --line-ending <E>, --encoding <E> 给出, 优先于位置参数
+                选项还可以来自 --config <FILE> 或环境变量 {1} 指定的配置文件(每行 key: value, 键为去掉 -- 的选项名,
+                开关写 true, 可重复的选项写 [a, b]), 以及环境变量 {2}(以空白分隔的选项);
+                优先级为 命令行 > {2} > 配置文件, 可重复的选项合并; 所有参数问题一次列出
+                选项:
+                --drop-invalid         - 丢弃编码无效的行, 原样写入 <output_prefix>.rejects
+                --expect-ratio <N:1>   - 预期压缩比, 分卷偏离过大时告警
+                --ratio-tolerance <P%> - 允许的压缩比偏差 (默认 50%)
+                --ratio-policy <p>     - 压缩比异常时 warn(告警, 默认) 或 abort(终止)
+                --checkpoint-interval <N> - 每 N 个分卷(如 10)或每隔一段时间(如 30s, 5m)同步分卷与清单并写入 <output_prefix>.state;
+                                         按时间时 --stream 正在写的分卷也刷出已压缩的数据
+                --max-runtime <T>      - 运行超过 T(如 2h, 90m, 30s)后在刚写完的分卷处停下, 进度写入 <output_prefix>.state,
+                                         以退出码 6 退出, 供维护窗口中的定时任务下次加上 --resume 继续
+                --resume               - 从 <output_prefix>.state 记录的分卷编号与输入偏移继续切分(--max-runtime 停下或
+                                         --checkpoint-interval 后崩溃), 不询问覆盖; 需要与上次相同的输入与选项,
+                                         manifest 保留停下之前的分卷; 切分点与一次跑完时相同. 不支持按位置跳过输入、依赖第一个分卷或整个输入的选项与 gzip 输入
+                --readahead <K>        - 后台线程预读 K 个缓冲区, 平滑磁盘/网络文件系统的延迟抖动
+                --pipeline-depth <N>   - 切分、压缩与写出分别在各自的线程中进行, 之间最多积压 N 个分卷(默认 2);
+                                         0 为在主线程中依次切分、压缩、写出
+                --stream               - 边切分边压缩写出分卷, 内存占用只与读取缓冲区和行长有关, 可以使用数 GB 的分块大小;
+                                         只支持 zstd 与逐个分卷输出, 不能与 --max-size, --drop-invalid, --jobs 及需要 manifest 的选项同时使用
+                --jobs <N>             - 同时压缩 N 个分卷(auto 为 CPU 数, 默认 1), 分卷仍按编号顺序写出, 内容与单线程时相同;
+                                         高压缩级别下吞吐约为 N 倍, 内存占用随之增加
+                --parallel-split       - 本地文件按位置分段, --jobs 个线程并行查找切分点、读取、检查编码并压缩, 仍按编号顺序写出;
+                                         第 k 个分卷结束于 k 倍分块大小处之后的第一个换行符, 与线程数无关, 但与不带此选项时不同
+                --buffer-size <MB>     - 固定读取缓冲区大小, 默认 auto 按实测吞吐自动调节
+                --record-bytes <N>     - 由 N 字节定长记录组成的二进制文件(N 可带 K/M/G 后缀): 按 --binary 处理, 分块大小向下取整为
+                                         N 的整数倍(至少 N), 只在记录边界切分; 输入长度不是 N 的整数倍时告警
+                --binary               - 二进制输入: 每个分卷正好是分块大小, 不检查字符编码, 不自动解压 gzip;
+                                         输入是块设备或字符设备(如 /dev/sdb, 磁带)时默认如此
+                --max-bytes <N>        - 最多读取 N 字节(可带 K/M/G 后缀), 用于从设备中截取一段
+                --skip-bytes <N>       - 跳过输入开头的 N 字节(可带 K/M/G 后缀)再读取, 与 --max-bytes 一起只切分其中一段;
+                                         gzip 输入按压缩前的字节计, 跳过后不再自动解压
+                --skip-lines <N>       - 跳过开头的 N 行(gzip 输入按解压后计), 用于重新处理损坏的区域或从中间取样
+                --max-lines <N>        - 最多读取 N 行, 可与 --skip-lines 一起使用; 分卷偏移从窗口开头算起
+                --hard-limit           - 分卷严格不超过分块大小, 单行超过分块大小时报错
+                --target-size <MB>     - 目标分卷大小, 与 chunk_size_mb 及 --chunk-size 相同
+                --max-size <MB>        - 分卷大小上限, 在目标与上限之间选离目标最近的换行符切分
+                --long-line-policy <P> - 单条记录超过上限仍未结束时: grow(默认, 分卷随之增大, 超过分块大小时告警),
+                                         error(报错并指出分卷与记录的偏移) 或 split(在上限处的字符边界强行切分);
+                                         有 --max-size 或 --hard-limit 时上限即为大小上限, 默认 error
+                --long-line-cap <MB>   - 长记录的上限, error 与 split 默认为分块大小; 给 grow 设置时超过即报错
+                --no-boundary <P>      - 读入 4 倍分块大小仍找不到任何换行符(换行符设置错误或输入其实是二进制)时:
+                                         warn(默认, 醒目地告警, 整个输入可能成为一个分卷), fallback-binary(改为每个分卷正好是分块大小)
+                                         或 error(报错)
+                --partition-by <N>     - 按第 N 列的值把记录分到各自的分区, 每个分区是一组 <output_prefix>.<值>.NNN.zst 分卷,
+                                         各自积累到分块大小时写出; 值中不能用于文件名的字符换成 _, 空值归入 <output_prefix>._;
+                                         每个分区缓冲最多一个分块, 分区数上限为 1024
+                --shard-by-key <N>     - 按第 N 列的 FNV-1a 哈希把记录分到 --shards 个分片, 同一个键总在同一个分片;
+                                         每个分片是一组 <output_prefix>.shardNNN.NNN.zst 分卷, 没有记录的分片不写出
+                --shards <N>           - --shard-by-key 的分片数, 1 到 1024
+                --round-robin <N>      - 像 split -n r/N 一样把记录依次轮流分给 N 个分区(1 到 1024), 各分区大小与内容分布相近,
+                                         供下游并行处理; 每个分区是一组 <output_prefix>.rrNNN.NNN.zst 分卷
+                --split-by-time <F>    - 按时间戳所在的时间窗口分区, 窗口变化时写出上一个窗口; F 描述时间戳的开头, 由 %Y(4 位年),
+                                         %m, %d, %H, %M, %S(2 位数字), %b(英文月份缩写)与原样匹配的字符组成, 如 %Y-%m-%dT%H
+                                         每小时一个窗口, %Y-%m-%d 每天一个. 每个窗口是一组 <output_prefix>.<匹配到的时间>.NNN.zst 分卷,
+                                         时间中不能用于文件名的字符换成 _; 不匹配的行(如多行日志的后续行)留在当前窗口
+                --time-field <N>       - --split-by-time 的时间戳在第 N 列(默认 1), 列以 --delimiter 分隔, 时间戳前的空白被忽略
+                --delimiter <C>        - --partition-by, --shard-by-key 与 --split-by-time 的列分隔符, 单个字符(默认 ,), 制表符可写作 tab; 不处理引号
+                --parts <N>            - 按输入文件的大小均分为 N 个分卷, 各自在离均分点最近的换行符处结束; 不再按分块大小切分,
+                                         行数少于 N 时分卷也更少. 不支持 gzip 输入, 可与 --skip-bytes, --max-bytes 一起使用
+                --lines <N>            - 每个分卷正好 N 行(按 --records 切分时为 N 条记录), 最后一个分卷可能更少; 不再按分块大小切分,
+                                         不能与 --chunk-size/--target-size 同时使用
+                --balance-compressed   - 先采样估算各处的压缩率, 使各分卷压缩后的大小接近
+                --single-output <FILE> - 所有分卷作为独立帧追加写入同一个文件, 帧偏移记录在 <FILE>.idx
+                --output-dirs <D,...>  - 分卷依次轮流写到这些目录(如各块磁盘的挂载点), 分摊写带宽与空间;
+                                         各分卷所在目录记录在 manifest 的 dir 列中, 合并与校验据此查找
+                --tee-plain <T>        - 压缩的同时写出未压缩的副本, 不必再读一遍输入: <DIR>(每个分卷一个 <前缀名>.NNN.txt,
+                                         内容与分卷压缩前相同) 或 single:<FILE>(拼成一个文件, 去掉复制的 CSV 表头, 与合并结果相同)
+                --format <F>           - 输出格式: zstd(默认), gzip, xz, lz4(后三者需要系统中的同名命令), snappy(分帧格式, 供 Hadoop/Spark 读取),
+                                         7z(全部分卷写入 <output_prefix>.7z, 编码器见 --7z-codec), brotli, bzip2 (后两者需以同名 feature 构建,
+                                         并需要系统中的同名命令; brotli 分卷不能合并与校验, 请用 brotli -dc 逐个解压)
+                --7z-codec <C>         - 7z 归档中分卷的编码器: lzma2(默认, 需要系统中的 xz, 任何 7-Zip 都能解压) 或
+                                         zstd(不需要外部命令, --level/--threads 同样适用; 解压需要 7-Zip-zstd、NanaZip 或 bsdtar)
+                --gzip-name <S>        - gzip 分卷成员头中的文件名, gunzip -N 据此恢复: chunk(默认, 分卷文件名去掉 .gz;
+                                         --single-output 时没有), input(输入文件名) 或 none
+                --gzip-mtime <T>       - gzip 分卷成员头中的修改时间: input(默认, 输入文件的修改时间), now, none 或 Unix 秒数
+                --inline-dictionary    - 从输入均匀采样训练 zstd 字典, 以可跳过帧内嵌在第一个分卷开头并记入 manifest,
+                                         小分卷的压缩比更高; 分卷集自带字典, 合并与校验时自动使用, 单独解压其他分卷需要先有第一个分卷;
+                                         只支持本地输入文件与逐个写出的 zstd 分卷, 需要以 dictionary feature(默认启用)构建
+                --level <N>            - zstd 压缩级别(默认 3), 负值为更快的快速级别, 也用于 compress 子命令
+                --fast, --best         - 分别相当于 --level 1 与 --level 19
+                --threads <N>          - zstd 库内的工作线程数, 每个分卷由 N 个线程并行压缩; 默认 0 即在压缩线程内单线程压缩,
+                                         auto 为 CPU 数; 也可写作 --compress-threads; 需要以 zstdmt feature(默认启用)构建
+                --output <DEST>        - 每个分卷同时镜像到 DEST(本地目录或 s3://bucket/prefix, 可多次指定),
+                                         各目标的写入状态记录在 <output_prefix>.manifest; DEST 为 unix:<套接字> 时(只支持 Linux)
+                                         把分卷放入密封的 memfd, 连接同机进程监听的套接字, 发送一行 <文件名>\t<字节数>
+                                         并以 SCM_RIGHTS 附带 memfd, 接收方不必从磁盘读回分卷
+                                         (manifest 表头之后还记录本次运行的来历: 运行 ID, 程序版本, 主机, 命令行, 输入的大小与修改时间;
+                                          镜像到 S3 的对象带 run-id 元数据)
+                --input-sha256 <HEX|FILE> - 读取输入的同时校验 SHA-256 (摘要或 sha256sum 格式的校验文件),
+                                         不符时不完成分卷集: 不写 7z 头部, 检查点不标记完成
+                --content-addressed    - 要求 <output_prefix>.manifest 记录每个分卷原始内容的 SHA-256, 供下游去重(不能与 --no-chunk-sha256 同时使用)
+                --name-by-hash         - 分卷文件以内容的 SHA-256 命名(<output_prefix>.<sha256>.zst), 顺序见 manifest
+                --no-chunk-sha256      - 不计算各分卷的 SHA-256(manifest 中没有 sha256 列, 合并时只核对大小), 省去单核约三成的开销
+                --name-template <T>    - 分卷文件名模板, 可用 {{prefix}}(前缀的文件名部分), {{n}}(分卷编号, 必须包含), {{ext}}
+                                         与 {{field:NAME}}; 如 {{prefix}}.{{field:date}}.{{n}}.{{ext}}, 文件名记录在 manifest 中
+                --field <NAME=REGEX>   - 定义模板字段: 取分卷首行中正则的第一个分组(没有分组时为整个匹配), 可多次指定;
+                                         如 'date=^(\\d{{4}}-\\d{{2}}-\\d{{2}})' 让按天轮转的日志以日期命名
+                --line-merkle          - 在 manifest 中记录每个分卷各行哈希的 Merkle 根, 之后可用 prove-line 证明某行存在
+                --journal              - 每个分卷开始写与落盘完成都先记入 <output_prefix>.journal,
+                                         崩溃后用 recover 删除未完成的分卷; 分区与分片模式下各分区的分卷记入同一个日志
+                --porcelain            - 标准输出只输出机器可读的行: 每个分卷一行 CHUNK <n> <path> <raw> <compressed> <sha256>,
+                                         结束时 DONE <分卷数> <字节数>; path 可能含空格, 按首尾字段解析; 警告与错误仍在标准错误
+                --units <U>            - 报告中的大小单位: binary(默认, 1024 进, KiB/MiB/GiB) 或 si(1000 进, kB/MB/GB),
+                                         按大小自动选用; 也用于 compress 与 decompress
+                --locale <L>           - 报告中数字的千位分隔符与小数点按此区域(如 en_US, de_DE), 默认取 LC_ALL, LC_NUMERIC 或 LANG;
+                                         C 不分组. --porcelain 与 JSON 中的数值字段不受影响
+                --yes                  - 不询问, 直接覆盖已有的分卷; 覆盖、recover 删除与 tier 迁移分卷前会在终端上确认,
+                                         非交互运行(定时任务、管道)时必须给出 --yes, 否则不执行
+                --fail-on-warning      - 告警即失败: 无效编码(退出码 3, --drop-invalid 丢弃的行除外), 压缩比异常(4),
+                                         无法设置 nice 值(1)
+                --profile-out <FILE>   - 把读取、切分、压缩、写出等各阶段的耗时与 CPU 时间写成 JSON,
+                                         其中 folded 字段可直接交给 flamegraph.pl 或 inferno 生成火焰图
+                --priority <S=N,...>   - 各阶段线程的 nice 值(-20 到 19, 越小越优先), 阶段为 read(预读线程, 需要 --readahead)
+                                         与 compress(压缩与写出线程); 如 read=-5,compress=10; 负值需要相应权限
+                --records <R>          - 按记录而不是按行切分: csv(引号内的换行不算), jsonl(每行一个 JSON 值, 按 LF 切分), fixed:N(每 N 字节一条),
+                                         fastq(每 4 行一条读段), fasta(以 > 开头的标题行开始一条序列, 折行的序列不会被切开),
+                                         sql(mysqldump/pg_dump 的输出, 只在语句之间切分; 引号、注释、$$ 函数体与 COPY 数据中的 ; 不算),
+                                         sql-table(只在两张表之间切分, 一张表的建表语句与数据在同一个分卷, 供并行导入; pg_dump
+                                         把所有建表语句放在数据之前, 需要先导入第一个分卷); 两者只支持 UTF-8,
+                                         xml:NAME(在元素 <NAME> 的结束标签或自闭合标签之后切分, 注释、CDATA 与属性值中的标签不算;
+                                         只支持 UTF-8),
+                                         regex:PATTERN(以匹配的行开始一条记录, 如 regex:^\\d{{4}}- 让异常堆栈与所属日志留在同一分卷)
+                --record-sep-regex <R> - 同 --records regex:R, 以匹配 R 的行开始一条记录, 如 '^----- BEGIN' 或 mbox 的 '^From '
+                --validate-json <P>    - 按 JSON Lines 切分并检查每一行是否为有效的 JSON(空行除外): report(告警, 前 20 行逐条列出,
+                                         --fail-on-warning 时报错) 或 quarantine(移入 <output_prefix>.rejects, 分卷中只留有效的行)
+                --quote-char <C>       - 按换行符切分时跳过引号 C 之间的换行符(如 \"), 带引号的多行字段不会被切到两个分卷;
+                                         换行符与引号都需要是不会出现在多字节字符内部的单字节 ASCII
+                --csv-header <H>       - 按 CSV 记录切分并在每个分卷开头写上表头: first(输入的第一条记录), auto(第一条记录
+                                         没有数值字段而第二条有时视为表头), text:<表头>(给出表头, 第一个分卷也加上);
+                                         manifest 记录各分卷的表头长度, 合并时去掉
+                --xml-wrap             - 配合 --records xml:NAME, 在每个分卷开头复制第一条记录之前的部分(XML 声明与根元素的开始标签),
+                                         结尾补上根元素的结束标签, 每个分卷都是完整的 XML 文档; manifest 记录复制的长度, 合并时去掉
+                --cdc                  - 按内容定义的切分点(FastCDC)切分: 分卷大小在分块大小的 1/4 到 4 倍之间, 平均约为分块大小;
+                                         输入中插入或删除少量数据后只有附近的分卷改变, 其余分卷与上次相同, 便于去重存储与增量同步.
+                                         切分点落在记录结束处(二进制输入按字节), 可与 --records 同时使用
+                --align-gz-members     - gzip 输入(会自动解压)只在成员边界处切分, 轮转后拼接的日志每段各自成卷或合并成卷
+                compress               - 不切分, 把整个文件压缩为一个文件(默认 <input_file>.zst), 格式同 --format(7z 除外)
+                decompress             - 按文件头识别 zstd, gzip, xz, lz4 或 bzip2(后三者需要系统中的同名命令)并解压, 默认输出为去掉扩展名的文件名
+                                         --recursive 处理目录下的所有文件(不跟随符号链接), 给出 output_dir 时在其下重建目录结构,
+                                         否则输出在原文件旁; --include/--exclude 按相对路径过滤(可多次指定, * 不跨越 /, ** 跨越目录,
+                                         不含 / 的模式只比较文件名); --parallel 为同时处理的文件数; 某个文件失败不影响其他文件,
+                                         --keep-going 时无法读取的子目录也只记为失败并继续, 汇总中列出
+                                         输出沿用原文件的修改时间与权限; 默认保留原文件(--keep), --rm 在成功后删除原文件
+                                         结束时报告原始大小、压缩后大小、压缩比、吞吐与内存峰值, --json 以 JSON 输出;
+                                         解压单个文件且标准错误是终端时显示进度
+                                         输入为 - 时读标准输入, 输出为 - 或给出 -c(--stdout) 时写到标准输出(输入为 - 时默认如此),
+                                         此时报告写到标准错误; 标准输出是终端时拒绝写出压缩数据, 除非给出 -f(--force)
+                info                   - 列出已有 .zst 文件中的各个帧
+                job                    - 批量执行任务文件中的多个切分任务, 最后输出汇总报告; 每个任务的键为
+                                         input, prefix, chunk_size, line_ending, encoding 以及去掉 -- 的选项名
+                                         (如 format: snappy, drop_invalid: true, output: [dir1, s3://b/p]);
+                                         --parallel 为同时执行的任务数, 默认等于 CPU 数; --keep-going 时配置无效或不允许覆盖的
+                                         任务记为失败, 其余任务照常执行; 部分任务失败时退出码为部分成功
+                capabilities           - 列出本机 CPU 的 SIMD 特性、zstd 库能力与可用的外部工具
+                serve                  - gRPC 服务模式(需以 grpc feature 构建): 在 ADDR(如 127.0.0.1:50051)上提供双向流的
+                                         zstd_compressor.Splitter/Split, 客户端推送原始数据, 服务端流式返回切好并压缩的分卷及其编号、
+                                         偏移、原始大小与 SHA-256; 第一条消息带切分选项, proto 定义见 src/grpc.rs
+                split-frames           - 不重新压缩, 把多帧 .zst 文件按帧拆成分卷文件
+                merge                  - 依次解压 <prefix>.001.zst 起的分卷并拼接, 输出为 - 时写到标准输出
+                verify                 - 解压所有分卷以校验完整性, 不写出数据
+                                         合并与校验时有 <prefix>.manifest 则按其中的分卷顺序读取, 并核对原始大小与 SHA-256
+                export                 - 把分卷集导出为自解压脚本, 接收方用 sh out.sh [输出文件] 还原, 只需要 sh, tail 与 gzip
+                compact                - 把相邻的小分卷首尾相接合并为不超过目标大小的分卷, 不重新压缩, 从 1 起重新编号并重写 manifest;
+                                         不支持 7z, brotli 与记录了 line_merkle 的分卷集
+                query                  - 按各分卷集的 manifest 回答: --run 分卷由哪次运行写出(运行 ID, 主机, 输入), --bytes-per-day
+                                         每天(UTC, 按分卷文件的修改时间)归档的分卷数与压缩后字节数, --unverified 还没有校验通过的分卷;
+                                         校验通过的分卷记录在 <prefix>.verified. 默认输出表格, --json 输出 JSON 数组
+                archive                - archive <input_file> <output_prefix> [选项]: 一步归档本地文件. 按开头 1 MB 探测编码(UTF-8/GBK)、
+                                         换行符与二进制输入, 先计算输入的 SHA-256, 再以 --balance-compressed --content-addressed
+                                         切分(分块不超过 4 MB 时加上 --inline-dictionary), 之后解压全部分卷与输入核对;
+                                         --upload <DEST> 同 --output, 可多次指定; 分卷与镜像都已落盘并核对(本地目录镜像逐个比较)后
+                                         提交, 之后才删除输入, --keep 保留; 其余切分选项照常使用, 并优先于探测结果.
+                                         阶段(split, verified, committed)记录在 <output_prefix>.archive 中, 中断后用同样的参数重新运行
+                                         从记录的阶段继续; 输入在归档开始后被改动时不删除
+                prove-line             - 输出分卷中第 line 行(从 1 起)到 manifest 中 Merkle 根的证明路径
+                recover                - 按 <prefix>.journal 删除开始写但未完成的分卷
+                manifest-upgrade       - 把旧版本程序写出的 <prefix>.manifest 升级到当前格式版本; 只有 <prefix>.manifest.json 时由它生成
+                                         输入文件与分卷前缀都可以是 https://, sftp:// 或 s3:// 地址
+                extract                - 按 --single-output 的索引只读取覆盖字节范围 A-B 的帧, 远程文件使用范围请求
+                --prefetch <K>         - 合并与校验时并发预取后续 K 个分卷, 掩盖远程读取的延迟
+                --check-source         - 合并与校验时核对整体结果与 manifest 中记录的输入大小, 以及 --input-sha256 给出的 SHA-256
+                tier                   - 把超过 days 天的分卷移到 DEST(目录或 s3://), 新位置记录在 <output_prefix>.tiers,
+                                         合并与校验时按它找到已迁移的分卷; 只执行一次, 需要定期分层时由 cron 等定时调用
+                manifest               - 逐个分卷输出时都写出 <output_prefix>.manifest(制表符分隔, 带格式版本): 各分卷的压缩前后大小、
+                                         行数与 SHA-256, 以及按选项记录的 Merkle 根、复制的首尾长度、目录与镜像状态
+                manifest.json          - 每次切分都写出 <output_prefix>.manifest.json, 供下游工具读取: 输入的文件名、大小与修改时间,
+                                         编码, 换行符, 以及由 <output_prefix>.manifest 派生的分卷列表, 两者的版本号相同
+                退出码:
+                0 成功, 1 读写错误, 2 参数无效, 3 遇到无效编码, 4 校验失败, 5 部分成功(镜像写入或部分批量任务失败),
+                6 到达 --max-runtime 上限, 已记录进度, 可用 --resume 继续", 
+        program,
+        config::CONFIG_ENV,
+        config::OPTIONS_ENV,
+        subcommand_usage(program),
+    )
+}
 
-        if n > 0 {
-            // 查找最后一个换行符的位置
-            let mut end_pos = if n == 0 { buffer.len() } else { n };
-            if !buffer.is_empty() {
-                if let Some(last_pos) = find_last_line_ending(&buffer[..end_pos], &config.line_ending, config.encoding) {
-                    end_pos = last_pos + config.line_ending.len();
-                }
-            }
+// recover 与 tier 只接受 --yes
+fn parse_yes(args: &[String]) -> io::Result<bool> {
+    match args {
+        [] => Ok(false),
+        [flag] if flag == "--yes" => Ok(true),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("未知选项: {}", args.join(" ")))),
+    }
+}
 
-            // 将数据添加到当前块
-            current_chunk.extend_from_slice(&buffer[..end_pos]);
-            total_bytes += end_pos;
-
-            // 如果当前块超过目标大小，在最后一个换行符处分割
-            if current_chunk.len() >= config.chunk_size {
-                if let Some(last_pos) = find_last_line_ending(&current_chunk[last_newline_pos..], &config.line_ending, config.encoding) {
-                    let split_pos = last_newline_pos + last_pos + config.line_ending.len();
-                    
-                    // 写入到分割位置的数据
-                    write_compressed_chunk(&current_chunk[..split_pos], &config.output_prefix, chunk_number)?;
-                    
-                    // 保留剩余数据
-                    let remaining = current_chunk[split_pos..].to_vec();
-                    current_chunk.clear();
-                    current_chunk.extend(remaining);
-                    last_newline_pos = 0;
-                    chunk_number += 1;
-                }
-            }
+// gRPC 服务模式, 见 grpc.rs
+#[cfg(feature = "grpc")]
+fn run_serve(address: &str) -> io::Result<()> {
+    grpc::serve(address)
+}
 
-            // 如果还有剩余数据，移动到下一个缓冲区
-            if end_pos < buffer.len() {
-                current_chunk.extend_from_slice(&buffer[end_pos..]);
-            }
-        }
+#[cfg(not(feature = "grpc"))]
+fn run_serve(_address: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::InvalidInput, "此构建未启用 gRPC 服务模式, 请以 --features grpc 构建"))
+}
 
-        // 处理最后的数据块
-        if n == 0 && !current_chunk.is_empty() {
-            write_compressed_chunk(&current_chunk, &config.output_prefix, chunk_number)?;
-            break;
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use zstd_compressor::exit::EXIT_CONFIG;
 
-    let duration = start_time.elapsed();
-    println!("\n压缩统计:");
-    println!("- 总分卷数: {}", chunk_number);
-    println!("- 总数据量: {:.2} MB", total_bytes as f64 / 1024.0 / 1024.0);
-    println!("- 处理耗时: {:.2} 秒", duration.as_secs_f64());
-    println!("- 平均速度: {:.2} MB/s", (total_bytes as f64 / 1024.0 / 1024.0) / duration.as_secs_f64());
-    
-    Ok(())
+    #[test]
+    fn subcommands_check_their_own_arguments() {
+        let names: HashSet<&str> = SUBCOMMANDS.iter().flat_map(|command| [Some(command.name), command.alias]).flatten().collect();
+        assert_eq!(names.len(), SUBCOMMANDS.len() + SUBCOMMANDS.iter().filter(|command| command.alias.is_some()).count());
+        // 旧写法不能与切分选项重名
+        assert!(SUBCOMMANDS.iter().filter_map(|command| command.alias).all(|alias| !config::OPTIONS.contains(&&alias[2..])));
+        assert_eq!(subcommand("--join").map(|command| command.name), Some("merge"));
+        assert!(subcommand("--level").is_none());
+
+        let run = |name: &str, args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            (subcommand(name).unwrap().run)(&ModeArgs { program: "zstd_compressor", command: subcommand(name).unwrap(), args: &args })
+        };
+        let error = run("info", &["a.zst", "b.zst"]).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_CONFIG);
+        assert_eq!(error.to_string(), "多余的参数: b.zst\n用法: zstd_compressor info <file.zst>");
+        assert!(run("merge", &["part", "--check-source"]).unwrap_err().to_string().starts_with("merge 缺少参数"));
+        assert!(run("--extract", &["a.zst", "out"]).unwrap_err().to_string().contains("extract 需要 --bytes"));
+        assert!(run("capabilities", &["--verbose"]).is_err());
+
+        let dir = env::temp_dir().join(format!("subcommands_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.log").display().to_string();
+        std::fs::write(&input, "line 0\nline 1\nline 2\n").unwrap();
+        let prefix = dir.join("part").display().to_string();
+        run("split", &[&input, &prefix, "--lines", "1", "--yes", "--porcelain"]).unwrap();
+        run("--verify", &[&prefix]).unwrap();
+        run("verify", &[&prefix, "--check-source"]).unwrap();
+        assert!(run("verify", &[&prefix, "--lines", "1"]).unwrap_err().to_string().contains("未知选项"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,779 @@
+// 分卷集的清单: 制表符分隔的 <prefix>.manifest 是唯一的版本化记录, <prefix>.manifest.json 由它派生;
+// 另有每次运行的来历信息(运行 ID, 主机, 命令行)与旧版本清单的升级
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Write, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::UNIX_EPOCH;
+use crate::config::Config;
+use crate::files::json_string;
+use crate::hash::{parse_sha256_hex, sha256, to_hex};
+use crate::header::{copied_footer_len, copied_header_len};
+use crate::naming::temp_path;
+use crate::source::{is_device, Source};
+use crate::tier::{relocated, tier_locations};
+use crate::{chunk_name, codec, json, Format};
+
+// 随机生成的 UUID (版本 4); 读不到 /dev/urandom 时以时间与进程号的摘要代替
+pub(crate) fn new_run_id() -> String {
+    let mut bytes = [0u8; 16];
+    if File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes)).is_err() {
+        let nanos = UNIX_EPOCH.elapsed().unwrap_or_default().as_nanos();
+        let seed = format!("{}.{}.{:?}", nanos, std::process::id(), thread::current().id());
+        bytes.copy_from_slice(&sha256(seed.as_bytes())[..16]);
+    }
+    bytes[6] = (bytes[6] & 0x0F) | 0x40;
+    bytes[8] = (bytes[8] & 0x3F) | 0x80;
+    let hex = to_hex(&bytes);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+// 含空白或引号的参数加单引号, 记录的命令行可以照原样重新执行
+pub(crate) fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.chars().any(|c| c.is_whitespace() || "'\"\\$`".contains(c)) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+fn host_name() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .ok()
+        .or_else(|| env::var("HOSTNAME").ok())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// 本次运行的来历: 运行 ID, 程序与 zstd 库版本, 主机, 命令行设置, 输入的身份(本地文件带大小与修改时间)
+pub(crate) fn lineage(config: &Config) -> Vec<(&'static str, String)> {
+    let mut source = config.input_path.clone();
+    if let Source::Local(path) = Source::parse(&config.input_path) {
+        // 设备没有大小信息, 只读取其中一段时读到的也不是整个文件
+        if let Ok(metadata) = std::fs::metadata(path) {
+            let windowed = config.max_bytes.is_some() || config.skip_bytes > 0 || config.skip_lines > 0 || config.max_lines.is_some();
+            if !is_device(&config.input_path) && !windowed {
+                source.push_str(&format!(" bytes={}", metadata.len()));
+            }
+            if let Some(mtime) = metadata.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+                source.push_str(&format!(" mtime={}", mtime.as_secs()));
+            }
+        }
+    }
+    if let Some(expected) = config.input_sha256 {
+        source.push_str(&format!(" sha256={}", to_hex(&expected)));
+    }
+    let tool = format!("{} {} (zstd {})", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), zstd::zstd_safe::version_string());
+    let mut lineage = vec![("run_id", config.run_id.clone()), ("tool", tool), ("host", host_name()), ("settings", config.settings.clone()), ("source", source)];
+    // 其余分卷都需要第一个分卷中的字典才能解压
+    if let Some(dictionary) = config.dictionary.get() {
+        let id = zstd::zstd_safe::get_dict_id_from_dict(dictionary).map_or(0, |id| id.get());
+        lineage.push(("dictionary", format!("chunk=1 id={} bytes={} sha256={}", id, dictionary.len(), to_hex(&sha256(dictionary)))));
+    }
+    lineage
+        .into_iter()
+        .map(|(key, value)| (key, value.replace(['\t', '\n', '\r'], " ")))
+        .collect()
+}
+
+// 逐个分卷输出时都写出的 manifest, 每个分卷一行: 大小、行数、摘要等, 以及把分卷镜像到各目标的状态;
+// 单个目标失败不影响其他目标. <prefix>.manifest.json 由它派生
+pub(crate) struct Manifest {
+    pub(crate) path: PathBuf,
+    pub(crate) manifest: File,
+    pub(crate) failures: usize,
+}
+
+impl Manifest {
+    pub(crate) fn create(config: &Config) -> io::Result<Self> {
+        let path = PathBuf::from(format!("{}.manifest", config.output_prefix));
+        let mut header = String::from("# chunk\tfile\tbytes\traw_bytes");
+        if !config.binary {
+            header.push_str("\tlines");
+        }
+        if config.chunk_sha256 {
+            header.push_str("\tsha256");
+        }
+        if config.line_merkle {
+            header.push_str("\tline_merkle");
+        }
+        if config.csv_header.is_some() || config.xml_wrap {
+            header.push_str("\theader_bytes");
+        }
+        if config.xml_wrap {
+            header.push_str("\tfooter_bytes");
+        }
+        if !config.output_dirs.is_empty() {
+            header.push_str("\tdir");
+        }
+        for destination in &config.mirrors {
+            header.push('\t');
+            header.push_str(&destination.to_string());
+        }
+        if let Some(point) = config.resume {
+            return Self::resume(path, &header, point.next_chunk);
+        }
+        let mut manifest = File::create(&path)?;
+        writeln!(manifest, "{}{}", MANIFEST_VERSION_PREFIX, MANIFEST_VERSION)?;
+        writeln!(manifest, "{}", header)?;
+        // 来历行在表头之后, 只认第一个注释行为表头的旧版本程序会忽略它们
+        for (key, value) in lineage(config) {
+            writeln!(manifest, "{}{}\t{}", MANIFEST_LINEAGE_PREFIX, key, value)?;
+        }
+        Ok(Manifest { path, manifest, failures: 0 })
+    }
+
+    // 续跑时保留上次的表头、来历与停下之前的分卷行, 丢弃之后写了一半的行, 新的行接着追加
+    fn resume(path: PathBuf, header: &str, next_chunk: usize) -> io::Result<Self> {
+        let text = std::fs::read_to_string(&path).map_err(|e| io::Error::new(e.kind(), format!("无法读取上次的 {}: {}", path.display(), e)))?;
+        if text.lines().nth(1) != Some(header) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} 的列与这次的选项不符, 续跑需要使用与上次相同的选项", path.display())));
+        }
+        let kept: String = text
+            .split_inclusive('\n')
+            .filter(|line| line.starts_with('#') || line.split('\t').next().and_then(|chunk| chunk.parse::<usize>().ok()).is_some_and(|chunk| chunk < next_chunk))
+            .filter(|line| line.ends_with('\n'))
+            .collect();
+        let mut manifest = File::create(&path)?;
+        manifest.write_all(kept.as_bytes())?;
+        Ok(Manifest { path, manifest, failures: 0 })
+    }
+
+    // fields 为 manifest_fields 给出的 raw_bytes 及之后各列
+    pub(crate) fn put(&mut self, chunk_path: &Path, data: &[u8], fields: &[String], chunk_number: usize, config: &Config) -> io::Result<Vec<PathBuf>> {
+        let name = chunk_path.file_name().unwrap().to_string_lossy();
+        let mut line = format!("{}\t{}\t{}", chunk_number, name, data.len());
+        for field in fields {
+            line.push('\t');
+            line.push_str(field);
+        }
+        let mut written = Vec::new();
+        for destination in &config.mirrors {
+            match destination.put(&name, data, Some(&config.run_id)) {
+                Ok(path) => {
+                    written.extend(path);
+                    line.push_str("\tok");
+                }
+                Err(e) => {
+                    eprintln!("警告: 分卷 {} 镜像到 {} 失败: {}", chunk_number, destination, e);
+                    self.failures += 1;
+                    line.push_str(&format!("\tfailed: {}", e.to_string().replace(['\t', '\n'], " ")));
+                }
+            }
+        }
+        writeln!(self.manifest, "{}", line)?;
+        Ok(written)
+    }
+
+    // --stream 的分卷: 已经写在磁盘上, 没有镜像目标
+    pub(crate) fn append(&mut self, chunk_path: &Path, bytes: u64, fields: &[String], chunk_number: usize) -> io::Result<()> {
+        let name = chunk_path.file_name().unwrap().to_string_lossy();
+        writeln!(self.manifest, "{}\t{}\t{}\t{}", chunk_number, name, bytes, fields.join("\t"))
+    }
+}
+
+// manifest 中 raw_bytes 及之后各列的值, 顺序与 Manifest::create 写出的表头一致
+pub(crate) fn manifest_fields(config: &Config, chunk_number: usize, raw_len: usize, lines: Option<u64>, digest: Option<[u8; 32]>, merkle: Option<[u8; 32]>, path: &Path) -> Vec<String> {
+    let mut fields = vec![raw_len.to_string()];
+    fields.extend(lines.map(|lines| lines.to_string()));
+    fields.extend(digest.map(|digest| to_hex(&digest)));
+    fields.extend(merkle.map(|root| to_hex(&root)));
+    if config.csv_header.is_some() || config.xml_wrap {
+        fields.push(copied_header_len(config, chunk_number).to_string());
+    }
+    if config.xml_wrap {
+        fields.push(copied_footer_len(config, chunk_number).to_string());
+    }
+    if !config.output_dirs.is_empty() {
+        fields.push(path.parent().unwrap().display().to_string());
+    }
+    fields
+}
+
+// <prefix>.manifest.json 中的一个分卷
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct JsonChunk {
+    pub(crate) chunk: usize,
+    pub(crate) file: String,
+    pub(crate) dir: Option<String>,
+    pub(crate) bytes: u64,
+    pub(crate) raw_bytes: u64,
+    // 二进制输入没有行数
+    pub(crate) lines: Option<u64>,
+    // --no-chunk-sha256 时不计算
+    pub(crate) sha256: Option<[u8; 32]>,
+    // 以下各项只在 manifest 中有对应的列时写出
+    pub(crate) line_merkle: Option<[u8; 32]>,
+    pub(crate) header_bytes: Option<u64>,
+    pub(crate) footer_bytes: Option<u64>,
+    // 各镜像目标与写出状态: ok 或 failed: <原因>
+    pub(crate) mirrors: Vec<(String, String)>,
+}
+
+impl JsonChunk {
+    fn to_json(&self) -> String {
+        let dir = self.dir.as_deref().map(|dir| format!(", \"dir\": {}", json_string(dir))).unwrap_or_default();
+        let lines = self.lines.map_or("null".to_string(), |n| n.to_string());
+        let sha256 = self.sha256.map_or("null".to_string(), |digest| format!("\"{}\"", to_hex(&digest)));
+        let mut json = format!(
+            "{{\"chunk\": {}, \"file\": {}{}, \"bytes\": {}, \"raw_bytes\": {}, \"lines\": {}, \"sha256\": {}",
+            self.chunk, json_string(&self.file), dir, self.bytes, self.raw_bytes, lines, sha256
+        );
+        if let Some(root) = self.line_merkle {
+            json.push_str(&format!(", \"line_merkle\": \"{}\"", to_hex(&root)));
+        }
+        for (key, value) in [("header_bytes", self.header_bytes), ("footer_bytes", self.footer_bytes)] {
+            if let Some(n) = value {
+                json.push_str(&format!(", \"{}\": {}", key, n));
+            }
+        }
+        if !self.mirrors.is_empty() {
+            let mirrors: Vec<String> = self.mirrors.iter().map(|(target, status)| format!("{}: {}", json_string(target), json_string(status))).collect();
+            json.push_str(&format!(", \"mirrors\": {{{}}}", mirrors.join(", ")));
+        }
+        json.push('}');
+        json
+    }
+
+    fn from_value(value: &json::Value) -> Option<Self> {
+        let number = |key: &str| value.get(key)?.as_u64();
+        let digest = |key: &str| match value.get(key) {
+            None | Some(json::Value::Null) => Some(None),
+            Some(digest) => parse_sha256_hex(digest.as_str()?).map(Some),
+        };
+        let mirrors = match value.get("mirrors") {
+            None => Vec::new(),
+            Some(mirrors) => mirrors.as_object()?.iter().map(|(target, status)| Some((target.clone(), status.as_str()?.to_string()))).collect::<Option<_>>()?,
+        };
+        Some(JsonChunk {
+            chunk: usize::try_from(number("chunk")?).ok()?,
+            file: value.get("file")?.as_str()?.to_string(),
+            dir: value.get("dir").and_then(json::Value::as_str).map(str::to_string),
+            bytes: number("bytes")?,
+            raw_bytes: number("raw_bytes")?,
+            lines: number("lines"),
+            sha256: digest("sha256")?,
+            line_merkle: digest("line_merkle")?,
+            header_bytes: number("header_bytes"),
+            footer_bytes: number("footer_bytes"),
+            mirrors,
+        })
+    }
+
+    // manifest 中的一行, 按列名取值; 不认识的列是镜像目标
+    fn from_row(table: &ManifestTable, row: &[String]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("无效的 manifest 行: {}", row.join("\t")));
+        let field = |name: &str| table.column(name).and_then(|i| row.get(i));
+        let number = |name: &str| field(name).map(|value| value.parse().map_err(|_| invalid())).transpose();
+        let digest = |name: &str| field(name).map(|hex| parse_sha256_hex(hex).ok_or_else(invalid)).transpose();
+        let mirrors = table.columns.iter().zip(row).filter(|(column, _)| !MANIFEST_COLUMNS.contains(&column.as_str()));
+        Ok(JsonChunk {
+            chunk: number("chunk")?.ok_or_else(invalid)? as usize,
+            file: field("file").ok_or_else(invalid)?.clone(),
+            dir: field("dir").cloned(),
+            bytes: number("bytes")?.ok_or_else(invalid)?,
+            raw_bytes: number("raw_bytes")?.ok_or_else(invalid)?,
+            lines: number("lines")?,
+            sha256: digest("sha256")?,
+            line_merkle: digest("line_merkle")?,
+            header_bytes: number("header_bytes")?,
+            footer_bytes: number("footer_bytes")?,
+            mirrors: mirrors.map(|(target, status)| (target.clone(), status.clone())).collect(),
+        })
+    }
+}
+
+// 每次切分都写出的 JSON 清单: 输入的身份、编码与换行符, 以及各分卷的大小、行数与摘要, 供下游工具读取.
+// 逐个分卷输出时分卷列表与来历取自 <prefix>.manifest, 与它使用同一个版本号; 合并与校验只读 <prefix>.manifest.
+// 所有分卷写完后一次写出; 到达 --max-runtime 上限时也写出, 标记为未完成
+pub(crate) struct JsonManifest {
+    pub(crate) path: PathBuf,
+    // 逐个分卷输出时的 <prefix>.manifest
+    pub(crate) table: Option<PathBuf>,
+    // 单文件、7z 与分区输出没有 manifest, 记录写出的各分卷
+    pub(crate) chunks: Vec<JsonChunk>,
+}
+
+impl JsonManifest {
+    // 续跑时接着上次的清单; 否则删掉旧的清单, 中途失败时不会留下与分卷不符的清单
+    pub(crate) fn create(config: &Config) -> io::Result<Self> {
+        let path = PathBuf::from(format!("{}.manifest.json", config.output_prefix));
+        let table = (json_layout(config) == "chunks").then(|| PathBuf::from(format!("{}.manifest", config.output_prefix)));
+        let Some(point) = config.resume.filter(|_| table.is_none()) else {
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => return Ok(JsonManifest { path, table, chunks: Vec::new() }),
+            }
+        };
+        let text = std::fs::read_to_string(&path).map_err(|e| io::Error::new(e.kind(), format!("无法读取上次的 {}: {}", path.display(), e)))?;
+        let manifest = parse_json_manifest(&text, &path.display().to_string())?;
+        let chunks = json_manifest_chunks(&manifest)?.into_iter().filter(|chunk| chunk.chunk < point.next_chunk).collect();
+        Ok(JsonManifest { path, table, chunks })
+    }
+
+    // 每个分卷与来历的每一项各占一行, 读回时逐行取值
+    pub(crate) fn write(&self, config: &Config, complete: bool) -> io::Result<()> {
+        let (mut bytes, mut mtime) = (None, None);
+        if let (Source::Local(path), false) = (Source::parse(&config.input_path), is_device(&config.input_path)) {
+            if let Ok(metadata) = std::fs::metadata(path) {
+                bytes = Some(metadata.len());
+                mtime = metadata.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs());
+            }
+        }
+        let (chunks, lineage) = match &self.table {
+            Some(path) => {
+                let table = ManifestTable::parse(&std::fs::read_to_string(path)?)?;
+                let chunks = table.rows.iter().map(|row| JsonChunk::from_row(&table, row)).collect::<io::Result<Vec<_>>>()?;
+                (chunks, table.lineage)
+            }
+            None => (self.chunks.clone(), lineage(config).into_iter().map(|(key, value)| (key.to_string(), value)).collect()),
+        };
+        let optional = |value: Option<u64>| value.map_or("null".to_string(), |n| n.to_string());
+        let name = config.input_path.rsplit('/').next().unwrap_or_default();
+
+        let mut json = String::from("{\n");
+        json.push_str(&format!("  \"manifest_version\": {},\n", MANIFEST_VERSION));
+        json.push_str(&format!("  \"complete\": {},\n", complete));
+        json.push_str(&format!(
+            "  \"input\": {{\"path\": {}, \"name\": {}, \"bytes\": {}, \"mtime\": {}}},\n",
+            json_string(&config.input_path), json_string(name), optional(bytes), optional(mtime)
+        ));
+        json.push_str(&format!("  \"encoding\": {},\n", json_string(config.encoding.name())));
+        match config.binary {
+            true => json.push_str("  \"line_ending\": null,\n"),
+            false => json.push_str(&format!("  \"line_ending\": {},\n", json_string(&config.line_ending))),
+        }
+        json.push_str(&format!("  \"format\": {},\n", json_string(config.format.name())));
+        json.push_str(&format!("  \"layout\": {},\n", json_string(json_layout(config))));
+        json.push_str(&format!("  \"chunk_size\": {},\n", config.chunk_size));
+        json.push_str("  \"lineage\": {\n");
+        let lineage: Vec<String> = lineage.iter().map(|(key, value)| format!("    {}: {}", json_string(key), json_string(value))).collect();
+        json.push_str(&lineage.join(",\n"));
+        json.push_str("\n  },\n  \"chunks\": [\n");
+        let rendered: Vec<String> = chunks.iter().map(|chunk| format!("    {}", chunk.to_json())).collect();
+        json.push_str(&rendered.join(",\n"));
+        let total = |field: fn(&JsonChunk) -> u64| chunks.iter().map(field).sum::<u64>();
+        let lines = chunks.iter().map(|chunk| chunk.lines).sum::<Option<u64>>();
+        json.push_str(&format!(
+            "\n  ],\n  \"totals\": {{\"chunks\": {}, \"bytes\": {}, \"raw_bytes\": {}, \"lines\": {}}}\n}}\n",
+            chunks.len(), total(|chunk| chunk.bytes), total(|chunk| chunk.raw_bytes), optional(lines)
+        ));
+        // 先写临时文件再重命名, 读取方不会看到写了一半的清单
+        let mut file = File::create(temp_path(&self.path))?;
+        file.write_all(json.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(temp_path(&self.path), &self.path)
+    }
+}
+
+fn json_layout(config: &Config) -> &'static str {
+    match (&config.single_output, config.format) {
+        (_, Format::SevenZip) => "7z",
+        (Some(_), _) => "single",
+        (None, _) if config.partitioned() => "partitions",
+        (None, _) => "chunks",
+    }
+}
+
+// 读回 JSON 清单, 不要求是本程序写出的排版(可以经 jq 等工具重新输出)
+fn parse_json_manifest(text: &str, path: &str) -> io::Result<json::Value> {
+    json::parse(text.as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{} 不是有效的 JSON: {}", path, e)))
+}
+
+// JSON 清单中的分卷; 有无法识别的分卷时报错
+pub(crate) fn json_manifest_chunks(manifest: &json::Value) -> io::Result<Vec<JsonChunk>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let chunks = manifest.get("chunks").and_then(json::Value::as_array).ok_or_else(|| invalid("JSON 清单缺少 chunks 数组".to_string()))?;
+    chunks.iter().enumerate().map(|(i, chunk)| JsonChunk::from_value(chunk).ok_or_else(|| invalid(format!("JSON 清单中第 {} 个分卷的记录无效", i + 1)))).collect()
+}
+
+// 读取 <prefix>.manifest.json; 没有时为 None
+fn read_json_manifest(prefix: &str) -> io::Result<Option<json::Value>> {
+    let source = Source::parse(&format!("{}.manifest.json", prefix));
+    if !source.exists()? {
+        return Ok(None);
+    }
+    let mut text = String::new();
+    source.open()?.read_to_string(&mut text)?;
+    parse_json_manifest(&text, &format!("{}.manifest.json", prefix)).map(Some)
+}
+
+// 没有 <prefix>.manifest 时按 JSON 清单说明原因: 7z 归档中的分卷不能逐个合并; 只有 JSON 清单的
+// 分卷集(早先的版本只在部分选项下写出 manifest)先用 manifest-upgrade 由 JSON 清单生成
+pub(crate) fn check_missing_manifest(prefix: &str) -> io::Result<()> {
+    let Some(manifest) = read_json_manifest(prefix)? else {
+        return Ok(());
+    };
+    match manifest.get("layout").and_then(json::Value::as_str) {
+        // 分卷都在归档内部, 不按编号查找 <prefix>.001.zst, 以免报出误导的"找不到分卷"
+        Some("7z") => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}.7z 为 7z 归档, 请用 7z 或 bsdtar 校验与解压", prefix))),
+        Some("chunks") => {
+            let message = format!("缺少 {0}.manifest, 请先运行 zstd_compressor manifest-upgrade {0} 由 {0}.manifest.json 生成", prefix);
+            Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+        }
+        _ => Ok(()),
+    }
+}
+
+// 由 JSON 清单还原 manifest: 各项取值与 JsonChunk::from_row 互逆, 某一项只在部分分卷中出现时整列省略
+fn json_manifest_table(manifest: &json::Value, prefix: &str) -> io::Result<ManifestTable> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let version = manifest.get("manifest_version").and_then(json::Value::as_u64).ok_or_else(|| invalid(format!("{}.manifest.json 缺少版本", prefix)))?;
+    if version > u64::from(MANIFEST_VERSION) {
+        return Err(invalid(format!("JSON 清单版本 {} 高于本程序支持的版本 {}, 请升级程序", version, MANIFEST_VERSION)));
+    }
+    if manifest.get("layout").and_then(json::Value::as_str) != Some("chunks") {
+        return Err(invalid(format!("{}.manifest.json 描述的不是逐个分卷的输出", prefix)));
+    }
+    let lineage = manifest
+        .get("lineage")
+        .and_then(json::Value::as_object)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+        .collect();
+    let chunks = json_manifest_chunks(manifest)?;
+    type Column = (&'static str, fn(&JsonChunk) -> Option<String>);
+    let optional: [Column; 6] = [
+        ("lines", |chunk| chunk.lines.map(|n| n.to_string())),
+        ("sha256", |chunk| chunk.sha256.map(|digest| to_hex(&digest))),
+        ("line_merkle", |chunk| chunk.line_merkle.map(|root| to_hex(&root))),
+        ("header_bytes", |chunk| chunk.header_bytes.map(|n| n.to_string())),
+        ("footer_bytes", |chunk| chunk.footer_bytes.map(|n| n.to_string())),
+        ("dir", |chunk| chunk.dir.clone()),
+    ];
+    let present: Vec<_> = optional.iter().filter(|(_, value)| !chunks.is_empty() && chunks.iter().all(|chunk| value(chunk).is_some())).collect();
+    let mut columns: Vec<String> = ["chunk", "file", "bytes", "raw_bytes"].iter().chain(present.iter().map(|(name, _)| name)).map(|name| name.to_string()).collect();
+    columns.extend(chunks.first().iter().flat_map(|chunk| chunk.mirrors.iter().map(|(target, _)| target.clone())));
+    let rows = chunks
+        .iter()
+        .map(|chunk| {
+            let mut row = vec![chunk.chunk.to_string(), chunk.file.clone(), chunk.bytes.to_string(), chunk.raw_bytes.to_string()];
+            row.extend(present.iter().map(|(_, value)| value(chunk).unwrap()));
+            row.extend(columns[row.len()..].iter().map(|target| chunk.mirrors.iter().find(|(t, _)| t == target).map_or(String::new(), |(_, status)| status.clone())));
+            row
+        })
+        .collect();
+    Ok(ManifestTable { version: MANIFEST_VERSION, columns, rows, lineage })
+}
+
+// manifest 格式版本. 读取方按表头中的列名取值, 不认识的列视为镜像目标, 因此新增列不需要升级版本;
+// 版本号只在已有列的含义改变时递增. 没有版本行的是版本 1 (没有 raw_bytes 列), 高于当前版本的拒绝读取
+const MANIFEST_VERSION: u32 = 2;
+
+const MANIFEST_VERSION_PREFIX: &str = "# manifest-version ";
+
+const MANIFEST_LINEAGE_PREFIX: &str = "# lineage\t";
+
+// 本程序写出的列; 其余的列是镜像目标
+const MANIFEST_COLUMNS: [&str; 10] = ["chunk", "file", "bytes", "raw_bytes", "lines", "sha256", "line_merkle", "header_bytes", "footer_bytes", "dir"];
+
+// 读入的 manifest: 表头列名与各行字段
+#[derive(Debug)]
+pub(crate) struct ManifestTable {
+    pub(crate) version: u32,
+    pub(crate) columns: Vec<String>,
+    pub(crate) rows: Vec<Vec<String>>,
+    // 写出该分卷集的运行的来历, 旧版本写出的 manifest 没有
+    pub(crate) lineage: Vec<(String, String)>,
+}
+
+// manifest 中的一个分卷
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ManifestEntry {
+    pub(crate) chunk: usize,
+    pub(crate) file: String,
+    pub(crate) raw_bytes: Option<u64>,
+    pub(crate) sha256: Option<[u8; 32]>,
+    // 切分时加在分卷开头的 CSV 表头或 XML 前导部分与结尾的结束标签长度, 合并时去掉
+    pub(crate) header_bytes: u64,
+    pub(crate) footer_bytes: u64,
+}
+
+impl ManifestEntry {
+    // 没有 manifest 时按编号命名的分卷, 无从校验
+    pub(crate) fn numbered(prefix: &str, chunk: usize) -> Self {
+        ManifestEntry { chunk, file: chunk_name(prefix, chunk, "zst"), raw_bytes: None, sha256: None, header_bytes: 0, footer_bytes: 0 }
+    }
+}
+
+impl ManifestTable {
+    pub(crate) fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut version = 1;
+        let mut columns = None;
+        let mut rows = Vec::new();
+        let mut lineage = Vec::new();
+        for line in text.lines().filter(|l| !l.is_empty()) {
+            if let Some(entry) = line.strip_prefix(MANIFEST_LINEAGE_PREFIX) {
+                let (key, value) = entry.split_once('\t').unwrap_or((entry, ""));
+                lineage.push((key.to_string(), value.to_string()));
+            } else if let Some(value) = line.strip_prefix(MANIFEST_VERSION_PREFIX) {
+                version = value.trim().parse().map_err(|_| invalid(format!("无效的 manifest 版本行: {}", line)))?;
+                if version > MANIFEST_VERSION {
+                    return Err(invalid(format!("manifest 版本 {} 高于本程序支持的版本 {}, 请升级程序", version, MANIFEST_VERSION)));
+                }
+            } else if let Some(header) = line.strip_prefix("# ") {
+                columns.get_or_insert_with(|| header.split('\t').map(str::to_string).collect::<Vec<_>>());
+            } else {
+                rows.push(line.split('\t').map(str::to_string).collect());
+            }
+        }
+        let columns = columns.ok_or_else(|| invalid("manifest 缺少表头".to_string()))?;
+        Ok(ManifestTable { version, columns, rows, lineage })
+    }
+
+    pub(crate) fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c == name)
+    }
+
+    pub(crate) fn entries(&self) -> io::Result<Vec<ManifestEntry>> {
+        let invalid = |row: &[String]| io::Error::new(io::ErrorKind::InvalidData, format!("无效的 manifest 行: {}", row.join("\t")));
+        let (Some(chunk), Some(file)) = (self.column("chunk"), self.column("file")) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "manifest 缺少 chunk 或 file 列"));
+        };
+        let (raw_bytes, sha256) = (self.column("raw_bytes"), self.column("sha256"));
+        let (header_bytes, footer_bytes) = (self.column("header_bytes"), self.column("footer_bytes"));
+        // --output-dirs 写出的分卷不在 prefix 所在目录, dir 列记录各自的目录
+        let dir = self.column("dir");
+        let mut entries = Vec::new();
+        for row in &self.rows {
+            let field = |i: usize| row.get(i).ok_or_else(|| invalid(row));
+            entries.push(ManifestEntry {
+                chunk: field(chunk)?.parse().map_err(|_| invalid(row))?,
+                file: match dir {
+                    Some(i) => Path::new(field(i)?).join(field(file)?).display().to_string(),
+                    None => field(file)?.clone(),
+                },
+                raw_bytes: raw_bytes.map(|i| field(i)?.parse().map_err(|_| invalid(row))).transpose()?,
+                sha256: sha256.map(|i| parse_sha256_hex(field(i)?).ok_or_else(|| invalid(row))).transpose()?,
+                header_bytes: header_bytes.map(|i| field(i)?.parse().map_err(|_| invalid(row))).transpose()?.unwrap_or(0),
+                footer_bytes: footer_bytes.map(|i| field(i)?.parse().map_err(|_| invalid(row))).transpose()?.unwrap_or(0),
+            });
+        }
+        Ok(entries)
+    }
+
+    pub(crate) fn to_text(&self) -> String {
+        let mut text = format!("{}{}\n# {}\n", MANIFEST_VERSION_PREFIX, self.version, self.columns.join("\t"));
+        for (key, value) in &self.lineage {
+            text.push_str(&format!("{}{}\t{}\n", MANIFEST_LINEAGE_PREFIX, key, value));
+        }
+        for row in &self.rows {
+            text.push_str(&row.join("\t"));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+// 把旧版本 manifest 逐版本升级到当前版本; raw_bytes 需要解压分卷取得.
+// 只有 JSON 清单的分卷集由 JSON 清单生成 manifest
+pub fn run_manifest_upgrade(prefix: &str) -> io::Result<()> {
+    let path = PathBuf::from(format!("{}.manifest", prefix));
+    if !path.exists() {
+        let Some(manifest) = read_json_manifest(prefix)? else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("找不到 {} 或 {}.manifest.json", path.display(), prefix)));
+        };
+        let table = json_manifest_table(&manifest, prefix)?;
+        let tmp = temp_path(&path);
+        std::fs::write(&tmp, table.to_text())?;
+        std::fs::rename(&tmp, &path)?;
+        println!("{} 已由 {}.manifest.json 生成, 共 {} 个分卷", path.display(), prefix, table.rows.len());
+        return Ok(());
+    }
+    let mut table = ManifestTable::parse(&std::fs::read_to_string(&path)?)?;
+    if table.version == MANIFEST_VERSION {
+        println!("{} 已是当前版本 {}", path.display(), MANIFEST_VERSION);
+        return Ok(());
+    }
+    let from = table.version;
+    while table.version < MANIFEST_VERSION {
+        match table.version {
+            1 => {
+                let file = table.column("file").ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "manifest 缺少 file 列"))?;
+                let at = table.column("bytes").map_or(table.columns.len(), |i| i + 1);
+                for row in &mut table.rows {
+                    let location = sibling(prefix, row.get(file).map(String::as_str).unwrap_or_default());
+                    let raw_bytes = codec::open_decoder(Source::parse(&location).open()?)
+                        .and_then(|(_, mut decoder)| io::copy(&mut decoder, &mut io::sink()))
+                        .map_err(|e| io::Error::new(e.kind(), format!("{} 解压失败: {}", location, e)))?;
+                    row.insert(at.min(row.len()), raw_bytes.to_string());
+                }
+                table.columns.insert(at, "raw_bytes".to_string());
+            }
+            version => return Err(io::Error::other(format!("不支持从 manifest 版本 {} 升级", version))),
+        }
+        table.version += 1;
+    }
+
+    // 写临时文件后重命名, 升级中途失败时保留原文件
+    let tmp = PathBuf::from(format!("{}.tmp", path.display()));
+    std::fs::write(&tmp, table.to_text())?;
+    std::fs::rename(&tmp, &path)?;
+    println!("{} 已从版本 {} 升级到 {}, 共 {} 个分卷", path.display(), from, table.version, table.rows.len());
+    Ok(())
+}
+
+// 与 prefix 同目录的文件, manifest 中只记录文件名; 带有 dir 列时已是绝对路径, 原样返回
+pub(crate) fn sibling(prefix: &str, name: &str) -> String {
+    if Path::new(name).is_absolute() {
+        return name.to_string();
+    }
+    match prefix.rfind('/') {
+        Some(pos) => format!("{}{}", &prefix[..=pos], name),
+        None => name.to_string(),
+    }
+}
+
+// 要合并的分卷: 有 <prefix>.manifest 或 <prefix>.manifest.json 时按其中的顺序与文件名, 并校验其中记录的原始大小与内容摘要;
+// 否则从 <prefix>.001.zst 起依次探测, 直到下一个分卷不存在
+pub(crate) fn read_manifest(prefix: &str) -> io::Result<Option<ManifestTable>> {
+    let source = Source::parse(&format!("{}.manifest", prefix));
+    if !source.exists()? {
+        return Ok(None);
+    }
+    let mut text = String::new();
+    source.open()?.read_to_string(&mut text)?;
+    ManifestTable::parse(&text).map(Some)
+}
+
+pub(crate) fn chunk_list(prefix: &str) -> io::Result<Option<Vec<ManifestEntry>>> {
+    let Some(table) = read_manifest(prefix)? else {
+        check_missing_manifest(prefix)?;
+        return Ok(None);
+    };
+    if table.version < MANIFEST_VERSION {
+        eprintln!("manifest 为旧版本 {}, 可用 manifest-upgrade 升级", table.version);
+    }
+    let tiers = tier_locations(prefix)?;
+    let mut entries = table.entries()?;
+    for entry in &mut entries {
+        entry.file = relocated(sibling(prefix, &entry.file), &tiers);
+    }
+    Ok(Some(entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::merge::{recorded_source, run_join, MergeOptions};
+    use crate::merkle::LineCounter;
+    use crate::split::run_split;
+    use crate::testing::{manifest_chunks, split_config, Scratch};
+
+    #[test]
+    fn manifest_versions_are_read_by_column_name() {
+        let key = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        // 版本 1 没有版本行与 raw_bytes 列, 镜像目标列不影响读取
+        let v1 = ManifestTable::parse(&format!("# chunk\tfile\tbytes\tsha256\ts3://b/p\n1\tout.001.zst\t10\t{}\tok\n", key)).unwrap();
+        assert_eq!(v1.version, 1);
+        assert_eq!(
+            v1.entries().unwrap(),
+            [ManifestEntry { chunk: 1, file: "out.001.zst".to_string(), raw_bytes: None, sha256: parse_sha256_hex(key), header_bytes: 0, footer_bytes: 0 }]
+        );
+
+        let v2 = ManifestTable::parse("# manifest-version 2\n# chunk\tfile\tbytes\traw_bytes\n2\tout.002.zst\t10\t30\n").unwrap();
+        assert_eq!(v2.version, MANIFEST_VERSION);
+        assert_eq!(v2.entries().unwrap()[0].raw_bytes, Some(30));
+        assert_eq!(ManifestTable::parse(&v2.to_text()).unwrap().rows, v2.rows);
+
+        // 来历行不影响表头与分卷, 升级时原样保留
+        let text = "# manifest-version 2\n# chunk\tfile\tbytes\traw_bytes\n# lineage\trun_id\tabc\n# lineage\tsettings\tin 'a b'\n1\tout.001.zst\t10\t30\n";
+        let table = ManifestTable::parse(text).unwrap();
+        assert_eq!(table.columns, ["chunk", "file", "bytes", "raw_bytes"]);
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.lineage, [("run_id".to_string(), "abc".to_string()), ("settings".to_string(), "in 'a b'".to_string())]);
+        assert_eq!(table.to_text(), text);
+        assert_eq!(recorded_source(&table), (None, None));
+        let hex = to_hex(&sha256(b"x"));
+        let table = ManifestTable::parse(&format!("# chunk\tfile\n# lineage\tsource\tmy logs/a.log bytes=30 mtime=5 sha256={}\n", hex)).unwrap();
+        assert_eq!(recorded_source(&table), (Some(30), Some(sha256(b"x"))));
+
+        assert!(ManifestTable::parse("# manifest-version 99\n# chunk\tfile\n").is_err());
+        assert!(ManifestTable::parse("# chunk\tfile\nx\tout.001.zst\n").unwrap().entries().is_err());
+    }
+
+    #[test]
+    fn run_ids_and_recorded_settings() {
+        let id = new_run_id();
+        assert_eq!(id.len(), 36);
+        assert_eq!(id.split('-').map(str::len).collect::<Vec<_>>(), [8, 4, 4, 4, 12]);
+        assert_eq!(&id[14..15], "4");
+        assert_ne!(id, new_run_id());
+
+        assert_eq!(quote_arg("--format"), "--format");
+        assert_eq!(quote_arg("a b"), "'a b'");
+        assert_eq!(quote_arg("it's"), "'it'\\''s'");
+        assert_eq!(quote_arg(""), "''");
+        let config = Config::parse(["zstd_compressor", "in.log", "out dir/x", "--yes"].map(String::from)).unwrap();
+        assert_eq!(config.settings, "in.log 'out dir/x' --yes");
+    }
+
+    #[test]
+    fn every_split_writes_a_json_manifest_derived_from_the_manifest() {
+        let dir = Scratch::new("json_manifest");
+        let input = dir.join("in \"a\".log");
+        let text: String = (0..3000).map(|i| format!("record {}\r\n", i)).collect::<String>() + "tail";
+        std::fs::write(&input, &text).unwrap();
+        let prefix = dir.arg("part");
+        let mut config = split_config(&[&input.display().to_string(), &prefix, "--line-ending", "CRLF"], 16 * 1024);
+        let stats = run_split(&config).unwrap();
+        assert!(stats.chunks > 2);
+
+        let json = std::fs::read_to_string(dir.join("part.manifest.json")).unwrap();
+        let manifest = json::parse(json.as_bytes()).unwrap();
+        let input_field = |key: &str| manifest.get("input").and_then(|input| input.get(key)).cloned();
+        assert_eq!(input_field("name").as_ref().and_then(json::Value::as_str), Some("in \"a\".log"));
+        assert_eq!(input_field("bytes").as_ref().and_then(json::Value::as_u64), Some(text.len() as u64));
+        assert_eq!(manifest.get("line_ending").and_then(json::Value::as_str), Some("\r\n"));
+        assert_eq!(manifest.get("manifest_version").and_then(json::Value::as_u64), Some(MANIFEST_VERSION.into()));
+        let chunks = manifest_chunks(&json);
+        assert_eq!(chunks.len(), stats.chunks);
+        assert_eq!(chunks.iter().map(|chunk| chunk.lines.unwrap()).sum::<u64>(), 3001);
+        assert_eq!(chunks.iter().map(|chunk| chunk.raw_bytes).sum::<u64>(), text.len() as u64);
+
+        // 分卷列表与 manifest 的各行一致, 合并按 manifest 取分卷并校验摘要
+        let table = read_manifest(&prefix).unwrap().unwrap();
+        assert_eq!(table.rows.iter().map(|row| JsonChunk::from_row(&table, row).unwrap()).collect::<Vec<_>>(), chunks);
+        let entries = chunk_list(&prefix).unwrap().unwrap();
+        assert_eq!(entries[0].sha256, chunks[0].sha256);
+        assert!(entries[0].file.ends_with("part.001.zst"));
+        let options = MergeOptions::parse(&["--check-source".to_string()]).unwrap();
+        let merged = dir.join("merged");
+        run_join(&prefix, &merged.display().to_string(), &options).unwrap();
+        assert_eq!(std::fs::read_to_string(&merged).unwrap(), text);
+
+        // 只有 JSON 清单时合并要求先升级; 经其他工具重新输出(去掉缩进与空格)的 JSON 清单同样可以还原 manifest,
+        // 分卷记录无法识别时报错
+        std::fs::remove_file(dir.join("part.manifest")).unwrap();
+        assert!(chunk_list(&prefix).unwrap_err().to_string().contains(&format!("manifest-upgrade {}", prefix)));
+        let compact: String = json.lines().map(str::trim_start).collect::<String>().replace("\": ", "\":").replace(", \"", ",\"");
+        std::fs::write(dir.join("part.manifest.json"), compact.replacen("\"raw_bytes\":", "\"raw\":", 1)).unwrap();
+        assert!(run_manifest_upgrade(&prefix).unwrap_err().to_string().contains("第 1 个分卷"));
+        std::fs::write(dir.join("part.manifest.json"), &compact).unwrap();
+        run_manifest_upgrade(&prefix).unwrap();
+        assert_eq!(read_manifest(&prefix).unwrap().unwrap().to_text(), table.to_text());
+        assert_eq!(chunk_list(&prefix).unwrap().unwrap(), entries);
+
+        // --no-chunk-sha256 时清单中没有摘要, 合并只核对大小
+        config.chunk_sha256 = false;
+        run_split(&config).unwrap();
+        let chunks = manifest_chunks(&std::fs::read_to_string(dir.join("part.manifest.json")).unwrap());
+        assert!(chunks.iter().all(|chunk| chunk.sha256.is_none()));
+        assert!(chunk_list(&prefix).unwrap().unwrap().iter().all(|entry| entry.sha256.is_none()));
+        run_join(&prefix, &merged.display().to_string(), &MergeOptions::default()).unwrap();
+        assert_eq!(std::fs::read_to_string(&merged).unwrap(), text);
+        assert!(Config::parse(["zstd_compressor", "in.log", "out/a", "--no-chunk-sha256", "--name-by-hash"].map(String::from)).is_err());
+
+        // 换行符跨两次到达时只计一次
+        let mut counter = LineCounter::new(&config).unwrap();
+        for piece in [&b"a\r"[..], b"\nb\r", b"", b"\n"] {
+            counter.update(piece);
+        }
+        assert_eq!(counter.total(), 2);
+    }
+}
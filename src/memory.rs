@@ -0,0 +1,89 @@
+// 内存用量统计, 供按容器限额估算内存: 计数的全局分配器记录堆上当前与峰值的字节数(读取缓冲、分卷与
+// 流水线中积压的数据都在堆上); Linux 上另从 /proc/self/status 读取进程的峰值常驻内存(VmHWM),
+// 其中还包括 zstd 等 C 库自行分配的内存. 两者都是整个进程的峰值, 批量任务并行时不区分任务
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+pub struct CountingAllocator;
+
+// SAFETY: 分配与释放都交给 System, 这里只做计数
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            match new_size.checked_sub(layout.size()) {
+                Some(grown) => grow(grown),
+                None => {
+                    CURRENT.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+                }
+            }
+        }
+        new_ptr
+    }
+}
+
+fn grow(size: usize) {
+    let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(current, Ordering::Relaxed);
+}
+
+// 运行以来堆上同时占用的最大字节数
+pub fn peak_heap() -> u64 {
+    PEAK.load(Ordering::Relaxed) as u64
+}
+
+// 进程的峰值常驻内存, 只支持 Linux
+#[cfg(target_os = "linux")]
+pub fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kb = status.lines().find_map(|line| line.strip_prefix("VmHWM:"))?.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn peak_rss() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 库的测试程序不经过 main.rs, 在这里装上计数的分配器
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    #[test]
+    fn peaks_cover_a_large_buffer() {
+        let buffer = vec![1u8; 8 * 1024 * 1024];
+        assert!(peak_heap() >= buffer.len() as u64);
+        #[cfg(target_os = "linux")]
+        assert!(peak_rss().unwrap() >= buffer.len() as u64);
+        drop(buffer);
+    }
+}
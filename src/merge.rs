@@ -0,0 +1,353 @@
+// 合并与校验: 按清单依次解压分卷, 核对大小与摘要, 可预取远程分卷
+
+use std::fs::File;
+use std::io::{self, Write, Read};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::UNIX_EPOCH;
+use crate::hash::{parse_sha256_hex, to_hex, Sha256};
+use crate::manifest::{check_missing_manifest, chunk_list, read_manifest, ManifestEntry, ManifestTable};
+use crate::source::Source;
+use crate::tier::{relocated, tier_locations};
+use crate::{chunk_name, codec};
+
+type Prefetched = thread::JoinHandle<io::Result<Option<Vec<u8>>>>;
+
+// 后台按顺序发起分卷下载, 每个分卷在独立线程中整体读入内存; 通道容量使最多领先 depth 个分卷,
+// 解压写出当前分卷时后续分卷已在下载, 掩盖远程读取的延迟
+fn prefetch_chunks(locations: Box<dyn Iterator<Item = String> + Send>, depth: usize) -> Receiver<Prefetched> {
+    let (tx, rx) = mpsc::sync_channel(depth - 1);
+    thread::spawn(move || {
+        for location in locations {
+            let source = Source::parse(&location);
+            let download = thread::spawn(move || {
+                if !source.exists()? {
+                    return Ok(None);
+                }
+                let mut data = Vec::new();
+                source.open()?.read_to_end(&mut data)?;
+                Ok(Some(data))
+            });
+            // 合并结束后接收端被丢弃, 不再发起新的下载
+            if tx.send(download).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+// 记录读取端是否出错, 解压失败时据此区分读取失败与数据损坏
+struct ReadTracker<R> {
+    inner: R,
+    failed: Arc<AtomicBool>,
+}
+
+impl<R: Read> Read for ReadTracker<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.inner.read(buf);
+        if result.is_err() {
+            self.failed.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+// 解压时顺带计算原始大小与摘要的写出端, 开头 skip 个字节(分卷的 CSV 表头或 XML 前导部分)与
+// 结尾 hold 个字节(补上的 XML 结束标签)只计入摘要, 不写出; 结尾的字节暂存在 held 中, 直到确定后面还有数据
+pub(crate) struct CheckedWriter<'a> {
+    out: &'a mut dyn Write,
+    pub(crate) hasher: Option<Sha256>,
+    skip: u64,
+    hold: usize,
+    held: Vec<u8>,
+}
+
+impl<'a> CheckedWriter<'a> {
+    pub(crate) fn new(out: &'a mut dyn Write, hasher: Option<Sha256>) -> Self {
+        CheckedWriter { out, hasher, skip: 0, hold: 0, held: Vec::new() }
+    }
+}
+
+impl Write for CheckedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = if self.skip > 0 {
+            let n = buf.len().min(self.skip as usize);
+            self.skip -= n as u64;
+            n
+        } else if self.hold > 0 {
+            self.held.extend_from_slice(buf);
+            let release = self.held.len().saturating_sub(self.hold);
+            self.out.write_all(&self.held[..release])?;
+            self.held.drain(..release);
+            buf.len()
+        } else {
+            self.out.write(buf)?
+        };
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// 依次解压各分卷并写出, 返回 (分卷数, 解压后字节数)
+pub(crate) fn decode_chunks(prefix: &str, out: &mut dyn Write, prefetch: usize) -> io::Result<(usize, u64)> {
+    let listed = chunk_list(prefix)?;
+    // 没有 manifest 时按编号探测, 已迁移的分卷同样按 <prefix>.tiers 查找
+    let tiers = tier_locations(prefix)?;
+    let prefetched = (prefetch > 0).then(|| {
+        let locations: Box<dyn Iterator<Item = String> + Send> = match &listed {
+            Some(entries) => Box::new(entries.iter().map(|e| e.file.clone()).collect::<Vec<_>>().into_iter()),
+            None => {
+                let (prefix, tiers) = (prefix.to_string(), tiers.clone());
+                Box::new((1..).map(move |n| relocated(chunk_name(&prefix, n, "zst"), &tiers)))
+            }
+        };
+        prefetch_chunks(locations, prefetch)
+    });
+    let mut chunk_number = 1;
+    let mut total = 0;
+    // 第一个分卷开头内嵌的字典, 之后的分卷都用它解压
+    let mut dictionary = None;
+    loop {
+        let entry = match &listed {
+            Some(entries) => match entries.get(chunk_number - 1) {
+                Some(entry) => entry.clone(),
+                None => break,
+            },
+            None => {
+                let entry = ManifestEntry::numbered(prefix, chunk_number);
+                ManifestEntry { file: relocated(entry.file, &tiers), ..entry }
+            }
+        };
+        // brotli 流没有文件头, 无法按内容识别格式
+        if entry.file.ends_with(".br") {
+            let message = format!("分卷 {} 为 brotli 格式, 不能合并或校验, 请用 brotli -dc 逐个解压", entry.file);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        let reader: Option<Box<dyn Read + Send>> = match &prefetched {
+            Some(rx) => match rx.recv() {
+                Ok(download) => download.join().map_err(|_| io::Error::other("下载线程异常退出"))??.map(|data| Box::new(io::Cursor::new(data)) as _),
+                Err(_) => None,
+            },
+            None => {
+                let source = Source::parse(&entry.file);
+                if source.exists()? { Some(source.open()?) } else { None }
+            }
+        };
+        let Some(reader) = reader else {
+            if listed.is_some() {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("manifest 中的分卷 {} 不存在: {}", entry.chunk, entry.file)));
+            }
+            break;
+        };
+
+        let mut checked = CheckedWriter { skip: entry.header_bytes, hold: entry.footer_bytes as usize, ..CheckedWriter::new(out, entry.sha256.map(|_| Sha256::new())) };
+        let failed = Arc::new(AtomicBool::new(false));
+        let reader = ReadTracker { inner: reader, failed: failed.clone() };
+        let result = codec::open_decoder_with(Box::new(reader), &mut dictionary).and_then(|(_, mut decoder)| io::copy(&mut decoder, &mut checked));
+        let bytes = result.map_err(|e| {
+            // 读取端没有出错时, 解码器报出的错误(ErrorKind::Other)意味着分卷数据损坏
+            let kind = if !failed.load(Ordering::Relaxed) && e.kind() == io::ErrorKind::Other { io::ErrorKind::InvalidData } else { e.kind() };
+            io::Error::new(kind, format!("分卷 {} 解压失败: {}", entry.chunk, e))
+        })?;
+        let mismatch = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if entry.raw_bytes.is_some_and(|raw_bytes| raw_bytes != bytes) {
+            return Err(mismatch(format!("分卷 {} 的原始大小与 manifest 不符", entry.chunk)));
+        }
+        if let (Some(expected), Some(hasher)) = (entry.sha256, checked.hasher) {
+            if hasher.finish() != expected {
+                return Err(mismatch(format!("分卷 {} 的 SHA-256 与 manifest 不符", entry.chunk)));
+            }
+        }
+        eprintln!("读取分卷 {} (解压后 {} 字节)", entry.chunk, bytes);
+        total += bytes - (entry.header_bytes + entry.footer_bytes).min(bytes);
+        chunk_number += 1;
+    }
+    // manifest 列出零个分卷(空输入)时合并结果为空; 只有按编号探测时才说明分卷集不存在
+    if chunk_number == 1 && listed.is_none() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("找不到分卷 {}", chunk_name(prefix, 1, "zst"))));
+    }
+    Ok((chunk_number - 1, total))
+}
+
+// 合并与校验模式的选项
+#[derive(Debug, Default, PartialEq)]
+pub struct MergeOptions {
+    pub(crate) prefetch: usize,
+    // 核对合并结果与 manifest 来历中记录的输入大小与 SHA-256
+    pub(crate) check_source: bool,
+}
+
+impl MergeOptions {
+    pub fn parse(args: &[String]) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let mut options = MergeOptions::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--prefetch" => {
+                    let value = args.next().ok_or_else(|| invalid("选项 --prefetch 缺少参数".to_string()))?;
+                    options.prefetch = value.parse().map_err(|_| invalid(format!("无效的预取数量: {}", value)))?;
+                }
+                "--check-source" => options.check_source = true,
+                _ => return Err(invalid(format!("未知选项: {}", arg))),
+            }
+        }
+        Ok(options)
+    }
+}
+
+// manifest 来历中记录的输入大小与 SHA-256; source 的值为 "<input> bytes=N mtime=T sha256=HEX", 输入路径可能含空格
+pub(crate) fn recorded_source(table: &ManifestTable) -> (Option<u64>, Option<[u8; 32]>) {
+    let Some((_, source)) = table.lineage.iter().find(|(key, _)| key == "source") else {
+        return (None, None);
+    };
+    let field = |name: &str| source.split(' ').rev().find_map(|token| token.strip_prefix(name));
+    (field("bytes=").and_then(|v| v.parse().ok()), field("sha256=").and_then(parse_sha256_hex))
+}
+
+// 解压所有分卷, 给出 --check-source 时再核对整体的大小与摘要
+pub(crate) fn merge_chunks(prefix: &str, out: &mut dyn Write, options: &MergeOptions) -> io::Result<(usize, u64)> {
+    if !options.check_source {
+        return decode_chunks(prefix, out, options.prefetch);
+    }
+    let Some(table) = read_manifest(prefix)? else {
+        check_missing_manifest(prefix)?;
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--check-source 需要 <prefix>.manifest"));
+    };
+    let (bytes, sha256) = recorded_source(&table);
+    if bytes.is_none() && sha256.is_none() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "manifest 中没有记录输入的大小或 SHA-256"));
+    }
+    let mut checked = CheckedWriter::new(out, sha256.map(|_| Sha256::new()));
+    let (chunks, total) = decode_chunks(prefix, &mut checked, options.prefetch)?;
+    let mismatch = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    if bytes.is_some_and(|bytes| bytes != total) {
+        return Err(mismatch("合并结果的大小与 manifest 记录的输入大小不符"));
+    }
+    if let (Some(expected), Some(hasher)) = (sha256, checked.hasher) {
+        if hasher.finish() != expected {
+            return Err(mismatch("合并结果的 SHA-256 与 manifest 记录的输入不符"));
+        }
+    }
+    eprintln!("与原始输入一致: {} 字节", total);
+    Ok((chunks, total))
+}
+
+// 进度信息写到标准错误, 输出为 - 时标准输出只有数据
+pub fn run_join(prefix: &str, output: &str, options: &MergeOptions) -> io::Result<()> {
+    let mut out: Box<dyn Write> = if output == "-" {
+        Box::new(io::BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(io::BufWriter::new(File::create(output)?))
+    };
+    let (chunks, bytes) = merge_chunks(prefix, &mut out, options)?;
+    out.flush()?;
+    eprintln!("合并 {} 个分卷, 共 {} 字节", chunks, bytes);
+    Ok(())
+}
+
+pub fn run_verify(prefix: &str, options: &MergeOptions) -> io::Result<()> {
+    let (chunks, bytes) = merge_chunks(prefix, &mut io::sink(), options)?;
+    record_verified(prefix)?;
+    println!("校验通过: {} 个分卷, 解压后共 {} 字节", chunks, bytes);
+    Ok(())
+}
+
+// 校验通过的分卷追加记录到 <prefix>.verified: 文件名, manifest 中原始内容的 SHA-256(没有时为 -)与校验时间;
+// 只记录本地有 manifest 的分卷集
+fn record_verified(prefix: &str) -> io::Result<()> {
+    let (Source::Local(_), Some(table)) = (Source::parse(prefix), read_manifest(prefix)?) else {
+        return Ok(());
+    };
+    let time = UNIX_EPOCH.elapsed().unwrap_or_default().as_secs();
+    let mut record = String::new();
+    for entry in table.entries()? {
+        let name = entry.file.rsplit('/').next().unwrap_or_default();
+        record.push_str(&format!("{}\t{}\t{}\n", name, entry.sha256.map_or("-".to_string(), |digest| to_hex(&digest)), time));
+    }
+    std::fs::OpenOptions::new().create(true).append(true).open(format!("{}.verified", prefix))?.write_all(record.as_bytes())
+}
+
+// <prefix>.verified 中各分卷最后一次校验时的摘要
+pub(crate) fn verified_chunks(prefix: &str) -> io::Result<HashMap<String, String>> {
+    let text = match std::fs::read_to_string(format!("{}.verified", prefix)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(text.lines().filter_map(|line| line.split_once('\t')).map(|(name, rest)| (name.to_string(), rest.split('\t').next().unwrap_or_default().to_string())).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exit::{exit_code, EXIT_CONFIG};
+    use crate::split::run_split;
+    use crate::testing::{split_config, Scratch};
+
+    #[test]
+    fn empty_input_round_trips_through_join_and_verify() {
+        let dir = Scratch::new("empty_input");
+        let (input, _) = dir.lines("in.log", 0);
+        let prefix = dir.arg("part");
+        assert_eq!(run_split(&split_config(&[&input, &prefix], 0)).unwrap().chunks, 0);
+        run_join(&prefix, &dir.arg("merged"), &MergeOptions::parse(&[]).unwrap()).unwrap();
+        assert_eq!(std::fs::read(dir.join("merged")).unwrap(), b"");
+        run_verify(&prefix, &MergeOptions::parse(&[]).unwrap()).unwrap();
+        // 没有 manifest 时仍然报告找不到分卷
+        std::fs::remove_file(dir.join("part.manifest")).unwrap();
+        std::fs::remove_file(dir.join("part.manifest.json")).unwrap();
+        assert_eq!(decode_chunks(&prefix, &mut io::sink(), 0).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn snappy_chunks_round_trip_through_join_and_verify() {
+        let dir = Scratch::new("snappy_join");
+        let (input, text) = dir.lines("in.log", 20_000);
+        let prefix = dir.arg("part");
+        let stats = run_split(&split_config(&[&input, &prefix, "--format", "snappy"], 32 * 1024)).unwrap();
+        assert!(stats.chunks > 2);
+        run_join(&prefix, &dir.arg("merged"), &MergeOptions::parse(&[]).unwrap()).unwrap();
+        assert!(std::fs::read(dir.join("merged")).unwrap() == text.as_bytes());
+        run_verify(&prefix, &MergeOptions::parse(&[]).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn seven_zip_output_is_refused_by_join_and_verify() {
+        let dir = Scratch::new("seven_zip_join");
+        let (input, _) = dir.lines("in.log", 100);
+        let prefix = dir.arg("part");
+        run_split(&split_config(&[&input, &prefix, "--format", "7z", "--7z-codec", "zstd"], 0)).unwrap();
+        let error = run_join(&prefix, &dir.arg("merged"), &MergeOptions::parse(&[]).unwrap()).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_CONFIG);
+        assert!(error.to_string().contains("请用 7z 或 bsdtar"), "{}", error);
+        let error = run_verify(&prefix, &MergeOptions::parse(&[]).unwrap()).unwrap_err();
+        assert!(error.to_string().contains("part.7z 为 7z 归档"), "{}", error);
+    }
+
+    #[test]
+    fn brotli_chunks_are_refused_by_join_and_verify() {
+        let dir = Scratch::new("brotli_join");
+        let (input, _) = dir.lines("in.log", 100);
+        let prefix = dir.arg("part");
+        run_split(&split_config(&[&input, &prefix], 0)).unwrap();
+        // 换成 brotli 分卷的文件名, 不需要系统中有 brotli 命令
+        std::fs::rename(dir.join("part.001.zst"), dir.join("part.001.br")).unwrap();
+        let manifest = std::fs::read_to_string(dir.join("part.manifest")).unwrap();
+        std::fs::write(dir.join("part.manifest"), manifest.replace("part.001.zst", "part.001.br")).unwrap();
+        let error = run_join(&prefix, &dir.arg("merged"), &MergeOptions::parse(&[]).unwrap()).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_CONFIG);
+        assert!(error.to_string().contains("brotli -dc"), "{}", error);
+        assert_eq!(exit_code(&run_verify(&prefix, &MergeOptions::parse(&[]).unwrap()).unwrap_err()), EXIT_CONFIG);
+    }
+}
@@ -0,0 +1,219 @@
+// 分卷各行哈希的 Merkle 树, 用于证明某一行属于某个分卷(prove-line)
+
+use std::io::{self, Read};
+use encoding_rs::UTF_8;
+use crate::codec;
+use crate::config::{parse_encoding, parse_line_ending, Config, DEFAULT_LINE_ENDING};
+use crate::hash::{parse_sha256_hex, sha256, to_hex, Sha256};
+use crate::manifest::{sibling, ManifestTable};
+use crate::source::Source;
+
+// 按换行符计行数, 内容可以分多次到达, 换行符可以跨两次; 最后一行没有换行符时也算一行
+pub(crate) struct LineCounter {
+    delimiter: Vec<u8>,
+    // 上一次结尾不足一个换行符的字节
+    carry: Vec<u8>,
+    lines: u64,
+    bytes: u64,
+    ended: bool,
+}
+
+impl LineCounter {
+    // 二进制输入没有行
+    pub(crate) fn new(config: &Config) -> Option<Self> {
+        let delimiter = config.encoding.encode(&config.line_ending).0.into_owned();
+        (!config.binary).then(|| LineCounter { delimiter, carry: Vec::new(), lines: 0, bytes: 0, ended: false })
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let d = self.delimiter.len();
+        let mut joined = std::mem::take(&mut self.carry);
+        joined.extend_from_slice(&data[..data.len().min(d - 1)]);
+        // joined 中每个窗口都从上一次的结尾开始, 与下面 data 中的不重复
+        self.lines += joined.windows(d).filter(|w| *w == self.delimiter).count() as u64;
+        self.lines += match d {
+            1 => data.iter().filter(|&&b| b == self.delimiter[0]).count(),
+            _ => data.windows(d).filter(|w| *w == self.delimiter).count(),
+        } as u64;
+        if data.len() >= d {
+            self.ended = data.ends_with(&self.delimiter);
+            self.carry = data[data.len() + 1 - d..].to_vec();
+        } else {
+            self.ended = joined.ends_with(&self.delimiter);
+            self.carry = joined[joined.len().saturating_sub(d - 1)..].to_vec();
+        }
+        self.bytes += data.len() as u64;
+    }
+
+    pub(crate) fn total(&self) -> u64 {
+        self.lines + u64::from(self.bytes > 0 && !self.ended)
+    }
+}
+
+pub(crate) fn count_lines(data: &[u8], config: &Config) -> Option<u64> {
+    let mut counter = LineCounter::new(config)?;
+    counter.update(data);
+    Some(counter.total())
+}
+
+// 按换行符切分行, 每行包含其换行符, 最后一行可以没有
+pub(crate) fn split_lines<'a>(data: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let end = data[start..]
+            .windows(delimiter.len())
+            .position(|w| w == delimiter)
+            .map(|pos| start + pos + delimiter.len())
+            .unwrap_or(data.len());
+        lines.push(&data[start..end]);
+        start = end;
+    }
+    lines
+}
+
+// 行哈希的 Merkle 树: 叶子为 SHA-256(0x00 || 行), 内部节点为 SHA-256(0x01 || 左 || 右),
+// 前缀区分叶子与节点; 每层落单的最后一个节点直接升入上一层. 返回自叶子起的各层
+fn merkle_levels(lines: &[&[u8]]) -> Vec<Vec<[u8; 32]>> {
+    let hash = |prefix: u8, parts: &[&[u8]]| {
+        let mut hasher = Sha256::new();
+        hasher.update(&[prefix]);
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finish()
+    };
+    let mut levels = vec![lines.iter().map(|line| hash(0, &[line])).collect::<Vec<_>>()];
+    while levels.last().unwrap().len() > 1 {
+        let next = levels
+            .last()
+            .unwrap()
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => hash(1, &[left, right]),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+        levels.push(next);
+    }
+    levels
+}
+
+// 没有行的分卷, 根为空数据的 SHA-256
+pub(crate) fn merkle_root(lines: &[&[u8]]) -> [u8; 32] {
+    merkle_levels(lines).last().and_then(|level| level.first().copied()).unwrap_or_else(|| sha256(b""))
+}
+
+// 第 index 行的证明路径: 自下而上的兄弟节点, true 表示兄弟在左侧; 落单升层的一层没有兄弟
+fn merkle_proof(levels: &[Vec<[u8; 32]>], mut index: usize) -> Vec<(bool, [u8; 32])> {
+    let mut proof = Vec::new();
+    for level in &levels[..levels.len() - 1] {
+        let sibling = index ^ 1;
+        if let Some(hash) = level.get(sibling) {
+            proof.push((sibling < index, *hash));
+        }
+        index /= 2;
+    }
+    proof
+}
+
+fn merkle_verify(line: &[u8], proof: &[(bool, [u8; 32])], root: &[u8; 32]) -> bool {
+    let mut node = merkle_levels(&[line])[0][0];
+    for (left, sibling) in proof {
+        let mut hasher = Sha256::new();
+        hasher.update(&[1]);
+        if *left {
+            hasher.update(sibling);
+            hasher.update(&node);
+        } else {
+            hasher.update(&node);
+            hasher.update(sibling);
+        }
+        node = hasher.finish();
+    }
+    node == *root
+}
+
+// 解压分卷重新计算各行哈希, 输出某一行到 manifest 中 Merkle 根的证明路径;
+// 审计方持有该行与路径即可对照根验证, 不需要其他行
+pub fn run_prove_line(prefix: &str, chunk: &str, options: &[String]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let chunk: usize = chunk.parse().map_err(|_| invalid(format!("无效的分卷编号: {}", chunk)))?;
+    let line_number: usize = options
+        .first()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n| n > 0)
+        .ok_or_else(|| invalid("需要从 1 开始的行号".to_string()))?;
+    let line_ending = match options.get(1) {
+        Some(value) => parse_line_ending(value).map_err(invalid)?,
+        None => String::from(DEFAULT_LINE_ENDING),
+    };
+    let encoding = match options.get(2) {
+        Some(value) => parse_encoding(value).map_err(invalid)?,
+        None => UTF_8,
+    };
+
+    let source = Source::parse(&format!("{}.manifest", prefix));
+    let mut text = String::new();
+    source.open()?.read_to_string(&mut text)?;
+    let table = ManifestTable::parse(&text)?;
+    let column = table.column("line_merkle").ok_or_else(|| invalid("manifest 中没有 line_merkle 列, 写出时需要 --line-merkle".to_string()))?;
+    let (entry, row) = table
+        .entries()?
+        .into_iter()
+        .zip(&table.rows)
+        .find(|(entry, _)| entry.chunk == chunk)
+        .ok_or_else(|| invalid(format!("manifest 中没有分卷 {}", chunk)))?;
+    let root = row.get(column).and_then(|hex| parse_sha256_hex(hex)).ok_or_else(|| invalid(format!("分卷 {} 的 Merkle 根无效", chunk)))?;
+
+    // 分卷集带内嵌字典时先从第一个分卷中取出
+    let mut dictionary = None;
+    if table.lineage.iter().any(|(key, _)| key == "dictionary") {
+        let first = table.entries()?.into_iter().find(|entry| entry.chunk == 1).ok_or_else(|| invalid("manifest 中没有分卷 1".to_string()))?;
+        dictionary = codec::take_dictionary(Source::parse(&sibling(prefix, &first.file)).open()?)?.0;
+    }
+    let mut data = Vec::new();
+    codec::open_decoder_with(Source::parse(&sibling(prefix, &entry.file)).open()?, &mut dictionary)?.1.read_to_end(&mut data)?;
+    let delimiter = encoding.encode(&line_ending).0;
+    let lines = split_lines(&data, &delimiter);
+    let line = *lines.get(line_number - 1).ok_or_else(|| invalid(format!("分卷 {} 只有 {} 行", chunk, lines.len())))?;
+    let levels = merkle_levels(&lines);
+    let proof = merkle_proof(&levels, line_number - 1);
+    if !merkle_verify(line, &proof, &root) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("分卷 {} 的内容与 manifest 中的 Merkle 根不符", chunk)));
+    }
+
+    println!("分卷 {} 第 {} 行 (共 {} 行): {}", chunk, line_number, lines.len(), encoding.decode(line).0.escape_debug());
+    println!("leaf\t{}", to_hex(&levels[0][line_number - 1]));
+    for (left, hash) in &proof {
+        println!("{}\t{}", if *left { "left" } else { "right" }, to_hex(hash));
+    }
+    println!("root\t{}", to_hex(&root));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_line_has_a_merkle_proof() {
+        assert_eq!(merkle_root(&[]), sha256(b""));
+        for n in 1..12 {
+            let data: Vec<u8> = (0..n).flat_map(|i| format!("line {}\n", i).into_bytes()).collect();
+            let lines = split_lines(&data, b"\n");
+            assert_eq!(lines.len(), n);
+            let levels = merkle_levels(&lines);
+            let root = merkle_root(&lines);
+            for (i, line) in lines.iter().enumerate() {
+                let proof = merkle_proof(&levels, i);
+                assert!(merkle_verify(line, &proof, &root));
+                assert!(!merkle_verify(b"forged\n", &proof, &root));
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+// 分卷文件名: 按编号、按内容摘要或按模板命名, 以及在多个输出目录间轮流存放
+
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::chunk_name;
+use crate::config::Config;
+use crate::hash::{sha256, to_hex};
+use crate::manifest::sibling;
+use crate::merkle::split_lines;
+use crate::regex::Regex;
+
+pub(crate) fn chunk_path(output_prefix: &str, chunk_number: usize, extension: &str) -> PathBuf {
+    PathBuf::from(chunk_name(output_prefix, chunk_number, extension))
+}
+
+// 分卷文件名模板, 如 "{prefix}.{field:date}.{n}.{ext}"; {field:NAME} 取自分卷首行, 由 --field NAME=REGEX 定义
+#[derive(Debug)]
+pub(crate) struct NameTemplate {
+    parts: Vec<TemplatePart>,
+    fields: Vec<(String, Regex)>,
+}
+
+#[derive(Debug)]
+enum TemplatePart {
+    Literal(String),
+    Prefix,
+    Number,
+    Extension,
+    Field(usize),
+}
+
+// 字段只在首行的前这么多个字符中匹配, 回溯匹配的递归深度随文本长度增长
+const FIELD_SCAN_CHARS: usize = 4096;
+
+impl NameTemplate {
+    pub(crate) fn parse(template: &str, fields: Vec<(String, Regex)>) -> Result<Self, String> {
+        if template.contains('/') {
+            return Err("--name-template 只是文件名, 不能包含 /; 目录由 output_prefix 决定".to_string());
+        }
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(TemplatePart::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or_else(|| format!("--name-template 中的 {{ 没有闭合: {}", template))? + start;
+            parts.push(match &rest[start + 1..end] {
+                "prefix" => TemplatePart::Prefix,
+                "n" => TemplatePart::Number,
+                "ext" => TemplatePart::Extension,
+                name => {
+                    let field = name.strip_prefix("field:").ok_or_else(|| format!("--name-template 中未知的变量: {{{}}}", name))?;
+                    let index = fields.iter().position(|(n, _)| n == field).ok_or_else(|| format!("字段 {} 没有用 --field 定义", field))?;
+                    TemplatePart::Field(index)
+                }
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Literal(rest.to_string()));
+        }
+        // 同一字段值会跨多个分卷, 没有编号时后写的分卷会覆盖先写的
+        if !parts.iter().any(|p| matches!(p, TemplatePart::Number)) {
+            return Err("--name-template 必须包含 {n}, 否则字段值相同的分卷会互相覆盖".to_string());
+        }
+        Ok(NameTemplate { parts, fields })
+    }
+
+    fn render(&self, output_prefix: &str, chunk_number: usize, extension: &str, first_line: &str) -> Result<String, String> {
+        let first_line: String = first_line.chars().take(FIELD_SCAN_CHARS).collect();
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(text) => name.push_str(text),
+                TemplatePart::Prefix => name.push_str(output_prefix.rsplit('/').next().unwrap_or(output_prefix)),
+                TemplatePart::Number => name.push_str(&format!("{:03}", chunk_number)),
+                TemplatePart::Extension => name.push_str(extension),
+                TemplatePart::Field(index) => {
+                    let (field, regex) = &self.fields[*index];
+                    let value = regex.extract(&first_line).ok_or_else(|| format!("分卷 {} 的首行不匹配字段 {} 的正则", chunk_number, field))?;
+                    // 字段值来自数据, 路径分隔符与控制字符(含 manifest 的分隔符 \t)替换掉
+                    name.extend(value.chars().map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c }));
+                }
+            }
+        }
+        Ok(name)
+    }
+}
+
+// 按模板给分卷命名, 字段取自首行(不含换行符, 按输入编码解码)
+fn template_name(raw: &[u8], config: &Config, chunk_number: usize) -> io::Result<String> {
+    let template = config.name_template.as_ref().unwrap();
+    let delimiter = config.encoding.encode(&config.line_ending).0;
+    let first_line = split_lines(raw, &delimiter).first().map_or(&raw[..0], |line| line.strip_suffix(&delimiter[..]).unwrap_or(line));
+    let first_line = config.encoding.decode_without_bom_handling(first_line).0;
+    let extension = config.format.extension();
+    template.render(&config.output_prefix, chunk_number, extension, &first_line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// 解析 "NAME=REGEX" 形式的字段定义
+pub(crate) fn parse_field(value: &str) -> Result<(String, Regex), String> {
+    let (name, pattern) = value.split_once('=').ok_or_else(|| format!("无效的字段定义: {}. 请使用 NAME=REGEX", value))?;
+    if name.is_empty() || name.contains(['{', '}']) {
+        return Err(format!("无效的字段名: {}", name));
+    }
+    let regex = Regex::parse(pattern).map_err(|e| format!("字段 {} 的正则无效: {}", name, e))?;
+    Ok((name.to_string(), regex))
+}
+
+// 分卷文件的命名方式; key 为原始内容的 SHA-256, 只在 --content-addressed 时计算
+pub(crate) trait ChunkNaming: Send {
+    fn path(&self, config: &Config, chunk_number: usize, raw: &[u8], key: Option<&str>) -> io::Result<PathBuf>;
+}
+
+// 默认: <output_prefix>.001.zst 起按编号命名
+struct NumberedNames;
+
+impl ChunkNaming for NumberedNames {
+    fn path(&self, config: &Config, chunk_number: usize, _raw: &[u8], _key: Option<&str>) -> io::Result<PathBuf> {
+        Ok(chunk_path(&config.output_prefix, chunk_number, config.format.extension()))
+    }
+}
+
+// --name-by-hash: <output_prefix>.<sha256>.zst, 内容相同的分卷名字相同
+struct HashNames;
+
+impl ChunkNaming for HashNames {
+    fn path(&self, config: &Config, _chunk_number: usize, raw: &[u8], key: Option<&str>) -> io::Result<PathBuf> {
+        let key = key.map_or_else(|| to_hex(&sha256(raw)), str::to_string);
+        Ok(PathBuf::from(format!("{}.{}.{}", config.output_prefix, key, config.format.extension())))
+    }
+}
+
+// --name-template
+struct TemplateNames;
+
+impl ChunkNaming for TemplateNames {
+    fn path(&self, config: &Config, chunk_number: usize, raw: &[u8], _key: Option<&str>) -> io::Result<PathBuf> {
+        Ok(PathBuf::from(sibling(&config.output_prefix, &template_name(raw, config, chunk_number)?)))
+    }
+}
+
+pub(crate) fn chunk_naming(config: &Config) -> Box<dyn ChunkNaming> {
+    if config.name_by_hash {
+        Box::new(HashNames)
+    } else if config.name_template.is_some() {
+        Box::new(TemplateNames)
+    } else {
+        Box::new(NumberedNames)
+    }
+}
+
+// --output-dirs: 第 n 个分卷轮流放到第 (n - 1) % 目录数 个目录, 文件名不变
+pub(crate) fn striped(dirs: &[PathBuf], chunk_number: usize, path: PathBuf) -> PathBuf {
+    match path.file_name() {
+        Some(name) if !dirs.is_empty() => dirs[(chunk_number - 1) % dirs.len()].join(name),
+        _ => path,
+    }
+}
+
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.tmp", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::read_manifest;
+    use crate::merge::{run_join, MergeOptions};
+    use crate::split::run_split;
+    use crate::testing::Scratch;
+
+    #[test]
+    fn regex_fields_name_chunks() {
+        let fields = vec![parse_field(r"date=^(\d{4}-\d{2}-\d{2})").unwrap()];
+        let template = NameTemplate::parse("{prefix}.{field:date}.{n}.{ext}", fields).unwrap();
+        assert_eq!(template.render("out/logs", 7, "zst", "2024-03-01 a/b").unwrap(), "logs.2024-03-01.007.zst");
+        assert!(template.render("out/logs", 8, "zst", "no date").is_err());
+        let fields = vec![parse_field(r"path=^(\S+)").unwrap()];
+        let template = NameTemplate::parse("{field:path}-{n}", fields).unwrap();
+        assert_eq!(template.render("out", 1, "zst", "a/b\u{1}c d").unwrap(), "a_b_c-001");
+        assert!(NameTemplate::parse("{prefix}.{ext}", Vec::new()).is_err());
+        assert!(NameTemplate::parse("{n}.{field:date}", Vec::new()).is_err());
+        assert!(NameTemplate::parse("dir/{n}", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn chunks_are_striped_across_output_dirs() {
+        let dir = Scratch::new("output_dirs");
+        let input = dir.join("in.log");
+        std::fs::write(&input, "a\nb\nc\n").unwrap();
+        let prefix = dir.join("part").display().to_string();
+        let disks = format!("{},{}", dir.join("d1").display(), dir.join("d2").display());
+        let args = ["zstd_compressor", &input.display().to_string(), &prefix, "--lines", "1", "--output-dirs", &disks, "--yes", "--porcelain"];
+        run_split(&Config::parse(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        for (n, disk) in [(1, "d1"), (2, "d2"), (3, "d1")] {
+            assert!(dir.join(disk).join(format!("part.{:03}.zst", n)).exists(), "{}", n);
+        }
+        assert!(!Path::new(&chunk_name(&prefix, 1, "zst")).exists());
+        let entries = read_manifest(&prefix).unwrap().unwrap().entries().unwrap();
+        assert_eq!(Path::new(&entries[1].file), std::fs::canonicalize(dir.join("d2")).unwrap().join("part.002.zst"));
+        let merged = dir.join("merged");
+        run_join(&prefix, &merged.display().to_string(), &MergeOptions::parse(&[]).unwrap()).unwrap();
+        assert_eq!(std::fs::read(&merged).unwrap(), b"a\nb\nc\n");
+        assert!(Config::parse(["zstd_compressor", "in.log", "out/a", "--output-dirs", ",", "--yes"].map(String::from)).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+// 按列的值、键的哈希、轮流或时间窗口把记录分到各自的一组分卷
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+use crate::compress::{compress_chunk, halve_chunk_limit};
+use crate::config::Config;
+use crate::hash::chunk_digest;
+use crate::merkle::{count_lines, split_lines};
+use crate::naming::{chunk_path, temp_path};
+use crate::profiler::Profiler;
+use crate::tier::fnv1a;
+use crate::writer::{gzip_member, ChunkWriter, Output};
+use crate::Format;
+
+// --split-by-time 的时间格式: 从时间戳开头逐项匹配, 匹配到的部分即时间窗口
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TimeFormat {
+    items: Vec<TimeItem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TimeItem {
+    Literal(char),
+    // 固定位数的数字及其取值范围
+    Number { digits: usize, min: u32, max: u32 },
+    MonthName,
+}
+
+impl TimeFormat {
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        let mut items = Vec::new();
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                items.push(TimeItem::Literal(c));
+                continue;
+            }
+            items.push(match chars.next() {
+                Some('Y') => TimeItem::Number { digits: 4, min: 0, max: 9999 },
+                Some('m') => TimeItem::Number { digits: 2, min: 1, max: 12 },
+                Some('d') => TimeItem::Number { digits: 2, min: 1, max: 31 },
+                Some('H') => TimeItem::Number { digits: 2, min: 0, max: 23 },
+                Some('M') => TimeItem::Number { digits: 2, min: 0, max: 59 },
+                Some('S') => TimeItem::Number { digits: 2, min: 0, max: 60 },
+                Some('b') => TimeItem::MonthName,
+                Some('%') => TimeItem::Literal('%'),
+                Some(other) => return Err(format!("无效的时间格式: {}. 不支持 %{}, 请使用 %Y, %m, %d, %H, %M, %S 与 %b", value, other)),
+                None => return Err(format!("无效的时间格式: {}. 末尾的 % 缺少字段", value)),
+            });
+        }
+        if !items.iter().any(|item| !matches!(item, TimeItem::Literal(_))) {
+            return Err(format!("无效的时间格式: {}. 至少需要一个 %Y, %m, %d, %H, %M, %S 或 %b", value));
+        }
+        Ok(TimeFormat { items })
+    }
+
+    // text 开头符合格式时返回匹配部分的长度
+    fn matched(&self, text: &str) -> Option<usize> {
+        const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+        let mut pos = 0;
+        for item in &self.items {
+            let rest = &text[pos..];
+            match *item {
+                TimeItem::Literal(c) => {
+                    if !rest.starts_with(c) {
+                        return None;
+                    }
+                    pos += c.len_utf8();
+                }
+                TimeItem::Number { digits, min, max } => {
+                    let number = rest.get(..digits).filter(|number| number.bytes().all(|b| b.is_ascii_digit()))?;
+                    if !(min..=max).contains(&number.parse().ok()?) {
+                        return None;
+                    }
+                    pos += digits;
+                }
+                TimeItem::MonthName => {
+                    let name = rest.get(..3)?.to_ascii_lowercase();
+                    if !MONTHS.contains(&name.as_str()) {
+                        return None;
+                    }
+                    pos += 3;
+                }
+            }
+        }
+        Some(pos)
+    }
+}
+
+// 同时缓冲的分区数上限, 每个分区最多缓冲一个分块
+pub(crate) const MAX_PARTITIONS: usize = 1024;
+
+// --partition-by: 按某一列的值把记录分到各自的分区; 分区是前缀为 <output_prefix>.<值> 的一组编号分卷,
+// 积累到分块大小时写出一个. 值中不能用于文件名的字符换成 _, 空值与缺少这一列的记录归入 <output_prefix>._;
+// --shard-by-key 时分区为 <output_prefix>.shardNNN, 由这一列的哈希决定; --round-robin 时为 <output_prefix>.rrNNN,
+// 第 i 条记录(从 0 开始)分给第 i % N 个; --split-by-time 时为 <output_prefix>.<时间窗口>, 窗口变化时写出上一个窗口,
+// 同时只缓冲一个窗口
+pub(crate) struct Partitions<'c> {
+    config: &'c Config,
+    line_ending: Vec<u8>,
+    pub(crate) partitions: BTreeMap<String, Partition>,
+    records: usize,
+    // --split-by-time 时上一条记录所在的时间窗口
+    window: Option<String>,
+}
+
+pub(crate) struct Partition {
+    prefix: String,
+    buffer: Vec<u8>,
+    next_number: usize,
+}
+
+impl<'c> Partitions<'c> {
+    pub(crate) fn new(config: &'c Config) -> Self {
+        let line_ending = config.encoding.encode(&config.line_ending).0.into_owned();
+        Partitions { config, line_ending, partitions: BTreeMap::new(), records: 0, window: None }
+    }
+
+    // 把切出的一块中的各条记录放入所属的分区, 分区攒满时写出
+    pub(crate) fn route(&mut self, chunk: &[u8], writer: &mut ChunkWriter, profiler: &Profiler) -> io::Result<()> {
+        let config = self.config;
+        for record in split_lines(chunk, &self.line_ending) {
+            let line = record.strip_suffix(&self.line_ending[..]).unwrap_or(record);
+            let key = match (config.round_robin, &config.split_by_time) {
+                (Some(n), _) => format!("rr{:03}", self.records % n),
+                (None, Some(format)) => {
+                    let key = time_window(line, format, config).or_else(|| self.window.clone()).unwrap_or_else(|| "_".to_string());
+                    if self.window.as_ref().is_some_and(|window| *window != key) {
+                        if let Some(previous) = self.window.take().and_then(|window| self.partitions.get_mut(&window)) {
+                            previous.flush(config, writer, profiler)?;
+                        }
+                    }
+                    self.window = Some(key.clone());
+                    key
+                }
+                (None, None) => partition_key(line, config),
+            };
+            self.records += 1;
+            if !self.partitions.contains_key(&key) {
+                if self.partitions.len() >= MAX_PARTITIONS && config.split_by_time.is_none() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("分区数超过 {}, 第 {} 列可能不适合分区", MAX_PARTITIONS, config.partition_by.unwrap_or_default())));
+                }
+                let prefix = format!("{}.{}", config.output_prefix, key);
+                self.partitions.insert(key.clone(), Partition { prefix, buffer: Vec::new(), next_number: 1 });
+            }
+            let partition = self.partitions.get_mut(&key).unwrap();
+            if !partition.buffer.is_empty() && partition.buffer.len() + record.len() > config.chunk_limit.load(Ordering::Relaxed) {
+                partition.flush(config, writer, profiler)?;
+            }
+            // 内存不足时分块大小减半, 先写出各分区已缓存的数据并释放缓冲区再重试
+            let mut size = config.chunk_limit.load(Ordering::Relaxed);
+            while let Err(error) = self.partitions.get_mut(&key).unwrap().buffer.try_reserve(record.len()) {
+                size = halve_chunk_limit(config, size, &format!("缓存分区 {} 的数据", key), &error)?;
+                for partition in self.partitions.values_mut().filter(|partition| !partition.buffer.is_empty()) {
+                    partition.flush(config, writer, profiler)?;
+                    partition.buffer = Vec::new();
+                }
+            }
+            self.partitions.get_mut(&key).unwrap().buffer.extend_from_slice(record);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(&mut self, writer: &mut ChunkWriter, profiler: &Profiler) -> io::Result<()> {
+        for partition in self.partitions.values_mut().filter(|partition| !partition.buffer.is_empty()) {
+            partition.flush(self.config, writer, profiler)?;
+        }
+        Ok(())
+    }
+}
+
+impl Partition {
+    fn flush(&mut self, config: &Config, writer: &mut ChunkWriter, profiler: &Profiler) -> io::Result<()> {
+        let chunk_number = self.next_number;
+        let compressed = compress_chunk(&self.buffer, config, chunk_number, profiler)?;
+        let _span = profiler.span("write");
+        let path = chunk_path(&self.prefix, chunk_number, config.format.extension());
+        let compressed = if config.format == Format::Gzip { gzip_member(config, Some(&path), &compressed)? } else { compressed };
+        // 各分区的分卷交错写出, 日志按路径区分编号相同的分卷, 崩溃后 --recover 删除其中未完成的
+        let Output::Files { journal, .. } = &mut writer.output else { unreachable!("分区模式只支持逐个分卷输出") };
+        if let Some(journal) = journal.as_mut() {
+            journal.record("begin", chunk_number, &path, None)?;
+        }
+        let temp = temp_path(&path);
+        let mut file = File::create(&temp)?;
+        file.write_all(&compressed)?;
+        if journal.is_some() {
+            file.sync_data()?;
+        }
+        std::fs::rename(&temp, &path)?;
+        if let Some(journal) = journal.as_mut() {
+            journal.record("done", chunk_number, &path, Some(compressed.len()))?;
+        }
+        writer.written(&path, self.buffer.len(), compressed.len(), chunk_digest(&self.buffer, config), count_lines(&self.buffer, config));
+        self.next_number += 1;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+// 记录(不含换行符)所属分区的名字
+fn partition_key(record: &[u8], config: &Config) -> String {
+    let text = config.encoding.decode_without_bom_handling(record).0;
+    let text = text.trim_end_matches('\r');
+    let value = text.split(config.field_delimiter).nth(config.partition_by.unwrap_or(1) - 1).unwrap_or_default();
+    if let Some(shards) = config.shards {
+        return format!("shard{:03}", fnv1a(value.as_bytes()) % shards as u64);
+    }
+    file_name_key(value)
+}
+
+// 记录的时间戳所在的时间窗口, 时间戳不符合格式时为 None
+fn time_window(record: &[u8], format: &TimeFormat, config: &Config) -> Option<String> {
+    let text = config.encoding.decode_without_bom_handling(record).0;
+    let value = text.split(config.field_delimiter).nth(config.time_field - 1)?.trim_start();
+    format.matched(value).map(|len| file_name_key(&value[..len]))
+}
+
+// 分区名中不能用于文件名的字符换成 _, 空值为 _
+fn file_name_key(value: &str) -> String {
+    let key: String = value.chars().map(|c| if c.is_alphanumeric() || "-_.".contains(c) { c } else { '_' }).collect();
+    if key.is_empty() { "_".to_string() } else { key }
+}
+
+#[cfg(test)]
+mod tests {
+use std::path::Path;
+    use super::*;
+    use crate::chunk_name;
+    use crate::config::parse_field_delimiter;
+    use crate::journal::run_recover;
+    use crate::split::run_split;
+    use crate::testing::Scratch;
+    use crate::tier::is_partition_file;
+
+    #[test]
+    fn records_are_partitioned_by_column() {
+        let dir = Scratch::new("partition");
+        let input = dir.join("in.csv");
+        std::fs::write(&input, "1,us-east,a\r\n2,eu,b\r\n3,us-east,c\r\n4,a/b\r\n5\r\n").unwrap();
+        let prefix = dir.join("part").display().to_string();
+        let args = ["zstd_compressor", &input.display().to_string(), &prefix, "--line-ending", "CRLF", "--partition-by", "2", "--yes", "--porcelain"];
+        run_split(&Config::parse(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        let partition = |key: &str| String::from_utf8(zstd::decode_all(File::open(chunk_name(&format!("{}.{}", prefix, key), 1, "zst")).unwrap()).unwrap()).unwrap();
+        assert_eq!(partition("us-east"), "1,us-east,a\r\n3,us-east,c\r\n");
+        assert_eq!(partition("eu"), "2,eu,b\r\n");
+        assert_eq!(partition("a_b"), "4,a/b\r\n");
+        assert_eq!(partition("_"), "5\r\n");
+        assert!(is_partition_file("part.us.east.001.zst", "part"));
+        assert!(!is_partition_file("part.001.zst", "part"));
+        assert_eq!(parse_field_delimiter("tab"), Ok('\t'));
+        assert!(Config::parse(["zstd_compressor", "in.csv", "out/a", "--delimiter", ";"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn records_with_the_same_key_land_in_the_same_shard() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        let dir = Scratch::new("shards");
+        let input = dir.join("in.tsv");
+        let text: String = (0..200).map(|i| format!("{}\tkey{}\n", i, i % 7)).collect();
+        std::fs::write(&input, &text).unwrap();
+        let prefix = dir.join("part").display().to_string();
+        let args = ["zstd_compressor", &input.display().to_string(), &prefix, "--shard-by-key", "2", "--shards", "4", "--delimiter", "tab", "--journal", "--yes", "--porcelain"];
+        run_split(&Config::parse(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        // 各分片的分卷都记入同一个日志; 崩溃时写了一半的分片分卷由 --recover 删除, 其他分片的不动
+        let journal = format!("{}.journal", prefix);
+        let done = std::fs::read_to_string(&journal).unwrap().lines().filter(|line| line.starts_with("done\t1\t")).count();
+        assert!(done > 1);
+        let torn = chunk_name(&format!("{}.shard000", prefix), 2, "zst");
+        std::fs::write(&torn, b"half").unwrap();
+        std::fs::OpenOptions::new().append(true).open(&journal).unwrap().write_all(format!("begin\t2\t{}\t\n", torn).as_bytes()).unwrap();
+        run_recover(&prefix, true).unwrap();
+        assert!(!Path::new(&torn).exists() && Path::new(&chunk_name(&format!("{}.shard000", prefix), 1, "zst")).exists());
+        let mut lines = 0;
+        for shard in 0..4 {
+            let path = chunk_name(&format!("{}.shard{:03}", prefix, shard), 1, "zst");
+            let Ok(file) = File::open(&path) else { continue };
+            let chunk = String::from_utf8(zstd::decode_all(file).unwrap()).unwrap();
+            for line in chunk.lines() {
+                let key = line.split('\t').nth(1).unwrap();
+                assert_eq!(fnv1a(key.as_bytes()) % 4, shard, "{}", line);
+                lines += 1;
+            }
+        }
+        assert_eq!(lines, 200);
+        assert!(Config::parse(["zstd_compressor", "in.csv", "out/a", "--shard-by-key", "2"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn round_robin_deals_records_in_turn() {
+        let dir = Scratch::new("round_robin");
+        let input = dir.join("in.log");
+        std::fs::write(&input, (0..10).map(|i| format!("{}\n", i)).collect::<String>()).unwrap();
+        let prefix = dir.join("part").display().to_string();
+        let args = ["zstd_compressor", &input.display().to_string(), &prefix, "--round-robin", "3", "--yes", "--porcelain"];
+        run_split(&Config::parse(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        let part = |n: usize| String::from_utf8(zstd::decode_all(File::open(chunk_name(&format!("{}.rr{:03}", prefix, n), 1, "zst")).unwrap()).unwrap()).unwrap();
+        assert_eq!([part(0), part(1), part(2)], ["0\n3\n6\n9\n", "1\n4\n7\n", "2\n5\n8\n"]);
+        let error = Config::parse(["zstd_compressor", "in.log", "out/a", "--round-robin", "3", "--partition-by", "1"].map(String::from)).unwrap_err();
+        assert!(error.contains("不能同时使用"), "{}", error);
+    }
+
+    #[test]
+    fn records_roll_over_when_the_time_window_changes() {
+        let dir = Scratch::new("split_by_time");
+        let input = dir.join("in.log");
+        let log = "2024-03-01T13:59:58Z a\n  at b\n2024-03-01T14:00:01Z c\n2024-03-01T13:59:59Z d\n2024-03-01T15:00:00Z e\n";
+        std::fs::write(&input, log).unwrap();
+        let prefix = dir.join("part").display().to_string();
+        let args = ["zstd_compressor", &input.display().to_string(), &prefix, "--split-by-time", "%Y-%m-%dT%H", "--time-field", "1", "--yes", "--porcelain"];
+        run_split(&Config::parse(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        let window = |key: &str, n: usize| String::from_utf8(zstd::decode_all(File::open(chunk_name(&format!("{}.{}", prefix, key), n, "zst")).unwrap()).unwrap()).unwrap();
+        assert_eq!(window("2024-03-01T13", 1), "2024-03-01T13:59:58Z a\n  at b\n");
+        assert_eq!(window("2024-03-01T14", 1), "2024-03-01T14:00:01Z c\n");
+        assert_eq!(window("2024-03-01T13", 2), "2024-03-01T13:59:59Z d\n");
+        assert_eq!(window("2024-03-01T15", 1), "2024-03-01T15:00:00Z e\n");
+
+        let nginx = TimeFormat::parse("[%d/%b/%Y:%H").unwrap();
+        assert_eq!(nginx.matched("[01/Mar/2024:13:45:12"), Some(15));
+        assert_eq!(nginx.matched("[01/Xyz/2024:13:45:12"), None);
+        assert_eq!(TimeFormat::parse("%Y-%m").unwrap().matched("2024-13"), None);
+        assert!(TimeFormat::parse("%Y-%q").is_err());
+        assert!(TimeFormat::parse("date").is_err());
+        assert!(Config::parse(["zstd_compressor", "in.log", "out/a", "--time-field", "2"].map(String::from)).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+// --profile-out: 记录各阶段的耗时与 CPU 时间
+
+use std::io;
+use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 分阶段计时: 阶段可以嵌套, 按 "外层;内层" 的调用栈累计墙钟时间、扣除子阶段后的自身时间与 CPU 时间;
+// 未启用时 span 什么也不做
+pub(crate) struct Profiler {
+    enabled: bool,
+    started: Instant,
+    // 每个线程各自的阶段栈, 流水线中各线程的阶段互不嵌套
+    stacks: Mutex<HashMap<thread::ThreadId, Vec<OpenStage>>>,
+    stages: Mutex<BTreeMap<String, StageStats>>,
+}
+
+// 进行中的阶段
+struct OpenStage {
+    path: String,
+    started: Instant,
+    // 开始时的线程 CPU 时间
+    cpu_start: Option<Duration>,
+    // 已结束的子阶段累计耗时
+    children: Duration,
+}
+
+#[derive(Default)]
+struct StageStats {
+    calls: u64,
+    wall: Duration,
+    self_wall: Duration,
+    cpu: Duration,
+}
+
+// 离开作用域时结束对应的阶段
+pub(crate) struct Span<'a> {
+    profiler: &'a Profiler,
+}
+
+impl Drop for Span<'_> {
+    fn drop(&mut self) {
+        self.profiler.exit();
+    }
+}
+
+// 当前线程的用户态与内核态 CPU 时间, 来自 /proc, 精度为时钟节拍 (10ms)
+fn thread_cpu_time() -> Option<Duration> {
+    let stat = std::fs::read_to_string("/proc/thread-self/stat").ok()?;
+    // 进程名可能含空格, 从最后一个 ')' 之后数字段; utime 与 stime 是第 14、15 个字段
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 2..].split(' ').collect();
+    let ticks = fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?;
+    Some(Duration::from_millis(ticks * 10))
+}
+
+impl Profiler {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Profiler {
+            enabled,
+            started: Instant::now(),
+            stacks: Mutex::new(HashMap::new()),
+            stages: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub(crate) fn span(&self, name: &str) -> Option<Span<'_>> {
+        if !self.enabled {
+            return None;
+        }
+        let mut stacks = self.stacks.lock().unwrap();
+        let stack = stacks.entry(thread::current().id()).or_default();
+        let path = match stack.last() {
+            Some(parent) => format!("{};{}", parent.path, name),
+            None => name.to_string(),
+        };
+        stack.push(OpenStage { path, started: Instant::now(), cpu_start: thread_cpu_time(), children: Duration::ZERO });
+        Some(Span { profiler: self })
+    }
+
+    fn exit(&self) {
+        let mut stacks = self.stacks.lock().unwrap();
+        let Some(stack) = stacks.get_mut(&thread::current().id()) else { return };
+        let Some(stage) = stack.pop() else { return };
+        let wall = stage.started.elapsed();
+        if let Some(parent) = stack.last_mut() {
+            parent.children += wall;
+        }
+        let mut stages = self.stages.lock().unwrap();
+        let stats = stages.entry(stage.path).or_default();
+        stats.calls += 1;
+        stats.wall += wall;
+        stats.self_wall += wall.saturating_sub(stage.children);
+        if let (Some(start), Some(end)) = (stage.cpu_start, thread_cpu_time()) {
+            stats.cpu += end.saturating_sub(start);
+        }
+    }
+
+    pub(crate) fn write(&self, path: &Path) -> io::Result<()> {
+        let stages = self.stages.lock().unwrap();
+        let mut json = format!("{{\n  \"wall_us\": {},\n  \"cpu_resolution_us\": 10000,\n  \"stages\": [\n", self.started.elapsed().as_micros());
+        let rows: Vec<String> = stages
+            .iter()
+            .map(|(stack, stats)| {
+                format!(
+                    "    {{\"stack\": \"{}\", \"calls\": {}, \"wall_us\": {}, \"self_wall_us\": {}, \"cpu_us\": {}}}",
+                    stack,
+                    stats.calls,
+                    stats.wall.as_micros(),
+                    stats.self_wall.as_micros(),
+                    stats.cpu.as_micros()
+                )
+            })
+            .collect();
+        json.push_str(&rows.join(",\n"));
+        // 折叠栈格式: 每行 "调用栈 自身时间(微秒)"
+        json.push_str("\n  ],\n  \"folded\": [\n");
+        let folded: Vec<String> = stages.iter().map(|(stack, stats)| format!("    \"{} {}\"", stack, stats.self_wall.as_micros())).collect();
+        json.push_str(&folded.join(",\n"));
+        json.push_str("\n  ]\n}\n");
+        std::fs::write(path, json)
+    }
+}
@@ -0,0 +1,21 @@
+// 破坏性操作前的确认
+
+use std::io::{self, IsTerminal, Write};
+
+// 破坏性操作前确认: 终端上询问, 非交互运行时必须显式给出 --yes, 避免配置错误的定时任务误删数据
+pub(crate) fn confirm(action: &str, yes: bool) -> io::Result<()> {
+    if yes {
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}; 非交互运行时需要 --yes 确认", action)));
+    }
+    eprint!("{}, 是否继续? [y/N] ", action);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(io::Error::other("操作已取消")),
+    }
+}
@@ -0,0 +1,187 @@
+// query 子命令: 按各分卷集的清单与校验记录回答分卷的来历、每日归档量与未校验的分卷
+
+use std::io;
+use std::collections::BTreeMap;
+use std::time::UNIX_EPOCH;
+use crate::files::json_string;
+use crate::hash::to_hex;
+use crate::manifest::{check_missing_manifest, read_manifest, sibling};
+use crate::merge::verified_chunks;
+
+// UNIX 时间所在的 UTC 日期
+fn utc_date(secs: u64) -> String {
+    let days = secs / 86400 + 719468;
+    let (era, day_of_era) = (days / 146097, days % 146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    format!("{:04}-{:02}-{:02}", era * 400 + year_of_era + u64::from(month <= 2), month, day)
+}
+
+// query 回答的问题
+enum Query {
+    Run(String),
+    BytesPerDay,
+    Unverified,
+}
+
+// 对一组分卷集的 manifest 回答常见问题, 不必手工翻看: 分卷由哪次运行写出, 每天归档的压缩后字节数, 哪些分卷还没有校验通过.
+// 归档日期取分卷文件的修改时间(UTC), 已被 --tier 移走的分卷取 manifest 的修改时间
+pub fn run_query(args: &[String]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let (mut prefixes, mut queries, mut json) = (Vec::new(), Vec::new(), false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--run" => queries.push(Query::Run(args.next().ok_or_else(|| invalid("选项 --run 缺少参数".to_string()))?.clone())),
+            "--bytes-per-day" => queries.push(Query::BytesPerDay),
+            "--unverified" => queries.push(Query::Unverified),
+            "--json" => json = true,
+            flag if flag.starts_with("--") => return Err(invalid(format!("未知选项: {}", flag))),
+            prefix => prefixes.push(prefix),
+        }
+    }
+    let [query] = queries.as_slice() else {
+        return Err(invalid("query 需要 --run <chunk>, --bytes-per-day 与 --unverified 之一".to_string()));
+    };
+    if prefixes.is_empty() {
+        return Err(invalid("query 需要至少一个 <prefix>".to_string()));
+    }
+    let (columns, rows) = answer_query(&prefixes, query)?;
+    print_query(columns, &rows, json);
+    Ok(())
+}
+
+// 查询结果的列: 列名, 是否为数值
+type QueryColumns = &'static [(&'static str, bool)];
+
+// 查询结果的列与各行
+fn answer_query(prefixes: &[&str], query: &Query) -> io::Result<(QueryColumns, Vec<Vec<String>>)> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let mut rows = Vec::new();
+    let mut days: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for &prefix in prefixes {
+        let Some(table) = read_manifest(prefix)? else {
+            check_missing_manifest(prefix)?;
+            return Err(invalid(format!("缺少 {}.manifest", prefix)));
+        };
+        let lineage = |key: &str| table.lineage.iter().find(|(k, _)| k == key).map_or("-".to_string(), |(_, value)| value.clone());
+        let bytes = table.column("bytes");
+        let verified = verified_chunks(prefix)?;
+        let manifest_time = std::fs::metadata(format!("{}.manifest", prefix)).and_then(|metadata| metadata.modified()).ok();
+        for (entry, row) in table.entries()?.into_iter().zip(&table.rows) {
+            let file = sibling(prefix, &entry.file);
+            let name = file.rsplit('/').next().unwrap_or_default().to_string();
+            match query {
+                Query::Run(chunk) if chunk.rsplit('/').next() == Some(name.as_str()) => {
+                    rows.push(vec![prefix.to_string(), entry.chunk.to_string(), name, lineage("run_id"), lineage("host"), lineage("source")]);
+                }
+                Query::BytesPerDay => {
+                    let time = std::fs::metadata(&file).and_then(|metadata| metadata.modified()).ok().or(manifest_time);
+                    let day = time.map_or("-".to_string(), |time| utc_date(time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()));
+                    let size: u64 = bytes.and_then(|i| row.get(i)?.parse().ok()).unwrap_or_default();
+                    let totals = days.entry(day).or_default();
+                    *totals = (totals.0 + 1, totals.1 + size);
+                }
+                // 记录的摘要与 manifest 中的不同时, 分卷已被重新切分覆盖
+                Query::Unverified => {
+                    let current = entry.sha256.map_or("-".to_string(), |digest| to_hex(&digest));
+                    if verified.get(&name) != Some(&current) {
+                        rows.push(vec![prefix.to_string(), entry.chunk.to_string(), name]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let columns: QueryColumns = match query {
+        Query::Run(chunk) if rows.is_empty() => return Err(io::Error::new(io::ErrorKind::NotFound, format!("没有找到分卷 {}", chunk))),
+        Query::Run(_) => &[("prefix", false), ("chunk", true), ("file", false), ("run_id", false), ("host", false), ("source", false)],
+        Query::BytesPerDay => {
+            rows = days.into_iter().map(|(day, (chunks, bytes))| vec![day, chunks.to_string(), bytes.to_string()]).collect();
+            &[("day", false), ("chunks", true), ("bytes", true)]
+        }
+        Query::Unverified => &[("prefix", false), ("chunk", true), ("file", false)],
+    };
+    Ok((columns, rows))
+}
+
+// 查询结果: 对齐的表格, 或每行一个对象的 JSON 数组(数值列不加引号)
+fn print_query(columns: &[(&str, bool)], rows: &[Vec<String>], json: bool) {
+    if json {
+        let objects: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let fields: Vec<String> = columns
+                    .iter()
+                    .zip(row)
+                    .map(|((name, numeric), value)| format!("\"{}\": {}", name, if *numeric { value.clone() } else { json_string(value) }))
+                    .collect();
+                format!("  {{{}}}", fields.join(", "))
+            })
+            .collect();
+        match objects.is_empty() {
+            true => println!("[]"),
+            false => println!("[\n{}\n]", objects.join(",\n")),
+        }
+        return;
+    }
+    let header: Vec<String> = columns.iter().map(|(name, _)| name.to_string()).collect();
+    let widths: Vec<usize> = (0..columns.len()).map(|i| std::iter::once(&header).chain(rows).map(|row| row[i].chars().count()).max().unwrap_or_default()).collect();
+    for row in std::iter::once(&header).chain(rows) {
+        let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{:width$}", cell, width = width)).collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_name;
+    use crate::merge::{run_verify, MergeOptions};
+    use crate::split::run_split;
+    use crate::testing::{split_config, Scratch};
+
+    #[test]
+    fn query_answers_from_manifests_and_verification_records() {
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(951_782_400), "2000-02-29");
+        assert_eq!(utc_date(1_709_251_199), "2024-02-29");
+
+        let dir = Scratch::new("query");
+        let (input, _) = dir.lines("in.log", 3);
+        let (a, b) = (dir.arg("a"), dir.arg("b"));
+        for prefix in [&a, &b] {
+            run_split(&split_config(&[&input, prefix, "--lines", "1"], 0)).unwrap();
+        }
+        let chunk_file = |prefix: &str, n| chunk_name(prefix, n, "zst").rsplit('/').next().unwrap().to_string();
+
+        let (columns, rows) = answer_query(&[&a, &b], &Query::Run(chunk_name(&b, 2, "zst"))).unwrap();
+        assert_eq!(columns[3].0, "run_id");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][..3], [b.clone(), "2".to_string(), chunk_file(&b, 2)]);
+        let run_id = read_manifest(&b).unwrap().unwrap().lineage.into_iter().find(|(key, _)| key == "run_id").unwrap().1;
+        assert_eq!(rows[0][3], run_id);
+        let missing = answer_query(&[&a], &Query::Run("nope.001.zst".to_string())).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+
+        let (_, rows) = answer_query(&[&a, &b], &Query::BytesPerDay).unwrap();
+        let bytes: u64 = (1..=3).map(|n| std::fs::metadata(chunk_name(&a, n, "zst")).unwrap().len()).sum();
+        assert_eq!(rows, [vec![utc_date(UNIX_EPOCH.elapsed().unwrap().as_secs()), "6".to_string(), (bytes * 2).to_string()]]);
+
+        let (_, rows) = answer_query(&[&a, &b], &Query::Unverified).unwrap();
+        assert_eq!(rows.len(), 6);
+        run_verify(&a, &MergeOptions::default()).unwrap();
+        let (_, rows) = answer_query(&[&a, &b], &Query::Unverified).unwrap();
+        assert_eq!(rows.iter().map(|row| row[2].clone()).collect::<Vec<_>>(), (1..=3).map(|n| chunk_file(&b, n)).collect::<Vec<_>>());
+
+        // 重新切分后记录的摘要不再相符
+        std::fs::write(dir.join("in.log"), "other\n").unwrap();
+        run_split(&split_config(&[&input, &a, "--lines", "1"], 0)).unwrap();
+        let (_, rows) = answer_query(&[&a], &Query::Unverified).unwrap();
+        assert_eq!(rows, [vec![a.clone(), "1".to_string(), chunk_file(&a, 1)]]);
+    }
+}
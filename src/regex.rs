@@ -0,0 +1,302 @@
+// 字段提取用的小型正则: 回溯匹配, 支持字面量, ., [...], \d \w \s 及其大写取反, * + ? {m} {m,} {m,n}
+// (后缀 ? 为非贪婪), 分组 (...) 与 (?:...), | 以及 ^ $
+#[derive(Debug)]
+pub struct Regex {
+    node: RegexNode,
+    groups: usize,
+}
+
+#[derive(Debug)]
+enum RegexNode {
+    Char(char),
+    Any,
+    // 字符区间, 是否取反
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    Group(Box<RegexNode>, Option<usize>),
+    Concat(Vec<RegexNode>),
+    Alternate(Vec<RegexNode>),
+    // 最少次数, 最多次数, 是否贪婪
+    Repeat(Box<RegexNode>, usize, Option<usize>, bool),
+}
+
+type Captures = Vec<Option<(usize, usize)>>;
+
+struct RegexParser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+const DIGIT_CLASS: &[(char, char)] = &[('0', '9')];
+const WORD_CLASS: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('a', 'z'), ('_', '_')];
+const SPACE_CLASS: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+impl Regex {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let mut parser = RegexParser { chars: pattern.chars().collect(), pos: 0, groups: 0 };
+        let node = parser.alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(format!("第 {} 个字符处多余的 )", parser.pos + 1));
+        }
+        Ok(Regex { node, groups: parser.groups })
+    }
+
+    // 文本中是否有任何位置匹配
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        (0..=chars.len()).any(|start| match_regex(&self.node, &chars, start, &mut vec![None; self.groups + 1], &mut |_, _| true))
+    }
+
+    // 返回最左匹配中第一个分组的内容, 没有分组时为整个匹配
+    pub fn extract(&self, text: &str) -> Option<String> {
+        let chars: Vec<char> = text.chars().collect();
+        for start in 0..=chars.len() {
+            let mut captures = vec![None; self.groups + 1];
+            let mut end = start;
+            if match_regex(&self.node, &chars, start, &mut captures, &mut |pos, _| {
+                end = pos;
+                true
+            }) {
+                let (from, to) = if self.groups > 0 { captures[1]? } else { (start, end) };
+                return Some(chars[from..to].iter().collect());
+            }
+        }
+        None
+    }
+}
+
+impl RegexParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let matched = self.peek() == Some(c);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = self.peek().ok_or("正则意外结束")?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn alternation(&mut self) -> Result<RegexNode, String> {
+        let mut branches = vec![self.concat()?];
+        while self.eat('|') {
+            branches.push(self.concat()?);
+        }
+        Ok(if branches.len() == 1 { branches.pop().unwrap() } else { RegexNode::Alternate(branches) })
+    }
+
+    fn concat(&mut self) -> Result<RegexNode, String> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            items.push(self.quantifier(atom)?);
+        }
+        Ok(RegexNode::Concat(items))
+    }
+
+    fn atom(&mut self) -> Result<RegexNode, String> {
+        Ok(match self.next()? {
+            '.' => RegexNode::Any,
+            '^' => RegexNode::Start,
+            '$' => RegexNode::End,
+            '(' => {
+                let index = if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let inner = self.alternation()?;
+                if !self.eat(')') {
+                    return Err("缺少 )".to_string());
+                }
+                RegexNode::Group(Box::new(inner), index)
+            }
+            '[' => self.class()?,
+            '\\' => match self.escape()? {
+                Ok(c) => RegexNode::Char(c),
+                Err((ranges, negated)) => RegexNode::Class(ranges.to_vec(), negated),
+            },
+            c @ ('*' | '+' | '?' | '{') => return Err(format!("第 {} 个字符处的 {} 前面没有可重复的内容", self.pos, c)),
+            c => RegexNode::Char(c),
+        })
+    }
+
+    // 转义: 单个字符, 或 \d \w \s 这样的字符类(区间, 是否取反)
+    #[allow(clippy::type_complexity)]
+    fn escape(&mut self) -> Result<Result<char, (&'static [(char, char)], bool)>, String> {
+        Ok(match self.next()? {
+            'd' => Err((DIGIT_CLASS, false)),
+            'D' => Err((DIGIT_CLASS, true)),
+            'w' => Err((WORD_CLASS, false)),
+            'W' => Err((WORD_CLASS, true)),
+            's' => Err((SPACE_CLASS, false)),
+            'S' => Err((SPACE_CLASS, true)),
+            't' => Ok('\t'),
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            c => Ok(c),
+        })
+    }
+
+    fn class(&mut self) -> Result<RegexNode, String> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.next().map_err(|_| "缺少 ]".to_string())?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let low = if c == '\\' {
+                match self.escape()? {
+                    Ok(c) => c,
+                    Err((_, true)) => return Err("[...] 中不支持 \\D \\W \\S".to_string()),
+                    Err((class, false)) => {
+                        ranges.extend_from_slice(class);
+                        continue;
+                    }
+                }
+            } else {
+                c
+            };
+            let high = if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                match self.next()? {
+                    '\\' => self.escape()?.map_err(|_| "字符区间的端点不能是字符类".to_string())?,
+                    c => c,
+                }
+            } else {
+                low
+            };
+            if high < low {
+                return Err(format!("无效的字符区间 {}-{}", low, high));
+            }
+            ranges.push((low, high));
+        }
+        Ok(RegexNode::Class(ranges, negated))
+    }
+
+    fn quantifier(&mut self, atom: RegexNode) -> Result<RegexNode, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let end = self.chars[self.pos..].iter().position(|&c| c == '}').ok_or("{ 没有闭合")? + self.pos;
+                let spec: String = self.chars[self.pos + 1..end].iter().collect();
+                let invalid = || format!("无效的重复次数: {{{}}}", spec);
+                let number = |s: &str| s.trim().parse::<usize>().map_err(|_| invalid());
+                let (min, max) = match spec.split_once(',') {
+                    None => (number(&spec)?, Some(number(&spec)?)),
+                    Some((min, "")) => (number(min)?, None),
+                    Some((min, max)) => (number(min)?, Some(number(max)?)),
+                };
+                if max.is_some_and(|max| max < min) {
+                    return Err(invalid());
+                }
+                self.pos = end;
+                (min, max)
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        let greedy = !self.eat('?');
+        Ok(RegexNode::Repeat(Box::new(atom), min, max, greedy))
+    }
+}
+
+// 回溯匹配: node 在 pos 处匹配成功后调用 next 匹配余下部分, next 失败时尝试 node 的其他匹配方式
+fn match_regex(node: &RegexNode, text: &[char], pos: usize, captures: &mut Captures, next: &mut dyn FnMut(usize, &mut Captures) -> bool) -> bool {
+    match node {
+        RegexNode::Char(c) => text.get(pos) == Some(c) && next(pos + 1, captures),
+        RegexNode::Any => text.get(pos).is_some_and(|&c| c != '\n') && next(pos + 1, captures),
+        RegexNode::Class(ranges, negated) => {
+            text.get(pos).is_some_and(|&c| ranges.iter().any(|&(low, high)| low <= c && c <= high) != *negated) && next(pos + 1, captures)
+        }
+        RegexNode::Start => pos == 0 && next(pos, captures),
+        RegexNode::End => pos == text.len() && next(pos, captures),
+        RegexNode::Group(inner, None) => match_regex(inner, text, pos, captures, next),
+        RegexNode::Group(inner, Some(index)) => match_regex(inner, text, pos, captures, &mut |end, captures| {
+            let saved = captures[*index].replace((pos, end));
+            next(end, captures) || {
+                captures[*index] = saved;
+                false
+            }
+        }),
+        RegexNode::Concat(items) => match_sequence(items, text, pos, captures, next),
+        RegexNode::Alternate(branches) => branches.iter().any(|branch| match_regex(branch, text, pos, captures, next)),
+        RegexNode::Repeat(inner, min, max, greedy) => match_repeat(inner, (*min, *max, *greedy), 0, text, pos, captures, next),
+    }
+}
+
+fn match_sequence(items: &[RegexNode], text: &[char], pos: usize, captures: &mut Captures, next: &mut dyn FnMut(usize, &mut Captures) -> bool) -> bool {
+    match items.split_first() {
+        None => next(pos, captures),
+        Some((first, rest)) => match_regex(first, text, pos, captures, &mut |pos, captures| match_sequence(rest, text, pos, captures, next)),
+    }
+}
+
+fn match_repeat(
+    inner: &RegexNode,
+    (min, max, greedy): (usize, Option<usize>, bool),
+    count: usize,
+    text: &[char],
+    pos: usize,
+    captures: &mut Captures,
+    next: &mut dyn FnMut(usize, &mut Captures) -> bool,
+) -> bool {
+    let can_stop = count >= min;
+    let can_repeat = max.is_none_or(|max| count < max);
+    // 凑够最少次数后不再接受空匹配, 否则 (a*)* 这样的模式会无限递归
+    let again = |captures: &mut Captures, next: &mut dyn FnMut(usize, &mut Captures) -> bool| {
+        can_repeat
+            && match_regex(inner, text, pos, captures, &mut |end, captures| {
+                (end > pos || count < min) && match_repeat(inner, (min, max, greedy), count + 1, text, end, captures, next)
+            })
+    };
+    // 贪婪时先尝试多重复一次, 非贪婪时先尝试就此停下
+    if !greedy && can_stop && next(pos, captures) {
+        return true;
+    }
+    again(captures, next) || (greedy && can_stop && next(pos, captures))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_first_group_of_leftmost_match() {
+        let extract = |pattern: &str, text: &str| Regex::parse(pattern).unwrap().extract(text);
+        assert_eq!(extract(r"^(\d{4}-\d{2}-\d{2})", "2024-03-01 12:00 start").as_deref(), Some("2024-03-01"));
+        assert_eq!(extract(r"\d+", "id=42;x").as_deref(), Some("42"));
+        assert_eq!(extract(r"level=(\w+)", "ts level=WARN msg").as_deref(), Some("WARN"));
+        assert_eq!(extract(r"<(.+?)>", "<a><b>").as_deref(), Some("a"));
+        assert_eq!(extract(r"<(.+)>", "<a><b>").as_deref(), Some("a><b"));
+        assert_eq!(extract(r"(?:GET|POST) (/[^ ?]*)", "POST /api/v1?q=1 HTTP").as_deref(), Some("/api/v1"));
+        assert_eq!(extract(r"^x(a*)*y$", "xaaay").as_deref(), Some("aaa"));
+        assert_eq!(extract(r"[a-c]{2,3}", "zzabcab").as_deref(), Some("abc"));
+        assert_eq!(extract(r"^\d", "x1"), None);
+        assert!(Regex::parse("(a").is_err());
+        assert!(Regex::parse("a)").is_err());
+        assert!(Regex::parse("*a").is_err());
+        assert!(Regex::parse("a{3,1}").is_err());
+        assert!(Regex::parse(r"^\d{4}-").unwrap().is_match("2024-03-01 start"));
+        assert!(!Regex::parse(r"^\d{4}-").unwrap().is_match("  at foo(Bar.java:12)"));
+    }
+}
@@ -0,0 +1,236 @@
+// 把分卷写成单个 7z 归档: LZMA2 由系统中的 xz 编码, 或使用 zstd 编码
+
+use std::fs::File;
+use std::io::{self, Write, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use crate::codec::crc32;
+use crate::config::SevenZipCodec;
+use crate::ZstdParams;
+
+const SEVEN_ZIP_SIGNATURE: [u8; 6] = [b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C];
+
+const LZMA2_DICT_PROP: u8 = 22; // 字典大小 (2 | 22 & 1) << (22 / 2 + 11) = 8MiB, 与 xz 的参数一致
+
+// 7-Zip-zstd 为 zstd 分配的编码器 ID, NanaZip 与 libarchive 沿用
+const SEVEN_ZIP_ZSTD_ID: [u8; 4] = [0x04, 0xF7, 0x11, 0x01];
+
+struct SevenZipEntry {
+    name: String,
+    packed_size: u64,
+    size: u64,
+    crc: u32,
+}
+
+// 7z 归档: 32 字节签名头之后依次追加各分卷的压缩流, 每个分卷是一个独立的 folder,
+// 全部写完后在末尾写入头部, 再回填签名头中的头部位置与校验
+pub(crate) struct SevenZipWriter {
+    pub(crate) path: PathBuf,
+    file: File,
+    // 归档内文件名的前缀
+    stem: String,
+    // 头部中每个 folder 的编码器描述
+    coder: Vec<u8>,
+    entries: Vec<SevenZipEntry>,
+}
+
+impl SevenZipWriter {
+    pub(crate) fn create(output_prefix: &str, codec: SevenZipCodec, zstd: ZstdParams) -> io::Result<Self> {
+        let path = PathBuf::from(format!("{}.7z", output_prefix));
+        let mut file = File::create(&path)?;
+        file.write_all(&[0; 32])?;
+        let stem = Path::new(output_prefix)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "chunk".to_string());
+        let coder = match codec {
+            // 单个编码器: ID 长度 1 且带属性, ID 0x21 即 LZMA2, 属性为字典大小
+            SevenZipCodec::Lzma2 => vec![0x01, 0x21, 0x21, 0x01, LZMA2_DICT_PROP],
+            // ID 长度 4 且带属性; 属性为 libzstd 的主次版本号、压缩级别与两个保留字节, 解压时不用
+            SevenZipCodec::Zstd => {
+                let version = zstd::zstd_safe::version_number();
+                let level = zstd.level().clamp(0, 22) as u8;
+                let mut coder = vec![0x01, 0x24];
+                coder.extend_from_slice(&SEVEN_ZIP_ZSTD_ID);
+                coder.extend_from_slice(&[5, (version / 10000) as u8, (version / 100 % 100) as u8, level, 0, 0]);
+                coder
+            }
+        };
+        Ok(SevenZipWriter { path, file, stem, coder, entries: Vec::new() })
+    }
+
+    pub(crate) fn add(&mut self, compressed: &[u8], raw: &[u8], chunk_number: usize) -> io::Result<()> {
+        self.file.write_all(compressed)?;
+        self.entries.push(SevenZipEntry {
+            name: format!("{}.{:03}", self.stem, chunk_number),
+            packed_size: compressed.len() as u64,
+            size: raw.len() as u64,
+            crc: crc32(raw),
+        });
+        Ok(())
+    }
+
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        let packed_total: u64 = self.entries.iter().map(|e| e.packed_size).sum();
+        // 没有分卷时头部大小记为 0, 表示空归档
+        let header = if self.entries.is_empty() { Vec::new() } else { seven_zip_header(&self.entries, &self.coder) };
+        self.file.write_all(&header)?;
+
+        let mut start_header = Vec::with_capacity(20);
+        start_header.extend_from_slice(&packed_total.to_le_bytes());
+        start_header.extend_from_slice(&(header.len() as u64).to_le_bytes());
+        start_header.extend_from_slice(&(if header.is_empty() { 0 } else { crc32(&header) }).to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&SEVEN_ZIP_SIGNATURE)?;
+        self.file.write_all(&[0, 4])?;
+        self.file.write_all(&crc32(&start_header).to_le_bytes())?;
+        self.file.write_all(&start_header)?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+// 7z 的变长整数: 首字节高位连续 1 的个数表示后续字节数
+fn write_7z_number(out: &mut Vec<u8>, mut value: u64) {
+    let mut first = 0u8;
+    let mut mask = 0x80u8;
+    let mut extra = 0;
+    while extra < 8 {
+        if value < 1 << (7 * (extra + 1)) {
+            first |= (value >> (8 * extra)) as u8;
+            break;
+        }
+        first |= mask;
+        mask >>= 1;
+        extra += 1;
+    }
+    out.push(first);
+    for _ in 0..extra {
+        out.push(value as u8);
+        value >>= 8;
+    }
+}
+
+// 未压缩的 7z 头部: 各压缩流的大小、每个 folder 的编码器与原始大小、CRC, 以及文件名
+fn seven_zip_header(entries: &[SevenZipEntry], coder: &[u8]) -> Vec<u8> {
+    const END: u8 = 0x00;
+    let count = entries.len() as u64;
+    let mut h = vec![0x01, 0x04]; // Header, MainStreamsInfo
+
+    h.push(0x06); // PackInfo
+    write_7z_number(&mut h, 0);
+    write_7z_number(&mut h, count);
+    h.push(0x09); // Size
+    for entry in entries {
+        write_7z_number(&mut h, entry.packed_size);
+    }
+    h.push(END);
+
+    h.push(0x07); // UnPackInfo
+    h.push(0x0B); // Folder
+    write_7z_number(&mut h, count);
+    h.push(0); // 不使用外部数据
+    for _ in entries {
+        h.extend_from_slice(coder);
+    }
+    h.push(0x0C); // CodersUnPackSize
+    for entry in entries {
+        write_7z_number(&mut h, entry.size);
+    }
+    h.push(END);
+
+    h.push(0x08); // SubStreamsInfo, 每个 folder 一个文件
+    h.push(0x0A); // CRC
+    h.push(1); // 全部都有
+    for entry in entries {
+        h.extend_from_slice(&entry.crc.to_le_bytes());
+    }
+    h.push(END);
+    h.push(END); // MainStreamsInfo 结束
+
+    h.push(0x05); // FilesInfo
+    write_7z_number(&mut h, count);
+    let names: Vec<u8> = entries
+        .iter()
+        .flat_map(|e| e.name.encode_utf16().chain([0]))
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    h.push(0x11); // Name
+    write_7z_number(&mut h, names.len() as u64 + 1);
+    h.push(0); // 不使用外部数据
+    h.extend(names);
+    h.push(END);
+    h.push(END); // Header 结束
+    h
+}
+
+#[cfg(test)]
+mod tests {
+use std::process::{Command, Stdio};
+    use super::*;
+    use crate::codec::crc32;
+    use crate::codec::find_tool;
+    use crate::split::run_split;
+    use crate::testing::{split_config, Scratch};
+
+    #[test]
+    fn seven_zip_numbers_and_crc() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let encode = |value| {
+            let mut out = Vec::new();
+            write_7z_number(&mut out, value);
+            out
+        };
+        assert_eq!(encode(0x7F), [0x7F]);
+        assert_eq!(encode(0x80), [0x80, 0x80]);
+        assert_eq!(encode(0x3FFF), [0xBF, 0xFF]);
+        assert_eq!(encode(0x4000), [0xC0, 0x00, 0x40]);
+        assert_eq!(encode(0x12_3456), [0xC0 | 0x12, 0x56, 0x34]);
+        assert_eq!(encode(u64::MAX), [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn seven_zip_archives_extract_with_a_real_reader() {
+        let dir = Scratch::new("seven_zip");
+        let (input, data) = dir.lines("in.log", 60_000);
+        // 7-Zip 与 bsdtar(libarchive) 都不是本程序写的读取端; 都没有时只检查签名头
+        let reader = ["7z", "7za", "7zz", "bsdtar"].into_iter().find(|name| find_tool(name).is_some());
+        for codec in ["lzma2", "zstd"] {
+            if codec == "lzma2" && find_tool("xz").is_none() {
+                continue;
+            }
+            let prefix = dir.arg(codec);
+            run_split(&split_config(&[&input, &prefix, "--format", "7z", "--7z-codec", codec], 100 * 1024)).unwrap();
+
+            let archive = std::fs::read(format!("{}.7z", prefix)).unwrap();
+            assert_eq!(archive[..6], SEVEN_ZIP_SIGNATURE);
+            assert_eq!(u32::from_le_bytes(archive[8..12].try_into().unwrap()), crc32(&archive[12..32]));
+            let offset = 32 + u64::from_le_bytes(archive[12..20].try_into().unwrap()) as usize;
+            let size = u64::from_le_bytes(archive[20..28].try_into().unwrap()) as usize;
+            assert_eq!(offset + size, archive.len());
+            assert_eq!(u32::from_le_bytes(archive[28..32].try_into().unwrap()), crc32(&archive[offset..]));
+
+            let Some(reader) = reader else {
+                eprintln!("跳过: 没有找到 7z 或 bsdtar");
+                continue;
+            };
+            let out = dir.join(format!("{}.x", codec));
+            std::fs::create_dir_all(&out).unwrap();
+            let mut command = Command::new(reader);
+            match reader {
+                "bsdtar" => command.arg("-xf").arg(format!("{}.7z", prefix)).arg("-C").arg(&out),
+                // 没有 zstd 的 7-Zip 不能解压 zstd 归档
+                _ if codec == "zstd" => continue,
+                _ => command.arg("x").arg(format!("-o{}", out.display())).arg(format!("{}.7z", prefix)),
+            };
+            assert!(command.stdout(Stdio::null()).status().unwrap().success(), "{} 解压 {} 归档失败", reader, codec);
+            let mut names: Vec<_> = std::fs::read_dir(&out).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+            names.sort();
+            assert!(names.len() > 2, "{:?}", names);
+            assert_eq!(names[0], format!("{}.001", codec));
+            let extracted: Vec<u8> = names.iter().flat_map(|name| std::fs::read(out.join(name)).unwrap()).collect();
+            assert!(extracted == data.as_bytes(), "{} 归档解压后与输入不同", codec);
+        }
+    }
+}
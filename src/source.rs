@@ -0,0 +1,584 @@
+// 输入来源与镜像目的地: 本地文件、标准输入、https://, sftp:// 与 s3:// 地址, 以及读取端的
+// 缓冲区自动调节、摘要计算、按行截取与 gzip 自动解压
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, Write, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::boundary::{self, Boundary};
+use crate::codec::{run_tool, GzipReader, ToolReader};
+use crate::config::Config;
+use crate::hash::Sha256;
+use crate::split::apply_thread_nice;
+use crate::{handoff, MemberEnds};
+
+pub(crate) const MIN_BUFFER_SIZE: usize = 1024 * 1024; // 自动调节的起始/下限 1MB
+
+const MAX_BUFFER_SIZE: usize = 64 * 1024 * 1024; // 自动调节的上限 64MB
+
+const BUFFER_TUNE_WINDOW: Duration = Duration::from_secs(2); // 自动调节的观测时长
+
+// 分卷的镜像目标: 本地目录, 或经 aws 命令行工具上传的 s3:// 前缀
+#[derive(Debug)]
+pub(crate) enum Destination {
+    Dir(PathBuf),
+    S3(String),
+    // 接收方监听的 Unix 套接字, 分卷经 memfd 交出, 见 handoff
+    Unix(PathBuf),
+}
+
+impl Destination {
+    pub(crate) fn parse(value: &str) -> Result<Self, String> {
+        if value.starts_with("s3://") {
+            Ok(Destination::S3(value.trim_end_matches('/').to_string()))
+        } else if let Some(socket) = value.strip_prefix("unix:") {
+            match socket {
+                "" => Err(format!("输出目标 {} 缺少套接字路径", value)),
+                socket => Ok(Destination::Unix(PathBuf::from(socket))),
+            }
+        } else if value.contains("://") {
+            Err(format!("不支持的输出目标: {}", value))
+        } else {
+            Ok(Destination::Dir(PathBuf::from(value)))
+        }
+    }
+
+    // 以 name 为文件名写入目标, 返回检查点时需要同步的本地文件; S3 对象带上运行 ID 作为元数据
+    pub(crate) fn put(&self, name: &str, data: &[u8], run_id: Option<&str>) -> io::Result<Option<PathBuf>> {
+        match self {
+            Destination::Dir(dir) => {
+                std::fs::create_dir_all(dir)?;
+                let path = dir.join(name);
+                File::create(&path)?.write_all(data)?;
+                Ok(Some(path))
+            }
+            Destination::S3(prefix) => {
+                let target = format!("{}/{}", prefix, name);
+                let mut args = vec!["s3", "cp", "--only-show-errors"];
+                let metadata = run_id.map(|run_id| format!("run-id={}", run_id));
+                if let Some(metadata) = &metadata {
+                    args.extend(["--metadata", metadata]);
+                }
+                args.extend(["-", &target]);
+                run_tool("aws", &args, data)?;
+                Ok(None)
+            }
+            Destination::Unix(socket) => {
+                handoff::send(socket, name, data)?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Destination {
+    // 把本地文件移到目标中, 返回新位置
+    pub(crate) fn move_file(&self, path: &Path) -> io::Result<String> {
+        let name = path.file_name().unwrap().to_string_lossy();
+        if let Destination::Dir(dir) = self {
+            std::fs::create_dir_all(dir)?;
+            let target = dir.join(&*name);
+            // 跨文件系统时 rename 会失败, 退回复制后删除
+            if std::fs::rename(path, &target).is_err() {
+                std::fs::copy(path, &target)?;
+                std::fs::remove_file(path)?;
+            }
+            // 记录绝对路径, 之后合并时不受当前目录影响
+            return Ok(std::fs::canonicalize(&target)?.display().to_string());
+        }
+        self.put(&name, &std::fs::read(path)?, None)?;
+        std::fs::remove_file(path)?;
+        Ok(format!("{}/{}", self, name))
+    }
+}
+
+impl std::fmt::Display for Destination {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Destination::Dir(dir) => write!(f, "{}", dir.display()),
+            Destination::S3(prefix) => write!(f, "{}", prefix),
+            Destination::Unix(socket) => write!(f, "unix:{}", socket.display()),
+        }
+    }
+}
+
+// 块设备与字符设备(磁盘、磁带等)没有大小信息, 也不一定能定位
+pub(crate) fn is_device(path: &str) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_block_device() || metadata.file_type().is_char_device())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+// 根据最初几秒的实测吞吐调节读取缓冲区大小: 吞吐仍明显提升就翻倍, 否则固定下来
+struct BufferTuner {
+    size: usize,
+    tuning: bool,
+    started: Instant,
+    best_size: usize,
+    best_throughput: f64,
+    quiet: bool,
+}
+
+impl BufferTuner {
+    fn new(config: &Config) -> Self {
+        let size = config.buffer_size.unwrap_or(MIN_BUFFER_SIZE);
+        BufferTuner {
+            size,
+            tuning: config.buffer_size.is_none(),
+            started: Instant::now(),
+            best_size: size,
+            best_throughput: 0.0,
+            quiet: config.porcelain,
+        }
+    }
+
+    fn record(&mut self, bytes: usize, elapsed: Duration) {
+        // 不足一个缓冲区说明到了文件末尾, 不代表存储的吞吐
+        if !self.tuning || bytes < self.size {
+            return;
+        }
+
+        let throughput = bytes as f64 / elapsed.as_secs_f64().max(1e-9);
+        if throughput > self.best_throughput * 1.1 {
+            self.best_throughput = throughput;
+            self.best_size = self.size;
+            self.size = (self.size * 2).min(MAX_BUFFER_SIZE);
+        } else {
+            self.size = self.best_size;
+        }
+
+        if self.size == self.best_size || self.started.elapsed() >= BUFFER_TUNE_WINDOW {
+            self.size = self.best_size;
+            self.tuning = false;
+            if !self.quiet {
+                println!("读取缓冲区自动调节为 {} MB", self.size / 1024 / 1024);
+            }
+        }
+    }
+}
+
+// curl 从 offset 处读取. 忽略 Range 的 HTTP 服务器会以 200 返回整个对象, 从中间开始的读取就成了从头读取:
+// 让 curl 把响应头也写到标准输出, 确认状态码是 206 后再交出响应体. sftp 的范围读取由 curl 自己定位, 不用检查
+fn curl_from(url: &str, offset: u64) -> io::Result<Box<dyn Read + Send>> {
+    let range = format!("{}-", offset);
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Ok(Box::new(ToolReader::spawn("curl", &["-fsS", "-r", &range, url])?));
+    }
+    let mut reader = io::BufReader::new(ToolReader::spawn("curl", &["-fsS", "-D", "-", "-r", &range, url])?);
+    loop {
+        let mut status = String::new();
+        reader.read_line(&mut status)?;
+        let code = status.split_whitespace().nth(1).unwrap_or("").to_string();
+        // 跳过这一组响应头; 100 Continue 之类的临时响应之后还有最终响应
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 && !line.trim_end().is_empty() {
+            line.clear();
+        }
+        match code.as_str() {
+            "206" => return Ok(Box::new(reader)),
+            code if code.starts_with('1') => continue,
+            code => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("{} 不支持范围请求(HTTP {}), 无法从偏移 {} 开始读取; 请先下载到本地再使用 --skip-bytes 或 --resume", url, code, offset),
+                ))
+            }
+        }
+    }
+}
+
+// 带吞吐测量的读取端
+pub(crate) struct Reader {
+    file: Box<dyn Read + Send>,
+    tuner: BufferTuner,
+}
+
+impl Reader {
+    fn read_buffer(&mut self, buffer: &mut Vec<u8>) -> io::Result<usize> {
+        let started = Instant::now();
+        let n = Read::by_ref(&mut self.file).take(self.tuner.size as u64).read_to_end(buffer)?;
+        self.tuner.record(n, started.elapsed());
+        Ok(n)
+    }
+}
+
+// 数据来源: 本地文件, 或经 curl / aws 命令行工具以流的方式读取的远程对象,
+// 切分的输入与合并时的分卷都通过它读取
+#[derive(Debug, PartialEq)]
+pub(crate) enum Source {
+    Local(PathBuf),
+    // https:// 与 sftp:// 都交给 curl
+    Curl(String),
+    S3(String),
+}
+
+impl Source {
+    pub(crate) fn parse(location: &str) -> Self {
+        if location.starts_with("s3://") {
+            Source::S3(location.to_string())
+        } else if ["http://", "https://", "sftp://"].iter().any(|scheme| location.starts_with(scheme)) {
+            Source::Curl(location.to_string())
+        } else {
+            Source::Local(PathBuf::from(location))
+        }
+    }
+
+    pub(crate) fn open(&self) -> io::Result<Box<dyn Read + Send>> {
+        match self {
+            Source::Local(path) => Ok(Box::new(File::open(path)?)),
+            Source::Curl(url) => Ok(Box::new(ToolReader::spawn("curl", &["-fsS", url])?)),
+            Source::S3(url) => Ok(Box::new(ToolReader::spawn("aws", &["s3", "cp", "--only-show-errors", url, "-"])?)),
+        }
+    }
+
+    // 从 offset 处开始读取: 本地文件定位, curl 用范围请求, S3 读过并丢弃之前的数据
+    fn open_at(&self, offset: u64) -> io::Result<Box<dyn Read + Send>> {
+        match self {
+            _ if offset == 0 => self.open(),
+            Source::Local(path) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                Ok(Box::new(file))
+            }
+            Source::Curl(url) => curl_from(url, offset),
+            Source::S3(_) => {
+                let mut source = self.open()?;
+                io::copy(&mut Read::by_ref(&mut source).take(offset), &mut io::sink())?;
+                Ok(source)
+            }
+        }
+    }
+
+    // 只读取 [offset, offset + len) 这一段: 本地文件定位后读取, 远程对象用范围请求;
+    // 忽略范围请求的服务器返回整个对象, 由长度不符发现
+    pub(crate) fn read_range(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(len as usize);
+        if len == 0 {
+            return Ok(data);
+        }
+        let last = offset + len - 1;
+        match self {
+            Source::Local(path) => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                file.take(len).read_to_end(&mut data)?;
+            }
+            Source::Curl(url) => {
+                ToolReader::spawn("curl", &["-fsS", "-r", &format!("{}-{}", offset, last), url])?.read_to_end(&mut data)?;
+            }
+            Source::S3(url) => {
+                // s3api 会把元数据打印到标准输出, 数据先写入临时文件
+                let (bucket, key) = url["s3://".len()..].split_once('/').unwrap_or((&url["s3://".len()..], ""));
+                let tmp = env::temp_dir().join(format!("zstd_compressor.{}.{}", std::process::id(), offset));
+                let range = format!("bytes={}-{}", offset, last);
+                let status = Command::new("aws")
+                    .args(["s3api", "get-object", "--bucket", bucket, "--key", key, "--range", &range])
+                    .arg(&tmp)
+                    .stdout(Stdio::null())
+                    .status()?;
+                let result = if status.success() { std::fs::read(&tmp) } else { Err(io::Error::other(format!("aws 执行失败: {}", status))) };
+                let _ = std::fs::remove_file(&tmp);
+                data = result?;
+            }
+        }
+        if data.len() as u64 > len {
+            return Err(io::Error::other("远程服务器不支持范围请求"));
+        }
+        if data.len() as u64 != len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("范围读取只得到 {} 字节, 预期 {} 字节", data.len(), len)));
+        }
+        Ok(data)
+    }
+
+    pub(crate) fn exists(&self) -> io::Result<bool> {
+        match self {
+            Source::Local(path) => Ok(path.exists()),
+            // 只取第一个字节来探测
+            Source::Curl(url) => Ok(Command::new("curl")
+                .args(["-fsS", "-r", "0-0", "-o", "/dev/null", url])
+                .stderr(Stdio::null())
+                .status()?
+                .success()),
+            Source::S3(url) => {
+                let output = Command::new("aws").args(["s3", "ls", url]).stderr(Stdio::null()).output()?;
+                Ok(output.status.success() && !output.stdout.is_empty())
+            }
+        }
+    }
+}
+
+// 把读过的原始字节同时计入 SHA-256; 摘要放在共享状态里, 预读线程持有读取端时也能取回
+struct HashingReader<R: Read> {
+    inner: R,
+    hasher: Arc<Mutex<Sha256>>,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.lock().unwrap().update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+// --skip-lines 与 --max-lines: 跳过开头的若干行, 之后最多交出若干行, 按 --line-ending 计行.
+// 只交出扫描过且不可能是换行符开头的字节, 跨两次读取的换行符也只算一次
+struct LineWindow<R: Read> {
+    inner: R,
+    delimiter: boundary::Delimiter,
+    skip: u64,
+    remaining: Option<u64>,
+    buffer: Vec<u8>,
+    // buffer 中已交出或丢弃的字节数, 可以交出的字节的结束位置, 以及换行符的扫描位置
+    start: usize,
+    ready: usize,
+    pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> LineWindow<R> {
+    fn new(inner: R, config: &Config) -> Self {
+        LineWindow {
+            inner,
+            delimiter: boundary::Delimiter::new(&config.line_ending, config.encoding),
+            skip: config.skip_lines,
+            remaining: config.max_lines,
+            buffer: Vec::new(),
+            start: 0,
+            ready: 0,
+            pos: 0,
+            eof: false,
+        }
+    }
+
+    // 扫描到有字节可以交出, 或需要更多数据为止; 跳过的行直接丢弃
+    fn scan(&mut self) {
+        while self.ready == self.start && self.remaining != Some(0) {
+            let more = !self.eof;
+            if self.skip == 0 && self.remaining.is_none() {
+                self.delimiter.last_end(&self.buffer, &mut self.pos, more);
+                self.ready = self.pos;
+                return;
+            }
+            let Some(end) = self.delimiter.next_end(&self.buffer, &mut self.pos, more) else {
+                if self.skip > 0 {
+                    self.start = self.pos;
+                }
+                self.ready = self.pos;
+                return;
+            };
+            if self.skip > 0 {
+                self.skip -= 1;
+                self.start = end;
+            } else if let Some(remaining) = &mut self.remaining {
+                *remaining -= 1;
+            }
+            self.ready = end;
+        }
+    }
+}
+
+impl<R: Read> Read for LineWindow<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            self.scan();
+            if self.ready > self.start {
+                let n = (self.ready - self.start).min(buf.len());
+                buf[..n].copy_from_slice(&self.buffer[self.start..self.start + n]);
+                self.start += n;
+                return Ok(n);
+            }
+            // 已交出足够的行, 或输入结束(结尾没有换行符的最后一行已在上面交出)
+            if self.remaining == Some(0) || self.eof {
+                return Ok(0);
+            }
+            self.buffer.drain(..self.start);
+            self.pos -= self.start;
+            self.ready -= self.start;
+            self.start = 0;
+            // 跳过完毕且不限行数时, 缓冲中的数据交完后直接读取
+            if self.skip == 0 && self.remaining.is_none() && self.buffer.is_empty() {
+                return self.inner.read(buf);
+            }
+            let n = Read::by_ref(&mut self.inner).take(MIN_BUFFER_SIZE as u64).read_to_end(&mut self.buffer)?;
+            self.eof = n == 0;
+        }
+    }
+}
+
+// 输入源: 直接读取, 或由预读线程提前填充缓冲区
+pub(crate) enum Input {
+    Direct(Reader),
+    Prefetch(Receiver<io::Result<Vec<u8>>>),
+}
+
+impl Input {
+    // gzip 输入自动解压; 给出 member_ends 时记录各成员的结束偏移, 输入不是 gzip 则报错;
+    // 给出 hasher 时对解压前的原始字节计算摘要. --skip-bytes 与 --max-bytes 作用于原始字节, 行窗口作用于解压后的数据
+    pub(crate) fn open(config: &Config, member_ends: Option<MemberEnds>, hasher: Option<Arc<Mutex<Sha256>>>) -> io::Result<Self> {
+        let mut source = Source::parse(&config.input_path).open_at(config.skip_bytes)?;
+        if let Some(max_bytes) = config.max_bytes {
+            source = Box::new(source.take(max_bytes));
+        }
+        if let Some(hasher) = hasher {
+            source = Box::new(HashingReader { inner: source, hasher });
+        }
+        let mut file = io::BufReader::new(source);
+        let file: Box<dyn Read + Send> = if !config.binary && file.fill_buf()?.starts_with(&[0x1F, 0x8B]) {
+            info!(config, "检测到 gzip 输入, 自动解压");
+            Box::new(GzipReader::new(file, member_ends))
+        } else if member_ends.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--align-gz-members 需要 gzip 输入"));
+        } else {
+            Box::new(file)
+        };
+        let file: Box<dyn Read + Send> = if config.skip_lines > 0 || config.max_lines.is_some() {
+            Box::new(LineWindow::new(file, config))
+        } else {
+            file
+        };
+        let mut reader = Reader {
+            file,
+            tuner: BufferTuner::new(config),
+        };
+        if config.readahead == 0 {
+            return Ok(Input::Direct(reader));
+        }
+
+        // 通道容量即预读深度, 读取线程最多领先 K 个缓冲区
+        let (tx, rx) = mpsc::sync_channel(config.readahead);
+        let read_nice = config.read_nice;
+        let fail_on_warning = config.fail_on_warning;
+        thread::spawn(move || {
+            if let Err(e) = apply_thread_nice("读取", read_nice, fail_on_warning) {
+                let _ = tx.send(Err(e));
+                return;
+            }
+            loop {
+                let mut buffer = Vec::with_capacity(reader.tuner.size);
+                let result = reader.read_buffer(&mut buffer);
+                let done = !matches!(result, Ok(n) if n > 0);
+                if tx.send(result.map(|_| buffer)).is_err() || done {
+                    break;
+                }
+            }
+        });
+        Ok(Input::Prefetch(rx))
+    }
+
+    // 读取下一个缓冲区, 返回读取的字节数, 0 表示输入结束
+    pub(crate) fn fill(&mut self, buffer: &mut Vec<u8>) -> io::Result<usize> {
+        match self {
+            Input::Direct(reader) => reader.read_buffer(buffer),
+            Input::Prefetch(rx) => match rx.recv() {
+                Ok(result) => {
+                    *buffer = result?;
+                    Ok(buffer.len())
+                }
+                Err(_) => Ok(0),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::find_tool;
+    use crate::testing::{split_config, Scratch};
+
+    #[test]
+    fn remote_reads_from_an_offset_need_a_partial_response() {
+        if find_tool("curl").is_none() {
+            eprintln!("跳过: 没有找到 curl 命令");
+            return;
+        }
+        // 路径为 /range 时按 Range 返回 206, 否则像不支持范围请求的服务器一样返回 200 与整个对象
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+                while reader.read_line(&mut request).unwrap() > 2 && !request.ends_with("\r\n\r\n") {}
+                let body = b"0123456789";
+                let start = request.lines().find_map(|line| line.strip_prefix("Range: bytes=")).and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+                let response = match start {
+                    Some(start) if request.starts_with("GET /range ") => {
+                        format!("HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-9/10\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", start, 10 - start, std::str::from_utf8(&body[start..]).unwrap())
+                    }
+                    _ => format!("HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\n{}", std::str::from_utf8(body).unwrap()),
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let mut data = String::new();
+        Source::parse(&format!("http://{}/range", address)).open_at(4).unwrap().read_to_string(&mut data).unwrap();
+        assert_eq!(data, "456789");
+        let Err(error) = Source::parse(&format!("http://{}/ignore", address)).open_at(4) else { panic!("应当报错") };
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        assert!(error.to_string().contains("HTTP 200"), "{}", error);
+    }
+
+    #[test]
+    fn sources_are_chosen_by_scheme() {
+        assert_eq!(Source::parse("data/out.001.zst"), Source::Local(PathBuf::from("data/out.001.zst")));
+        assert_eq!(Source::parse("https://host/out.001.zst"), Source::Curl("https://host/out.001.zst".to_string()));
+        assert_eq!(Source::parse("sftp://host/out.001.zst"), Source::Curl("sftp://host/out.001.zst".to_string()));
+        assert_eq!(Source::parse("s3://bucket/out.001.zst"), Source::S3("s3://bucket/out.001.zst".to_string()));
+    }
+
+    #[test]
+    fn line_windows_skip_and_limit_lines() {
+        let window = |extra: &[&str], data: &[u8]| {
+            let mut args = vec!["zstd_compressor", "in.log", "out/a", "--line-ending", "CRLF"];
+            args.extend(extra);
+            let config = Config::parse(args.into_iter().map(String::from)).unwrap();
+            let mut out = Vec::new();
+            LineWindow::new(data, &config).read_to_end(&mut out).unwrap();
+            out
+        };
+        let data = b"a\r\nb\rb\r\nc\r\nd";
+        assert_eq!(window(&["--skip-lines", "1"], data), b"b\rb\r\nc\r\nd");
+        assert_eq!(window(&["--skip-lines", "1", "--max-lines", "2"], data), b"b\rb\r\nc\r\n");
+        assert_eq!(window(&["--max-lines", "5"], data), data);
+        assert_eq!(window(&["--skip-lines", "3"], data), b"d");
+        assert_eq!(window(&["--skip-lines", "4"], data), b"");
+        assert!(Config::parse(["zstd_compressor", "in.log", "out/a", "--binary", "--max-lines", "1"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn readahead_delivers_the_same_buffers_as_direct_reads() {
+        let dir = Scratch::new("readahead");
+        let (input, text) = dir.lines("in.log", 20_000);
+        let buffers = |readahead: &str| {
+            let mut input = Input::open(&split_config(&[&input, &dir.arg("part"), "--readahead", readahead], 32 * 1024), None, None).unwrap();
+            let mut buffers = Vec::new();
+            loop {
+                let mut buffer = Vec::new();
+                if input.fill(&mut buffer).unwrap() == 0 {
+                    return buffers;
+                }
+                buffers.push(buffer);
+            }
+        };
+        let direct = buffers("0");
+        assert!(direct.len() > 10 && direct.concat() == text.as_bytes());
+        assert_eq!(buffers("1"), direct);
+        assert_eq!(buffers("4"), direct);
+    }
+}
@@ -0,0 +1,1069 @@
+// 切分流水线: 读取、切分、压缩与写出, 可在各自的线程中进行, 本地文件还可以按位置并行切分
+
+use std::fs::File;
+use std::io::{self, Write, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::boundary::{self, Boundary};
+use crate::checkpoint::Checkpoint;
+use crate::compress::{compress_chunk, emit_chunk, sample_compression_model, train_inline_dictionary};
+use crate::config::{Config, JsonCheck};
+use crate::exit::{exit_code, failure, EXIT_PARTIAL, EXIT_TIMEOUT};
+use crate::files::peak_memory;
+use crate::hash::{chunk_digest, to_hex, Sha256};
+use crate::header::{copied_footer_for, copied_header_for};
+use crate::manifest::JsonManifest;
+use crate::partition::Partitions;
+use crate::profiler::Profiler;
+use crate::prompt::confirm;
+use crate::source::{Input, MIN_BUFFER_SIZE};
+use crate::tier::{is_chunk_file, is_partition_file};
+use crate::writer::{ChunkWriter, Output, StreamingWriter};
+use crate::{json, Chunker, Format, InvalidEncoding, LineEndings, MemberEnds};
+
+// 调整当前线程的 nice 值; Linux 上 setpriority 作用于线程 ID 时只影响该线程
+#[cfg(target_os = "linux")]
+fn set_thread_nice(nice: i32) -> io::Result<()> {
+    extern "C" {
+        fn gettid() -> i32;
+        fn setpriority(which: i32, who: u32, prio: i32) -> i32;
+    }
+    const PRIO_PROCESS: i32 = 0;
+    // SAFETY: 两个函数都只接受整数参数, 不涉及内存
+    let result = unsafe { setpriority(PRIO_PROCESS, gettid() as u32, nice) };
+    if result == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_thread_nice(_nice: i32) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "只支持 Linux"))
+}
+
+// 优先级设置失败不影响处理, 只给出警告
+pub(crate) fn apply_thread_nice(stage: &str, nice: Option<i32>, fail_on_warning: bool) -> io::Result<()> {
+    if let Some(nice) = nice {
+        if let Err(e) = set_thread_nice(nice) {
+            let message = format!("无法把{}线程的 nice 值设为 {}: {}", stage, nice, e);
+            if fail_on_warning {
+                return Err(io::Error::new(e.kind(), message));
+            }
+            eprintln!("警告: {}", message);
+        }
+    }
+    Ok(())
+}
+
+// 被丢弃的数据原样写入 <output_prefix>.rejects, 每条记录前附带原因与源偏移
+pub(crate) struct Rejects {
+    path: PathBuf,
+    file: Option<File>,
+    count: usize,
+    // --validate-json report 只告警不丢弃的行数
+    reported: usize,
+}
+
+impl Rejects {
+    // 续跑时追加到上次的文件; 否则删掉旧的文件, 本次没有丢弃数据时不会留下上次的记录
+    fn create(config: &Config) -> io::Result<Self> {
+        let path = PathBuf::from(format!("{}.rejects", config.output_prefix));
+        let file = match config.resume {
+            Some(_) if path.exists() => Some(std::fs::OpenOptions::new().append(true).open(&path)?),
+            Some(_) => None,
+            None => match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => None,
+            },
+        };
+        Ok(Rejects { path, file, count: 0, reported: 0 })
+    }
+
+    fn reject(&mut self, reason: &str, offset: usize, data: &[u8]) -> io::Result<()> {
+        // 首次需要时才创建文件, 没有被丢弃的数据就不留下空文件
+        if self.file.is_none() {
+            self.file = Some(File::create(&self.path)?);
+        }
+        let file = self.file.as_mut().unwrap();
+        writeln!(file, "[{}] offset={} len={}", reason, offset, data.len())?;
+        file.write_all(data)?;
+        file.write_all(b"\n")?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+// --validate-json report 时在标准错误上逐条列出的无效行数, 之后只计数
+const JSON_REPORT_LIMIT: usize = 20;
+
+// 按换行符把块拆成行(每行包含自身的换行符), 编码无效的行与 --validate-json quarantine 时不是 JSON 的行移入 rejects
+pub(crate) fn filter_lines(chunk: &[u8], config: &Config, chunk_offset: usize, rejects: &mut Rejects) -> io::Result<Vec<u8>> {
+    let delimiter = match config.validate_json {
+        Some(_) => b"\n".into(),
+        None => config.encoding.encode(&config.line_ending).0,
+    };
+    let mut kept = Vec::with_capacity(chunk.len());
+    let mut start = 0;
+
+    while start < chunk.len() {
+        let end = chunk[start..]
+            .windows(delimiter.len())
+            .position(|w| w == &delimiter[..])
+            .map(|pos| start + pos + delimiter.len())
+            .unwrap_or(chunk.len());
+        let line = &chunk[start..end];
+
+        // 空行不是 JSON 值, 但常见于文件结尾, 不算错误
+        let malformed = match config.validate_json {
+            Some(_) if line.iter().all(u8::is_ascii_whitespace) => None,
+            Some(check) => json::validate(line).err().map(|reason| (check, reason)),
+            None => None,
+        };
+        if config.drop_invalid && config.encoding.decode_without_bom_handling_and_without_replacement(line).is_none() {
+            rejects.reject("invalid-encoding", chunk_offset + start, line)?;
+        } else if let Some((JsonCheck::Quarantine, _)) = malformed {
+            rejects.reject("invalid-json", chunk_offset + start, line)?;
+        } else if let Some((JsonCheck::Report, reason)) = malformed {
+            let message = format!("偏移 {} 处的行不是有效的 JSON: {}", chunk_offset + start, reason);
+            if config.fail_on_warning {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+            if rejects.reported < JSON_REPORT_LIMIT {
+                eprintln!("警告: {}", message);
+            }
+            rejects.reported += 1;
+            kept.extend_from_slice(line);
+        } else {
+            kept.extend_from_slice(line);
+        }
+        start = end;
+    }
+
+    Ok(kept)
+}
+
+// 压缩比偏离预期过大时告警或终止, 用于发现混入日志的二进制/加密/损坏数据
+pub(crate) fn check_ratio(raw_len: usize, compressed_len: usize, config: &Config, chunk_number: usize) -> io::Result<()> {
+    let expected = match config.expect_ratio {
+        Some(expected) if raw_len > 0 => expected,
+        _ => return Ok(()),
+    };
+
+    let actual = raw_len as f64 / compressed_len as f64;
+    if (actual - expected).abs() / expected <= config.ratio_tolerance {
+        return Ok(());
+    }
+
+    let message = format!(
+        "分卷 {} 压缩比异常: {:.2}:1, 预期 {:.2}:1 (允许偏差 {:.0}%)",
+        chunk_number, actual, expected, config.ratio_tolerance * 100.0
+    );
+    if config.ratio_abort || config.fail_on_warning {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    eprintln!("警告: {}", message);
+    Ok(())
+}
+
+// 本次切分会覆盖的已有文件: 同前缀的分卷(包括 --output-dirs 各目录中的), manifest, JSON 清单, 日志, 单文件输出与 7z 归档
+fn existing_outputs(config: &Config) -> io::Result<Vec<PathBuf>> {
+    let prefix = Path::new(&config.output_prefix);
+    let dir = match prefix.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let stem = prefix.file_name().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut existing = Vec::new();
+    for dir in std::iter::once(dir).chain(config.output_dirs.iter().map(PathBuf::as_path)) {
+        match std::fs::read_dir(dir) {
+            Ok(entries) => {
+                for entry in entries {
+                    let entry = entry?;
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if is_chunk_file(&name, &stem) || (config.partitioned() && is_partition_file(&name, &stem)) {
+                        existing.push(entry.path());
+                    }
+                }
+            }
+            // 目录不存在时写出会失败或由写出时创建
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    existing.sort();
+    let mut others: Vec<PathBuf> = ["manifest", "manifest.json", "journal", "rejects", "7z"].iter().map(|ext| PathBuf::from(format!("{}.{}", config.output_prefix, ext))).collect();
+    others.extend(config.single_output.clone());
+    existing.extend(others.into_iter().filter(|path| path.exists()));
+    Ok(existing)
+}
+
+pub fn confirm_overwrite(config: &Config) -> io::Result<()> {
+    // 续跑接着上次的分卷写, 停下之后的分卷本来就要重写
+    if config.resume.is_some() {
+        return Ok(());
+    }
+    let existing = existing_outputs(config)?;
+    match existing.first() {
+        None => Ok(()),
+        Some(first) => confirm(&format!("将覆盖已有的 {} 个文件(如 {})", existing.len(), first.display()), config.yes),
+    }
+}
+
+// 输入中的行结束符统计; 分卷保留原始字节, 不做换行符转换.
+// 切分用的换行符不是输入中最常见的那种, 或输入混用多种时提示, 以免下游按错误的换行符处理
+fn report_line_endings(endings: &LineEndings, config: &Config) {
+    println!("- 行结束符: LF {}, CRLF {}, CR {} (分卷保留原样, 未做转换)", endings.lf, endings.crlf, endings.cr);
+    let kinds = [endings.lf, endings.crlf, endings.cr].iter().filter(|&&count| count > 0).count();
+    if kinds > 1 {
+        println!("  注意: 输入混用了多种行结束符");
+    }
+    let configured = match config.line_ending.as_str() {
+        "\n" => Some("LF"),
+        "\r\n" => Some("CRLF"),
+        "\r" => Some("CR"),
+        _ => None,
+    };
+    // --records 按记录切分时换行符只是记录的一部分, 不比较
+    if let (Some(configured), Some(dominant), None) = (configured, endings.dominant(), &config.records) {
+        if configured != dominant {
+            println!("  注意: 按 {} 切分, 但输入中最多的是 {}", configured, dominant);
+        }
+    }
+}
+
+// 一次切分的结果, 批量任务的汇总报告使用
+pub struct SplitStats {
+    pub chunks: usize,
+    pub bytes: usize,
+    pub duration: Duration,
+}
+
+// 按配置切分并压缩一个输入
+// 读取输入并交给切分器, 直到输入结束; 返回读取的字节数. 给出 endings 时顺带统计行结束符
+fn split_input(
+    input: &mut Input,
+    chunker: &mut Chunker,
+    endings: Option<&mut LineEndings>,
+    profiler: &Profiler,
+    emit: &mut impl FnMut(&[u8], usize) -> io::Result<()>,
+) -> io::Result<usize> {
+    let total_bytes = read_input(input, endings, profiler, &mut |buffer: &[u8]| chunker.push(buffer, emit))?;
+
+    // 输入结束, 写出最后一个换行符之后剩余的数据
+    let _span = profiler.span("split");
+    chunker.finish(emit)?;
+    Ok(total_bytes)
+}
+
+// 逐个缓冲区读取输入交给 push(计入 split 阶段), 直到输入结束
+fn read_input(input: &mut Input, mut endings: Option<&mut LineEndings>, profiler: &Profiler, push: &mut dyn FnMut(&[u8]) -> io::Result<()>) -> io::Result<usize> {
+    let mut buffer = Vec::with_capacity(MIN_BUFFER_SIZE);
+    let mut total_bytes = 0;
+    loop {
+        buffer.clear();
+        let span = profiler.span("read");
+        let n = input.fill(&mut buffer)?;
+        drop(span);
+        if n == 0 {
+            break;
+        }
+        total_bytes += n;
+        let _span = profiler.span("split");
+        if let Some(endings) = endings.as_deref_mut() {
+            endings.update(&buffer);
+        }
+        push(&buffer)?;
+    }
+    if let Some(endings) = endings {
+        endings.finish();
+    }
+    Ok(total_bytes)
+}
+
+// 待压缩的分卷: (编号, 过滤后的数据, 在输入中的偏移, 过滤前的长度)
+type PendingChunk = (usize, Vec<u8>, usize, usize);
+
+// 流水线: 主线程读取、切分并过滤, --jobs 个压缩线程各取一个分卷压缩, 写出线程按编号顺序写出并记录检查点;
+// 线程之间是容量为 --pipeline-depth 的通道, 读取下一段、压缩当前分卷与写出上一个分卷同时进行.
+// 压缩完成的顺序不定, 写出线程暂存先完成的分卷, 分卷文件与单线程时逐字节相同
+// split 在主线程中读取并切分输入(即 split_input), 把每个分卷交给传入的回调
+fn split_pipelined(
+    config: &Config,
+    split: impl FnOnce(&mut dyn FnMut(&[u8], usize) -> io::Result<()>) -> io::Result<usize>,
+    rejects: &mut Rejects,
+    writer: &mut ChunkWriter,
+    checkpoint: &mut Checkpoint,
+    profiler: &Profiler,
+) -> io::Result<usize> {
+    // 续跑时接着上次的编号
+    let mut chunk_number = writer.chunks();
+    let (chunk_tx, chunk_rx) = mpsc::sync_channel::<PendingChunk>(config.pipeline_depth);
+    let (compressed_tx, compressed_rx) = mpsc::sync_channel::<(PendingChunk, Vec<u8>, Option<[u8; 32]>)>(config.pipeline_depth + config.jobs);
+    // 任一线程出错时丢弃接收端, 主线程的发送随即失败, 其余压缩线程也不再取新的分卷
+    let chunk_rx = Mutex::new(Some(chunk_rx));
+    let abort = || drop(chunk_rx.lock().unwrap().take());
+    thread::scope(|scope| {
+        let compressors: Vec<_> = (0..config.jobs)
+            .map(|_| {
+                let (chunk_rx, compressed_tx) = (&chunk_rx, compressed_tx.clone());
+                scope.spawn(move || -> io::Result<()> {
+                    apply_thread_nice("压缩", config.compress_nice, config.fail_on_warning).inspect_err(|_| abort())?;
+                    loop {
+                        let received = chunk_rx.lock().unwrap().as_ref().map(Receiver::recv);
+                        let Some(Ok(pending)) = received else { break };
+                        let compressed = compress_chunk(&pending.1, config, pending.0, profiler).inspect_err(|_| abort())?;
+                        let digest = chunk_digest(&pending.1, config);
+                        if compressed_tx.send((pending, compressed, digest)).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        drop(compressed_tx);
+        let storer = scope.spawn(|| -> io::Result<()> {
+            apply_thread_nice("写出", config.compress_nice, config.fail_on_warning).inspect_err(|_| abort())?;
+            let mut waiting = BTreeMap::new();
+            for ((chunk_number, chunk, offset, raw_len), compressed, digest) in compressed_rx {
+                waiting.insert(chunk_number, (chunk, compressed, digest, offset, raw_len));
+                while let Some((chunk, compressed, digest, offset, raw_len)) = waiting.remove(&writer.next_number) {
+                    let written = writer.store(&chunk, &compressed, digest, offset, profiler).inspect_err(|_| abort())?;
+                    let _span = profiler.span("checkpoint");
+                    checkpoint.chunk_written(written, writer, offset + raw_len).inspect_err(|_| abort())?;
+                }
+            }
+            Ok(())
+        });
+
+        let split = split(&mut |chunk: &[u8], offset: usize| {
+            chunk_number += 1;
+            let header = copied_header_for(chunk, config, chunk_number);
+            let footer = copied_footer_for(chunk, config, chunk_number);
+            let kept = if config.drop_invalid || config.validate_json.is_some() {
+                let _span = profiler.span("filter");
+                [header, &filter_lines(chunk, config, offset, rejects)?, footer].concat()
+            } else {
+                [header, chunk, footer].concat()
+            };
+            // 发送失败说明下游线程已经出错, 错误在下面取回
+            chunk_tx.send((chunk_number, kept, offset, chunk.len())).map_err(|_| io::Error::other("流水线已中止"))
+        });
+        drop(chunk_tx);
+        let mut compressed = Ok(());
+        for compressor in compressors {
+            let result = compressor.join().map_err(|_| io::Error::other("压缩线程异常退出"))?;
+            compressed = compressed.and(result);
+        }
+        let stored = storer.join().map_err(|_| io::Error::other("写出线程异常退出"))?;
+        // 下游的错误是流水线中止的原因, 优先报告
+        compressed?;
+        stored?;
+        split
+    })
+}
+
+// --parts 均分的总长: 输入文件中 --skip-bytes 与 --max-bytes 限定的一段; gzip 输入解压后的大小未知
+fn parts_total(config: &Config) -> io::Result<u64> {
+    let mut file = File::open(&config.input_path)?;
+    let len = file.metadata()?.len().saturating_sub(config.skip_bytes);
+    file.seek(SeekFrom::Start(config.skip_bytes))?;
+    let mut magic = [0u8; 2];
+    if file.read(&mut magic)? == 2 && magic == [0x1F, 0x8B] {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--parts 按文件大小均分, 不支持 gzip 输入"));
+    }
+    Ok(config.max_bytes.map_or(len, |max_bytes| max_bytes.min(len)))
+}
+
+// 切分的输入: 顺序读取, 或 --parallel-split 时按位置分段读取
+enum SplitInput {
+    Sequential(Input),
+    Seekable(SeekableInput),
+}
+
+// 按位置读取的本地普通文件, len 已按 --max-bytes 截短; 每个线程各自打开, 互不影响读取位置
+struct SeekableInput {
+    path: PathBuf,
+    len: u64,
+}
+
+impl SeekableInput {
+    fn open(config: &Config) -> io::Result<Self> {
+        let path = PathBuf::from(&config.input_path);
+        let mut file = File::open(&path)?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--parallel-split 需要可按位置读取的普通文件"));
+        }
+        let len = config.max_bytes.map_or(metadata.len(), |max_bytes| max_bytes.min(metadata.len()));
+        let mut magic = [0u8; 2];
+        if !config.binary && len >= 2 && file.read_exact(&mut magic).is_ok() && magic == [0x1F, 0x8B] {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--parallel-split 不能自动解压 gzip 输入, 请先解压或去掉此选项"));
+        }
+        Ok(SeekableInput { path, len })
+    }
+}
+
+fn read_at(file: &mut File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    let mut data = vec![0u8; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+// 查找切分点时每次读取的字节数, 多数情况下一次就能找到换行符
+const CUT_SCAN_BLOCK: u64 = 64 * 1024;
+
+// target 之后(含)结束的第一条记录的结束位置, 之后再没有换行符时为 len;
+// 换行符可能跨过 target, 从它之前换行符长度处开始查找
+fn record_end_after(file: &mut File, target: u64, len: u64, delimiter: &mut boundary::Delimiter) -> io::Result<u64> {
+    let mut start = target.saturating_sub(delimiter.as_bytes().len() as u64);
+    let mut data = Vec::new();
+    let mut pos = 0;
+    loop {
+        let read_len = CUT_SCAN_BLOCK.min(len - start - data.len() as u64);
+        data.extend(read_at(file, start + data.len() as u64, read_len)?);
+        let more = start + (data.len() as u64) < len;
+        if let Some(end) = delimiter.next_end(&data, &mut pos, more) {
+            return Ok(start + end as u64);
+        }
+        if !more {
+            return Ok(len);
+        }
+        data.drain(..pos);
+        start += pos as u64;
+        pos = 0;
+    }
+}
+
+// 第 k 个切分点是 k 倍分块大小处之后的第一个记录结束位置, 与线程数和读取缓冲无关;
+// --jobs 个线程分头查找, 超长记录使几个切分点重合时只保留一个. 二进制输入正好按分块大小切分
+fn find_cuts(config: &Config, input: &SeekableInput) -> io::Result<Vec<u64>> {
+    let chunk_size = config.chunk_size as u64;
+    let targets = input.len.saturating_sub(1) / chunk_size;
+    let mut cuts: Vec<u64> = if config.binary {
+        (1..=targets).map(|k| k * chunk_size).collect()
+    } else {
+        let next = AtomicU64::new(1);
+        let found = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            let finders: Vec<_> = (0..config.jobs)
+                .map(|_| {
+                    scope.spawn(|| -> io::Result<()> {
+                        let mut file = File::open(&input.path)?;
+                        let mut delimiter = boundary::Delimiter::new(&config.line_ending, config.encoding);
+                        loop {
+                            let k = next.fetch_add(1, Ordering::Relaxed);
+                            if k > targets {
+                                return Ok(());
+                            }
+                            let cut = record_end_after(&mut file, k * chunk_size, input.len, &mut delimiter)?;
+                            found.lock().unwrap().push(cut);
+                        }
+                    })
+                })
+                .collect();
+            finders.into_iter().try_for_each(|finder| finder.join().map_err(|_| io::Error::other("切分线程异常退出"))?)
+        })?;
+        found.into_inner().unwrap()
+    };
+    cuts.push(input.len);
+    cuts.sort_unstable();
+    cuts.dedup();
+    cuts.retain(|&cut| cut > 0);
+    Ok(cuts)
+}
+
+// 已读取并压缩的范围: (编号, 原始数据, 在输入中的偏移, 其中的行结束符, 压缩后的数据, 原始数据的 SHA-256)
+type RangeChunk = (usize, Vec<u8>, u64, LineEndings, Vec<u8>, Option<[u8; 32]>);
+
+// --parallel-split: 先并行查找切分点, 再由 --jobs 个线程各取一个范围读取、检查编码、统计行结束符并压缩,
+// 写出线程按编号顺序计算输入摘要、写出并记录检查点. 分卷不经过主线程, 切分本身也随线程数扩展
+fn split_seekable(
+    config: &Config,
+    input: &SeekableInput,
+    hasher: Option<&Mutex<Sha256>>,
+    endings: Option<&mut LineEndings>,
+    writer: &mut ChunkWriter,
+    checkpoint: &mut Checkpoint,
+    profiler: &Profiler,
+) -> io::Result<usize> {
+    let cuts = {
+        let _span = profiler.span("split");
+        find_cuts(config, input)?
+    };
+    let next = AtomicUsize::new(0);
+    let aborted = AtomicBool::new(false);
+    let abort = || aborted.store(true, Ordering::Relaxed);
+    let (compressed_tx, compressed_rx) = mpsc::sync_channel::<RangeChunk>(config.pipeline_depth + config.jobs);
+    thread::scope(|scope| {
+        let compressors: Vec<_> = (0..config.jobs)
+            .map(|_| {
+                let (cuts, next, aborted, compressed_tx) = (&cuts, &next, &aborted, compressed_tx.clone());
+                scope.spawn(move || -> io::Result<()> {
+                    apply_thread_nice("压缩", config.compress_nice, config.fail_on_warning).inspect_err(|_| abort())?;
+                    let mut file = File::open(&input.path).inspect_err(|_| abort())?;
+                    while !aborted.load(Ordering::Relaxed) {
+                        let n = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&end) = cuts.get(n) else { break };
+                        let offset = n.checked_sub(1).map_or(0, |previous| cuts[previous]);
+                        let span = profiler.span("read");
+                        let chunk = read_at(&mut file, offset, end - offset).inspect_err(|_| abort())?;
+                        drop(span);
+                        let mut chunk_endings = LineEndings::default();
+                        if !config.binary {
+                            let _span = profiler.span("split");
+                            chunk_endings.update(&chunk);
+                            if crate::check_encoding(&chunk, config.encoding).0 {
+                                if config.fail_on_warning {
+                                    abort();
+                                    return Err(io::Error::new(io::ErrorKind::InvalidData, InvalidEncoding { offset: offset as usize }));
+                                }
+                                eprintln!("警告: 发现无效的字符编码");
+                            }
+                        }
+                        let compressed = compress_chunk(&chunk, config, n + 1, profiler).inspect_err(|_| abort())?;
+                        let digest = chunk_digest(&chunk, config);
+                        if compressed_tx.send((n + 1, chunk, offset, chunk_endings, compressed, digest)).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        drop(compressed_tx);
+        // 写出线程出错返回时丢弃接收端, 压缩线程的发送随即失败
+        let storer = scope.spawn(move || -> io::Result<()> {
+            apply_thread_nice("写出", config.compress_nice, config.fail_on_warning).inspect_err(|_| abort())?;
+            let mut endings = endings;
+            let mut waiting = BTreeMap::new();
+            for (chunk_number, chunk, offset, chunk_endings, compressed, digest) in compressed_rx {
+                waiting.insert(chunk_number, (chunk, offset, chunk_endings, compressed, digest));
+                while let Some((chunk, offset, chunk_endings, compressed, digest)) = waiting.remove(&writer.next_number) {
+                    if let Some(hasher) = hasher {
+                        hasher.lock().unwrap().update(&chunk);
+                    }
+                    if let Some(endings) = endings.as_deref_mut() {
+                        endings.append(&chunk_endings, chunk.first().copied());
+                    }
+                    let offset = offset as usize;
+                    let written = writer.store(&chunk, &compressed, digest, offset, profiler).inspect_err(|_| abort())?;
+                    let _span = profiler.span("checkpoint");
+                    checkpoint.chunk_written(written, writer, offset + chunk.len()).inspect_err(|_| abort())?;
+                }
+            }
+            if let Some(endings) = endings {
+                endings.finish();
+            }
+            Ok(())
+        });
+
+        let mut compressed = Ok(());
+        for compressor in compressors {
+            let result = compressor.join().map_err(|_| io::Error::other("压缩线程异常退出"))?;
+            compressed = compressed.and(result);
+        }
+        let stored = storer.join().map_err(|_| io::Error::other("写出线程异常退出"))?;
+        compressed?;
+        stored
+    })?;
+    Ok(input.len as usize)
+}
+
+pub fn run_split(config: &Config) -> io::Result<SplitStats> {
+    let start_time = Instant::now();
+
+    if !config.porcelain {
+        println!("使用配置:");
+        println!("- 运行 ID: {}", config.run_id);
+        println!("- 编码: {}", config.encoding.name());
+        match config.format {
+            Format::Zstd if config.zstd.threads() > 0 => println!("- 输出格式: zstd (级别 {}, {} 个工作线程)", config.zstd.level(), config.zstd.threads()),
+            Format::Zstd => println!("- 输出格式: zstd (级别 {})", config.zstd.level()),
+            format => println!("- 输出格式: {}", format.name()),
+        }
+        if config.jobs > 1 {
+            println!("- 并行压缩: {} 个分卷", config.jobs);
+        }
+        if config.parallel_split {
+            println!("- 并行切分: {} 个线程", config.jobs);
+        }
+        println!("- 换行符: {}", config.line_ending.escape_default());
+        match (config.lines, config.parts, config.max_size) {
+            _ if config.cdc => println!("- 分块大小: 按内容切分, 平均约 {}", config.numbers.size(config.chunk_size as u64)),
+            (Some(lines), _, _) => println!("- 分块大小: 每卷 {} 条记录", lines),
+            (None, Some(parts), _) => println!("- 分块大小: 均分为 {} 个分卷", parts),
+            (None, None, Some(max_size)) => println!("- 分块大小: 目标 {}, 上限 {}", config.numbers.size(config.chunk_size as u64), config.numbers.size(max_size as u64)),
+            (None, None, None) => println!("- 分块大小: {}", config.numbers.size(config.chunk_size as u64)),
+        }
+        match config.buffer_size {
+            Some(size) => println!("- 读取缓冲: {}", config.numbers.size(size as u64)),
+            None => println!("- 读取缓冲: 自动"),
+        }
+    }
+
+    // 初始化文件读取
+    let member_ends = config.align_gz_members.then(MemberEnds::default);
+    let hasher = config.input_sha256.map(|_| Arc::new(Mutex::new(Sha256::new())));
+    let input = if config.parallel_split {
+        SplitInput::Seekable(SeekableInput::open(config)?)
+    } else {
+        SplitInput::Sequential(Input::open(config, member_ends.clone(), hasher.clone())?)
+    };
+    let mut chunker = Chunker::new(config.chunk_size, &config.line_ending, config.encoding);
+    chunker.max_size = config.max_size;
+    chunker.long_line_policy = config.long_line_policy;
+    chunker.long_line_cap = config.long_line_cap;
+    chunker.no_boundary = config.no_boundary;
+    chunker.lines = config.lines;
+    if let Some(parts) = config.parts {
+        chunker.set_parts(parts, parts_total(config)? as usize);
+    }
+    chunker.member_ends = member_ends;
+    chunker.chunk_limit = Some(config.chunk_limit.clone());
+    if let Some(records) = &config.records {
+        chunker.set_records(boundary::parse(records, config.encoding).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?);
+    }
+    if let Some(quote) = config.quote_char {
+        let records = boundary::Quoted::new(&config.line_ending, quote, config.encoding).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        chunker.set_records(Box::new(records));
+    }
+    if config.cdc {
+        chunker.set_content_defined(config.binary);
+    }
+    // --drop-invalid 已经处理了无效编码的行, 不算告警
+    chunker.fail_on_invalid = config.fail_on_warning && !config.drop_invalid && !config.binary;
+    chunker.warn_invalid = !config.binary;
+    let profiler = Profiler::new(config.profile_out.is_some());
+    let main_span = profiler.span("main");
+    if config.balance_compressed {
+        let _span = profiler.span("sample");
+        let model = sample_compression_model(config)?;
+        info!(
+            config,
+            "采样估算: 压缩后共约 {:.2} MB, 每卷目标约 {:.2} MB",
+            model.total_compressed() / 1024.0 / 1024.0,
+            model.target_compressed() / 1024.0 / 1024.0
+        );
+        chunker.set_balance(model);
+    }
+    if config.inline_dictionary {
+        let _span = profiler.span("sample");
+        if let Some(dictionary) = train_inline_dictionary(config)? {
+            info!(config, "训练字典: {} 字节, 内嵌在第一个分卷开头", dictionary.len());
+            let _ = config.dictionary.set(dictionary);
+        }
+    }
+    let mut rejects = Rejects::create(config)?;
+    let mut checkpoint = Checkpoint::new(config);
+    let mut writer = ChunkWriter::new(config, Output::open(config)?);
+    writer.json = Some(JsonManifest::create(config)?);
+    // 二进制输入中的 CR/LF 字节不是行结束符, 不统计
+    let mut endings = (!config.binary).then(LineEndings::default);
+
+    let split = match input {
+        SplitInput::Seekable(input) => split_seekable(config, &input, hasher.as_deref(), endings.as_mut(), &mut writer, &mut checkpoint, &profiler),
+        SplitInput::Sequential(mut input) if config.stream => {
+            apply_thread_nice("压缩", config.compress_nice, config.fail_on_warning)?;
+            let mut sink = StreamingWriter { writer: &mut writer, checkpoint: &mut checkpoint, profiler: &profiler, current: None };
+            read_input(&mut input, endings.as_mut(), &profiler, &mut |buffer: &[u8]| chunker.push_streaming(buffer, &mut sink)).and_then(|total_bytes| {
+                let _span = profiler.span("split");
+                chunker.finish_streaming(&mut sink).map(|()| total_bytes)
+            })
+        }
+        SplitInput::Sequential(mut input) if config.partitioned() => {
+            apply_thread_nice("压缩", config.compress_nice, config.fail_on_warning)?;
+            let mut partitions = Partitions::new(config);
+            let total_bytes = split_input(&mut input, &mut chunker, endings.as_mut(), &profiler, &mut |chunk: &[u8], offset: usize| {
+                if config.drop_invalid {
+                    let _span = profiler.span("filter");
+                    let kept = filter_lines(chunk, config, offset, &mut rejects)?;
+                    return partitions.route(&kept, &mut writer, &profiler);
+                }
+                partitions.route(chunk, &mut writer, &profiler)
+            })?;
+            partitions.finish(&mut writer, &profiler)?;
+            info!(config, "共 {} 个分区", partitions.partitions.len());
+            Ok(total_bytes)
+        }
+        SplitInput::Sequential(mut input) if config.pipeline_depth == 0 => {
+            apply_thread_nice("压缩", config.compress_nice, config.fail_on_warning)?;
+            split_input(&mut input, &mut chunker, endings.as_mut(), &profiler, &mut |chunk: &[u8], offset: usize| {
+                let written = emit_chunk(chunk, config, offset, &mut rejects, &mut writer, &profiler)?;
+                let _span = profiler.span("checkpoint");
+                checkpoint.chunk_written(written, &writer, offset + chunk.len())
+            })
+        }
+        SplitInput::Sequential(mut input) => {
+            let split = |mut emit: &mut dyn FnMut(&[u8], usize) -> io::Result<()>| split_input(&mut input, &mut chunker, endings.as_mut(), &profiler, &mut emit);
+            split_pipelined(config, split, &mut rejects, &mut writer, &mut checkpoint, &profiler)
+        }
+    };
+    // 到达时间上限时已写出的分卷完整可用, 清单记下它们, 续跑时接着写
+    let total_bytes = match split {
+        Err(e) if exit_code(&e) == EXIT_TIMEOUT => {
+            writer.finish()?;
+            if let Some(json) = &writer.json {
+                json.write(config, false)?;
+            }
+            return Err(e);
+        }
+        split => split?,
+    };
+
+    if let Some(n) = config.record_bytes.filter(|n| total_bytes % n != 0) {
+        let message = format!("输入长度 {} 不是记录长度 {} 的整数倍, 最后 {} 字节不足一条记录", total_bytes, n, total_bytes % n);
+        if config.fail_on_warning {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        eprintln!("警告: {}", message);
+    }
+
+    if let Some(parts) = config.parts.filter(|&parts| writer.chunks() < parts) {
+        let message = format!("输入中的记录不够均分, 只切出 {} 个分卷 (--parts {})", writer.chunks(), parts);
+        if config.fail_on_warning {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        eprintln!("警告: {}", message);
+    }
+
+    // 摘要不符时不完成分卷集, 已写出的分卷不可信
+    if let (Some(expected), Some(hasher)) = (config.input_sha256, hasher) {
+        let actual = std::mem::replace(&mut *hasher.lock().unwrap(), Sha256::new()).finish();
+        if actual != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("输入 SHA-256 不符: 预期 {}, 实际 {}; 分卷集未完成", to_hex(&expected), to_hex(&actual)),
+            ));
+        }
+        info!(config, "输入 SHA-256 校验通过: {}", to_hex(&actual));
+    }
+    let span = profiler.span("finish");
+    writer.finish()?;
+    if let Some(json) = &writer.json {
+        json.write(config, true)?;
+    }
+    checkpoint.finish(&writer)?;
+    drop(span);
+    drop(main_span);
+    if let Some(path) = &config.profile_out {
+        profiler.write(path)?;
+    }
+
+    let duration = start_time.elapsed();
+    if config.porcelain {
+        println!("DONE {} {}", writer.chunks(), total_bytes);
+    } else {
+        println!("\n压缩统计:");
+        let numbers = &config.numbers;
+        println!("- 总分卷数: {}", numbers.integer(writer.chunks() as u64));
+        println!("- 总数据量: {}", numbers.size(total_bytes as u64));
+        if writer.compressed_bytes > 0 {
+            let ratio = numbers.decimal(writer.raw_bytes as f64 / writer.compressed_bytes as f64, 2);
+            match config.format {
+                Format::Zstd => println!("- 压缩后: {} (压缩比 {}:1, 级别 {})", numbers.size(writer.compressed_bytes), ratio, config.zstd.level()),
+                _ => println!("- 压缩后: {} (压缩比 {}:1)", numbers.size(writer.compressed_bytes), ratio),
+            }
+        }
+        match &writer.output {
+            Output::Single { path, index_path, .. } => println!("- 输出文件: {} (索引 {})", path.display(), index_path.display()),
+            Output::SevenZip(archive) => println!("- 输出文件: {}", archive.path.display()),
+            Output::Files { manifest: Some(manifest), .. } => {
+                println!("- 分卷清单: {}", manifest.path.display());
+                if !config.mirrors.is_empty() {
+                    println!("- 镜像目标: {} 个", config.mirrors.len());
+                }
+                if manifest.failures > 0 {
+                    println!("- 镜像失败: {} 次", manifest.failures);
+                }
+            }
+            Output::Files { manifest: None, .. } => {}
+        }
+        if let Some(json) = &writer.json {
+            println!("- JSON 清单: {}", json.path.display());
+        }
+        if rejects.count > 0 {
+            println!("- 丢弃记录: {} 条 (见 {})", rejects.count, rejects.path.display());
+        }
+        if rejects.reported > 0 {
+            println!("- 无效 JSON 行: {} 行 (只告警, 仍写入分卷)", rejects.reported);
+        }
+        if let Some(endings) = &endings {
+            report_line_endings(endings, config);
+        }
+        if let Some(path) = &config.profile_out {
+            println!("- 性能分析: {}", path.display());
+        }
+        println!("- 处理耗时: {}", numbers.duration(duration.as_secs_f64()));
+        println!("- 平均速度: {}", numbers.rate(total_bytes as u64, duration.as_secs_f64()));
+        println!("- 内存峰值: {}", peak_memory(numbers));
+    }
+    if let Output::Files { manifest: Some(manifest), .. } = &writer.output {
+        if manifest.failures > 0 {
+            return Err(failure(EXIT_PARTIAL, format!("分卷已写出, 但有 {} 次镜像写入失败, 见 {}", manifest.failures, manifest.path.display())));
+        }
+    }
+    Ok(SplitStats { chunks: writer.chunks(), bytes: total_bytes, duration })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::UTF_8;
+    use crate::chunk_name;
+    use crate::codec::GzipReader;
+    use crate::exit::EXIT_VERIFY;
+    use crate::merge::{run_join, MergeOptions};
+    use crate::naming::chunk_path;
+    use crate::testing::{split_config, Scratch};
+
+    // 三个 gzip 成员: 带文件名的固定 Huffman 块, 存储块, 动态 Huffman 块
+    const GZIP_MEMBERS: [&[u8]; 3] = [
+        &[
+            0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x61, 0x2e, 0x6c, 0x6f, 0x67, 0x00, 0xcb, 0x48,
+            0xcd, 0xc9, 0xc9, 0xe7, 0xca, 0x40, 0x22, 0x01, 0x3a, 0x37, 0x66, 0x3d, 0x12, 0x00, 0x00, 0x00,
+        ],
+        &[
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x01, 0x06, 0x00, 0xf9, 0xff, 0x70, 0x6c, 0x61,
+            0x69, 0x6e, 0x0a, 0xd0, 0xf9, 0x15, 0x39, 0x06, 0x00, 0x00, 0x00,
+        ],
+        &[
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x45, 0x8f, 0x0b, 0x02, 0x80, 0x20, 0x0c, 0x42,
+            0xcf, 0x3a, 0xb8, 0xff, 0x1d, 0x1a, 0x9f, 0x59, 0xae, 0x96, 0x8a, 0x0f, 0x04, 0xc6, 0x0f, 0x00, 0x02, 0xc3,
+            0x1d, 0x6a, 0x24, 0xa8, 0x55, 0x97, 0xdb, 0xee, 0x4b, 0x30, 0x37, 0xdd, 0x6d, 0x66, 0x65, 0x5f, 0x97, 0x3f,
+            0xb4, 0x84, 0x12, 0x65, 0x1c, 0x9e, 0x6f, 0x86, 0x20, 0x25, 0x5f, 0xc0, 0x1c, 0x82, 0x36, 0x33, 0x57, 0x15,
+            0x4f, 0xbc, 0x64, 0xb8, 0xa0, 0x4c, 0x67, 0x5d, 0xb6, 0x09, 0x9c, 0x24, 0x35, 0xf1, 0x2f, 0x73, 0x7a, 0x0a,
+            0x4b, 0x88, 0x38, 0xfe, 0xe9, 0xf0, 0xee, 0x53, 0x70, 0xcf, 0x98, 0x00, 0x5d, 0xb1, 0x62, 0x79, 0x7d, 0x0a,
+            0xe4, 0x26, 0x39, 0x2c, 0x01, 0x00, 0x00,
+        ],
+    ];
+
+    #[test]
+    fn gzip_members_decompress_and_align_chunks() {
+        // 第三个成员的内容由线性同余序列生成
+        let mut x = 1u32;
+        let mut expected = b"hello\nhello\nhello\nplain\n".to_vec();
+        for _ in 0..300 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12345) & 0x7FFF_FFFF;
+            expected.push(b"aaaabbbc"[(x >> 16) as usize & 7]);
+        }
+
+        let gzip = GZIP_MEMBERS.concat();
+        let member_ends = MemberEnds::default();
+        let mut reader = GzipReader::new(&gzip[..], Some(member_ends.clone()));
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, expected);
+        assert_eq!(*member_ends.lock().unwrap(), [18, 24, 324]);
+
+        // 分块大小 20: 累积超过 20 字节时在已知的最后一个成员结束处切分, 成员内部的换行符不作为切分点
+        member_ends.lock().unwrap().extend([18, 24, 324]);
+        let mut chunker = Chunker::new(20, "\n", UTF_8);
+        chunker.member_ends = Some(member_ends);
+        let mut chunks = Vec::new();
+        let mut emit = |chunk: &[u8], offset: usize| -> io::Result<()> {
+            chunks.push((offset, chunk.len()));
+            Ok(())
+        };
+        for buffer in data.chunks(7) {
+            chunker.push(buffer, &mut emit).unwrap();
+        }
+        chunker.finish(&mut emit).unwrap();
+        assert_eq!(chunks, [(0, 18), (18, 6), (24, 300)]);
+
+        let mut corrupt = gzip.clone();
+        corrupt[GZIP_MEMBERS[0].len() - 8] ^= 1;
+        let err = GzipReader::new(&corrupt[..], None).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn gzip_block_types_and_trailing_padding() {
+        // 三个成员依次是固定 Huffman 块、存储块与动态 Huffman 块; 第一个成员头带文件名, 数据从第 16 字节开始
+        let block_types: Vec<u8> = GZIP_MEMBERS.iter().zip([16, 10, 10]).map(|(member, start)| member[start] >> 1 & 0b11).collect();
+        assert_eq!(block_types, [1, 0, 2]);
+        let read = |data: &[u8]| {
+            let mut out = Vec::new();
+            GzipReader::new(data, None).read_to_end(&mut out).map(|_| out)
+        };
+        assert_eq!(read(GZIP_MEMBERS[0]).unwrap(), b"hello\nhello\nhello\n");
+        assert_eq!(read(GZIP_MEMBERS[1]).unwrap(), b"plain\n");
+        assert_eq!(read(GZIP_MEMBERS[2]).unwrap().len(), 300);
+
+        let gzip = GZIP_MEMBERS.concat();
+        let expected = read(&gzip).unwrap();
+        let padded = [&gzip[..], &[0; 512]].concat();
+        assert_eq!(read(&padded).unwrap(), expected);
+        for trailing in [&b"\0\0junk"[..], b"junk"] {
+            let error = read(&[&gzip[..], trailing].concat()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", trailing);
+        }
+        // 第一个成员之前的 0 不是填充
+        assert!(read(&[&[0; 4][..], &gzip].concat()).is_err());
+    }
+
+    #[test]
+    fn pipelined_parallel_and_streaming_splits_match_sequential() {
+        let dir = Scratch::new("pipeline");
+        let input = dir.join("in.log");
+        let lines: String = (0..150_000).map(|n| format!("第 {} 行 {}\n", n, n * 7919 % 1000)).collect();
+        std::fs::write(&input, &lines).unwrap();
+
+        let mut chunk_sets = Vec::new();
+        for (n, extra) in [["--pipeline-depth", "0"], ["--pipeline-depth", "1"], ["--jobs", "3"], ["--stream", "--journal"]].iter().enumerate() {
+            let prefix = dir.join(format!("run{}", n)).display().to_string();
+            let mut args = vec!["zstd_compressor".to_string(), input.display().to_string(), prefix.clone()];
+            args.extend(["1", "--buffer-size", "1"].iter().chain(extra).map(|arg| arg.to_string()));
+            let config = Config::parse(args).unwrap();
+            let stats = run_split(&config).unwrap();
+            assert_eq!(stats.chunks, 3);
+            let chunks: Vec<Vec<u8>> = (1..=stats.chunks).map(|n| std::fs::read(chunk_path(&prefix, n, "zst")).unwrap()).collect();
+            chunk_sets.push(chunks);
+        }
+        assert!(chunk_sets.iter().all(|chunks| *chunks == chunk_sets[0]));
+    }
+
+    #[test]
+    fn parallel_split_cuts_after_each_multiple_of_the_chunk_size() {
+        let dir = Scratch::new("parallel");
+        let input = dir.join("in.log");
+        std::fs::write(&input, "aaaa\r\nbbbbbbbbbbbbbbbbbbbbbbbbb\r\nc\r\ndddddd\r\ne").unwrap();
+        let args = |extra: &[&str]| {
+            let mut args = vec!["zstd_compressor".to_string(), input.display().to_string(), dir.join("part").display().to_string()];
+            args.extend(["--parallel-split", "--line-ending", "CRLF"].iter().chain(extra).map(|arg| arg.to_string()));
+            args
+        };
+        // 目标 8, 16, 24 与 32 之后的第一个记录结束处相同, 只切一次; 32 落在 CRLF 中间
+        for jobs in ["1", "3"] {
+            let mut config = Config::parse(args(&["--jobs", jobs])).unwrap();
+            config.chunk_size = 8;
+            let seekable = SeekableInput::open(&config).unwrap();
+            assert_eq!(find_cuts(&config, &seekable).unwrap(), [33, 44, 45]);
+            config.binary = true;
+            assert_eq!(find_cuts(&config, &seekable).unwrap(), [8, 16, 24, 32, 40, 45]);
+        }
+
+        let mut config = Config::parse(args(&["--jobs", "2", "--yes"])).unwrap();
+        config.chunk_size = 8;
+        config.porcelain = true;
+        let stats = run_split(&config).unwrap();
+        assert_eq!((stats.chunks, stats.bytes), (3, 45));
+        let prefix = dir.join("part").display().to_string();
+        let merged = dir.join("merged");
+        run_join(&prefix, &merged.display().to_string(), &MergeOptions::parse(&[]).unwrap()).unwrap();
+        assert_eq!(std::fs::read(&merged).unwrap(), std::fs::read(&input).unwrap());
+        assert!(Config::parse(args(&["--line-ending", "custom:\r\n\r\n"])).unwrap_err().contains("可能与自身重叠"));
+        assert!(Config::parse(args(&["--stream", "--drop-invalid"])).is_err());
+    }
+
+    #[test]
+    fn content_defined_chunks_survive_an_insertion() {
+        let dir = Scratch::new("cdc");
+        let lines: Vec<String> = (0..40_000u64).map(|i| format!("{} {:x}\n", i, i.wrapping_mul(0x9E37_79B9_7F4A_7C15))).collect();
+        let split = |name: &str, text: String| {
+            let input = dir.join(name);
+            std::fs::write(&input, &text).unwrap();
+            let prefix = dir.join(name).with_extension("part").display().to_string();
+            let args = ["zstd_compressor", &input.display().to_string(), &prefix, "--cdc", "--yes", "--porcelain"];
+            let mut config = Config::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+            config.chunk_size = 64 * 1024;
+            run_split(&config).unwrap();
+            let merged = dir.join("merged");
+            run_join(&prefix, &merged.display().to_string(), &MergeOptions::parse(&[]).unwrap()).unwrap();
+            assert_eq!(std::fs::read_to_string(&merged).unwrap(), text);
+            let chunks = (1..).map(|n| chunk_name(&prefix, n, "zst")).take_while(|path| Path::new(path).exists());
+            chunks.map(|path| zstd::decode_all(File::open(path).unwrap()).unwrap()).collect::<Vec<_>>()
+        };
+        let before = split("a.log", lines.concat());
+        let after = split("b.log", [&lines[..100], &["edited\n".to_string()], &lines[100..]].concat().concat());
+        assert!(before.len() > 4 && before.iter().all(|chunk| chunk.ends_with(b"\n")));
+        assert!(before[1..].iter().all(|chunk| after.contains(chunk)));
+
+        assert!(Config::parse(["zstd_compressor", "a.log", "out/a", "--cdc", "--lines", "2"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn malformed_json_lines_are_reported_or_quarantined() {
+        let dir = Scratch::new("jsonl");
+        let prefix = dir.join("part").display().to_string();
+        let chunk = b"{\"a\": 1}\r\n{\"a\": \n\n[1, 2]";
+        let filter = |check: &str| {
+            let config = Config::parse(["zstd_compressor", "in.jsonl", &prefix, "--validate-json", check].map(String::from)).unwrap();
+            let mut rejects = Rejects::create(&config).unwrap();
+            let kept = filter_lines(chunk, &config, 100, &mut rejects).unwrap();
+            (kept, rejects.count, rejects.reported)
+        };
+        assert_eq!(filter("report"), (chunk.to_vec(), 0, 1));
+        assert_eq!(filter("quarantine"), (b"{\"a\": 1}\r\n\n[1, 2]".to_vec(), 1, 0));
+        assert!(std::fs::read_to_string(dir.join("part.rejects")).unwrap().starts_with("[invalid-json] offset=110 len=7\n"));
+        assert!(Config::parse(["zstd_compressor", "in.jsonl", "out/a", "--validate-json", "report", "--records", "csv"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn dropped_lines_go_to_a_fresh_rejects_file() {
+        let dir = Scratch::new("rejects");
+        let input = dir.join("in.log");
+        let prefix = dir.join("part").display().to_string();
+        let split = || {
+            let args = ["zstd_compressor", &input.display().to_string(), &prefix, "--drop-invalid", "--yes", "--porcelain"];
+            run_split(&Config::parse(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap()
+        };
+
+        std::fs::write(&input, b"good\nbad \xff\xfe\nalso good\n\x80\n").unwrap();
+        split();
+        let chunk = zstd::decode_all(File::open(chunk_name(&prefix, 1, "zst")).unwrap()).unwrap();
+        assert_eq!(chunk, b"good\nalso good\n");
+        let rejects = std::fs::read(dir.join("part.rejects")).unwrap();
+        assert_eq!(rejects, b"[invalid-encoding] offset=5 len=7\nbad \xff\xfe\n\n[invalid-encoding] offset=22 len=2\n\x80\n\n");
+
+        // 重跑时没有无效的行, 上次的 rejects 不能留下
+        std::fs::write(&input, "good\n").unwrap();
+        split();
+        assert!(!dir.join("part.rejects").exists());
+    }
+
+    #[test]
+    fn fixed_size_binary_records_are_never_split() {
+        let dir = Scratch::new("record_bytes");
+        let input = dir.join("in.bin");
+        let data: Vec<u8> = (0..10 * 300_000 + 5).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&input, &data).unwrap();
+        let prefix = dir.join("part").display().to_string();
+        let args = ["zstd_compressor", &input.display().to_string(), &prefix, "1", "--record-bytes", "300000", "--yes", "--porcelain"];
+        let config = Config::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+        assert!(config.binary && config.chunk_size == 900_000);
+        run_split(&config).unwrap();
+        let sizes: Vec<usize> = (1..=4).map(|n| zstd::decode_all(File::open(chunk_name(&prefix, n, "zst")).unwrap()).unwrap().len()).collect();
+        assert_eq!(sizes, [900_000, 900_000, 900_000, 300_005]);
+        let mut strict = args.map(String::from).to_vec();
+        strict.push("--fail-on-warning".to_string());
+        let Err(error) = run_split(&Config::parse(strict).unwrap()) else { panic!("应当报错") };
+        assert!(error.to_string().contains("不足一条记录"), "{}", error);
+        assert!(Config::parse(["zstd_compressor", "in.bin", "out/a", "--record-bytes", "100", "--skip-bytes", "150"].map(String::from)).is_err());
+        assert!(Config::parse(["zstd_compressor", "in.bin", "out/a", "--record-bytes", "100", "--records", "csv"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn compression_ratio_anomalies_warn_or_abort() {
+        // 5:1 允许偏差 50%, 即 2.5:1 到 7.5:1
+        let policy = |policy: &str| split_config(&["in.log", "out/a", "--expect-ratio", "5:1", "--ratio-tolerance", "50%", "--ratio-policy", policy], 0);
+        let (warn, abort) = (policy("warn"), policy("abort"));
+        assert!(check_ratio(1000, 140, &abort, 1).is_ok());
+        assert!(check_ratio(1000, 390, &abort, 1).is_ok());
+        assert!(check_ratio(1000, 100, &warn, 1).is_ok());
+        let error = check_ratio(1000, 100, &abort, 7).unwrap_err();
+        assert!(error.to_string().contains("分卷 7 压缩比异常: 10.00:1"), "{}", error);
+        assert!(check_ratio(1000, 900, &abort, 1).is_err());
+
+        // 日志中混入高度重复的数据时切分中止
+        let dir = Scratch::new("expect_ratio");
+        std::fs::write(dir.join("in.log"), "0000000000\n".repeat(10_000)).unwrap();
+        let config = split_config(&[&dir.arg("in.log"), &dir.arg("part"), "--expect-ratio", "5:1", "--ratio-policy", "abort"], 0);
+        let Err(error) = run_split(&config) else { panic!("应当中止") };
+        assert_eq!(exit_code(&error), EXIT_VERIFY);
+    }
+}
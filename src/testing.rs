@@ -0,0 +1,66 @@
+// 测试共用的临时目录与切分配置
+
+use std::env;
+use std::path::{Path, PathBuf};
+use crate::config::Config;
+use crate::json;
+use crate::manifest::{json_manifest_chunks, JsonChunk};
+
+// 测试用的临时目录, 离开作用域时删除, 断言失败时也不留下
+pub(crate) struct Scratch(PathBuf);
+
+impl Scratch {
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Scratch(dir)
+    }
+
+    // 目录中 name 的路径, 作为切分参数使用
+    pub(crate) fn arg(&self, name: &str) -> String {
+        self.0.join(name).display().to_string()
+    }
+
+    // 写入 n 行 "line {i}" 作为输入, 返回其路径与内容
+    pub(crate) fn lines(&self, name: &str, n: usize) -> (String, String) {
+        let text: String = (0..n).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(self.0.join(name), &text).unwrap();
+        (self.arg(name), text)
+    }
+}
+
+impl std::ops::Deref for Scratch {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for Scratch {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// 切分配置, 参数之后加上 --yes 与 --porcelain; chunk_size 非零时配合 4KB 读取缓冲切出多个小分卷
+pub(crate) fn split_config(args: &[&str], chunk_size: usize) -> Config {
+    let args = ["zstd_compressor"].iter().chain(args).chain(&["--yes", "--porcelain"]).map(|arg| arg.to_string());
+    let mut config = Config::parse(args).unwrap();
+    if chunk_size > 0 {
+        config.chunk_size = chunk_size;
+        config.buffer_size = Some(4096);
+    }
+    config
+}
+
+pub(crate) fn manifest_chunks(text: &str) -> Vec<JsonChunk> {
+    json_manifest_chunks(&json::parse(text.as_bytes()).unwrap()).unwrap()
+}
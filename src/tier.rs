@@ -0,0 +1,135 @@
+// 分卷的识别与分层存储: 把旧分卷迁到其他位置(tier), 合并与校验时按 <prefix>.tiers 找到它们
+
+use std::io::{self, Write, Read};
+use std::path::Path;
+use std::collections::HashMap;
+use std::time::Duration;
+use crate::prompt::confirm;
+use crate::source::{Destination, Source};
+
+// 形如 <stem>.001.zst 的分卷文件名
+pub(crate) fn is_chunk_file(name: &str, stem: &str) -> bool {
+    let Some((number, extension)) = name.strip_prefix(stem).and_then(|rest| rest.strip_prefix('.')).and_then(|rest| rest.split_once('.')) else {
+        return false;
+    };
+    number.len() >= 3 && number.bytes().all(|b| b.is_ascii_digit()) && !extension.is_empty() && !extension.contains('.')
+}
+
+// 分片用的 64 位 FNV-1a 哈希: 与平台和版本无关, 同一个键在每次运行中都落在同一个分片
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+// --partition-by 写出的 <stem>.<值>.001.zst, 值中可能有 .
+pub(crate) fn is_partition_file(name: &str, stem: &str) -> bool {
+    let Some(rest) = name.strip_prefix(stem).and_then(|rest| rest.strip_prefix('.')) else {
+        return false;
+    };
+    match rest.rsplitn(3, '.').nth(2) {
+        Some(key) if !key.is_empty() => is_chunk_file(name, &format!("{}.{}", stem, key)),
+        _ => false,
+    }
+}
+
+// 冷存储分层: 把修改时间超过 days 天的分卷移到次级位置. 本程序没有常驻的监视模式, 由定时任务反复调用;
+// 新位置追加记录到 <output_prefix>.tiers, 合并与校验时按它查找已迁移的分卷
+pub fn run_tier(output_prefix: &str, days: &str, destination: &str, yes: bool) -> io::Result<()> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let days: u64 = days.parse().map_err(|_| invalid(format!("无效的天数: {}", days)))?;
+    if destination.is_empty() {
+        return Err(invalid("缺少迁移目标".to_string()));
+    }
+    let destination = Destination::parse(destination).map_err(invalid)?;
+    let max_age = Duration::from_secs(days * 24 * 60 * 60);
+
+    let prefix = Path::new(output_prefix);
+    let dir = match prefix.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let stem = prefix.file_name().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let mut chunks = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let age = entry.metadata()?.modified()?.elapsed().unwrap_or_default();
+        if is_chunk_file(&name, &stem) && age >= max_age {
+            chunks.push(entry.path());
+        }
+    }
+    chunks.sort();
+    if !chunks.is_empty() {
+        confirm(&format!("将把 {} 个分卷从本地移到 {}", chunks.len(), destination), yes)?;
+    }
+
+    let mut catalog = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(format!("{}.tiers", output_prefix))?;
+    for path in &chunks {
+        let location = destination.move_file(path)?;
+        writeln!(catalog, "{}\t{}", path.file_name().unwrap().to_string_lossy(), location)?;
+        println!("迁移 {} -> {}", path.display(), location);
+    }
+    println!("共迁移 {} 个超过 {} 天的分卷", chunks.len(), days);
+    Ok(())
+}
+
+// <prefix>.tiers 中记录的已迁移分卷: 文件名 -> 新位置, 同一分卷以最后一次记录为准
+pub(crate) fn tier_locations(prefix: &str) -> io::Result<HashMap<String, String>> {
+    let source = Source::parse(&format!("{}.tiers", prefix));
+    if !source.exists()? {
+        return Ok(HashMap::new());
+    }
+    let mut text = String::new();
+    source.open()?.read_to_string(&mut text)?;
+    Ok(text.lines().filter_map(|line| line.split_once('\t')).map(|(name, location)| (name.to_string(), location.to_string())).collect())
+}
+
+// 本地分卷已被 --tier 移走时换成记录的新位置; 原处仍有同名文件(如之后重新切分)时以原处为准
+pub(crate) fn relocated(file: String, tiers: &HashMap<String, String>) -> String {
+    let name = file.rsplit('/').next().unwrap_or_default();
+    match (tiers.get(name), Source::parse(&file)) {
+        (Some(location), Source::Local(path)) if !path.exists() => location.clone(),
+        _ => file,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_name;
+    use crate::merge::{decode_chunks, run_join, MergeOptions};
+    use crate::split::run_split;
+    use crate::testing::{split_config, Scratch};
+
+    #[test]
+    fn chunk_file_names() {
+        assert!(is_chunk_file("out.001.zst", "out"));
+        assert!(is_chunk_file("out.1234.bz2", "out"));
+        assert!(!is_chunk_file("out.01.zst", "out"));
+        assert!(!is_chunk_file("out.state", "out"));
+        assert!(!is_chunk_file("out.001.zst.tmp", "out"));
+        assert!(!is_chunk_file("output.001.zst", "out"));
+    }
+
+    #[test]
+    fn tiered_chunks_are_still_found_by_merge() {
+        let dir = Scratch::new("tier");
+        let (input, text) = dir.lines("in.log", 3000);
+        let prefix = dir.arg("part");
+        let stats = run_split(&split_config(&[&input, &prefix], 8 * 1024)).unwrap();
+        assert!(stats.chunks > 1);
+
+        let cold = dir.join("cold");
+        run_tier(&prefix, "0", &cold.display().to_string(), true).unwrap();
+        assert!(!Path::new(&chunk_name(&prefix, 1, "zst")).exists() && cold.join("part.001.zst").exists());
+        let merged = dir.join("merged");
+        run_join(&prefix, &merged.display().to_string(), &MergeOptions::parse(&[]).unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(&merged).unwrap(), text);
+        // 没有清单时按编号探测也能找到
+        std::fs::remove_file(dir.join("part.manifest.json")).unwrap();
+        assert_eq!(decode_chunks(&prefix, &mut io::sink(), 2).unwrap(), (stats.chunks, text.len() as u64));
+    }
+}
@@ -0,0 +1,147 @@
+// 报告中数字的格式: 大小按 SI(1000 进, kB/MB) 或二进制(1024 进, KiB/MiB)自动选择单位,
+// 千位分隔符与小数点按区域设置; 机器可读的输出(--porcelain, JSON 中的数值字段)不经过这里
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnitSystem {
+    Binary,
+    Si,
+}
+
+impl UnitSystem {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "binary" | "iec" => Ok(UnitSystem::Binary),
+            "si" | "decimal" => Ok(UnitSystem::Si),
+            _ => Err(format!("无效的单位制: {}. 请使用 binary(KiB, MiB) 或 si(kB, MB)", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumberFormat {
+    pub units: UnitSystem,
+    // 整数部分每三位之间的分隔符, None 时不分组
+    pub grouping: Option<char>,
+    pub decimal_mark: char,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat { units: UnitSystem::Binary, grouping: None, decimal_mark: '.' }
+    }
+}
+
+impl NumberFormat {
+    // 按区域名(如 en_US.UTF-8, de_DE, fr)取千位分隔符与小数点; C, POSIX 与不认识的语言不分组
+    pub fn for_locale(locale: &str, units: UnitSystem) -> Self {
+        let name = locale.split(['.', '@']).next().unwrap_or_default();
+        let (language, region) = name.split_once(['_', '-']).unwrap_or((name, ""));
+        let (grouping, decimal_mark) = match (language.to_lowercase().as_str(), region.to_uppercase().as_str()) {
+            ("de" | "fr" | "it", "CH") => (Some('\''), '.'),
+            ("en" | "zh" | "ja" | "ko" | "th" | "he", _) => (Some(','), '.'),
+            ("de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da" | "el", _) => (Some('.'), ','),
+            ("fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" | "uk" | "hu", _) => (Some('\u{a0}'), ','),
+            _ => (None, '.'),
+        };
+        NumberFormat { units, grouping, decimal_mark }
+    }
+
+    // 整数, 按区域分组
+    pub fn integer(&self, value: u64) -> String {
+        self.group(&value.to_string())
+    }
+
+    // 保留 precision 位小数
+    pub fn decimal(&self, value: f64, precision: usize) -> String {
+        let text = format!("{:.*}", precision, value.abs());
+        let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+        let sign = if value < 0.0 && text.bytes().any(|b| b.is_ascii_digit() && b != b'0') { "-" } else { "" };
+        match fraction {
+            "" => format!("{}{}", sign, self.group(whole)),
+            fraction => format!("{}{}{}{}", sign, self.group(whole), self.decimal_mark, fraction),
+        }
+    }
+
+    // 大小选用不小于 1 的最大单位, 如 1.50 MiB; 不足一个单位时以字节计
+    pub fn size(&self, bytes: u64) -> String {
+        let (base, units) = match self.units {
+            UnitSystem::Binary => (1024.0, ["KiB", "MiB", "GiB", "TiB", "PiB"]),
+            UnitSystem::Si => (1000.0, ["kB", "MB", "GB", "TB", "PB"]),
+        };
+        if (bytes as f64) < base {
+            return format!("{} B", bytes);
+        }
+        let mut value = bytes as f64;
+        let mut unit = units[0];
+        for candidate in units {
+            value /= base;
+            unit = candidate;
+            if value < base {
+                break;
+            }
+        }
+        format!("{} {}", self.decimal(value, 2), unit)
+    }
+
+    pub fn rate(&self, bytes: u64, seconds: f64) -> String {
+        format!("{}/s", self.size((bytes as f64 / seconds.max(1e-9)) as u64))
+    }
+
+    // 不足一分钟时保留两位小数, 否则为时、分、秒
+    pub fn duration(&self, seconds: f64) -> String {
+        if seconds < 60.0 {
+            return format!("{} 秒", self.decimal(seconds, 2));
+        }
+        let total = seconds.round() as u64;
+        match (total / 3600, total / 60 % 60, total % 60) {
+            (0, minutes, seconds) => format!("{} 分 {} 秒", minutes, seconds),
+            (hours, minutes, seconds) => format!("{} 时 {} 分 {} 秒", self.integer(hours), minutes, seconds),
+        }
+    }
+
+    fn group(&self, digits: &str) -> String {
+        let Some(separator) = self.grouping else { return digits.to_string() };
+        let mut grouped = String::with_capacity(digits.len() * 4 / 3);
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push(separator);
+            }
+            grouped.push(c);
+        }
+        grouped
+    }
+}
+
+// 环境变量中的区域设置, 优先级与 C 库相同
+pub fn env_locale() -> String {
+    ["LC_ALL", "LC_NUMERIC", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_else(|| "C".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_follow_units_and_locale() {
+        let c = NumberFormat::default();
+        assert_eq!(c.size(512), "512 B");
+        assert_eq!(c.size(1536 * 1024), "1.50 MiB");
+        assert_eq!(c.integer(1234567), "1234567");
+        let en = NumberFormat::for_locale("en_US.UTF-8", UnitSystem::Si);
+        assert_eq!(en.size(1_500_000), "1.50 MB");
+        assert_eq!(en.size(2_500_000_000_000_000_000), "2,500.00 PB");
+        assert_eq!(en.integer(1234567), "1,234,567");
+        let de = NumberFormat::for_locale("de_DE", UnitSystem::Binary);
+        assert_eq!(de.decimal(-1234.5, 2), "-1.234,50");
+        assert_eq!(de.decimal(-0.001, 2), "0,00");
+        assert_eq!(NumberFormat::for_locale("de_CH", UnitSystem::Binary).integer(1000), "1'000");
+        assert_eq!(de.duration(3.256), "3,26 秒");
+        assert_eq!(de.duration(3725.0), "1 时 2 分 5 秒");
+        assert_eq!(en.rate(3_000_000, 2.0), "1.50 MB/s");
+        assert!(UnitSystem::parse("bits").is_err());
+    }
+}
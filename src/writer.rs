@@ -0,0 +1,467 @@
+// 分卷的写出: 逐个分卷文件、单个文件或 7z 归档, 镜像到其他目的地, 以及边切分边压缩的流式写出
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use crate::checkpoint::Checkpoint;
+use crate::compress::compress_chunk;
+use crate::config::{inject_failure, Config, FailureStage, GzipMtime, GzipName, TeePlain};
+use crate::hash::{chunk_digest, to_hex, Sha256};
+use crate::header::{copied_footer_len, copied_header_len};
+use crate::journal::Journal;
+use crate::manifest::{lineage, manifest_fields, JsonChunk, JsonManifest, Manifest};
+use crate::merkle::{count_lines, merkle_root, split_lines, LineCounter};
+use crate::naming::{chunk_naming, striped, temp_path, ChunkNaming};
+use crate::profiler::Profiler;
+use crate::seven_zip::SevenZipWriter;
+use crate::split::check_ratio;
+use crate::{codec, ChunkSink, Format};
+
+// 输出目标: 每个分卷一个文件, 或全部作为独立的 zstd 帧依次追加到同一个文件;
+// zstd 帧可以直接拼接, 解压整个文件即得到完整数据, 索引中的帧偏移保留按分卷随机访问
+pub(crate) enum Output {
+    Files {
+        naming: Box<dyn ChunkNaming>,
+        // --output-dirs 的目录, 已转换为绝对路径
+        dirs: Vec<PathBuf>,
+        manifest: Option<Manifest>,
+        journal: Option<Journal>,
+    },
+    Single {
+        path: PathBuf,
+        file: File,
+        index_path: PathBuf,
+        index: File,
+        offset: u64,
+    },
+    SevenZip(SevenZipWriter),
+}
+
+impl Output {
+    pub(crate) fn open(config: &Config) -> io::Result<Self> {
+        if config.format == Format::SevenZip {
+            return Ok(Output::SevenZip(SevenZipWriter::create(&config.output_prefix, config.seven_zip_codec, config.zstd)?));
+        }
+        let Some(path) = &config.single_output else {
+            // 分区模式各分区的分卷前缀不同, 不写 manifest
+            let manifest = if config.partitioned() { None } else { Some(Manifest::create(config)?) };
+            let journal = if config.journal { Some(Journal::create(&config.output_prefix)?) } else { None };
+            // manifest 中记录绝对路径, 合并时不受当前目录影响
+            let mut dirs = Vec::new();
+            for dir in &config.output_dirs {
+                std::fs::create_dir_all(dir)?;
+                dirs.push(std::fs::canonicalize(dir)?);
+            }
+            return Ok(Output::Files { naming: chunk_naming(config), dirs, manifest, journal });
+        };
+        let index_path = PathBuf::from(format!("{}.idx", path.display()));
+        let mut index = File::create(&index_path)?;
+        for (key, value) in lineage(config) {
+            writeln!(index, "# {}: {}", key, value)?;
+        }
+        writeln!(index, "# chunk\tframe_offset\tframe_len\tinput_offset\traw_len")?;
+        Ok(Output::Single {
+            path: path.clone(),
+            file: File::create(path)?,
+            index_path,
+            index,
+            offset: 0,
+        })
+    }
+
+    // 写出一个压缩好的分卷, 返回检查点时需要同步到磁盘的文件; digest 为原始内容的 SHA-256, 以内容命名时总会计算
+    #[allow(clippy::too_many_arguments)]
+    fn write(&mut self, compressed: &[u8], raw: &[u8], digest: Option<[u8; 32]>, lines: Option<u64>, config: &Config, chunk_number: usize, input_offset: usize) -> io::Result<Vec<PathBuf>> {
+        match self {
+            Output::Files { naming, dirs, manifest, journal } => {
+                // 内容相同的分卷键相同, 以键命名时重复写出的是同一个文件
+                let key = digest.filter(|_| config.content_addressed).map(|digest| to_hex(&digest));
+                let output_path = striped(dirs, chunk_number, naming.path(config, chunk_number, raw, key.as_deref())?);
+                let member;
+                let compressed = if config.format == Format::Gzip {
+                    member = gzip_member(config, Some(&output_path), compressed)?;
+                    &member[..]
+                } else {
+                    compressed
+                };
+                // 以内容命名的分卷已经存在时内容必然相同, 不再重写; 重写会先截断已完成的文件
+                if !(config.name_by_hash && output_path.exists()) {
+                    // 先写临时文件再重命名, 分卷文件名下不会出现写了一半的数据
+                    let temp = temp_path(&output_path);
+                    if let Some(journal) = journal.as_mut() {
+                        journal.record("begin", chunk_number, &output_path, None)?;
+                    }
+                    let mut file = File::create(&temp)?;
+                    if inject_failure(config, FailureStage::Write, chunk_number) {
+                        // 只写出一半, 与写到一半时磁盘写满或进程被杀的现场相同
+                        file.write_all(&compressed[..compressed.len() / 2])?;
+                        return Err(io::Error::other(format!("注入的故障: 写出分卷 {} 时失败", chunk_number)));
+                    }
+                    file.write_all(compressed)?;
+                    // 分卷落盘后才记录完成, 之后才写 manifest, 恢复时删掉的分卷不会出现在 manifest 中
+                    if journal.is_some() {
+                        file.sync_data()?;
+                    }
+                    std::fs::rename(&temp, &output_path)?;
+                    if let Some(journal) = journal.as_mut() {
+                        journal.record("done", chunk_number, &output_path, Some(compressed.len()))?;
+                    }
+                }
+                let mut written = vec![output_path];
+                if let Some(manifest) = manifest {
+                    let merkle = config.line_merkle.then(|| merkle_root(&split_lines(raw, &config.encoding.encode(&config.line_ending).0)));
+                    let fields = manifest_fields(config, chunk_number, raw.len(), lines, digest, merkle, &written[0]);
+                    written.extend(manifest.put(&written[0], compressed, &fields, chunk_number, config)?);
+                }
+                Ok(written)
+            }
+            Output::Single { path, file, index_path, index, offset } => {
+                let member;
+                let compressed = if config.format == Format::Gzip {
+                    member = gzip_member(config, None, compressed)?;
+                    &member[..]
+                } else {
+                    compressed
+                };
+                if inject_failure(config, FailureStage::Write, chunk_number) {
+                    file.write_all(&compressed[..compressed.len() / 2])?;
+                    return Err(io::Error::other(format!("注入的故障: 写出分卷 {} 时失败", chunk_number)));
+                }
+                file.write_all(compressed)?;
+                writeln!(index, "{}\t{}\t{}\t{}\t{}", chunk_number, offset, compressed.len(), input_offset, raw.len())?;
+                *offset += compressed.len() as u64;
+                Ok(vec![path.clone(), index_path.clone()])
+            }
+            Output::SevenZip(archive) => {
+                if inject_failure(config, FailureStage::Write, chunk_number) {
+                    return Err(io::Error::other(format!("注入的故障: 写出分卷 {} 时失败", chunk_number)));
+                }
+                archive.add(compressed, raw, chunk_number)?;
+                Ok(vec![archive.path.clone()])
+            }
+        }
+    }
+
+    // 所有分卷写完后收尾, 7z 归档在此写入头部
+    fn finish(&mut self) -> io::Result<()> {
+        match self {
+            Output::SevenZip(archive) => archive.finish(),
+            _ => Ok(()),
+        }
+    }
+}
+
+// 按 --gzip-name 与 --gzip-mtime 填写 gzip 分卷的成员头; chunk 为分卷文件, 单文件输出时没有
+pub(crate) fn gzip_member(config: &Config, chunk: Option<&Path>, member: &[u8]) -> io::Result<Vec<u8>> {
+    let file_name = |path: &Path| path.file_name().map(|name| name.to_string_lossy().into_owned());
+    let name = match config.gzip_name {
+        GzipName::Chunk => chunk.and_then(file_name).map(|name| name.strip_suffix(".gz").map(str::to_string).unwrap_or(name)),
+        GzipName::Input if config.input_path != "-" => file_name(Path::new(&config.input_path)),
+        _ => None,
+    };
+    let seconds = |time: std::time::SystemTime| time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs().min(u32::MAX as u64) as u32);
+    let mtime = match config.gzip_mtime {
+        // 标准输入与远程输入没有修改时间
+        GzipMtime::Input => std::fs::metadata(&config.input_path).and_then(|m| m.modified()).map_or(0, seconds),
+        GzipMtime::Now => seconds(std::time::SystemTime::now()),
+        GzipMtime::Omit => 0,
+        GzipMtime::At(mtime) => mtime,
+    };
+    codec::set_gzip_header(member, name.as_deref(), mtime)
+}
+
+// 压缩并写出分卷: 负责编号, 经 Output 选择写到分卷文件、单个文件或 7z 归档, 与读取循环无关
+pub(crate) struct ChunkWriter<'a> {
+    pub(crate) config: &'a Config,
+    pub(crate) output: Output,
+    // 下一个分卷的编号, 从 1 开始
+    pub(crate) next_number: usize,
+    // 已写出分卷压缩前后的总字节数, --drop-invalid 丢弃的行不计
+    pub(crate) raw_bytes: u64,
+    pub(crate) compressed_bytes: u64,
+    plain: Option<PlainTee>,
+    // 切分结束时写出的 <prefix>.manifest.json, 只在 run_split 中启用
+    pub(crate) json: Option<JsonManifest>,
+}
+
+impl<'a> ChunkWriter<'a> {
+    pub(crate) fn new(config: &'a Config, output: Output) -> Self {
+        let plain = config.tee_plain.clone().map(|target| PlainTee::new(target, &config.output_prefix));
+        let next_number = config.resume.map_or(1, |point| point.next_chunk);
+        ChunkWriter { config, output, next_number, raw_bytes: 0, compressed_bytes: 0, plain, json: None }
+    }
+
+    pub(crate) fn chunks(&self) -> usize {
+        self.next_number - 1
+    }
+
+    // 返回检查点时需要同步到磁盘的文件
+    pub(crate) fn write(&mut self, chunk: &[u8], chunk_offset: usize, profiler: &Profiler) -> io::Result<Vec<PathBuf>> {
+        let compressed = compress_chunk(chunk, self.config, self.next_number, profiler)?;
+        self.store(chunk, &compressed, chunk_digest(chunk, self.config), chunk_offset, profiler)
+    }
+
+    // 写出已经压缩好的分卷, 分卷必须按编号顺序到达; 摘要在压缩线程中与压缩一起算好
+    pub(crate) fn store(&mut self, chunk: &[u8], compressed: &[u8], digest: Option<[u8; 32]>, chunk_offset: usize, profiler: &Profiler) -> io::Result<Vec<PathBuf>> {
+        let (config, chunk_number) = (self.config, self.next_number);
+        let _span = profiler.span("write");
+        let lines = count_lines(chunk, config);
+        let written = self.output.write(compressed, chunk, digest, lines, config, chunk_number, chunk_offset)?;
+        if let Some(plain) = &mut self.plain {
+            plain.write(chunk_number, chunk, copied_header_len(config, chunk_number), copied_footer_len(config, chunk_number))?;
+        }
+        self.written(&written[0], chunk.len(), compressed.len(), digest, lines);
+        Ok(written)
+    }
+
+    // 一个分卷写出完成: 计数, 记入 JSON 清单并输出一行进度
+    pub(crate) fn written(&mut self, path: &Path, raw_len: usize, compressed_len: usize, digest: Option<[u8; 32]>, lines: Option<u64>) {
+        let chunk_number = self.next_number;
+        self.next_number += 1;
+        self.raw_bytes += raw_len as u64;
+        self.compressed_bytes += compressed_len as u64;
+        if let Some(json) = self.json.as_mut().filter(|json| json.table.is_none()) {
+            // --output-dirs 时分卷不在 prefix 所在目录, 与 manifest 的 dir 列一样记录绝对目录
+            let dir = (!self.config.output_dirs.is_empty()).then(|| {
+                let parent = path.parent().unwrap();
+                std::path::absolute(parent).unwrap_or_else(|_| parent.to_path_buf()).display().to_string()
+            });
+            let file = path.file_name().unwrap().to_string_lossy().into_owned();
+            let (line_merkle, header_bytes, footer_bytes, mirrors) = (None, None, None, Vec::new());
+            json.chunks.push(JsonChunk { chunk: chunk_number, file, dir, bytes: compressed_len as u64, raw_bytes: raw_len as u64, lines, sha256: digest, line_merkle, header_bytes, footer_bytes, mirrors });
+        }
+        if self.config.porcelain {
+            println!("CHUNK {} {} {} {} {}", chunk_number, path.display(), raw_len, compressed_len, digest.map_or("-".to_string(), |digest| to_hex(&digest)));
+        } else {
+            println!("写入分卷 {} (压缩后 {} 字节)", chunk_number, compressed_len);
+        }
+    }
+
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        self.output.finish()
+    }
+}
+
+// --tee-plain 的写出端, 文件在第一次写入时创建, 没有分卷就不留下空文件
+struct PlainTee {
+    target: TeePlain,
+    stem: String,
+    // 正在写入的文件与它所属的分卷编号; 拼成一个文件时编号始终为 0
+    file: Option<(usize, File)>,
+}
+
+impl PlainTee {
+    fn new(target: TeePlain, output_prefix: &str) -> Self {
+        let stem = Path::new(output_prefix).file_name().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        PlainTee { target, stem, file: None }
+    }
+
+    // 写入分卷的一段原始内容, --stream 时同一分卷分多次到达; 拼成一个文件时跳过开头复制的表头与结尾补上的结束标签
+    fn write(&mut self, chunk_number: usize, data: &[u8], header_len: usize, footer_len: usize) -> io::Result<()> {
+        let (key, data) = match &self.target {
+            TeePlain::Chunks(_) => (chunk_number, data),
+            TeePlain::Single(_) => (0, &data[header_len.min(data.len())..data.len().saturating_sub(footer_len).max(header_len.min(data.len()))]),
+        };
+        if self.file.as_ref().map(|(number, _)| *number) != Some(key) {
+            let path = match &self.target {
+                TeePlain::Chunks(dir) => {
+                    std::fs::create_dir_all(dir)?;
+                    dir.join(format!("{}.{:03}.txt", self.stem, chunk_number))
+                }
+                TeePlain::Single(path) => path.clone(),
+            };
+            self.file = Some((key, File::create(path)?));
+        }
+        self.file.as_mut().unwrap().1.write_all(data)
+    }
+}
+
+// --stream 的接收端: 分卷的数据一到就经 zstd 流式压缩写入临时文件, 分卷结束时收尾并改名;
+// 内存中只有读取缓冲区与 zstd 的窗口, 与分块大小无关. 分卷编号、日志、检查点与 ChunkWriter 相同
+pub(crate) struct StreamingWriter<'a, 'c> {
+    pub(crate) writer: &'a mut ChunkWriter<'c>,
+    pub(crate) checkpoint: &'a mut Checkpoint,
+    pub(crate) profiler: &'a Profiler,
+    pub(crate) current: Option<OpenChunk>,
+}
+
+// 正在写入的分卷
+pub(crate) struct OpenChunk {
+    path: PathBuf,
+    encoder: zstd::stream::Encoder<'static, File>,
+    // 原始内容的摘要与行数, 二进制输入不计行数
+    hasher: Option<Sha256>,
+    lines: Option<LineCounter>,
+    offset: usize,
+    raw_len: usize,
+}
+
+impl StreamingWriter<'_, '_> {
+    fn open(&mut self, offset: usize) -> io::Result<OpenChunk> {
+        let (config, chunk_number) = (self.writer.config, self.writer.next_number);
+        if inject_failure(config, FailureStage::Compress, chunk_number) {
+            return Err(io::Error::other(format!("注入的故障: 压缩分卷 {} 时失败", chunk_number)));
+        }
+        let Output::Files { naming, dirs, journal, .. } = &mut self.writer.output else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--stream 只支持逐个分卷输出"));
+        };
+        let path = striped(dirs, chunk_number, naming.path(config, chunk_number, &[], None)?);
+        if let Some(journal) = journal.as_mut() {
+            journal.record("begin", chunk_number, &path, None)?;
+        }
+        let encoder = config.zstd.encoder(File::create(temp_path(&path))?)?;
+        Ok(OpenChunk { path, encoder, hasher: config.chunk_sha256.then(Sha256::new), lines: LineCounter::new(config), offset, raw_len: 0 })
+    }
+}
+
+impl ChunkSink for StreamingWriter<'_, '_> {
+    fn data(&mut self, data: &[u8], offset: usize) -> io::Result<()> {
+        if self.current.is_none() {
+            self.current = Some(self.open(offset)?);
+        }
+        let chunk = self.current.as_mut().unwrap();
+        let _span = self.profiler.span("compress");
+        chunk.encoder.write_all(data)?;
+        if let Some(plain) = &mut self.writer.plain {
+            plain.write(self.writer.next_number, data, 0, 0)?;
+        }
+        if let Some(hasher) = &mut chunk.hasher {
+            hasher.update(data);
+        }
+        if let Some(lines) = &mut chunk.lines {
+            lines.update(data);
+        }
+        chunk.raw_len += data.len();
+        if self.checkpoint.due_within_chunk() {
+            let _span = self.profiler.span("checkpoint");
+            chunk.encoder.flush()?;
+            chunk.encoder.get_ref().sync_data()?;
+            self.checkpoint.flush(self.writer, false)?;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> io::Result<()> {
+        let Some(chunk) = self.current.take() else { return Ok(()) };
+        let (config, chunk_number) = (self.writer.config, self.writer.next_number);
+        let span = self.profiler.span("write");
+        let file = chunk.encoder.finish()?;
+        let compressed_len = file.metadata()?.len();
+        check_ratio(chunk.raw_len, compressed_len as usize, config, chunk_number)?;
+        if inject_failure(config, FailureStage::Truncate, chunk_number) {
+            file.set_len(compressed_len / 2)?;
+        }
+        if inject_failure(config, FailureStage::Write, chunk_number) {
+            file.set_len(compressed_len / 2)?;
+            return Err(io::Error::other(format!("注入的故障: 写出分卷 {} 时失败", chunk_number)));
+        }
+        let Output::Files { journal, manifest, .. } = &mut self.writer.output else { unreachable!() };
+        if journal.is_some() {
+            file.sync_data()?;
+        }
+        std::fs::rename(temp_path(&chunk.path), &chunk.path)?;
+        if let Some(journal) = journal.as_mut() {
+            journal.record("done", chunk_number, &chunk.path, Some(compressed_len as usize))?;
+        }
+        let (lines, digest) = (chunk.lines.as_ref().map(LineCounter::total), chunk.hasher.map(Sha256::finish));
+        if let Some(manifest) = manifest {
+            manifest.append(&chunk.path, compressed_len, &manifest_fields(config, chunk_number, chunk.raw_len, lines, digest, None, &chunk.path), chunk_number)?;
+        }
+        drop(span);
+
+        self.writer.written(&chunk.path, chunk.raw_len, compressed_len as usize, digest, lines);
+        let _span = self.profiler.span("checkpoint");
+        self.checkpoint.chunk_written(vec![chunk.path], self.writer, chunk.offset + chunk.raw_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+use crate::hash::sha256;
+    use super::*;
+    use crate::chunk_name;
+    use crate::exit::{exit_code, EXIT_PARTIAL};
+    use crate::naming::chunk_path;
+    use crate::split::run_split;
+    use crate::testing::{split_config, Scratch};
+
+    #[test]
+    fn chunk_writer_numbers_and_names_chunks() {
+        let dir = Scratch::new("chunk_writer");
+        let prefix = dir.join("part").display().to_string();
+        let profiler = Profiler::new(false);
+
+        let config = Config::parse(["zstd_compressor", "in.log", &prefix].map(String::from)).unwrap();
+        let mut writer = ChunkWriter::new(&config, Output::open(&config).unwrap());
+        let first = writer.write(b"a\n", 0, &profiler).unwrap();
+        let second = writer.write(b"b\n", 2, &profiler).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(writer.chunks(), 2);
+        assert_eq!(first[0], chunk_path(&prefix, 1, "zst"));
+        assert_eq!(second[0], chunk_path(&prefix, 2, "zst"));
+        assert_eq!(zstd::decode_all(File::open(&second[0]).unwrap()).unwrap(), b"b\n");
+        assert!(!temp_path(&first[0]).exists());
+
+        let config = Config::parse(["zstd_compressor", "in.log", &prefix, "--content-addressed", "--name-by-hash"].map(String::from)).unwrap();
+        let mut writer = ChunkWriter::new(&config, Output::open(&config).unwrap());
+        let first = writer.write(b"same\n", 0, &profiler).unwrap();
+        let second = writer.write(b"same\n", 5, &profiler).unwrap();
+        assert_eq!(first[0], second[0]);
+        assert!(first[0].display().to_string().contains(&to_hex(&sha256(b"same\n"))));
+    }
+
+    #[test]
+    fn plain_copies_are_written_alongside_the_chunks() {
+        let dir = Scratch::new("tee_plain");
+        let input = dir.join("in.csv");
+        std::fs::write(&input, "name,age\na,1\nb,2\nc,3\n").unwrap();
+        let prefix = dir.join("part").display().to_string();
+        let plain = dir.join("plain");
+        let single = dir.join("all.csv");
+        for tee in [plain.display().to_string(), format!("single:{}", single.display())] {
+            let args = ["zstd_compressor", &input.display().to_string(), &prefix, "--lines", "2", "--csv-header", "first", "--tee-plain", &tee, "--yes", "--porcelain"];
+            run_split(&Config::parse(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        }
+        for n in 1..=2 {
+            let chunk = zstd::decode_all(File::open(chunk_name(&prefix, n, "zst")).unwrap()).unwrap();
+            assert_eq!(std::fs::read(plain.join(format!("part.{:03}.txt", n))).unwrap(), chunk);
+        }
+        assert_eq!(std::fs::read(&single).unwrap(), std::fs::read(&input).unwrap());
+
+        // --stream 时分卷的内容分段到达
+        std::fs::remove_dir_all(&plain).unwrap();
+        let args = ["zstd_compressor", &input.display().to_string(), &prefix, "--stream", "--tee-plain", &plain.display().to_string(), "--yes", "--porcelain"];
+        run_split(&Config::parse(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        assert_eq!(std::fs::read(plain.join("part.001.txt")).unwrap(), std::fs::read(&input).unwrap());
+        assert!(TeePlain::parse("single:").is_err());
+    }
+
+    #[test]
+    fn mirrored_chunks_record_each_destination_in_the_manifest() {
+        let dir = Scratch::new("mirrors");
+        let (input, _) = dir.lines("in.log", 5000);
+        let prefix = dir.arg("part");
+        let local = dir.arg("local");
+        // 以普通文件为上级目录的路径无法创建, root 运行时也一样
+        std::fs::write(dir.join("not_a_dir"), b"").unwrap();
+        let broken = dir.arg("not_a_dir/mirror");
+        let Err(error) = run_split(&split_config(&[&input, &prefix, "--output", &local, "--output", &broken], 8 * 1024)) else {
+            panic!("镜像失败时应当报告部分完成")
+        };
+        assert_eq!(exit_code(&error), EXIT_PARTIAL);
+
+        let manifest = std::fs::read_to_string(dir.join("part.manifest")).unwrap();
+        assert_eq!(manifest.lines().nth(1).unwrap(), format!("# chunk\tfile\tbytes\traw_bytes\tlines\tsha256\t{}\t{}", local, broken));
+        let rows: Vec<Vec<&str>> = manifest.lines().filter(|line| !line.starts_with('#')).map(|line| line.split('\t').collect()).collect();
+        assert!(rows.len() > 2);
+        for (n, row) in rows.iter().enumerate() {
+            assert_eq!(row[0], (n + 1).to_string());
+            assert_eq!(row[6], "ok");
+            assert!(row[7].starts_with("failed: "), "{}", row[7]);
+            // 成功的镜像与主分卷逐字节相同
+            let chunk = std::fs::read(chunk_name(&prefix, n + 1, "zst")).unwrap();
+            assert!(std::fs::read(Path::new(&local).join(row[1])).unwrap() == chunk);
+        }
+    }
+}